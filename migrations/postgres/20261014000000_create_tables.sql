@@ -0,0 +1,30 @@
+-- Initial schema. IF NOT EXISTS lets databases that were set up by hand with
+-- reset.sh adopt migrations without being recreated.
+
+CREATE TABLE IF NOT EXISTS articles (
+    id SERIAL PRIMARY KEY,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    bump_time BIGINT NOT NULL,
+    delete_password_hash TEXT,
+    edited_at BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS article_media (
+    id SERIAL PRIMARY KEY,
+    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    media_path TEXT NOT NULL,
+    original_name TEXT,
+    thumb_path TEXT
+);
+
+CREATE TABLE IF NOT EXISTS comments (
+    id SERIAL PRIMARY KEY,
+    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    comment TEXT NOT NULL,
+    delete_password_hash TEXT
+);
+
+CREATE INDEX IF NOT EXISTS articles_bump_time_idx ON articles (bump_time);
+CREATE INDEX IF NOT EXISTS article_media_article_id_idx ON article_media (article_id);
+CREATE INDEX IF NOT EXISTS comments_article_id_idx ON comments (article_id);
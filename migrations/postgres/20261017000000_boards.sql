@@ -0,0 +1,18 @@
+-- Boards group articles by topic. Articles that predate boards move to the
+-- "main" board, which is served at the original routes unless DEFAULT_BOARD
+-- names another one.
+
+CREATE TABLE IF NOT EXISTS boards (
+    id SERIAL PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO boards (slug, title) VALUES ('main', 'All Articles') ON CONFLICT (slug) DO NOTHING;
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS board_id INT REFERENCES boards(id);
+UPDATE articles SET board_id = (SELECT id FROM boards WHERE slug = 'main') WHERE board_id IS NULL;
+ALTER TABLE articles ALTER COLUMN board_id SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS articles_board_id_bump_time_idx ON articles (board_id, bump_time);
@@ -0,0 +1,7 @@
+-- Readable URL segment generated from the title. Articles from before slugs,
+-- and ones whose title has nothing to slugify, keep NULL and are served at
+-- /articles/{id}.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS slug TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS articles_slug_idx ON articles (slug);
@@ -0,0 +1,11 @@
+-- Words and phrases that get posts rejected or rewritten, managed with the
+-- add-filter / remove-filter commands. Plain patterns match whole words,
+-- ignoring case; regex patterns are used as written.
+
+CREATE TABLE IF NOT EXISTS word_filters (
+    id SERIAL PRIMARY KEY,
+    pattern TEXT NOT NULL,
+    is_regex BOOLEAN NOT NULL DEFAULT FALSE,
+    action TEXT NOT NULL CHECK (action IN ('reject', 'replace')),
+    replacement TEXT NOT NULL DEFAULT ''
+);
@@ -0,0 +1,16 @@
+-- Where posts came from, for moderation, and the bans on posting. cidr holds a
+-- single address or a range such as 2001:db8::/64; expires_at is NULL for
+-- permanent bans. Posts from before this migration have no address.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS poster_ip TEXT;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS poster_ip TEXT;
+
+CREATE TABLE IF NOT EXISTS bans (
+    id SERIAL PRIMARY KEY,
+    cidr TEXT NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS bans_expires_at_idx ON bans (expires_at);
@@ -0,0 +1,17 @@
+-- Reports readers file against articles and comments; comment_id is NULL for
+-- reports on the article itself. resolved_at is set when an admin dismisses
+-- the report or deletes what it was about.
+
+CREATE TABLE IF NOT EXISTS reports (
+    id SERIAL PRIMARY KEY,
+    article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    comment_id INT REFERENCES comments(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL DEFAULT '',
+    reporter_ip TEXT,
+    created_at BIGINT NOT NULL,
+    resolved_at BIGINT
+);
+
+-- One open report per target from each address
+CREATE UNIQUE INDEX IF NOT EXISTS reports_open_dedupe_idx
+    ON reports (article_id, COALESCE(comment_id, 0), reporter_ip) WHERE resolved_at IS NULL;
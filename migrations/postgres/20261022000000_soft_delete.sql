@@ -0,0 +1,9 @@
+-- Deleting an article or comment sets deleted_at instead of removing the row,
+-- so admins can restore it. `articles purge-deleted` removes rows (and media)
+-- once they have been deleted for longer than DELETED_RETENTION_DAYS.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS articles_deleted_at_idx ON articles (deleted_at);
+CREATE INDEX IF NOT EXISTS comments_deleted_at_idx ON comments (deleted_at);
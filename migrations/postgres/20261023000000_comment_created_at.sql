@@ -0,0 +1,4 @@
+-- When each comment was posted, shown next to its permalink. Comments from
+-- before this migration have no time and are left NULL.
+
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS created_at BIGINT;
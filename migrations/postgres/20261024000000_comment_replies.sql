@@ -0,0 +1,6 @@
+-- A comment may reply to another comment on the same article. Purging a parent
+-- leaves its replies in place as top-level comments.
+
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS parent_comment_id INT REFERENCES comments(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS comments_parent_comment_id_idx ON comments (parent_comment_id);
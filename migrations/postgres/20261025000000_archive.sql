@@ -0,0 +1,6 @@
+-- Articles pushed past the newest ARCHIVE_AFTER on their board get archived_at
+-- set: they leave the main list for /archive and stop taking comments.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS archived_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS articles_archived_at_idx ON articles (archived_at);
@@ -0,0 +1,4 @@
+-- Sticky articles stay at the top of their board's list whatever their
+-- bump_time, and are never archived or pruned. Admins set the flag.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS is_sticky BOOLEAN NOT NULL DEFAULT FALSE;
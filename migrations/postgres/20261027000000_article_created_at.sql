@@ -0,0 +1,6 @@
+-- When an article was posted. bump_time used to double as this, but moves
+-- with every bump, so existing articles get it as the best guess available.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS created_at BIGINT;
+UPDATE articles SET created_at = bump_time WHERE created_at IS NULL;
+ALTER TABLE articles ALTER COLUMN created_at SET NOT NULL;
@@ -0,0 +1,14 @@
+-- Give the comments posted before created_at existed a best guess: the time
+-- of the next timestamped comment on the article, so the order doesn't
+-- change, or failing that the article's bump_time. New comments default to
+-- now, though submit_comment sets it anyway.
+
+UPDATE comments SET created_at = COALESCE(
+    (SELECT MIN(later.created_at) FROM comments later
+     WHERE later.article_id = comments.article_id AND later.id > comments.id AND later.created_at IS NOT NULL),
+    (SELECT bump_time FROM articles WHERE articles.id = comments.article_id)
+)
+WHERE created_at IS NULL;
+
+ALTER TABLE comments ALTER COLUMN created_at SET DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT;
+ALTER TABLE comments ALTER COLUMN created_at SET NOT NULL;
@@ -0,0 +1,5 @@
+-- The name a post was made under. Posters may leave it blank, which is
+-- stored as "Anonymous", as is everything posted before names existed.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS name TEXT NOT NULL DEFAULT 'Anonymous';
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS name TEXT NOT NULL DEFAULT 'Anonymous';
@@ -0,0 +1,5 @@
+-- The tripcode shown after a poster's name, see src/tripcode.rs. NULL when
+-- the name was entered without a secret.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS tripcode TEXT;
+ALTER TABLE comments ADD COLUMN IF NOT EXISTS tripcode TEXT;
@@ -0,0 +1,5 @@
+-- Media attached to comments lives alongside the article's own, marked with
+-- the comment it belongs to. Article media keeps comment_id NULL.
+
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS comment_id INT REFERENCES comments(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS article_media_comment_id_idx ON article_media (comment_id);
@@ -0,0 +1,13 @@
+-- Errors logged while the server runs (see app_errors.rs), with the request
+-- path they happened on and the rest of what was logged in context. Kept for
+-- ERROR_RETENTION_DAYS by purge-deleted.
+
+CREATE TABLE IF NOT EXISTS app_errors (
+    id SERIAL PRIMARY KEY,
+    occurred_at BIGINT NOT NULL,
+    route TEXT,
+    message TEXT NOT NULL,
+    context JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS app_errors_occurred_at_idx ON app_errors (occurred_at);
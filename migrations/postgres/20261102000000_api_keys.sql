@@ -0,0 +1,10 @@
+-- Keys for writing through the JSON API (see api_key.rs). Only the SHA-256
+-- of each key is kept; revoked keys stay listed with revoked_at set.
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id SERIAL PRIMARY KEY,
+    key_hash TEXT NOT NULL UNIQUE,
+    label TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    revoked_at BIGINT
+);
@@ -0,0 +1,4 @@
+-- What an API key may do: "write" posts through the API, "admin" also
+-- deletes articles and comments
+
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS scope TEXT NOT NULL DEFAULT 'write';
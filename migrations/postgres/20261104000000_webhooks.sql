@@ -0,0 +1,24 @@
+-- URLs told about new articles and comments (see webhook.rs). `events` is a
+-- bitmask of webhook::Event; deliveries that kept failing are kept in
+-- webhook_failures, with the payload that was sent.
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id SERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    events INTEGER NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS webhook_failures (
+    id SERIAL PRIMARY KEY,
+    webhook_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    event TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS webhook_failures_failed_at_idx ON webhook_failures (failed_at);
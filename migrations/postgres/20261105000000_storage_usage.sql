@@ -0,0 +1,8 @@
+-- Bytes of media files under UPLOADS_DIR, for UPLOADS_QUOTA_BYTES. The one
+-- row (id 1) is written when serve starts, from the files themselves, and
+-- kept up to date as files are stored and removed.
+
+CREATE TABLE IF NOT EXISTS storage_usage (
+    id INTEGER PRIMARY KEY,
+    bytes BIGINT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- The SHA-256 of each uploaded file, hex encoded. An upload identical to one
+-- already stored gets its own row pointing at the same files, which are only
+-- removed once no row points at them. NULL for media stored before this.
+
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS content_hash TEXT;
+CREATE INDEX IF NOT EXISTS article_media_content_hash_idx ON article_media (content_hash);
@@ -0,0 +1,18 @@
+-- What each upload is: its size in bytes, MIME type and, for images, its
+-- dimensions in pixels. Set as files are stored; `articles backfill-media`
+-- fills in sizes and dimensions for older rows from the files themselves.
+
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS byte_size BIGINT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS mime_type TEXT;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS width INTEGER;
+ALTER TABLE article_media ADD COLUMN IF NOT EXISTS height INTEGER;
+
+-- Stored files are named with the extension of their sniffed type
+UPDATE article_media SET mime_type = CASE
+    WHEN media_path LIKE '%.jpg' THEN 'image/jpeg'
+    WHEN media_path LIKE '%.png' THEN 'image/png'
+    WHEN media_path LIKE '%.gif' THEN 'image/gif'
+    WHEN media_path LIKE '%.webp' THEN 'image/webp'
+    WHEN media_path LIKE '%.mp4' THEN 'video/mp4'
+END
+WHERE mime_type IS NULL;
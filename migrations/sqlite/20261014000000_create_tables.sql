@@ -0,0 +1,29 @@
+-- Initial schema, kept in step with migrations/postgres
+
+CREATE TABLE IF NOT EXISTS articles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    bump_time INTEGER NOT NULL,
+    delete_password_hash TEXT,
+    edited_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS article_media (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    article_id INTEGER NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    media_path TEXT NOT NULL,
+    original_name TEXT,
+    thumb_path TEXT
+);
+
+CREATE TABLE IF NOT EXISTS comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    article_id INTEGER NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    comment TEXT NOT NULL,
+    delete_password_hash TEXT
+);
+
+CREATE INDEX IF NOT EXISTS articles_bump_time_idx ON articles (bump_time);
+CREATE INDEX IF NOT EXISTS article_media_article_id_idx ON article_media (article_id);
+CREATE INDEX IF NOT EXISTS comments_article_id_idx ON comments (article_id);
@@ -0,0 +1,34 @@
+-- Full-text search, kept in step with migrations/postgres: FTS5 indexes over
+-- article titles and bodies and over comments, with English (Porter)
+-- stemming, kept current by triggers
+
+CREATE VIRTUAL TABLE articles_fts USING fts5(
+    title, body, content = 'articles', content_rowid = 'id', tokenize = 'porter unicode61'
+);
+CREATE VIRTUAL TABLE comments_fts USING fts5(
+    comment, content = 'comments', content_rowid = 'id', tokenize = 'porter unicode61'
+);
+INSERT INTO articles_fts (articles_fts) VALUES ('rebuild');
+INSERT INTO comments_fts (comments_fts) VALUES ('rebuild');
+
+CREATE TRIGGER articles_fts_insert AFTER INSERT ON articles BEGIN
+    INSERT INTO articles_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+CREATE TRIGGER articles_fts_delete AFTER DELETE ON articles BEGIN
+    INSERT INTO articles_fts (articles_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+CREATE TRIGGER articles_fts_update AFTER UPDATE OF title, body ON articles BEGIN
+    INSERT INTO articles_fts (articles_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+    INSERT INTO articles_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+CREATE TRIGGER comments_fts_insert AFTER INSERT ON comments BEGIN
+    INSERT INTO comments_fts (rowid, comment) VALUES (new.id, new.comment);
+END;
+CREATE TRIGGER comments_fts_delete AFTER DELETE ON comments BEGIN
+    INSERT INTO comments_fts (comments_fts, rowid, comment) VALUES ('delete', old.id, old.comment);
+END;
+CREATE TRIGGER comments_fts_update AFTER UPDATE OF comment ON comments BEGIN
+    INSERT INTO comments_fts (comments_fts, rowid, comment) VALUES ('delete', old.id, old.comment);
+    INSERT INTO comments_fts (rowid, comment) VALUES (new.id, new.comment);
+END;
@@ -0,0 +1,17 @@
+-- Boards, kept in step with migrations/postgres. SQLite can't add a NOT NULL
+-- foreign key column to an existing table, so board_id stays nullable here;
+-- every insert sets it.
+
+CREATE TABLE IF NOT EXISTS boards (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    slug TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO boards (slug, title) VALUES ('main', 'All Articles') ON CONFLICT (slug) DO NOTHING;
+
+ALTER TABLE articles ADD COLUMN board_id INTEGER REFERENCES boards(id);
+UPDATE articles SET board_id = (SELECT id FROM boards WHERE slug = 'main') WHERE board_id IS NULL;
+
+CREATE INDEX IF NOT EXISTS articles_board_id_bump_time_idx ON articles (board_id, bump_time);
@@ -0,0 +1,5 @@
+-- Article slugs, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN slug TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS articles_slug_idx ON articles (slug);
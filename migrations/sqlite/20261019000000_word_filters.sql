@@ -0,0 +1,9 @@
+-- Word filters, kept in step with migrations/postgres
+
+CREATE TABLE IF NOT EXISTS word_filters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pattern TEXT NOT NULL,
+    is_regex BOOLEAN NOT NULL DEFAULT FALSE,
+    action TEXT NOT NULL CHECK (action IN ('reject', 'replace')),
+    replacement TEXT NOT NULL DEFAULT ''
+);
@@ -0,0 +1,14 @@
+-- Poster addresses and bans, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN poster_ip TEXT;
+ALTER TABLE comments ADD COLUMN poster_ip TEXT;
+
+CREATE TABLE IF NOT EXISTS bans (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    cidr TEXT NOT NULL,
+    reason TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS bans_expires_at_idx ON bans (expires_at);
@@ -0,0 +1,14 @@
+-- Reports, kept in step with migrations/postgres
+
+CREATE TABLE IF NOT EXISTS reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    article_id INTEGER NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    comment_id INTEGER REFERENCES comments(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL DEFAULT '',
+    reporter_ip TEXT,
+    created_at INTEGER NOT NULL,
+    resolved_at INTEGER
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS reports_open_dedupe_idx
+    ON reports (article_id, COALESCE(comment_id, 0), reporter_ip) WHERE resolved_at IS NULL;
@@ -0,0 +1,7 @@
+-- Soft deletion, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN deleted_at INTEGER;
+ALTER TABLE comments ADD COLUMN deleted_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS articles_deleted_at_idx ON articles (deleted_at);
+CREATE INDEX IF NOT EXISTS comments_deleted_at_idx ON comments (deleted_at);
@@ -0,0 +1,3 @@
+-- Comment timestamps, kept in step with migrations/postgres
+
+ALTER TABLE comments ADD COLUMN created_at INTEGER;
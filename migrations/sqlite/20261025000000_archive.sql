@@ -0,0 +1,5 @@
+-- Archiving, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN archived_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS articles_archived_at_idx ON articles (archived_at);
@@ -0,0 +1,3 @@
+-- Sticky articles, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN is_sticky BOOLEAN NOT NULL DEFAULT 0;
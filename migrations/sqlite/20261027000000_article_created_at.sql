@@ -0,0 +1,4 @@
+-- When articles were posted, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+UPDATE articles SET created_at = bump_time;
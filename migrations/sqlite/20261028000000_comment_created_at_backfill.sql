@@ -0,0 +1,15 @@
+-- Timestamps for every comment, kept in step with migrations/postgres
+
+UPDATE comments SET created_at = COALESCE(
+    (SELECT MIN(later.created_at) FROM comments later
+     WHERE later.article_id = comments.article_id AND later.id > comments.id AND later.created_at IS NOT NULL),
+    (SELECT bump_time FROM articles WHERE articles.id = comments.article_id)
+)
+WHERE created_at IS NULL;
+
+-- SQLite can't give an existing column a default, so a trigger fills it in
+CREATE TRIGGER IF NOT EXISTS comments_created_at_default AFTER INSERT ON comments
+WHEN NEW.created_at IS NULL
+BEGIN
+    UPDATE comments SET created_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = NEW.id;
+END;
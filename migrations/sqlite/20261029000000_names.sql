@@ -0,0 +1,4 @@
+-- Poster names, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN name TEXT NOT NULL DEFAULT 'Anonymous';
+ALTER TABLE comments ADD COLUMN name TEXT NOT NULL DEFAULT 'Anonymous';
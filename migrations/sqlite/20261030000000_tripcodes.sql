@@ -0,0 +1,4 @@
+-- Tripcodes, kept in step with migrations/postgres
+
+ALTER TABLE articles ADD COLUMN tripcode TEXT;
+ALTER TABLE comments ADD COLUMN tripcode TEXT;
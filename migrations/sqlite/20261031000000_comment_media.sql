@@ -0,0 +1,4 @@
+-- Comment media, kept in step with migrations/postgres
+
+ALTER TABLE article_media ADD COLUMN comment_id INTEGER REFERENCES comments(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS article_media_comment_id_idx ON article_media (comment_id);
@@ -0,0 +1,11 @@
+-- Logged errors, kept in step with migrations/postgres; context is JSON text
+
+CREATE TABLE IF NOT EXISTS app_errors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    occurred_at INTEGER NOT NULL,
+    route TEXT,
+    message TEXT NOT NULL,
+    context TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS app_errors_occurred_at_idx ON app_errors (occurred_at);
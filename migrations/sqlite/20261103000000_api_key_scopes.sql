@@ -0,0 +1,3 @@
+-- API key scopes, kept in step with migrations/postgres
+
+ALTER TABLE api_keys ADD COLUMN scope TEXT NOT NULL DEFAULT 'write';
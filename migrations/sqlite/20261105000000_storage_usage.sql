@@ -0,0 +1,6 @@
+-- Bytes of media files under UPLOADS_DIR, kept in step with migrations/postgres
+
+CREATE TABLE IF NOT EXISTS storage_usage (
+    id INTEGER PRIMARY KEY,
+    bytes BIGINT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Media content hashes, kept in step with migrations/postgres
+
+ALTER TABLE article_media ADD COLUMN content_hash TEXT;
+CREATE INDEX IF NOT EXISTS article_media_content_hash_idx ON article_media (content_hash);
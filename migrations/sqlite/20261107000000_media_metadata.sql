@@ -0,0 +1,16 @@
+-- Media metadata, kept in step with migrations/postgres
+
+ALTER TABLE article_media ADD COLUMN byte_size BIGINT;
+ALTER TABLE article_media ADD COLUMN mime_type TEXT;
+ALTER TABLE article_media ADD COLUMN width INTEGER;
+ALTER TABLE article_media ADD COLUMN height INTEGER;
+
+-- Stored files are named with the extension of their sniffed type
+UPDATE article_media SET mime_type = CASE
+    WHEN media_path LIKE '%.jpg' THEN 'image/jpeg'
+    WHEN media_path LIKE '%.png' THEN 'image/png'
+    WHEN media_path LIKE '%.gif' THEN 'image/gif'
+    WHEN media_path LIKE '%.webp' THEN 'image/webp'
+    WHEN media_path LIKE '%.mp4' THEN 'video/mp4'
+END
+WHERE mime_type IS NULL;
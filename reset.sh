@@ -10,31 +10,43 @@ export PGPASSWORD="$DB_PASSWORD"
 
 psql -h "$DB_HOST" -U "$DB_USER" -d "$DB_NAME" <<EOF
 -- Drop existing tables if they exist
+DROP TABLE IF EXISTS reports;
+DROP TABLE IF EXISTS bans;
+DROP TABLE IF EXISTS word_filters;
+DROP TABLE IF EXISTS article_tags;
+DROP TABLE IF EXISTS tags;
 DROP TABLE IF EXISTS article_media;
 DROP TABLE IF EXISTS comments;
 DROP TABLE IF EXISTS articles;
+DROP TABLE IF EXISTS boards;
 DROP TABLE IF EXISTS admins;
+DROP TABLE IF EXISTS _sqlx_migrations;
 
 -- Create articles table
 CREATE TABLE articles (
     id SERIAL PRIMARY KEY,
     title TEXT NOT NULL,
     body TEXT NOT NULL,
-    bump_time BIGINT NOT NULL
+    bump_time BIGINT NOT NULL,
+    delete_password_hash TEXT,
+    edited_at BIGINT
 );
 
 -- Create table for associated media
 CREATE TABLE article_media (
     id SERIAL PRIMARY KEY,
     article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
-    media_path TEXT NOT NULL
+    media_path TEXT NOT NULL,
+    original_name TEXT,
+    thumb_path TEXT
 );
 
 -- Create table for comments
 CREATE TABLE comments (
     id SERIAL PRIMARY KEY,
     article_id INT NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
-    comment TEXT NOT NULL
+    comment TEXT NOT NULL,
+    delete_password_hash TEXT
 );
 
 -- Create admins table
@@ -0,0 +1,261 @@
+// Admin login. There is a single admin password, stored as an Argon2 hash in
+// ADMIN_PASSWORD_HASH, which `articles hash-password` makes; logging in records
+// the time in the session cookie, and handlers that take an AdminUser only run
+// for a live admin session.
+
+use actix_session::{Session, SessionExt};
+use actix_web::{
+    dev::Payload,
+    error::InternalError,
+    http::{header, Method, StatusCode},
+    web, Error, FromRequest, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use futures_util::future::{ready, Ready};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::csrf::CsrfToken;
+use crate::db::ArticleRepository;
+use crate::page_cache::PageCache;
+use crate::password;
+use crate::render::{format_size, url_encode};
+use crate::templates::{render_html, AdminIndexContext, AdminLoginContext, MessageContext};
+
+// Session key holding when the admin logged in
+const LOGGED_IN_AT: &str = "admin_logged_in_at";
+// Sessions end this long after logging in, however active they are
+const MAX_SESSION_SECS: i64 = 12 * 60 * 60;
+
+pub fn is_admin(session: &Session) -> bool {
+    match session.get::<i64>(LOGGED_IN_AT) {
+        Ok(Some(logged_in_at)) => Utc::now().timestamp() - logged_in_at < MAX_SESSION_SECS,
+        _ => false,
+    }
+}
+
+// Only paths on this site are followed after logging in
+fn safe_next(next: &str) -> &str {
+    if next.starts_with('/') && !next.starts_with("//") && !next.starts_with("/\\") {
+        next
+    } else {
+        "/admin"
+    }
+}
+
+fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+// Proof that the request comes from a logged-in admin. Pages send everyone
+// else to the login form; anything else gets a 403.
+pub struct AdminUser;
+
+impl FromRequest for AdminUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        if is_admin(&req.get_session()) {
+            return ready(Ok(AdminUser));
+        }
+        let response = if req.method() == Method::GET || req.method() == Method::HEAD {
+            let here = req.uri().path_and_query().map_or("/admin", |p| p.as_str());
+            see_other(&format!("/admin/login?next={}", url_encode(here)))
+        } else {
+            render_html(
+                StatusCode::FORBIDDEN,
+                &MessageContext {
+                    title: "Admins Only",
+                    message: "You need to be logged in as an admin to do that.",
+                    link_href: "/admin/login",
+                    link_text: "Log in",
+                },
+            )
+        };
+        ready(Err(InternalError::from_response(
+            "admin login required",
+            response,
+        )
+        .into()))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StickyPath {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+pub struct StickyForm {
+    // The state to set, so repeating a request changes nothing
+    sticky: bool,
+    #[serde(default)]
+    next: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    #[serde(default)]
+    next: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    password: String,
+    #[serde(default)]
+    next: String,
+}
+
+// GET /admin/login
+pub async fn login_form(
+    session: Session,
+    csrf: CsrfToken,
+    query: web::Query<LoginQuery>,
+) -> HttpResponse {
+    let next = safe_next(&query.next);
+    if is_admin(&session) {
+        return see_other(next);
+    }
+    render_html(
+        StatusCode::OK,
+        &AdminLoginContext {
+            csrf_token: &csrf.0,
+            next,
+            error: None,
+        },
+    )
+}
+
+// POST /admin/login
+pub async fn login(
+    config: web::Data<Config>,
+    session: Session,
+    csrf: CsrfToken,
+    form: web::Form<LoginForm>,
+) -> HttpResponse {
+    let next = safe_next(&form.next);
+    // verify_password compares in constant time, and does the same work when
+    // no hash is configured
+    if !password::verify_password(&form.password, config.admin_password_hash.as_deref()) {
+        return render_html(
+            StatusCode::UNAUTHORIZED,
+            &AdminLoginContext {
+                csrf_token: &csrf.0,
+                next,
+                error: Some("Wrong password."),
+            },
+        );
+    }
+
+    // A fresh session id, so one planted before logging in is worthless
+    session.renew();
+    if let Err(e) = session.insert(LOGGED_IN_AT, Utc::now().timestamp()) {
+        error!(error = %e, "Failed to start admin session");
+        return HttpResponse::InternalServerError().body("Failed to log in");
+    }
+    see_other(next)
+}
+
+// POST /admin/logout
+pub async fn logout(session: Session) -> HttpResponse {
+    session.purge();
+    see_other("/")
+}
+
+// GET /admin
+pub async fn index(
+    _admin: AdminUser,
+    csrf: CsrfToken,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let used = match repo.storage_usage().await {
+        Ok(used) => used,
+        Err(e) => {
+            error!(error = %e, "Failed to load storage usage");
+            None
+        }
+    };
+    let uploads = used.map(|used| {
+        let used = used.max(0) as u64;
+        match config.uploads_quota_bytes {
+            0 => format!("{} of files stored", format_size(used)),
+            quota => format!(
+                "{} of the {} quota used ({}%)",
+                format_size(used),
+                format_size(quota),
+                used.saturating_mul(100) / quota
+            ),
+        }
+    });
+    render_html(
+        StatusCode::OK,
+        &AdminIndexContext {
+            csrf_token: &csrf.0,
+            uploads: uploads.as_deref(),
+        },
+    )
+}
+
+// POST /admin/articles/{id}/sticky
+pub async fn set_sticky(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<StickyPath>,
+    form: web::Form<StickyForm>,
+) -> HttpResponse {
+    match repo.set_sticky(path.id, form.sticky).await {
+        Ok(true) => {
+            page_cache.invalidate_article(path.id);
+            see_other(safe_next(&form.next))
+        }
+        Ok(false) => HttpResponse::NotFound().body("Article not found"),
+        Err(e) => {
+            error!(article_id = path.id, error = %e, "Failed to set sticky");
+            HttpResponse::InternalServerError().body("Failed to update article")
+        }
+    }
+}
+
+// `articles hash-password`: hash a password read from stdin, so it never
+// appears in the shell history or process list
+pub fn hash_admin_password() -> CommandResult {
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err("Expected a password on stdin".into());
+    }
+    let hash =
+        password::hash_password(password).map_err(|e| format!("Failed to hash password: {}", e))?;
+    println!("{}", hash);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_follows_local_paths() {
+        assert_eq!(
+            safe_next("/b/tech/articles?page=2"),
+            "/b/tech/articles?page=2"
+        );
+        for unsafe_next in [
+            "",
+            "https://evil.example",
+            "//evil.example",
+            "/\\evil.example",
+            "admin",
+        ] {
+            assert_eq!(safe_next(unsafe_next), "/admin", "{}", unsafe_next);
+        }
+    }
+}
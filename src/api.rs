@@ -0,0 +1,622 @@
+// JSON endpoints under /api for programmatic clients. Each is described for
+// the OpenAPI spec (see openapi.rs) by its #[utoipa::path], and the types it
+// takes and returns by their derives, so changes here show up there.
+
+use actix_web::{
+    error::InternalError,
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api_key::{ApiAdmin, ApiKey};
+use crate::article::{soft_delete, trim_board};
+use crate::board::board_base;
+use crate::client_ip::ClientIp;
+use crate::config::Config;
+use crate::db::{ArticleRepository, ArticleSort, NewArticleRow, RelatedArticle, SearchScope};
+use crate::error::AppError;
+use crate::listing::search_query;
+use crate::notify::Notifier;
+use crate::openapi::ErrorBody;
+use crate::page_cache::PageCache;
+use crate::post::{poster_name, validate_article};
+use crate::render::{highlight_html, strip_match_markers};
+use crate::slug::slugify;
+use crate::storage::MediaStorage;
+use crate::tripcode;
+use crate::webhook::Webhooks;
+use crate::word_filter::{apply_word_filters, WordFilters};
+use crate::{
+    Article, DbBoard, DbComment, DEFAULT_NAME, DEFAULT_PER_PAGE, MAX_PER_PAGE, RELATED_ARTICLES,
+};
+
+// The `///` comments on query parameters and fields are their descriptions
+// in the spec
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApiListQuery {
+    /// Slug of the board; the default board when omitted
+    board: Option<String>,
+    /// How many articles to return; out of range values are clamped
+    #[param(minimum = 1, maximum = 100, default = 25)]
+    limit: Option<i64>,
+    /// How many articles to skip
+    #[param(minimum = 0, default = 0)]
+    offset: Option<i64>,
+    /// The order after sticky articles; unknown values read as bump
+    #[serde(default)]
+    #[param(inline)]
+    sort: ArticleSort,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApiSearchQuery {
+    /// Slug of the board; the default board when omitted
+    board: Option<String>,
+    /// What to search for; required
+    q: Option<String>,
+    /// Whether to search articles or comments
+    #[serde(default)]
+    #[param(inline)]
+    scope: SearchScope,
+    /// How many results to return; out of range values are clamped
+    #[param(minimum = 1, maximum = 100, default = 25)]
+    limit: Option<i64>,
+    /// How many results to skip
+    #[param(minimum = 0, default = 0)]
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewArticle {
+    /// Slug of the board; the default board when omitted
+    board: Option<String>,
+    title: String,
+    body: String,
+    /// Shown as the poster; name#secret adds a tripcode
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Created {
+    id: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ArticleWithComments {
+    #[serde(flatten)]
+    article: Article,
+    comments: Vec<DbComment>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResults<'a> {
+    query: &'a str,
+    // Of all pages
+    total: i64,
+    results: Vec<SearchHit>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchHit {
+    article_id: i32,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment_id: Option<i32>,
+    archived: bool,
+    // Plain text, plus the same text with matches wrapped in <mark>
+    snippet: String,
+    snippet_html: String,
+}
+
+// The paging headers of a listing, besides what its body says: how many
+// there are in all, and links to the pages either side (RFC 8288)
+pub const TOTAL_COUNT: &str = "x-total-count";
+
+fn paged(req: &HttpRequest, total: i64, limit: i64, offset: i64) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response.insert_header((TOTAL_COUNT, total.to_string()));
+    let query: Vec<(String, String)> =
+        serde_urlencoded::from_str(req.query_string()).unwrap_or_default();
+    let link = |offset: i64, rel: &str| {
+        let mut pairs: Vec<(String, String)> = query
+            .iter()
+            .filter(|(key, _)| key != "limit" && key != "offset")
+            .cloned()
+            .collect();
+        pairs.push(("limit".to_string(), limit.to_string()));
+        pairs.push(("offset".to_string(), offset.to_string()));
+        format!(
+            "<{}?{}>; rel=\"{}\"",
+            req.path(),
+            serde_urlencoded::to_string(pairs).unwrap_or_default(),
+            rel
+        )
+    };
+    let mut links = Vec::new();
+    if offset > 0 {
+        links.push(link((offset - limit).max(0), "prev"));
+    }
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    if !links.is_empty() {
+        response.insert_header((header::LINK, links.join(", ")));
+    }
+    response
+}
+
+// Errors are always reported as {"error": "..."}
+pub fn json_error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({ "error": message }))
+}
+
+// The board named by a `board` parameter, or the default board when it is
+// omitted; unknown slugs become JSON 404s
+async fn resolve_board(
+    repo: &dyn ArticleRepository,
+    config: &Config,
+    slug: Option<&str>,
+) -> Result<DbBoard, HttpResponse> {
+    let slug = slug.unwrap_or(&config.default_board);
+    match repo.get_board(slug).await {
+        Ok(board) => Ok(board),
+        Err(sqlx::Error::RowNotFound) => Err(json_error(StatusCode::NOT_FOUND, "Board not found")),
+        Err(e) => {
+            error!(board = %slug, error = %e, "Failed to look up board");
+            Err(json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to load board",
+            ))
+        }
+    }
+}
+
+// Malformed path segments (e.g. a non-numeric id) become JSON 400s
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|err, _req| {
+        let response = json_error(StatusCode::BAD_REQUEST, &err.to_string());
+        InternalError::from_response(err, response).into()
+    })
+}
+
+// Malformed query strings become JSON 400s
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _req| {
+        let response = json_error(StatusCode::BAD_REQUEST, &err.to_string());
+        InternalError::from_response(err, response).into()
+    })
+}
+
+// Malformed JSON bodies become JSON 400s
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req| {
+        let response = json_error(StatusCode::BAD_REQUEST, &err.to_string());
+        InternalError::from_response(err, response).into()
+    })
+}
+
+// GET /api/articles
+#[utoipa::path(
+    get,
+    path = "/api/articles",
+    tag = "articles",
+    params(ApiListQuery),
+    responses(
+        (status = 200, description = "A page of the board's articles", body = Vec<Article>, headers(
+            ("x-total-count" = i64, description = "How many articles the board has"),
+            ("link" = String, description = "The previous and next pages, where there are any"),
+        )),
+        (status = 400, description = "Malformed parameters", body = ErrorBody),
+        (status = 404, description = "No such board", body = ErrorBody),
+    )
+)]
+pub async fn list_articles(
+    req: HttpRequest,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+    query: web::Query<ApiListQuery>,
+) -> HttpResponse {
+    let board = match resolve_board(repo.get_ref(), &config, query.board.as_deref()).await {
+        Ok(board) => board,
+        Err(response) => return response,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let total = match repo.count_articles(board.id).await {
+        Ok(n) => n,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to count articles");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load articles");
+        }
+    };
+    let articles_db = match repo
+        .list_articles(board.id, query.sort, limit, offset)
+        .await
+    {
+        Ok(a) => a,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to fetch articles");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load articles");
+        }
+    };
+
+    // The listing only has the first of each article's media
+    let ids: Vec<i32> = articles_db.iter().map(|a| a.article.id).collect();
+    let mut media = match repo.media_for_articles(&ids).await {
+        Ok(m) => m,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to fetch media");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load articles");
+        }
+    };
+
+    let articles: Vec<Article> = articles_db
+        .into_iter()
+        .map(|listed| {
+            let tags = listed.tags();
+            let a = listed.article;
+            Article {
+                media: media.remove(&a.id).unwrap_or_default(),
+                tags,
+                id: a.id,
+                board_id: a.board_id,
+                title: a.title,
+                slug: a.slug,
+                body: a.body,
+                name: a.name,
+                tripcode: a.tripcode,
+                created_at: a.created_at,
+                bump_time: a.bump_time,
+                edited_at: a.edited_at,
+                archived_at: a.archived_at,
+                is_sticky: a.is_sticky,
+                last_comment_at: listed.last_comment_at,
+            }
+        })
+        .collect();
+
+    paged(&req, total, limit, offset).json(articles)
+}
+
+// GET /api/articles/{id}
+#[utoipa::path(
+    get,
+    path = "/api/articles/{id}",
+    tag = "articles",
+    params(("id" = i32, Path, description = "The article's id")),
+    responses(
+        (status = 200, description = "The article and its comments", body = ArticleWithComments),
+        (status = 404, description = "No such article", body = ErrorBody),
+    )
+)]
+pub async fn get_article(
+    repo: web::Data<dyn ArticleRepository>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let article = repo.get_article_with_media(path.into_inner()).await?;
+    let comments = repo.list_comments(article.id).await?;
+    Ok(HttpResponse::Ok().json(ArticleWithComments { article, comments }))
+}
+
+// GET /api/articles/{id}/related
+#[utoipa::path(
+    get,
+    path = "/api/articles/{id}/related",
+    tag = "articles",
+    params(("id" = i32, Path, description = "The article's id")),
+    responses(
+        (status = 200, description = "Articles like it", body = Vec<RelatedArticle>),
+        (status = 404, description = "No such article", body = ErrorBody),
+    )
+)]
+pub async fn related_articles(
+    repo: web::Data<dyn ArticleRepository>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let article = repo.get_article_with_media(path.into_inner()).await?;
+    let related = repo.related_articles(&article, RELATED_ARTICLES).await?;
+    Ok(HttpResponse::Ok().json(related))
+}
+
+// POST /api/articles
+#[utoipa::path(
+    post,
+    path = "/api/articles",
+    tag = "articles",
+    request_body = NewArticle,
+    security(("api_key" = [])),
+    responses(
+        (status = 201, description = "Posted", body = Created,
+            headers(("Location" = String, description = "Where the article can be fetched"))),
+        (status = 400, description = "Malformed JSON", body = ErrorBody),
+        (status = 401, description = "No API key was sent", body = ErrorBody,
+            headers(("WWW-Authenticate" = String))),
+        (status = 403, description = "The API key is unknown or revoked, or the client is banned", body = ErrorBody),
+        (status = 404, description = "No such board", body = ErrorBody),
+        (status = 422, description = "A field isn't allowed; `fields` says why", body = ErrorBody),
+        (status = 429, description = "Too many articles posted", body = ErrorBody,
+            headers(("Retry-After" = u64, description = "Seconds until another may be posted"))),
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_article(
+    _key: ApiKey,
+    repo: web::Data<dyn ArticleRepository>,
+    storage: web::Data<dyn MediaStorage>,
+    config: web::Data<Config>,
+    word_filters: web::Data<WordFilters>,
+    page_cache: web::Data<PageCache>,
+    webhooks: web::Data<Webhooks>,
+    notifier: web::Data<Notifier>,
+    client_ip: ClientIp,
+    payload: web::Json<NewArticle>,
+) -> HttpResponse {
+    let mut payload = payload.into_inner();
+    let board = match resolve_board(repo.get_ref(), &config, payload.board.as_deref()).await {
+        Ok(board) => board,
+        Err(response) => return response,
+    };
+    let (name, tripcode) = tripcode::parse(&payload.name, &config.secret_key);
+    let mut name = name.to_string();
+    let mut errors = validate_article(&config, &payload.title, &payload.body);
+    if let Err(message) = poster_name(&name) {
+        errors.insert("name", message);
+    }
+    if !errors.is_empty() {
+        return HttpResponse::UnprocessableEntity()
+            .json(json!({ "error": "Validation failed", "fields": errors }));
+    }
+    if apply_word_filters(
+        &word_filters,
+        &mut [&mut payload.title, &mut payload.body, &mut name],
+    )
+    .is_err()
+    {
+        return json_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Post contains a word or phrase that isn't allowed",
+        );
+    }
+
+    let now = Utc::now().timestamp();
+    let article_id = match repo
+        .insert_article(NewArticleRow {
+            board_id: board.id,
+            title: payload.title.trim(),
+            slug: slugify(&payload.title).as_deref(),
+            body: &payload.body,
+            name: poster_name(&name).unwrap_or(DEFAULT_NAME),
+            tripcode: tripcode.as_deref(),
+            created_at: now,
+            bump_time: now,
+            delete_password_hash: None,
+            tags: &[],
+            poster_ip: client_ip.0.map(|ip| ip.to_string()).as_deref(),
+            media: &[],
+        })
+        .await
+    {
+        Ok((id, _)) => id,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to store article");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Database insert failed");
+        }
+    };
+    page_cache.invalidate_lists();
+    webhooks.article_created(article_id);
+    notifier.article_created(&board_base(&board.slug, &config.default_board), article_id);
+    trim_board(
+        repo.get_ref(),
+        storage.get_ref(),
+        &config,
+        &page_cache,
+        board.id,
+    )
+    .await;
+
+    HttpResponse::Created()
+        .append_header(("Location", format!("/api/articles/{}", article_id)))
+        .json(Created { id: article_id })
+}
+
+// DELETE /api/articles/{id}: hidden as a moderator deleting it from the site
+// would, restorable from /admin/deleted
+#[utoipa::path(
+    delete,
+    path = "/api/articles/{id}",
+    tag = "moderation",
+    params(("id" = i32, Path, description = "The article's id")),
+    security(("api_key" = []), ("admin_session" = [])),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Neither an admin API key nor an admin login", body = ErrorBody),
+        (status = 404, description = "No such article", body = ErrorBody),
+    )
+)]
+pub async fn delete_article(
+    _admin: ApiAdmin,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    if !soft_delete(repo.get_ref(), &page_cache, path.into_inner(), None).await? {
+        return Err(AppError::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// DELETE /api/comments/{id}
+#[utoipa::path(
+    delete,
+    path = "/api/comments/{id}",
+    tag = "moderation",
+    params(("id" = i32, Path, description = "The comment's id")),
+    security(("api_key" = []), ("admin_session" = [])),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 403, description = "Neither an admin API key nor an admin login", body = ErrorBody),
+        (status = 404, description = "No such comment", body = ErrorBody),
+    )
+)]
+pub async fn delete_comment(
+    _admin: ApiAdmin,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let comment_id = path.into_inner();
+    let article_id = repo
+        .comment_article_id(comment_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if !soft_delete(repo.get_ref(), &page_cache, article_id, Some(comment_id)).await? {
+        return Err(AppError::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /api/search
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "articles",
+    params(ApiSearchQuery),
+    responses(
+        (status = 200, description = "A page of matches", body = SearchResults, headers(
+            ("x-total-count" = i64, description = "How many matches there are"),
+            ("link" = String, description = "The previous and next pages, where there are any"),
+        )),
+        (status = 400, description = "A missing, empty or malformed query", body = ErrorBody),
+        (status = 404, description = "No such board", body = ErrorBody),
+    )
+)]
+pub async fn search(
+    req: HttpRequest,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+    query: web::Query<ApiSearchQuery>,
+) -> HttpResponse {
+    let board = match resolve_board(repo.get_ref(), &config, query.board.as_deref()).await {
+        Ok(board) => board,
+        Err(response) => return response,
+    };
+    let q = match search_query(query.q.as_deref()) {
+        Ok(Some(q)) => q,
+        Ok(None) => {
+            return json_error(
+                StatusCode::BAD_REQUEST,
+                "Query parameter q must not be empty",
+            )
+        }
+        Err(message) => return json_error(StatusCode::BAD_REQUEST, &message),
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let total = match repo.count_search_results(board.id, q, query.scope).await {
+        Ok(n) => n,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to count search results");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Search failed");
+        }
+    };
+    let rows = match repo.search(board.id, q, query.scope, limit, offset).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to search articles");
+            return json_error(StatusCode::INTERNAL_SERVER_ERROR, "Search failed");
+        }
+    };
+
+    let results: Vec<SearchHit> = rows
+        .into_iter()
+        .map(|row| SearchHit {
+            snippet: strip_match_markers(&row.snippet),
+            snippet_html: highlight_html(&row.snippet),
+            article_id: row.article_id,
+            title: row.title,
+            comment_id: row.comment_id,
+            archived: row.archived,
+        })
+        .collect();
+    paged(&req, total, limit, offset).json(SearchResults {
+        query: q,
+        total,
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn articles_are_fetched_by_id() {
+        let repo = crate::db::connect("sqlite::memory:").await.unwrap();
+        repo.run_migrations().await.unwrap();
+        let board = repo.get_board("main").await.unwrap();
+        let (article_id, _) = repo
+            .insert_article(NewArticleRow {
+                board_id: board.id,
+                title: "Title",
+                slug: None,
+                body: "Body",
+                name: crate::DEFAULT_NAME,
+                tripcode: None,
+                created_at: 1,
+                bump_time: 1,
+                delete_password_hash: None,
+                tags: &[],
+                poster_ip: None,
+                media: &[],
+            })
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new().app_data(web::Data::from(repo)).service(
+                web::scope("/api")
+                    .wrap(from_fn(crate::error::json_errors))
+                    .app_data(path_config())
+                    .route("/articles/{id}", web::get().to(get_article)),
+            ),
+        )
+        .await;
+        let get = |path: String| test::TestRequest::get().uri(&path).to_request();
+
+        let response = test::call_service(&app, get(format!("/api/articles/{}", article_id))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let article: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(
+            (article["id"].as_i64(), &article["title"]),
+            (Some(article_id as i64), &json!("Title"))
+        );
+        assert_eq!(article["comments"], json!([]));
+
+        let response =
+            test::call_service(&app, get(format!("/api/articles/{}", article_id + 1))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert!(body["error"].is_string(), "{}", body);
+
+        let response = test::call_service(&app, get("/api/articles/first".to_string())).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert!(body["error"].is_string(), "{}", body);
+    }
+}
@@ -0,0 +1,356 @@
+// Keys for writing through the JSON API. They're made with `articles api-key
+// create`, which shows each one once; only its SHA-256 is stored. Requests
+// that write send one as `Authorization: Bearer <key>`, checked by the ApiKey
+// extractor against the keys not revoked, which are kept for
+// API_KEY_CACHE_SECONDS so a revoked key stops working within that long. Keys
+// not among them are looked for again, so new ones work straight away; that
+// costs a query, but only on routes that are rate limited. Reading stays open
+// to everyone. Deleting needs an admin key, made with --admin, or an admin
+// logged in to the site.
+
+use actix_session::SessionExt;
+use actix_web::{
+    dev::Payload,
+    error::{ErrorInternalServerError, InternalError},
+    http::{header, StatusCode},
+    web, Error, FromRequest, HttpRequest,
+};
+use chrono::Utc;
+use futures_util::future::LocalBoxFuture;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, Span};
+
+use crate::admin::is_admin;
+use crate::api::json_error;
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::db::{self, ApiKeyRow, ArticleRepository};
+use crate::error::AppError;
+use crate::render::format_timestamp;
+use crate::signing::constant_time_eq;
+
+// Marks keys in configuration files and logs
+const PREFIX: &str = "ak_";
+
+// What a key may do
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    // Post through the API
+    Write,
+    // That, and delete articles and comments
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Write => "write",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+// A new key and the hash to store for it
+pub fn generate() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let key = format!(
+        "{}{}",
+        PREFIX,
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+    let hash = hash(&key);
+    (key, hash)
+}
+
+fn hash(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// The keys not revoked, as of when they were last loaded
+pub struct ApiKeys {
+    ttl: Duration,
+    active: Mutex<Option<(Instant, Arc<Vec<ApiKeyRow>>)>>,
+}
+
+impl ApiKeys {
+    // A ttl of 0 loads them for every request
+    pub fn new(ttl: Duration) -> Self {
+        ApiKeys {
+            ttl,
+            active: Mutex::new(None),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        ApiKeys::new(Duration::from_secs(config.api_key_cache_secs))
+    }
+
+    fn cached(&self, now: Instant) -> Option<Arc<Vec<ApiKeyRow>>> {
+        match &*self.active.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some((at, keys)) if now.duration_since(*at) < self.ttl => Some(keys.clone()),
+            _ => None,
+        }
+    }
+
+    async fn load(
+        &self,
+        repo: &dyn ArticleRepository,
+        now: Instant,
+    ) -> Result<Arc<Vec<ApiKeyRow>>, sqlx::Error> {
+        let keys = Arc::new(repo.active_api_keys().await?);
+        if !self.ttl.is_zero() {
+            *self.active.lock().unwrap_or_else(|e| e.into_inner()) = Some((now, keys.clone()));
+        }
+        Ok(keys)
+    }
+
+    // The active key `key` is, if any
+    async fn find(
+        &self,
+        repo: &dyn ArticleRepository,
+        key: &str,
+        now: Instant,
+    ) -> Result<Option<ApiKeyRow>, sqlx::Error> {
+        let key_hash = hash(key);
+        if let Some(found) = self
+            .cached(now)
+            .and_then(|keys| matching(&keys, &key_hash).cloned())
+        {
+            return Ok(Some(found));
+        }
+        Ok(matching(&self.load(repo, now).await?, &key_hash).cloned())
+    }
+}
+
+// Every hash is compared in full, so how long it takes says nothing about
+// which keys there are
+fn matching<'a>(keys: &'a [ApiKeyRow], key_hash: &str) -> Option<&'a ApiKeyRow> {
+    keys.iter().fold(None, |found, key| {
+        let equal = constant_time_eq(key.key_hash.as_bytes(), key_hash.as_bytes());
+        if equal {
+            Some(key)
+        } else {
+            found
+        }
+    })
+}
+
+// The active key a request sends, recorded in its log lines; Ok(None) when it
+// sends none
+async fn authenticate(req: &HttpRequest) -> Result<Option<ApiKeyRow>, Error> {
+    let repo = req.app_data::<web::Data<dyn ArticleRepository>>().cloned();
+    let keys = req.app_data::<web::Data<ApiKeys>>().cloned();
+    let (Some(repo), Some(keys)) = (repo, keys) else {
+        return Err(ErrorInternalServerError("API keys are not configured"));
+    };
+    let Some(key) = bearer(req) else {
+        return Ok(None);
+    };
+    match keys.find(repo.get_ref(), &key, Instant::now()).await {
+        Ok(Some(found)) => {
+            Span::current().record("api_key", found.label.as_str());
+            Ok(Some(found))
+        }
+        Ok(None) => {
+            let response = json_error(StatusCode::FORBIDDEN, "The API key is unknown or revoked");
+            Err(InternalError::from_response("unknown API key", response).into())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to load API keys");
+            let response = json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to check the API key",
+            );
+            Err(InternalError::from_response("API key lookup failed", response).into())
+        }
+    }
+}
+
+// The key in an `Authorization: Bearer <key>` header
+fn bearer(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, key) = value.trim().split_once(' ')?;
+    let key = key.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !key.is_empty()).then(|| key.to_string())
+}
+
+// Taken by the API's handlers that write; a request without a key gets a 401,
+// and one with a key that's unknown or revoked a 403. The key's label goes in
+// the request's log lines.
+pub struct ApiKey;
+
+impl FromRequest for ApiKey {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            if authenticate(&req).await?.is_some() {
+                return Ok(ApiKey);
+            }
+            let mut response = json_error(StatusCode::UNAUTHORIZED, "An API key is required");
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+            Err(InternalError::from_response("missing API key", response).into())
+        })
+    }
+}
+
+// Taken by the API's moderation handlers: an admin key, or the session of an
+// admin logged in to the site. Anyone else gets a 403.
+pub struct ApiAdmin;
+
+impl FromRequest for ApiAdmin {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            if bearer(&req).is_none() && is_admin(&req.get_session()) {
+                return Ok(ApiAdmin);
+            }
+            match authenticate(&req).await? {
+                Some(key) if key.scope == Scope::Admin.as_str() => Ok(ApiAdmin),
+                Some(_) => {
+                    Err(AppError::Forbidden("The API key isn't an admin key".to_string()).into())
+                }
+                None => Err(AppError::Forbidden(
+                    "An admin API key or login is required".to_string(),
+                )
+                .into()),
+            }
+        })
+    }
+}
+
+// `articles api-key create LABEL [--admin]`: the key is printed on its own
+// last line, for scripts
+pub async fn create_api_key(config: &Config, label: &str, scope: Scope) -> CommandResult {
+    if label.trim().is_empty() {
+        return Err("API key labels must not be empty".into());
+    }
+    let (key, key_hash) = generate();
+    let repo = db::connect(&config.database_url).await?;
+    let id = repo
+        .insert_api_key(
+            &key_hash,
+            label.trim(),
+            scope.as_str(),
+            Utc::now().timestamp(),
+        )
+        .await
+        .map_err(|e| format!("Failed to create API key: {}", e))?;
+
+    println!(
+        "Created {} API key {} ({}); it won't be shown again:",
+        scope.as_str(),
+        id,
+        label.trim()
+    );
+    println!("{}", key);
+    Ok(())
+}
+
+// `articles api-key revoke ID`
+pub async fn revoke_api_key(config: &Config, id: i32) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let revoked = repo
+        .revoke_api_key(id, Utc::now().timestamp())
+        .await
+        .map_err(|e| format!("Failed to revoke API key {}: {}", id, e))?;
+    if !revoked {
+        return Err(format!("There is no API key {} in use", id).into());
+    }
+    println!(
+        "Revoked API key {}; running servers stop taking it within {}s",
+        id, config.api_key_cache_secs
+    );
+    Ok(())
+}
+
+// `articles api-key list`
+pub async fn list_api_keys(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let keys = repo
+        .list_api_keys()
+        .await
+        .map_err(|e| format!("Failed to list API keys: {}", e))?;
+    if keys.is_empty() {
+        println!("No API keys");
+    }
+    for k in keys {
+        let state = match k.revoked_at {
+            Some(t) => format!("revoked {}", format_timestamp(t)),
+            None => "in use".to_string(),
+        };
+        println!(
+            "{}\t{}\t{}\tcreated {}, {}",
+            k.id,
+            k.label,
+            k.scope,
+            format_timestamp(k.created_at),
+            state
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i32, key: &str) -> ApiKeyRow {
+        ApiKeyRow {
+            id,
+            key_hash: hash(key),
+            label: format!("key {}", id),
+            scope: Scope::Write.as_str().to_string(),
+            created_at: 0,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn keys_are_found_by_their_hash() {
+        let (key, key_hash) = generate();
+        assert!(key.starts_with(PREFIX));
+        assert_eq!(key_hash, hash(&key));
+        let keys = [row(1, "ak_other"), row(2, &key)];
+        assert_eq!(matching(&keys, &key_hash).map(|key| key.id), Some(2));
+        assert!(matching(&keys, &hash("ak_unknown")).is_none());
+    }
+
+    #[test]
+    fn only_bearer_credentials_count() {
+        let request = |value: &str| {
+            actix_web::test::TestRequest::default()
+                .insert_header((header::AUTHORIZATION, value))
+                .to_http_request()
+        };
+        assert_eq!(bearer(&request("Bearer ak_123")).as_deref(), Some("ak_123"));
+        assert_eq!(
+            bearer(&request("bearer  ak_123 ")).as_deref(),
+            Some("ak_123")
+        );
+        assert_eq!(bearer(&request("Basic ak_123")), None);
+        assert_eq!(bearer(&request("Bearer ")), None);
+        assert_eq!(
+            bearer(&actix_web::test::TestRequest::default().to_http_request()),
+            None
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// Errors logged while the server runs are kept in the app_errors table, where
+// admins can read them at /admin/errors. ErrorLayer turns each error event
+// into a row and queues it; a task started by serve writes the queue out, so
+// a slow or failing database never holds up the request that logged the
+// error. Rows that can't be queued or written are reported on stderr instead.
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpResponse,
+};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{error, Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::admin::AdminUser;
+use crate::csrf::CsrfToken;
+use crate::db::{AppErrorRow, ArticleRepository};
+use crate::render::format_timestamp;
+use crate::templates::{render_html, AdminErrorsContext, AppErrorItem};
+
+// Errors waiting to be written; past this many, new ones only go to stderr
+const QUEUE_CAPACITY: usize = 1024;
+// How many are listed, newest first
+const MAX_LISTED: i64 = 200;
+
+pub fn channel() -> (Sender<AppErrorRow>, Receiver<AppErrorRow>) {
+    mpsc::channel(QUEUE_CAPACITY)
+}
+
+// Collects an event's or span's fields as JSON, keeping the message apart
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    values: Map<String, Value>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, Value::from(format!("{:?}", value)));
+    }
+}
+
+impl Fields {
+    fn record(&mut self, field: &Field, value: Value) {
+        match (field.name(), value) {
+            ("message", Value::String(message)) => self.message = Some(message),
+            (name, value) => {
+                self.values.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+// What a span was created with, kept in its extensions
+struct SpanFields(Map<String, Value>);
+
+pub struct ErrorLayer {
+    queue: Sender<AppErrorRow>,
+}
+
+impl ErrorLayer {
+    pub fn new(queue: Sender<AppErrorRow>) -> Self {
+        ErrorLayer { queue }
+    }
+}
+
+impl<S> Layer<S> for ErrorLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(fields.values));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut context = fields.values;
+        context.insert("target".to_string(), Value::from(event.metadata().target()));
+        // Fields of the enclosing spans (the request id, method and path),
+        // without overwriting the event's own
+        for span in ctx.event_scope(event).into_iter().flatten() {
+            if let Some(SpanFields(span_fields)) = span.extensions().get::<SpanFields>() {
+                for (name, value) in span_fields {
+                    context.entry(name.clone()).or_insert_with(|| value.clone());
+                }
+            }
+        }
+        let route = context
+            .remove("path")
+            .and_then(|path| path.as_str().map(str::to_string));
+
+        let row = AppErrorRow {
+            occurred_at: Utc::now().timestamp(),
+            route,
+            message: fields.message.unwrap_or_default(),
+            context: Json(Value::Object(context)),
+        };
+        match self.queue.try_send(row) {
+            Ok(()) => {}
+            Err(TrySendError::Full(row)) => {
+                eprintln!("Error queue is full, not recording: {}", row.message)
+            }
+            // Nothing writes errors out for commands other than serve
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+// Write queued errors to the database for as long as the server runs
+pub fn spawn_writer(mut queue: Receiver<AppErrorRow>, repo: Arc<dyn ArticleRepository>) {
+    tokio::spawn(async move {
+        while let Some(row) = queue.recv().await {
+            // Logged through tracing, this would come straight back here
+            if let Err(e) = repo.insert_app_error(&row).await {
+                eprintln!(
+                    "Failed to record error ({}): {} {}",
+                    e, row.message, row.context.0
+                );
+            }
+        }
+    });
+}
+
+// GET /admin/errors
+pub async fn errors_page(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    csrf: CsrfToken,
+) -> HttpResponse {
+    let rows = match repo.recent_app_errors(MAX_LISTED).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!(error = %e, "Failed to fetch logged errors");
+            return HttpResponse::InternalServerError().body("Failed to load errors");
+        }
+    };
+    render_html(
+        StatusCode::OK,
+        &AdminErrorsContext {
+            csrf_token: &csrf.0,
+            errors: rows
+                .into_iter()
+                .map(|row| AppErrorItem {
+                    occurred_at: format_timestamp(row.occurred_at),
+                    route: row.route,
+                    message: row.message,
+                    context: serde_json::to_string_pretty(&row.context.0).unwrap_or_default(),
+                })
+                .collect(),
+        },
+    )
+}
+
+// POST /admin/errors/clear
+pub async fn clear_errors(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+) -> HttpResponse {
+    if let Err(e) = repo.delete_app_errors(None).await {
+        error!(error = %e, "Failed to clear logged errors");
+        return HttpResponse::InternalServerError().body("Failed to clear errors");
+    }
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, "/admin/errors"))
+        .finish()
+}
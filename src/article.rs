@@ -0,0 +1,912 @@
+// An article's pages: the submission form and what it posts to, the article
+// with its comments, and editing and deleting it by its poster
+
+use actix_multipart::Multipart;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use askama::Template as _;
+use chrono::Utc;
+use futures_util::stream::StreamExt as _;
+use sanitize_filename::sanitize;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+use tracing::error;
+
+use crate::admin::AdminUser;
+use crate::board::Board;
+use crate::captcha::Captchas;
+use crate::client_ip::ClientIp;
+use crate::comment::{comment_page, CommentForm};
+use crate::conditional::Validator;
+use crate::config::Config;
+use crate::csrf::CsrfToken;
+use crate::db::{ArticleRepository, NewArticleRow};
+use crate::duplicate::{RecentPosts, Seen};
+use crate::error::AppError;
+use crate::listing::Reuse;
+use crate::markdown;
+use crate::notify::Notifier;
+use crate::page_cache::{CachedPage, PageCache, PageKey};
+use crate::password;
+use crate::post::{describe_errors, normalize_tags, poster_name, validate_article};
+use crate::quote;
+use crate::render::{
+    absolute_url, display_time, escape_comment, format_size, format_timestamp, summary,
+};
+use crate::slug::{article_path, slugify};
+use crate::spam::{self, passes_spam_checks, spam_rejected};
+use crate::storage::MediaStorage;
+use crate::templates::{
+    render_html, ArticlePageContext, CommentLink, CommentView, EditArticleContext,
+    NewArticleContext,
+};
+use crate::thread;
+use crate::tripcode;
+use crate::upload::{
+    read_text_field, remove_media_files, remove_replaced_files, save_media,
+    spawn_poster_extractions, upload_rejected, MAX_FIELD_BYTES,
+};
+use crate::webhook::Webhooks;
+use crate::word_filter::{apply_word_filters, post_blocked, WordFilters};
+use crate::{
+    Article, ArticlePath, DeleteForm, Media, CAPTCHA_ERROR, COMMENTS_PER_PAGE, EXCERPT_CHARS,
+    RELATED_ARTICLES,
+};
+
+// The slug is optional; view_article redirects to the canonical one
+#[derive(Deserialize)]
+pub struct ArticleViewPath {
+    id: i32,
+    #[serde(default)]
+    slug: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ArticleViewQuery {
+    // The comment the form should reply to
+    reply_to: Option<i32>,
+    // Defaults to the last page, where the newest comments are
+    comments_page: Option<i64>,
+    // ?raw=1 returns the body's Markdown as written, for editing clients
+    raw: Option<u8>,
+}
+
+#[derive(Deserialize)]
+pub struct EditForm {
+    title: String,
+    body: String,
+    password: String,
+}
+
+// Whether an article exists on `board`; articles are only reachable through
+// the board they were posted to
+pub async fn article_on_board(
+    repo: &dyn ArticleRepository,
+    board: &Board,
+    article_id: i32,
+) -> Result<bool, sqlx::Error> {
+    match repo.get_article(article_id).await {
+        Ok(article) => Ok(article.board_id == board.id),
+        Err(sqlx::Error::RowNotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// The largest upload, as the form shows it, with audio's when that differs
+fn upload_limit(config: &Config) -> String {
+    let limit = format_size(config.max_upload_bytes);
+    if config.max_audio_upload_bytes == config.max_upload_bytes {
+        limit
+    } else {
+        format!(
+            "{}, audio {}",
+            limit,
+            format_size(config.max_audio_upload_bytes)
+        )
+    }
+}
+
+// Route to display the article submission form
+pub async fn new_article_form(
+    board: Board,
+    config: web::Data<Config>,
+    captchas: web::Data<Captchas>,
+    csrf: CsrfToken,
+) -> HttpResponse {
+    render_html(
+        StatusCode::OK,
+        &NewArticleContext {
+            board: &board,
+            csrf_token: &csrf.0,
+            max_upload: upload_limit(&config),
+            captcha: config.captcha_enabled.then(|| captchas.issue()),
+            form_token: spam::form_token(&config.secret_key, Utc::now().timestamp()),
+            error: None,
+            title: "",
+            body: "",
+            name: "",
+            tags: "",
+        },
+    )
+}
+
+// Handle submission of new articles
+#[allow(clippy::too_many_arguments)]
+pub async fn submit_article(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    storage: web::Data<dyn MediaStorage>,
+    config: web::Data<Config>,
+    captchas: web::Data<Captchas>,
+    recent_posts: web::Data<RecentPosts>,
+    word_filters: web::Data<WordFilters>,
+    page_cache: web::Data<PageCache>,
+    webhooks: web::Data<Webhooks>,
+    notifier: web::Data<Notifier>,
+    csrf: CsrfToken,
+    client_ip: ClientIp,
+    mut payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let mut title = String::new();
+    let mut body = String::new();
+    let mut name = String::new();
+    let mut password = String::new();
+    let mut tags = String::new();
+    let mut captcha_token = String::new();
+    let mut captcha_answer = String::new();
+    let mut honeypot = String::new();
+    let mut form_token = String::new();
+    let mut media: Vec<Media> = Vec::new();
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+        // Form fields always have a name; a part without one is skipped
+        let Some(content_disposition) = field.content_disposition() else {
+            continue;
+        };
+        let Some(field_name) = content_disposition.get_name() else {
+            continue;
+        };
+
+        if field_name == "title" {
+            title = read_text_field(&mut field, config.max_title_chars * 4).await?;
+        } else if field_name == "body" {
+            body = read_text_field(&mut field, config.max_body_chars * 4).await?;
+        } else if field_name == "name" {
+            name = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "password" {
+            password = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "tags" {
+            tags = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "captcha_token" {
+            captcha_token = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "captcha_answer" {
+            captcha_answer = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == spam::HONEYPOT_FIELD {
+            honeypot = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "form_token" {
+            form_token = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "media" {
+            // An empty file input still sends the field, with an empty
+            // filename; either way there's no file
+            if let Some(filename) = content_disposition.get_filename().filter(|f| !f.is_empty()) {
+                let original_name = sanitize(filename);
+                match save_media(&mut field, original_name, storage.get_ref(), &config).await {
+                    Ok(item) => media.push(item),
+                    Err(e) => {
+                        remove_media_files(
+                            storage.get_ref(),
+                            media.iter().flat_map(Media::file_paths),
+                        )
+                        .await;
+                        return Err(upload_rejected(e, &config));
+                    }
+                }
+            }
+        }
+    }
+
+    if !passes_spam_checks(&config, &honeypot, &form_token) {
+        remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+        return Ok(spam_rejected(&format!("{}/", board.base)));
+    }
+
+    // Checked before anything is stored; a failed captcha or a title or body
+    // of the wrong length sends the form back, with a fresh captcha and the
+    // text that was entered
+    let send_back = |error: &str| {
+        render_html(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &NewArticleContext {
+                board: &board,
+                csrf_token: &csrf.0,
+                max_upload: upload_limit(&config),
+                captcha: config.captcha_enabled.then(|| captchas.issue()),
+                form_token: spam::form_token(&config.secret_key, Utc::now().timestamp()),
+                error: Some(error),
+                title: &title,
+                body: &body,
+                name: &name,
+                tags: &tags,
+            },
+        )
+    };
+    if config.captcha_enabled && !captchas.verify(&captcha_token, &captcha_answer) {
+        remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+        return Ok(send_back(CAPTCHA_ERROR));
+    }
+    let errors = validate_article(&config, &title, &body);
+    if !errors.is_empty() {
+        remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+        return Ok(send_back(&describe_errors(&errors)));
+    }
+
+    // The secret is dropped here; only its hash is kept
+    let (name, tripcode) = tripcode::parse(&name, &config.secret_key);
+    let mut name = name.to_string();
+    if apply_word_filters(&word_filters, &mut [&mut title, &mut body, &mut name]).is_err() {
+        remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+        return Ok(post_blocked(&format!("{}/", board.base)));
+    }
+
+    if media.is_empty() {
+        return Err(AppError::Validation {
+            field: "media",
+            message: "Media file is required".to_string(),
+        });
+    }
+
+    let tags = match normalize_tags(&tags) {
+        Ok(tags) => tags,
+        Err(message) => {
+            remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+            return Err(AppError::Validation {
+                field: "tags",
+                message,
+            });
+        }
+    };
+    let name = match poster_name(&name) {
+        Ok(name) => name,
+        Err(message) => {
+            remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+            return Err(AppError::Validation {
+                field: "name",
+                message: format!("Name {}", message),
+            });
+        }
+    };
+
+    // The same article sent again goes where the first one did
+    let listing = HttpResponse::Found()
+        .append_header(("Location", format!("{}/articles", board.base)))
+        .finish();
+    let media_names: Vec<_> = media.iter().map(|m| m.original_name.as_deref()).collect();
+    let claim = match recent_posts.claim(
+        client_ip.0,
+        (board.id, &title, &body, &media_names),
+        Instant::now(),
+    ) {
+        Seen::New(claim) => claim,
+        Seen::Posted(_) | Seen::Pending => {
+            remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+            return Ok(listing);
+        }
+    };
+
+    let created_at = Utc::now().timestamp();
+
+    let password_hash = if password.is_empty() {
+        None
+    } else {
+        Some(password::hash_password(&password)?)
+    };
+
+    let inserted = repo
+        .insert_article(NewArticleRow {
+            board_id: board.id,
+            title: title.trim(),
+            slug: slugify(&title).as_deref(),
+            body: &body,
+            name,
+            tripcode: tripcode.as_deref(),
+            created_at,
+            bump_time: created_at,
+            delete_password_hash: password_hash.as_deref(),
+            tags: &tags,
+            poster_ip: client_ip.0.map(|ip| ip.to_string()).as_deref(),
+            media: &media,
+        })
+        .await;
+    // Nothing was stored, so nothing refers to the files
+    let (article_id, stored) = match inserted {
+        Ok(ids) => ids,
+        Err(e) => {
+            remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+            return Err(e.into());
+        }
+    };
+    claim.posted(article_id);
+    page_cache.invalidate_lists();
+    webhooks.article_created(article_id);
+    notifier.article_created(&board.base, article_id);
+
+    remove_replaced_files(storage.get_ref(), &media, &stored).await;
+    spawn_poster_extractions(&repo, &storage, &config, stored);
+
+    trim_board(
+        repo.get_ref(),
+        storage.get_ref(),
+        &config,
+        &page_cache,
+        board.id,
+    )
+    .await;
+
+    Ok(listing)
+}
+
+// After a new article, delete the lowest-bumped ones once the board is over
+// MAX_ARTICLES, then archive whatever was pushed past the newest
+// ARCHIVE_AFTER. Failures are only logged; the post itself went through.
+pub async fn trim_board(
+    repo: &dyn ArticleRepository,
+    storage: &dyn MediaStorage,
+    config: &Config,
+    page_cache: &PageCache,
+    board_id: i32,
+) {
+    if config.max_articles > 0 {
+        match repo.prune_over_cap(board_id, config.max_articles).await {
+            Ok(media_paths) => remove_media_files(storage, &media_paths).await,
+            Err(e) => error!(board_id, error = %e, "Failed to prune articles"),
+        }
+    }
+    if config.archive_after > 0 {
+        if let Err(e) = repo
+            .archive_overflow(board_id, config.archive_after, Utc::now().timestamp())
+            .await
+        {
+            error!(board_id, error = %e, "Failed to archive old articles");
+        }
+    }
+    // Any of the kept article pages could be one trimmed
+    if config.max_articles > 0 || config.archive_after > 0 {
+        page_cache.clear();
+    }
+}
+
+// View an article by ID
+#[allow(clippy::too_many_arguments)]
+pub async fn view_article(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+    captchas: web::Data<Captchas>,
+    page_cache: web::Data<PageCache>,
+    csrf: CsrfToken,
+    path: web::Path<ArticleViewPath>,
+    query: web::Query<ArticleViewQuery>,
+    admin: Option<AdminUser>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    // Moderators get the article as it is
+    let cache = (admin.is_none() && query.raw.is_none() && page_cache.enabled())
+        .then_some(page_cache.get_ref());
+    let key = PageKey::Article {
+        board_id: board.id,
+        article_id: path.id,
+        slug: path.slug.clone(),
+        comments_page: query.comments_page,
+        reply_to: query.reply_to,
+    };
+    if let Some(page) = cache.and_then(|cache| cache.get(&key, Instant::now())) {
+        return Ok(send_cached_article(
+            &page,
+            &req,
+            &page_cache,
+            &config,
+            &captchas,
+            &csrf,
+        ));
+    }
+    let generation = page_cache.generation();
+
+    let article = repo.get_article_with_media(path.id).await?;
+    if article.board_id != board.id {
+        return Err(AppError::NotFound);
+    }
+
+    if query.raw == Some(1) {
+        return Ok(HttpResponse::Ok()
+            .content_type("text/markdown; charset=utf-8")
+            .body(article.body));
+    }
+
+    // Bare ids and stale or mistyped slugs all move to the one canonical URL
+    if path.slug != article.slug {
+        let mut location = format!(
+            "{}{}",
+            board.base,
+            article_path(article.id, article.slug.as_deref())
+        );
+        let mut params = Vec::new();
+        if let Some(comments_page) = query.comments_page {
+            params.push(format!("comments_page={}", comments_page));
+        }
+        if let Some(reply_to) = query.reply_to {
+            params.push(format!("reply_to={}", reply_to));
+        }
+        if !params.is_empty() {
+            location.push_str(&format!("?{}", params.join("&")));
+        }
+        if query.reply_to.is_some() {
+            location.push_str("#comment-form");
+        }
+        return Ok(HttpResponse::MovedPermanently()
+            .append_header(("Location", location))
+            .finish());
+    }
+
+    let page = ArticlePage {
+        reply_to: query.reply_to,
+        comments_page: query.comments_page,
+        sent_back: None,
+        admin: admin.is_some(),
+        reuse: Some(Reuse {
+            request: &req,
+            cache: cache.map(|cache| (cache, key, generation)),
+        }),
+    };
+    article_page(
+        &board,
+        repo.get_ref(),
+        &config,
+        &captchas,
+        &csrf,
+        &article,
+        page,
+    )
+    .await
+}
+
+// A kept article page: a 304 when the client's copy is still good, otherwise
+// the page with the visitor's own tokens filled in
+fn send_cached_article(
+    page: &CachedPage,
+    req: &HttpRequest,
+    cache: &PageCache,
+    config: &Config,
+    captchas: &Captchas,
+    csrf: &CsrfToken,
+) -> HttpResponse {
+    let now = Utc::now().timestamp();
+    let validator = page.validator.with(&csrf.0);
+    if let Some(not_modified) = validator.not_modified(req, now) {
+        return not_modified;
+    }
+    let captcha = if config.captcha_enabled {
+        captchas.issue()
+    } else {
+        String::new()
+    };
+    let form_token = spam::form_token(&config.secret_key, now);
+    let html = cache.fill(
+        &page.html,
+        &[
+            ("csrf", &csrf.0),
+            ("form", &form_token),
+            ("captcha", &captcha),
+        ],
+    );
+    let mut response = HttpResponse::Ok().content_type("text/html").body(html);
+    validator.apply(&mut response, now);
+    response
+}
+
+// How an article's page is shown, beyond the article itself
+pub struct ArticlePage<'a> {
+    // Points the comment form at a comment
+    pub reply_to: Option<i32>,
+    // None for the last page
+    pub comments_page: Option<i64>,
+    // When a comment is sent back, what was entered and the reason; the
+    // comment form is refilled
+    pub sent_back: Option<(&'a CommentForm, &'a str)>,
+    // Show the admin controls
+    pub admin: bool,
+    // For a GET
+    pub reuse: Option<Reuse<'a>>,
+}
+
+// Render an article's page
+pub async fn article_page(
+    board: &Board,
+    repo: &dyn ArticleRepository,
+    config: &Config,
+    captchas: &Captchas,
+    csrf: &CsrfToken,
+    article: &Article,
+    page: ArticlePage<'_>,
+) -> Result<HttpResponse, AppError> {
+    let ArticlePage {
+        reply_to,
+        comments_page,
+        sent_back,
+        admin,
+        reuse,
+    } = page;
+    let now = Utc::now().timestamp();
+    let canonical_path = format!(
+        "{}{}",
+        board.base,
+        article_path(article.id, article.slug.as_deref())
+    );
+    // With the article's own query, these make four for the whole page
+    let live_ids = repo.live_comment_ids(article.id).await?;
+    let comment_pages =
+        ((live_ids.len() as i64 + COMMENTS_PER_PAGE - 1) / COMMENTS_PER_PAGE).max(1);
+    let comments_page = comments_page
+        .unwrap_or(comment_pages)
+        .clamp(1, comment_pages);
+    // Comments change the live ids whether or not they bump the article
+    let validator = Validator::new(
+        (
+            (
+                article.id,
+                article.bump_time,
+                article.edited_at,
+                article.archived_at,
+                article.is_sticky,
+                &live_ids,
+            ),
+            (comments_page, reply_to, admin),
+        ),
+        [
+            article.last_comment_at,
+            article.edited_at,
+            article.archived_at,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(article.bump_time, i64::max),
+    );
+    if let Some(not_modified) = reuse
+        .as_ref()
+        .and_then(|reuse| validator.with(&csrf.0).not_modified(reuse.request, now))
+    {
+        return Ok(not_modified);
+    }
+    let (comments, mut media) = repo
+        .list_comments_page(
+            article.id,
+            COMMENTS_PER_PAGE,
+            (comments_page - 1) * COMMENTS_PER_PAGE,
+        )
+        .await?;
+    // Placeholders for deleted comments can't be quoted or replied to
+    let comment_texts: Vec<(i32, &str)> = comments
+        .iter()
+        .filter(|c| !c.deleted)
+        .map(|c| (c.id, c.comment.as_str()))
+        .collect();
+    let positions: HashMap<i32, usize> = live_ids
+        .iter()
+        .enumerate()
+        .map(|(i, &id)| (id, i))
+        .collect();
+    // Where a live comment is: here, or on the page its position puts it on
+    let comment_href = |id: i32| {
+        let position = *positions.get(&id)?;
+        if comment_texts.iter().any(|&(other, _)| other == id) {
+            Some(format!("#c{}", id))
+        } else {
+            Some(format!(
+                "{}?comments_page={}#c{}",
+                canonical_path,
+                comment_page(position),
+                id
+            ))
+        }
+    };
+    let comment_link = |id: i32| comment_href(id).map(|href| CommentLink { id, href });
+    // Only quotes from this page are listed
+    let mut replies = quote::backlinks(&comment_texts);
+
+    // Extras, so the page is shown without them if they fail
+    let related = repo
+        .related_articles(article, RELATED_ARTICLES)
+        .await
+        .unwrap_or_else(|e| {
+            error!(article_id = article.id, error = %e, "Failed to find related articles");
+            Vec::new()
+        });
+    let media_hashes = if admin {
+        repo.media_hashes(article.id).await.unwrap_or_else(|e| {
+            error!(article_id = article.id, error = %e, "Failed to load media hashes");
+            HashMap::new()
+        })
+    } else {
+        HashMap::new()
+    };
+
+    // Link previews show the first image; videos only have a poster frame
+    // once ffmpeg gets to them, so they fall back to the configured default
+    let image = article
+        .media
+        .iter()
+        .find(|m| !m.is_video() && !m.is_audio())
+        .map(|m| m.media_path.as_str())
+        .or(config.og_default_image.as_deref());
+
+    // A kept page has placeholders for the visitor's tokens, filled in as
+    // it's sent
+    let (csrf_token, form_token, captcha) =
+        match reuse.as_ref().and_then(|reuse| reuse.cache.as_ref()) {
+            Some((cache, ..)) => (
+                cache.placeholder("csrf"),
+                cache.placeholder("form"),
+                config.captcha_enabled.then(|| cache.placeholder("captcha")),
+            ),
+            None => (
+                csrf.0.clone(),
+                spam::form_token(&config.secret_key, now),
+                config.captcha_enabled.then(|| captchas.issue()),
+            ),
+        };
+
+    let context = ArticlePageContext {
+        board,
+        description: summary(&markdown::to_plain_text(&article.body), EXCERPT_CHARS),
+        url: absolute_url(&config.public_url, &canonical_path),
+        image_url: image.map(|path| absolute_url(&config.public_url, path)),
+        body_html: markdown::to_html(
+            &article.body,
+            config.markdown_images,
+            config.max_links_per_post,
+        ),
+        posted_at: display_time(article.created_at, now),
+        edited_at: article.edited_at.map(format_timestamp),
+        archived_at: article.archived_at.map(format_timestamp),
+        comments: thread::thread_order(&comments)
+            .into_iter()
+            .map(|(i, depth)| {
+                let c = &comments[i];
+                CommentView {
+                    id: c.id,
+                    body_html: quote::link_quotes(
+                        &escape_comment(&c.comment, config.max_links_per_post),
+                        comment_href,
+                    ),
+                    name: c.name.clone(),
+                    tripcode: c.tripcode.clone(),
+                    media: media.remove(&c.id).unwrap_or_default(),
+                    created_at: display_time(c.created_at, now),
+                    replies: replies.remove(&c.id).unwrap_or_default(),
+                    depth,
+                    // Too deep to sit under its parent, or starting a page
+                    // without it, so it says which it answers
+                    parent: match (c.parent_comment_id, depth) {
+                        (Some(parent), thread::MAX_DEPTH) => Some(CommentLink {
+                            id: parent,
+                            href: format!("#c{}", parent),
+                        }),
+                        (Some(parent), 0) => comment_link(parent),
+                        _ => None,
+                    },
+                    deleted: c.deleted,
+                }
+            })
+            .collect(),
+        comments_page,
+        comment_pages,
+        reply_to: reply_to.and_then(comment_link),
+        admin,
+        bump_limit_reached: config.bump_limit > 0 && live_ids.len() as i64 >= config.bump_limit,
+        article,
+        captcha,
+        form_token,
+        csrf_token: &csrf_token,
+        comment_error: sent_back.map(|(_, error)| error),
+        comment: sent_back.map_or("", |(form, _)| form.comment.as_str()),
+        name: sent_back.map_or("", |(form, _)| form.name.as_str()),
+        related,
+        media_hashes,
+    };
+    match reuse {
+        Some(Reuse {
+            request,
+            cache: Some((cache, key, generation)),
+        }) => {
+            let page = CachedPage {
+                html: context.render()?,
+                validator,
+            };
+            let page = cache.put(key, page, generation, Instant::now());
+            Ok(send_cached_article(
+                &page, request, cache, config, captchas, csrf,
+            ))
+        }
+        reuse => {
+            let status = if sent_back.is_some() {
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                StatusCode::OK
+            };
+            let mut response = render_html(status, &context);
+            if reuse.is_some() {
+                validator.with(&csrf.0).apply(&mut response, now);
+            }
+            Ok(response)
+        }
+    }
+}
+
+// Show the edit form prefilled with the article's current content
+pub async fn edit_article_form(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    csrf: CsrfToken,
+    path: web::Path<ArticlePath>,
+) -> HttpResponse {
+    let article_id = path.id;
+
+    match repo.get_article_with_media(article_id).await {
+        Ok(article) if article.board_id == board.id => render_html(
+            StatusCode::OK,
+            &EditArticleContext {
+                board: &board,
+                csrf_token: &csrf.0,
+                article_id: article.id,
+                title: &article.title,
+                body: &article.body,
+                errors: &BTreeMap::new(),
+            },
+        ),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => AppError::NotFound.error_response(),
+        Err(e) => {
+            error!(article_id, error = %e, "Failed to fetch article");
+            HttpResponse::InternalServerError().body("Failed to load article")
+        }
+    }
+}
+
+// Update an article's title and body; bump_time is intentionally left untouched
+#[allow(clippy::too_many_arguments)]
+pub async fn edit_article(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+    word_filters: web::Data<WordFilters>,
+    page_cache: web::Data<PageCache>,
+    csrf: CsrfToken,
+    path: web::Path<ArticlePath>,
+    form: web::Form<EditForm>,
+) -> HttpResponse {
+    let article_id = path.id;
+    let mut form = form.into_inner();
+
+    let stored_hash = match article_on_board(repo.get_ref(), &board, article_id).await {
+        Ok(true) => repo.article_password_hash(article_id).await,
+        Ok(false) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let stored_hash = match stored_hash {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return AppError::NotFound.error_response(),
+        Err(e) => {
+            error!(article_id, error = %e, "Failed to look up article");
+            return HttpResponse::InternalServerError().body("Failed to edit article.");
+        }
+    };
+
+    if !password::verify_password(&form.password, stored_hash.as_deref()) {
+        return HttpResponse::Forbidden().body("Incorrect password.");
+    }
+
+    let errors = validate_article(&config, &form.title, &form.body);
+    if !errors.is_empty() {
+        return render_html(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            &EditArticleContext {
+                board: &board,
+                csrf_token: &csrf.0,
+                article_id,
+                title: &form.title,
+                body: &form.body,
+                errors: &errors,
+            },
+        );
+    }
+
+    if apply_word_filters(&word_filters, &mut [&mut form.title, &mut form.body]).is_err() {
+        return post_blocked(&format!("{}/articles/{}/edit", board.base, article_id));
+    }
+
+    if let Err(e) = repo
+        .update_article(
+            article_id,
+            form.title.trim(),
+            &form.body,
+            Utc::now().timestamp(),
+        )
+        .await
+    {
+        error!(article_id, error = %e, "Failed to update article");
+        return HttpResponse::InternalServerError().body("Failed to edit article.");
+    }
+    page_cache.invalidate_article(article_id);
+
+    HttpResponse::Found()
+        .append_header((
+            "Location",
+            format!("{}/articles/{}", board.base, article_id),
+        ))
+        .finish()
+}
+
+// Delete an article whose poster supplied the matching deletion password. It
+// is only hidden; purge-deleted removes it and its media later.
+pub async fn delete_article(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<ArticlePath>,
+    form: web::Form<DeleteForm>,
+) -> HttpResponse {
+    let article_id = path.id;
+
+    // Missing articles (including ones on another board), articles without a
+    // password, and wrong passwords all get the same 403 so the response
+    // doesn't reveal which case applied
+    let stored_hash = match article_on_board(repo.get_ref(), &board, article_id).await {
+        Ok(true) => repo.article_password_hash(article_id).await,
+        Ok(false) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let stored_hash = match stored_hash {
+        Ok(hash) => hash.flatten(),
+        Err(e) => {
+            error!(article_id, error = %e, "Failed to look up article");
+            return HttpResponse::InternalServerError().body("Failed to delete article.");
+        }
+    };
+
+    if !password::verify_password(&form.password, stored_hash.as_deref()) {
+        return HttpResponse::Forbidden().body("Incorrect password.");
+    }
+
+    if let Err(e) = soft_delete(repo.get_ref(), &page_cache, article_id, None).await {
+        error!(article_id, error = %e, "Failed to delete article");
+        return HttpResponse::InternalServerError().body("Failed to delete article.");
+    }
+
+    HttpResponse::Found()
+        .append_header(("Location", format!("{}/articles", board.base)))
+        .finish()
+}
+
+// Hide an article, or one of its comments, and drop the pages showing it; the
+// one way content is deleted, whether by its poster or a moderator. Its media
+// stays, so it can be restored, until purge-deleted removes the lot after
+// DELETED_RETENTION_DAYS. Whether there was anything left to delete.
+pub async fn soft_delete(
+    repo: &dyn ArticleRepository,
+    page_cache: &PageCache,
+    article_id: i32,
+    comment_id: Option<i32>,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now().timestamp();
+    let deleted = match comment_id {
+        // Removing a comment deliberately leaves the article's bump_time alone
+        Some(comment_id) => {
+            repo.soft_delete_comment(article_id, comment_id, now)
+                .await?
+        }
+        None => repo.soft_delete_article(article_id, now).await?,
+    };
+    if deleted {
+        page_cache.invalidate_article(article_id);
+    }
+    Ok(deleted)
+}
@@ -0,0 +1,292 @@
+// Static files are built into the binary, so it runs on its own; a ./static
+// directory next to it is served instead, so they can be changed without a
+// rebuild. Either way they're hashed at startup, and templates link them as
+// /static/{file}?v={hash} through static_url. Those links change whenever the
+// file does, so browsers keep what they fetch for good; other requests for
+// static files are only cached briefly. Uploads never change once written but
+// may be deleted, so they're kept for a day. Browsers ask for /favicon.ico
+// without being linked to it, so static/favicon.ico is served there too, kept
+// for a week.
+
+use actix_files::{file_extension_to_mime, Files, NamedFile};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{EntityTag, HeaderValue, IfNoneMatch, CACHE_CONTROL, ETAG},
+    middleware::{from_fn, Next},
+    mime, web, Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use include_dir::{include_dir, Dir};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+use crate::error;
+
+pub const FAVICON: &str = "/favicon.ico";
+const STATIC_DIR: &str = "./static";
+// Hex digits of a file's SHA-256 in its links
+const HASH_CHARS: usize = 16;
+const VERSIONED: &str = "public, max-age=31536000, immutable";
+const UNVERSIONED: &str = "public, max-age=300";
+const UPLOADS: &str = "public, max-age=86400";
+const ICON: &str = "public, max-age=604800";
+
+static EMBEDDED: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/static");
+
+// Each static file's hash, by its path under STATIC_DIR such as "style.css"
+static HASHES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+// Where static files are served from
+#[derive(Clone, Copy)]
+pub enum StaticFiles {
+    Disk,
+    Embedded,
+}
+
+// Pick where static files come from and hash them, logging which it is
+pub fn init() -> StaticFiles {
+    let mut hashes = HashMap::new();
+    let source = if Path::new(STATIC_DIR).is_dir() {
+        info!(dir = STATIC_DIR, "Serving static files from disk");
+        // Without hashes, links go unversioned
+        if let Err(e) = hash_dir(Path::new(STATIC_DIR), "", &mut hashes) {
+            warn!(dir = STATIC_DIR, error = %e, "Failed to hash static files; they won't be cached for long");
+        }
+        StaticFiles::Disk
+    } else {
+        info!("Serving the static files built into the binary");
+        hash_embedded(&EMBEDDED, &mut hashes);
+        StaticFiles::Embedded
+    };
+    let _ = HASHES.set(hashes);
+    source
+}
+
+fn hash(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    digest
+        .iter()
+        .take(HASH_CHARS / 2)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn hash_dir(dir: &Path, prefix: &str, hashes: &mut HashMap<String, String>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            hash_dir(&entry.path(), &format!("{}/", name), hashes)?;
+        } else {
+            hashes.insert(name, hash(&fs::read(entry.path())?));
+        }
+    }
+    Ok(())
+}
+
+// Embedded paths are already relative to STATIC_DIR
+fn hash_embedded(dir: &Dir<'_>, hashes: &mut HashMap<String, String>) {
+    for file in dir.files() {
+        hashes.insert(
+            file.path().to_string_lossy().into_owned(),
+            hash(file.contents()),
+        );
+    }
+    for dir in dir.dirs() {
+        hash_embedded(dir, hashes);
+    }
+}
+
+// The /static and /uploads services, and /favicon.ico
+pub fn configure(cfg: &mut web::ServiceConfig, source: StaticFiles, uploads_dir: Option<&Path>) {
+    let scope = web::scope("/static").wrap(from_fn(static_caching));
+    match source {
+        StaticFiles::Disk => cfg.service(scope.service(Files::new("", STATIC_DIR))),
+        StaticFiles::Embedded => cfg.service(scope.route("/{file:.*}", web::get().to(embedded))),
+    };
+    cfg.route(
+        FAVICON,
+        web::get()
+            .to(move |req| favicon(req, source))
+            .wrap(from_fn(icon_caching)),
+    );
+    if let Some(uploads_dir) = uploads_dir {
+        cfg.service(
+            web::scope("/uploads")
+                .wrap(from_fn(upload_caching))
+                .service(Files::new("", uploads_dir)),
+        );
+    }
+}
+
+async fn favicon(req: HttpRequest, source: StaticFiles) -> HttpResponse {
+    match source {
+        StaticFiles::Disk => {
+            match NamedFile::open_async(Path::new(STATIC_DIR).join("favicon.ico")).await {
+                Ok(file) => file.into_response(&req),
+                Err(_) => error::not_found(req).await,
+            }
+        }
+        StaticFiles::Embedded => send_embedded(req, "favicon.ico").await,
+    }
+}
+
+async fn embedded(req: HttpRequest) -> HttpResponse {
+    let path = req.match_info().query("file").to_string();
+    send_embedded(req, &path).await
+}
+
+// A static file built into the binary, with its hash for an ETag
+async fn send_embedded(req: HttpRequest, path: &str) -> HttpResponse {
+    let (Some(file), Some(hash)) = (
+        EMBEDDED.get_file(path),
+        HASHES.get().and_then(|hashes| hashes.get(path)),
+    ) else {
+        return error::not_found(req).await;
+    };
+    let etag = EntityTag::new_strong(hash.clone());
+    if let Some(IfNoneMatch::Items(etags)) = req.get_header::<IfNoneMatch>() {
+        if etags.iter().any(|other| other.weak_eq(&etag)) {
+            return HttpResponse::NotModified()
+                .insert_header((ETAG, etag))
+                .finish();
+        }
+    }
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let content_type = file_extension_to_mime(extension);
+    // As Files sends them
+    let content_type =
+        if content_type.type_() == mime::TEXT || content_type.subtype() == mime::JAVASCRIPT {
+            format!("{}; charset=utf-8", content_type)
+        } else {
+            content_type.to_string()
+        };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((ETAG, etag))
+        .body(file.contents())
+}
+
+// The link to a static file, such as static_url("style.css"), for templates
+pub fn static_url(file: &str) -> String {
+    match HASHES.get().and_then(|hashes| hashes.get(file)) {
+        Some(hash) => format!("/static/{}?v={}", file, hash),
+        None => format!("/static/{}", file),
+    }
+}
+
+// Middleware for /static
+async fn static_caching(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let hashes = HASHES.get();
+    let current = hashes.is_some_and(|hashes| versioned(hashes, req.path(), req.query_string()));
+    let res = next.call(req).await?;
+    Ok(cache_for(
+        res,
+        if current { VERSIONED } else { UNVERSIONED },
+    ))
+}
+
+// Middleware for /uploads
+async fn upload_caching(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    Ok(cache_for(next.call(req).await?, UPLOADS))
+}
+
+// Middleware for /favicon.ico
+async fn icon_caching(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    Ok(cache_for(next.call(req).await?, ICON))
+}
+
+// Files found (or not modified) get `cache_control`; 404s aren't kept
+fn cache_for<B>(mut res: ServiceResponse<B>, cache_control: &'static str) -> ServiceResponse<B> {
+    let status = res.status();
+    if status.is_success() || status.is_redirection() {
+        res.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    }
+    res
+}
+
+// Whether a request is for a static file as it is now: ?v= its current hash
+fn versioned(hashes: &HashMap<String, String>, path: &str, query: &str) -> bool {
+    let Some(hash) = path
+        .strip_prefix("/static/")
+        .and_then(|file| hashes.get(file))
+    else {
+        return false;
+    };
+    query
+        .split('&')
+        .any(|pair| pair.strip_prefix("v=") == Some(hash.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_current_hash_is_versioned() {
+        let hashes = HashMap::from([("style.css".to_string(), "0123456789abcdef".to_string())]);
+        assert!(versioned(
+            &hashes,
+            "/static/style.css",
+            "v=0123456789abcdef"
+        ));
+        assert!(!versioned(
+            &hashes,
+            "/static/style.css",
+            "v=fedcba9876543210"
+        ));
+        assert!(!versioned(&hashes, "/static/style.css", ""));
+        assert!(!versioned(
+            &hashes,
+            "/static/other.css",
+            "v=0123456789abcdef"
+        ));
+    }
+
+    #[test]
+    fn files_in_subdirectories_are_hashed() {
+        let dir = std::env::temp_dir().join(format!("articles-assets-{}", std::process::id()));
+        fs::create_dir_all(dir.join("img")).unwrap();
+        fs::write(dir.join("a.css"), "a").unwrap();
+        fs::write(dir.join("img/b.svg"), "b").unwrap();
+        let mut hashes = HashMap::new();
+        hash_dir(&dir, "", &mut hashes).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(hashes.len(), 2);
+        // SHA-256 of "a"
+        assert_eq!(hashes["a.css"], "ca978112ca1bbdca");
+        assert_eq!(hashes["img/b.svg"].len(), HASH_CHARS);
+    }
+
+    #[test]
+    fn embedded_files_hash_like_the_ones_on_disk() {
+        let mut on_disk = HashMap::new();
+        hash_dir(
+            Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/static")),
+            "",
+            &mut on_disk,
+        )
+        .unwrap();
+        let mut embedded = HashMap::new();
+        hash_embedded(&EMBEDDED, &mut embedded);
+        assert_eq!(embedded, on_disk);
+    }
+}
@@ -0,0 +1,283 @@
+// Posting bans on single addresses or CIDR ranges. Bans are managed with the
+// ban / unban commands and checked by middleware on the routes that create
+// articles and comments; reading is never blocked.
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::ErrorInternalServerError,
+    http::StatusCode,
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use chrono::Utc;
+use serde_json::json;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use tracing::error;
+
+use crate::cli::CommandResult;
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::db::{self, ArticleRepository, BanRow};
+use crate::rate_limit::back_link;
+use crate::render::format_timestamp;
+use crate::templates::{render_html, MessageContext};
+
+// An address with a prefix length; a bare address is a range of one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & mask_u32(self.prefix) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & mask_u128(self.prefix) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn mask_u32(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+}
+
+fn mask_u128(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0)
+}
+
+// "203.0.113.7", "203.0.113.0/24", "2001:db8:1:2::/64". Host bits below the
+// prefix are cleared, so "2001:db8:1:2::5/64" bans the whole /64.
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("{:?} is not an IP address or CIDR range", s))?;
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|&p| p <= max)
+                .ok_or_else(|| format!("{:?} has an invalid prefix length (0-{})", s, max))?,
+            None => max,
+        };
+        let network = match addr {
+            IpAddr::V4(ip) => IpAddr::V4((u32::from(ip) & mask_u32(prefix)).into()),
+            IpAddr::V6(ip) => IpAddr::V6((u128::from(ip) & mask_u128(prefix)).into()),
+        };
+        Ok(IpRange { network, prefix })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = if self.network.is_ipv4() { 32 } else { 128 };
+        if self.prefix == max {
+            write!(f, "{}", self.network)
+        } else {
+            write!(f, "{}/{}", self.network, self.prefix)
+        }
+    }
+}
+
+// The first of `bans` covering `ip`. Rows whose range no longer parses are
+// skipped.
+pub fn find_ban(bans: Vec<BanRow>, ip: IpAddr) -> Option<BanRow> {
+    bans.into_iter().find(|ban| {
+        ban.cidr
+            .parse::<IpRange>()
+            .is_ok_and(|range| range.contains(ip))
+    })
+}
+
+// Wraps the posting routes; banned addresses get a page saying why and until when
+pub async fn reject_banned(
+    repo: web::Data<dyn ArticleRepository>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(ip) = client_ip(req.request()) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let bans = repo
+        .active_bans(Utc::now().timestamp())
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to check bans");
+            ErrorInternalServerError("Failed to check bans")
+        })?;
+
+    match find_ban(bans, ip) {
+        None => Ok(next.call(req).await?.map_into_left_body()),
+        Some(ban) => {
+            let response = banned(req.path(), &ban);
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+fn banned(path: &str, ban: &BanRow) -> HttpResponse {
+    let reason = if ban.reason.is_empty() {
+        "No reason was given"
+    } else {
+        &ban.reason
+    };
+    let until = match ban.expires_at {
+        Some(expires_at) => format!("until {}", format_timestamp(expires_at)),
+        None => "permanently".to_string(),
+    };
+
+    if path.starts_with("/api/") {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "You are banned from posting",
+            "reason": reason,
+            "expires_at": ban.expires_at,
+        }));
+    }
+    render_html(
+        StatusCode::FORBIDDEN,
+        &MessageContext {
+            title: "Banned",
+            message: &format!("You are banned from posting {}. Reason: {}", until, reason),
+            link_href: &back_link(path),
+            link_text: "Go back",
+        },
+    )
+}
+
+// `articles ban IP_OR_CIDR [--reason TEXT] [--duration 7d]`
+pub async fn ban(
+    config: &Config,
+    target: &str,
+    reason: &str,
+    duration: Option<i64>,
+) -> CommandResult {
+    let range: IpRange = target.parse()?;
+    let now = Utc::now().timestamp();
+    let expires_at = duration.map(|secs| now + secs);
+    let repo = db::connect(&config.database_url).await?;
+    let id = repo
+        .insert_ban(&range.to_string(), reason.trim(), now, expires_at)
+        .await
+        .map_err(|e| format!("Failed to add ban: {}", e))?;
+
+    match expires_at {
+        Some(expires_at) => println!(
+            "Ban {} on {} lasts until {}",
+            id,
+            range,
+            format_timestamp(expires_at)
+        ),
+        None => println!("Ban {} on {} is permanent", id, range),
+    }
+    Ok(())
+}
+
+// `articles unban ID`
+pub async fn unban(config: &Config, id: i32) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let removed = repo
+        .delete_ban(id)
+        .await
+        .map_err(|e| format!("Failed to lift ban {}: {}", id, e))?;
+    if !removed {
+        return Err(format!("There is no ban {}", id).into());
+    }
+    println!("Lifted ban {}", id);
+    Ok(())
+}
+
+// `articles list-bans`
+pub async fn list_bans(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let bans = repo
+        .list_bans()
+        .await
+        .map_err(|e| format!("Failed to list bans: {}", e))?;
+    if bans.is_empty() {
+        println!("No bans");
+    }
+    let now = Utc::now().timestamp();
+    for b in bans {
+        let until = match b.expires_at {
+            Some(t) if t <= now => format!("expired {}", format_timestamp(t)),
+            Some(t) => format!("until {}", format_timestamp(t)),
+            None => "permanent".to_string(),
+        };
+        println!(
+            "{}\t{}\tadded {}, {}\t{}",
+            b.id,
+            b.cidr,
+            format_timestamp(b.created_at),
+            until,
+            b.reason
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn single_addresses_match_only_themselves() {
+        let range: IpRange = "203.0.113.7".parse().unwrap();
+        assert!(range.contains(ip("203.0.113.7")));
+        assert!(range.contains(ip("::ffff:203.0.113.7")));
+        assert!(!range.contains(ip("203.0.113.8")));
+        assert_eq!(range.to_string(), "203.0.113.7");
+    }
+
+    #[test]
+    fn ranges_cover_their_prefix() {
+        let v4: IpRange = "203.0.113.77/24".parse().unwrap();
+        assert_eq!(v4.to_string(), "203.0.113.0/24");
+        assert!(v4.contains(ip("203.0.113.1")));
+        assert!(!v4.contains(ip("203.0.114.1")));
+
+        let v6: IpRange = "2001:db8:1:2::5/64".parse().unwrap();
+        assert_eq!(v6.to_string(), "2001:db8:1:2::/64");
+        assert!(v6.contains(ip("2001:db8:1:2:ffff::1")));
+        assert!(!v6.contains(ip("2001:db8:1:3::1")));
+        assert!(!v6.contains(ip("203.0.113.1")));
+
+        let everything: IpRange = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(ip("198.51.100.1")));
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        for bad in [
+            "",
+            "nope",
+            "203.0.113.0/33",
+            "2001:db8::/129",
+            "203.0.113.0/",
+            "1.2.3.4/-1",
+        ] {
+            assert!(bad.parse::<IpRange>().is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// The board a request is addressed to: the one named by the {board} segment
+// under /b/{board}/..., or the default board for the original routes. Boards
+// are made with `articles add-board` and listed at /boards.
+
+use actix_web::{
+    dev::Payload, error::ErrorInternalServerError, error::InternalError, http::StatusCode, web,
+    Error, FromRequest, HttpRequest, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use tracing::error;
+
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::db::{self, ArticleRepository};
+use crate::templates::{render_html, BoardIndexContext, BoardListItem, MessageContext};
+use crate::DbBoard;
+
+const MAX_SLUG_CHARS: usize = 32;
+
+pub struct Board {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    // Prefix for links within the board: "/b/{slug}", or "" for the default
+    // board so its pages keep their original URLs
+    pub base: String,
+}
+
+impl Board {
+    pub fn new(row: DbBoard, default_slug: &str) -> Self {
+        Board {
+            base: board_base(&row.slug, default_slug),
+            id: row.id,
+            title: row.title,
+            description: row.description,
+        }
+    }
+}
+
+// See Board::base
+pub fn board_base(slug: &str, default_slug: &str) -> String {
+    if slug == default_slug {
+        String::new()
+    } else {
+        format!("/b/{}", slug)
+    }
+}
+
+// Slugs appear in URLs unescaped, so they are limited to lowercase letters,
+// digits and dashes
+pub fn validate_slug(slug: &str) -> Result<(), String> {
+    if slug.is_empty() || slug.len() > MAX_SLUG_CHARS {
+        return Err(format!(
+            "Board slugs must be 1 to {} characters",
+            MAX_SLUG_CHARS
+        ));
+    }
+    if !slug
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    {
+        return Err("Board slugs may only contain lowercase letters, digits and -".to_string());
+    }
+    Ok(())
+}
+
+impl FromRequest for Board {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let repo = req.app_data::<web::Data<dyn ArticleRepository>>().cloned();
+        let config = req.app_data::<web::Data<Config>>().cloned();
+        let slug = req.match_info().get("board").map(str::to_string);
+
+        Box::pin(async move {
+            let (Some(repo), Some(config)) = (repo, config) else {
+                return Err(ErrorInternalServerError("Board lookup is not configured"));
+            };
+            let slug = slug.unwrap_or_else(|| config.default_board.clone());
+
+            match repo.get_board(&slug).await {
+                Ok(row) => Ok(Board::new(row, &config.default_board)),
+                Err(sqlx::Error::RowNotFound) => {
+                    let response = render_html(
+                        StatusCode::NOT_FOUND,
+                        &MessageContext {
+                            title: "Board Not Found",
+                            message: &format!("There is no board called \"{}\".", slug),
+                            link_href: "/boards",
+                            link_text: "View all boards",
+                        },
+                    );
+                    Err(InternalError::from_response("Board not found", response).into())
+                }
+                Err(e) => {
+                    error!(board = %slug, error = %e, "Failed to look up board");
+                    Err(ErrorInternalServerError("Failed to load board"))
+                }
+            }
+        })
+    }
+}
+
+// `articles add-board SLUG TITLE`: create a board
+pub async fn add_board(
+    config: &Config,
+    slug: &str,
+    title: &str,
+    description: &str,
+) -> CommandResult {
+    validate_slug(slug)?;
+    if title.trim().is_empty() {
+        return Err("Board titles must not be empty".into());
+    }
+    let repo = db::connect(&config.database_url).await?;
+    repo.insert_board(slug, title.trim(), description.trim())
+        .await
+        .map_err(|e| format!("Failed to create board {}: {}", slug, e))?;
+
+    println!("Created board {} at /b/{}", slug, slug);
+    Ok(())
+}
+
+// List every board with its article count
+pub async fn board_index(
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let boards = match repo.list_boards().await {
+        Ok(boards) => boards,
+        Err(e) => {
+            error!(error = %e, "Failed to fetch boards");
+            return HttpResponse::InternalServerError().body("Failed to load boards");
+        }
+    };
+
+    render_html(
+        StatusCode::OK,
+        &BoardIndexContext {
+            boards: boards
+                .into_iter()
+                .map(|b| BoardListItem {
+                    href: format!("{}/articles", board_base(&b.slug, &config.default_board)),
+                    title: b.title,
+                    description: b.description,
+                    article_count: b.article_count,
+                })
+                .collect(),
+        },
+    )
+}
@@ -0,0 +1,318 @@
+// Text captchas for the submission and comment forms. Each form render issues
+// a challenge whose answer stays on the server, keyed by a random token that
+// the form carries in a hidden field; GET /captcha?token=... draws the image.
+
+use actix_web::{http::header, web, HttpResponse};
+use image::{ImageFormat, Rgb, RgbImage};
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+const ANSWER_CHARS: usize = 5;
+const CHALLENGE_TTL: Duration = Duration::from_secs(15 * 60);
+// Every page with a form issues a challenge, so unanswered ones are capped
+const MAX_PENDING: usize = 10_000;
+
+// Letters and digits that can't be mistaken for one another (no I/1/L, O/0)
+const ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ2346789";
+
+// Each glyph is 7 rows of 5 pixels, most significant bit on the left
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const SCALE: u32 = 5;
+const IMAGE_WIDTH: u32 = 200;
+const IMAGE_HEIGHT: u32 = 70;
+
+fn glyph(c: u8) -> [u8; 7] {
+    match c {
+        b'A' => [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        b'B' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+        b'C' => [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+        b'D' => [
+            0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110,
+        ],
+        b'E' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+        b'F' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        b'G' => [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+        b'H' => [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        b'J' => [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+        b'K' => [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+        b'M' => [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+        b'N' => [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+        b'P' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        b'Q' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+        b'R' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+        b'S' => [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+        b'T' => [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        b'U' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        b'V' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+        b'W' => [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+        b'X' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+        b'Y' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        b'Z' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+        b'2' => [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+        b'3' => [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+        b'4' => [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+        b'6' => [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+        b'7' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+        b'8' => [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+        b'9' => [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+        _ => [0; 7],
+    }
+}
+
+struct Challenge {
+    answer: String,
+    expires: Instant,
+}
+
+// Outstanding challenges, shared by every worker
+pub struct Captchas {
+    challenges: Mutex<HashMap<String, Challenge>>,
+}
+
+impl Captchas {
+    pub fn new() -> Self {
+        Captchas {
+            challenges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Start a challenge and return its token
+    pub fn issue(&self) -> String {
+        let mut rng = rand::thread_rng();
+        let answer: String = (0..ANSWER_CHARS)
+            .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+            .collect();
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let now = Instant::now();
+
+        let mut challenges = self.challenges.lock().unwrap_or_else(|e| e.into_inner());
+        if challenges.len() >= MAX_PENDING {
+            challenges.retain(|_, c| c.expires > now);
+        }
+        if challenges.len() >= MAX_PENDING {
+            // Still full of live challenges: drop the one closest to expiring
+            let oldest = challenges
+                .iter()
+                .min_by_key(|(_, c)| c.expires)
+                .map(|(t, _)| t.clone());
+            if let Some(oldest) = oldest {
+                challenges.remove(&oldest);
+            }
+        }
+        challenges.insert(
+            token.clone(),
+            Challenge {
+                answer,
+                expires: now + CHALLENGE_TTL,
+            },
+        );
+        token
+    }
+
+    fn answer(&self, token: &str) -> Option<String> {
+        let challenges = self.challenges.lock().unwrap_or_else(|e| e.into_inner());
+        challenges
+            .get(token)
+            .filter(|c| c.expires > Instant::now())
+            .map(|c| c.answer.clone())
+    }
+
+    // Check an answer, ignoring case and surrounding whitespace. A challenge
+    // can only be answered once, right or wrong.
+    pub fn verify(&self, token: &str, answer: &str) -> bool {
+        let mut challenges = self.challenges.lock().unwrap_or_else(|e| e.into_inner());
+        match challenges.remove(token) {
+            Some(c) => c.expires > Instant::now() && c.answer.eq_ignore_ascii_case(answer.trim()),
+            None => false,
+        }
+    }
+}
+
+// Draw the answer as sheared, jittered glyphs over a noisy background
+fn render_png(answer: &str) -> image::ImageResult<Vec<u8>> {
+    let mut rng = rand::thread_rng();
+    let mut img = RgbImage::from_fn(IMAGE_WIDTH, IMAGE_HEIGHT, |_, _| {
+        let shade = rng.gen_range(215..=255);
+        Rgb([shade, shade, rng.gen_range(215..=255)])
+    });
+
+    let cell = IMAGE_WIDTH / answer.len().max(1) as u32;
+    for (i, c) in answer.bytes().enumerate() {
+        let color = Rgb([
+            rng.gen_range(0..90),
+            rng.gen_range(0..90),
+            rng.gen_range(0..120),
+        ]);
+        let shear: f32 = rng.gen_range(-0.35..0.35);
+        let x0 = (i as u32 * cell + rng.gen_range(4..10)) as f32;
+        let y0 = rng.gen_range(4..IMAGE_HEIGHT - GLYPH_HEIGHT * SCALE - 4) as f32;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..SCALE {
+                    let y = y0 + (row as u32 * SCALE + dy) as f32;
+                    let lean = shear * (y - y0 - (GLYPH_HEIGHT * SCALE / 2) as f32);
+                    for dx in 0..SCALE {
+                        let x = x0 + (col * SCALE + dx) as f32 + lean;
+                        if x >= 0.0 && (x as u32) < IMAGE_WIDTH && (y as u32) < IMAGE_HEIGHT {
+                            img.put_pixel(x as u32, y as u32, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Lines across the text make simple segmentation harder
+    for _ in 0..4 {
+        let color = Rgb([
+            rng.gen_range(40..160),
+            rng.gen_range(40..160),
+            rng.gen_range(40..160),
+        ]);
+        let (x1, y1) = (0.0f32, rng.gen_range(0..IMAGE_HEIGHT) as f32);
+        let (x2, y2) = (IMAGE_WIDTH as f32, rng.gen_range(0..IMAGE_HEIGHT) as f32);
+        for step in 0..IMAGE_WIDTH {
+            let t = step as f32 / IMAGE_WIDTH as f32;
+            let (x, y) = (x1 + (x2 - x1) * t, y1 + (y2 - y1) * t);
+            for thickness in 0..2 {
+                let y = y as u32 + thickness;
+                if (x as u32) < IMAGE_WIDTH && y < IMAGE_HEIGHT {
+                    img.put_pixel(x as u32, y, color);
+                }
+            }
+        }
+    }
+
+    let mut encoded = Cursor::new(Vec::new());
+    img.write_to(&mut encoded, ImageFormat::Png)?;
+    Ok(encoded.into_inner())
+}
+
+#[derive(Deserialize)]
+pub struct CaptchaQuery {
+    token: String,
+}
+
+// GET /captcha?token=...
+pub async fn captcha_image(
+    captchas: web::Data<Captchas>,
+    query: web::Query<CaptchaQuery>,
+) -> HttpResponse {
+    let Some(answer) = captchas.answer(&query.token) else {
+        return HttpResponse::NotFound().body("Captcha expired");
+    };
+    match web::block(move || render_png(&answer)).await {
+        Ok(Ok(png)) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(png),
+        Ok(Err(e)) => {
+            error!(error = %e, "Failed to render captcha");
+            HttpResponse::InternalServerError().body("Failed to render captcha")
+        }
+        Err(e) => {
+            error!(error = %e, "Captcha task failed");
+            HttpResponse::InternalServerError().body("Failed to render captcha")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_checked_once_ignoring_case() {
+        let captchas = Captchas::new();
+        let token = captchas.issue();
+        let answer = captchas.answer(&token).unwrap();
+        assert_eq!(answer.len(), ANSWER_CHARS);
+
+        assert!(captchas.verify(&token, &format!(" {} ", answer.to_lowercase())));
+        assert!(!captchas.verify(&token, &answer), "tokens are single use");
+    }
+
+    #[test]
+    fn a_wrong_answer_uses_up_the_challenge() {
+        let captchas = Captchas::new();
+        let token = captchas.issue();
+        let answer = captchas.answer(&token).unwrap();
+        assert!(!captchas.verify(&token, "nope"));
+        assert!(!captchas.verify(&token, &answer));
+        assert!(!captchas.verify("unknown", ""));
+    }
+
+    #[test]
+    fn renders_a_png() {
+        let png = render_png("AB234").unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+    }
+}
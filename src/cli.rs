@@ -0,0 +1,208 @@
+// Command-line interface; running without a subcommand is the same as `serve`
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::webhook::Event;
+
+pub type CommandResult = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(
+    name = "articles",
+    about = "Articles board server and maintenance tasks"
+)]
+pub struct Cli {
+    /// Database connection string, overriding DATABASE_URL
+    #[arg(long, global = true, value_name = "URL")]
+    pub database_url: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server
+    Serve {
+        /// Address to listen on, overriding BIND_ADDR and PORT
+        #[arg(long, value_name = "HOST:PORT", value_parser = parse_bind)]
+        bind: Option<(String, u16)>,
+    },
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Delete the oldest articles beyond the newest N, with their media and
+    /// comments. Sticky articles are never deleted, and don't count toward N
+    Prune {
+        /// Number of articles to keep, sticky ones aside
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(i64).range(0..))]
+        keep: i64,
+    },
+    /// Remove articles and comments deleted more than DELETED_RETENTION_DAYS
+    /// ago, with their media, and errors older than ERROR_RETENTION_DAYS
+    PurgeDeleted,
+    /// Remove upload files that no media row refers to, once older than
+    /// MEDIA_PRUNE_GRACE_MINUTES
+    PruneMedia {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Fill in the size, type and dimensions of media stored before they
+    /// were kept, from the files
+    BackfillMedia,
+    /// Create a new board, served under /b/SLUG
+    AddBoard {
+        /// Lowercase letters, digits and dashes
+        slug: String,
+        /// Heading shown on the board's pages
+        title: String,
+        /// Shown under the title and on the board index
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    /// Add a word filter; posts that match are rejected unless --replace is given
+    AddFilter {
+        /// Word or phrase to match as whole words, ignoring case
+        pattern: String,
+        /// Treat the pattern as a regular expression instead
+        #[arg(long)]
+        regex: bool,
+        /// Rewrite matches to this text rather than rejecting the post
+        #[arg(long, value_name = "TEXT")]
+        replace: Option<String>,
+    },
+    /// Remove a word filter by the id shown by list-filters
+    RemoveFilter { id: i32 },
+    /// List the word filters
+    ListFilters,
+    /// Ban an address or CIDR range (e.g. 2001:db8::/64) from posting
+    Ban {
+        /// IP address or CIDR range
+        target: String,
+        /// Shown to the banned poster
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// How long the ban lasts, e.g. 30m, 12h, 7d or 2w; permanent if omitted
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        duration: Option<i64>,
+    },
+    /// Lift a ban by the id shown by list-bans
+    Unban { id: i32 },
+    /// List bans, including expired ones
+    ListBans,
+    /// Write every board, article and comment to a JSON file, for backups
+    Export {
+        /// File to write
+        #[arg(long, value_name = "PATH")]
+        out: PathBuf,
+        /// Write a tar of the JSON and the uploads directory instead
+        #[arg(long)]
+        include_files: bool,
+    },
+    /// Add the boards, articles and comments from a file written by export
+    Import {
+        /// The JSON file, or the tar written with --include-files
+        path: PathBuf,
+        /// The exported uploads directory, to import media files with a JSON file
+        #[arg(long, value_name = "DIR")]
+        files: Option<PathBuf>,
+        /// Import articles that are already here (same title and time) again
+        #[arg(long)]
+        force: bool,
+        /// Only report what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage the keys that allow writing through the JSON API
+    ApiKey {
+        #[command(subcommand)]
+        command: ApiKeyCommand,
+    },
+    /// Manage the URLs told about new articles and comments
+    Webhook {
+        #[command(subcommand)]
+        command: WebhookCommand,
+    },
+    /// Read a password from stdin and print its hash, for ADMIN_PASSWORD_HASH
+    HashPassword,
+}
+
+#[derive(Subcommand)]
+pub enum ApiKeyCommand {
+    /// Make a key and print it; it can't be shown again
+    Create {
+        /// Who or what the key is for
+        label: String,
+        /// Let the key delete articles and comments too
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Revoke a key by the id shown by list
+    Revoke { id: i32 },
+    /// List the keys, including revoked ones
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum WebhookCommand {
+    /// POST new posts to a URL as JSON
+    Add {
+        url: String,
+        /// Which posts to send, comma separated
+        #[arg(long, value_delimiter = ',', value_parser = parse_event, default_value = "article,comment")]
+        events: Vec<Event>,
+        /// Signs each body; one is made up and printed if omitted
+        #[arg(long)]
+        secret: Option<String>,
+        /// Send it a ping once it's added
+        #[arg(long)]
+        test: bool,
+    },
+    /// Stop sending to a webhook by the id shown by list
+    Remove { id: i32 },
+    /// List the webhooks
+    List,
+    /// Send a ping to a webhook and show how it answered
+    Test { id: i32 },
+    /// List the latest deliveries given up on
+    Failures,
+}
+
+fn parse_event(value: &str) -> Result<Event, String> {
+    Event::parse(value).ok_or_else(|| format!("expected article or comment, got {:?}", value))
+}
+
+fn parse_bind(value: &str) -> Result<(String, u16), String> {
+    let (host, port) = value
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected HOST:PORT, got {:?}", value))?;
+    let port = port
+        .parse()
+        .map_err(|_| format!("invalid port {:?}", port))?;
+    Ok((host.trim_matches(['[', ']']).to_string(), port))
+}
+
+// A number of seconds from "30m", "12h", "7d" or "2w"
+fn parse_duration(value: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "expected a number followed by m, h, d or w, got {:?}",
+            value
+        )
+    };
+    let value = value.trim();
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let seconds = match unit {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let count: i64 = value[..value.len() - 1].parse().map_err(|_| invalid())?;
+    if count <= 0 {
+        return Err(invalid());
+    }
+    count.checked_mul(seconds).ok_or_else(invalid)
+}
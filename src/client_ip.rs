@@ -0,0 +1,57 @@
+// The address a request came from. Behind a reverse proxy every connection
+// comes from the proxy, so TRUSTED_PROXY_HEADER names the header it puts the
+// real client address in; without it only the direct peer is believed.
+
+use actix_web::{dev::Payload, web, Error, FromRequest, HttpRequest};
+use futures_util::future::{ready, Ready};
+use std::net::IpAddr;
+
+use crate::config::Config;
+
+// The client's address, or None when the connection has none (tests, Unix
+// sockets)
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr().map(|a| a.ip());
+    let forwarded = req
+        .app_data::<web::Data<Config>>()
+        .and_then(|config| config.trusted_proxy_header.as_deref())
+        .and_then(|name| req.headers().get(name))
+        .and_then(|value| value.to_str().ok())
+        .and_then(forwarded_ip);
+    // IPv4 clients of a dual-stack listener show up as ::ffff:a.b.c.d
+    forwarded.or(peer).map(|ip| ip.to_canonical())
+}
+
+// Proxies append to X-Forwarded-For, so the last entry is the one our proxy
+// added and anything before it came from the client
+fn forwarded_ip(value: &str) -> Option<IpAddr> {
+    value.rsplit(',').next()?.trim().parse().ok()
+}
+
+// Extractor for handlers that record where a post came from
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequest for ClientIp {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(ClientIp(client_ip(req))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_address_the_proxy_added() {
+        assert_eq!(forwarded_ip("203.0.113.7"), "203.0.113.7".parse().ok());
+        assert_eq!(
+            forwarded_ip("1.1.1.1, 203.0.113.7"),
+            "203.0.113.7".parse().ok()
+        );
+        assert_eq!(forwarded_ip("2001:db8::1"), "2001:db8::1".parse().ok());
+        assert_eq!(forwarded_ip("1.1.1.1, unknown"), None);
+    }
+}
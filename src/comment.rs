@@ -0,0 +1,445 @@
+// Comments: posting them from an article's page, links that find one's page,
+// and deleting them by their poster
+
+use actix_multipart::Multipart;
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use chrono::Utc;
+use futures_util::stream::StreamExt as _;
+use sanitize_filename::sanitize;
+use std::time::Instant;
+use tracing::error;
+
+use crate::article::{article_on_board, article_page, soft_delete, ArticlePage};
+use crate::board::Board;
+use crate::captcha::Captchas;
+use crate::client_ip::ClientIp;
+use crate::config::Config;
+use crate::csrf::CsrfToken;
+use crate::db::{ArticleRepository, NewCommentRow};
+use crate::duplicate::{RecentPosts, Seen};
+use crate::error::AppError;
+use crate::notify::Notifier;
+use crate::page_cache::PageCache;
+use crate::password;
+use crate::post::poster_name;
+use crate::slug::article_path;
+use crate::spam::{self, passes_spam_checks, spam_rejected};
+use crate::storage::MediaStorage;
+use crate::templates::{render_html, MessageContext};
+use crate::tripcode;
+use crate::upload::{
+    attach_media, read_text_field, remove_media_files, save_media, upload_rejected, MAX_FIELD_BYTES,
+};
+use crate::webhook::Webhooks;
+use crate::word_filter::{apply_word_filters, post_blocked, WordFilters};
+use crate::{ArticlePath, CommentPath, DeleteForm, Media, CAPTCHA_ERROR, COMMENTS_PER_PAGE};
+
+// The text fields of the comment form, read from the multipart body by
+// submit_comment
+#[derive(Default)]
+pub struct CommentForm {
+    pub comment: String,
+    pub password: String,
+    pub captcha_token: String,
+    pub captcha_answer: String,
+    // See spam.rs
+    pub website: String,
+    pub form_token: String,
+    // Set by the reply links, see thread.rs
+    pub parent_comment_id: Option<i32>,
+    // The "don't bump" checkbox, sent only when ticked
+    pub sage: bool,
+    pub name: String,
+}
+
+// Which page the live comment at `position` is on
+pub fn comment_page(position: usize) -> i64 {
+    position as i64 / COMMENTS_PER_PAGE + 1
+}
+
+// GET /articles/{article_id}/comments/{comment_id}: a link to a comment that
+// stays good as later comments push it onto earlier pages
+pub async fn view_comment(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    path: web::Path<CommentPath>,
+) -> HttpResponse {
+    let CommentPath {
+        article_id,
+        comment_id,
+    } = path.into_inner();
+    let found = match repo.get_article(article_id).await {
+        Ok(article) if article.board_id == board.id => {
+            repo.live_comment_ids(article_id).await.map(|ids| {
+                ids.iter()
+                    .position(|&id| id == comment_id)
+                    .map(|position| (article, position))
+            })
+        }
+        Ok(_) | Err(sqlx::Error::RowNotFound) => Ok(None),
+        Err(e) => Err(e),
+    };
+    match found {
+        Ok(Some((article, position))) => HttpResponse::Found()
+            .append_header((
+                "Location",
+                format!(
+                    "{}{}?comments_page={}#c{}",
+                    board.base,
+                    article_path(article.id, article.slug.as_deref()),
+                    comment_page(position),
+                    comment_id
+                ),
+            ))
+            .finish(),
+        Ok(None) => AppError::NotFound.error_response(),
+        Err(e) => {
+            error!(article_id, comment_id, error = %e, "Failed to look up comment");
+            HttpResponse::InternalServerError().body("Failed to load comment")
+        }
+    }
+}
+
+// Submit comment
+#[allow(clippy::too_many_arguments)]
+pub async fn submit_comment(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    storage: web::Data<dyn MediaStorage>,
+    config: web::Data<Config>,
+    captchas: web::Data<Captchas>,
+    recent_posts: web::Data<RecentPosts>,
+    word_filters: web::Data<WordFilters>,
+    page_cache: web::Data<PageCache>,
+    webhooks: web::Data<Webhooks>,
+    notifier: web::Data<Notifier>,
+    csrf: CsrfToken,
+    client_ip: ClientIp,
+    path: web::Path<ArticlePath>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, AppError> {
+    let article_id = path.id;
+    let mut form = CommentForm::default();
+    let mut media: Vec<Media> = Vec::new();
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+        // Form fields always have a name; a part without one is skipped
+        let Some(content_disposition) = field.content_disposition() else {
+            continue;
+        };
+        let Some(field_name) = content_disposition.get_name() else {
+            continue;
+        };
+
+        if field_name == "comment" {
+            form.comment = read_text_field(&mut field, config.max_comment_chars * 4).await?;
+        } else if field_name == "name" {
+            form.name = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "password" {
+            form.password = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "parent_comment_id" {
+            form.parent_comment_id = read_text_field(&mut field, MAX_FIELD_BYTES)
+                .await?
+                .trim()
+                .parse()
+                .ok();
+        } else if field_name == "sage" {
+            form.sage = true;
+        } else if field_name == "captcha_token" {
+            form.captcha_token = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "captcha_answer" {
+            form.captcha_answer = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == spam::HONEYPOT_FIELD {
+            form.website = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "form_token" {
+            form.form_token = read_text_field(&mut field, MAX_FIELD_BYTES).await?;
+        } else if field_name == "media" {
+            if let Some(filename) = content_disposition.get_filename().filter(|f| !f.is_empty()) {
+                let original_name = sanitize(filename);
+                match save_media(&mut field, original_name, storage.get_ref(), &config).await {
+                    Ok(item) => media.push(item),
+                    Err(e) => {
+                        remove_media_files(
+                            storage.get_ref(),
+                            media.iter().flat_map(Media::file_paths),
+                        )
+                        .await;
+                        return Err(upload_rejected(e, &config));
+                    }
+                }
+            }
+        }
+    }
+
+    let stored = store_comment(
+        &board,
+        repo.get_ref(),
+        &config,
+        &captchas,
+        &recent_posts,
+        &word_filters,
+        &csrf,
+        &client_ip,
+        article_id,
+        form,
+        &media,
+    )
+    .await;
+    let comment_id = match stored {
+        Ok(comment_id) => comment_id,
+        Err(response) => {
+            remove_media_files(storage.get_ref(), media.iter().flat_map(Media::file_paths)).await;
+            return Ok(response);
+        }
+    };
+    let attached = attach_media(&repo, &storage, &config, article_id, comment_id, media).await;
+    // The comment is in either way
+    page_cache.invalidate_article(article_id);
+    webhooks.comment_created(article_id, comment_id);
+    notifier.comment_created(&board.base, article_id, comment_id);
+    attached?;
+
+    // Straight to the new comment
+    Ok(HttpResponse::Found()
+        .append_header((
+            "Location",
+            format!("{}/articles/{}#c{}", board.base, article_id, comment_id),
+        ))
+        .finish())
+}
+
+// Check and store a comment read by submit_comment, returning its id. An Err
+// is the response to send instead (a message page, the article with the
+// comment sent back, an AppError's, or for a comment sent twice a redirect to
+// the first); the caller removes any uploaded files.
+#[allow(clippy::too_many_arguments)]
+async fn store_comment(
+    board: &Board,
+    repo: &dyn ArticleRepository,
+    config: &Config,
+    captchas: &Captchas,
+    recent_posts: &RecentPosts,
+    word_filters: &WordFilters,
+    csrf: &CsrfToken,
+    client_ip: &ClientIp,
+    article_id: i32,
+    mut form: CommentForm,
+    media: &[Media],
+) -> Result<i32, HttpResponse> {
+    if !passes_spam_checks(config, &form.website, &form.form_token) {
+        return Err(spam_rejected(&format!(
+            "{}/articles/{}",
+            board.base, article_id
+        )));
+    }
+
+    // A failed captcha shows the article again with the comment refilled
+    if config.captcha_enabled && !captchas.verify(&form.captcha_token, &form.captcha_answer) {
+        return Err(comment_sent_back(
+            board,
+            repo,
+            config,
+            captchas,
+            csrf,
+            article_id,
+            &form,
+            CAPTCHA_ERROR,
+        )
+        .await);
+    }
+
+    let back = format!("{}/articles/{}", board.base, article_id);
+    form.comment = form.comment.trim().to_string();
+    if form.comment.is_empty() && media.is_empty() {
+        return Err(render_html(
+            StatusCode::BAD_REQUEST,
+            &MessageContext {
+                title: "Empty Comment",
+                message: "A comment needs some text or an attached file.",
+                link_href: &back,
+                link_text: "Back to the article",
+            },
+        ));
+    }
+    // Too long a comment is sent back too, so it can be cut down
+    let chars = form.comment.chars().count();
+    if chars > config.max_comment_chars {
+        let error = format!(
+            "Comments are limited to {} characters, and this one has {}.",
+            config.max_comment_chars, chars
+        );
+        return Err(comment_sent_back(
+            board, repo, config, captchas, csrf, article_id, &form, &error,
+        )
+        .await);
+    }
+
+    let (name, tripcode) = tripcode::parse(&form.name, &config.secret_key);
+    let mut name = name.to_string();
+    if apply_word_filters(word_filters, &mut [&mut form.comment, &mut name]).is_err() {
+        return Err(post_blocked(&back));
+    }
+    let name = poster_name(&name).map_err(|message| {
+        AppError::Validation {
+            field: "name",
+            message: format!("Name {}", message),
+        }
+        .error_response()
+    })?;
+
+    let article = repo
+        .get_article(article_id)
+        .await
+        .map_err(|e| AppError::from(e).error_response())?;
+    if article.board_id != board.id {
+        return Err(AppError::NotFound.error_response());
+    }
+    if article.archived_at.is_some() {
+        return Err(AppError::Forbidden(
+            "This article is archived and no longer takes comments.".to_string(),
+        )
+        .error_response());
+    }
+
+    // Replies must answer a live comment on the same article
+    if let Some(parent_id) = form.parent_comment_id {
+        let exists = repo
+            .comment_exists(article_id, parent_id)
+            .await
+            .map_err(|e| AppError::from(e).error_response())?;
+        if !exists {
+            return Err(AppError::Validation {
+                field: "parent_comment_id",
+                message: "The comment you replied to doesn't exist".to_string(),
+            }
+            .error_response());
+        }
+    }
+
+    let password_hash = if form.password.is_empty() {
+        None
+    } else {
+        Some(
+            password::hash_password(&form.password)
+                .map_err(|e| AppError::from(e).error_response())?,
+        )
+    };
+
+    // The same comment sent again goes to the first one, without bumping the
+    // article a second time
+    let media_names: Vec<_> = media.iter().map(|m| m.original_name.as_deref()).collect();
+    let key = (
+        article_id,
+        form.parent_comment_id,
+        &form.comment,
+        &media_names,
+    );
+    let claim = match recent_posts.claim(client_ip.0, key, Instant::now()) {
+        Seen::New(claim) => claim,
+        Seen::Posted(comment_id) => {
+            let location = format!("{}#c{}", back, comment_id);
+            return Err(HttpResponse::Found()
+                .append_header(("Location", location))
+                .finish());
+        }
+        Seen::Pending => {
+            return Err(HttpResponse::Found()
+                .append_header(("Location", back))
+                .finish())
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let comment_id = repo
+        .insert_comment(NewCommentRow {
+            article_id,
+            comment: &form.comment,
+            name,
+            tripcode: tripcode.as_deref(),
+            delete_password_hash: password_hash.as_deref(),
+            poster_ip: client_ip.0.map(|ip| ip.to_string()).as_deref(),
+            created_at: now,
+            parent_comment_id: form.parent_comment_id,
+            // Sage: the comment is posted without moving the article up the list
+            bump_to: if form.sage { None } else { Some(now) },
+            bump_limit: config.bump_limit,
+        })
+        .await
+        .map_err(|e| AppError::from(e).error_response())?;
+    claim.posted(comment_id);
+    Ok(comment_id)
+}
+
+// The article page again, with `form` refilled in the comment box and
+// `error` above it
+#[allow(clippy::too_many_arguments)]
+async fn comment_sent_back(
+    board: &Board,
+    repo: &dyn ArticleRepository,
+    config: &Config,
+    captchas: &Captchas,
+    csrf: &CsrfToken,
+    article_id: i32,
+    form: &CommentForm,
+    error: &str,
+) -> HttpResponse {
+    let page = match repo.get_article_with_media(article_id).await {
+        Ok(article) if article.board_id == board.id => {
+            let page = ArticlePage {
+                reply_to: form.parent_comment_id,
+                comments_page: None,
+                sent_back: Some((form, error)),
+                admin: false,
+                reuse: None,
+            };
+            article_page(board, repo, config, captchas, csrf, &article, page).await
+        }
+        Ok(_) => Err(AppError::NotFound),
+        Err(e) => Err(e.into()),
+    };
+    page.unwrap_or_else(|e| e.error_response())
+}
+
+// Delete a comment whose poster supplied the matching deletion password
+pub async fn delete_comment(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<CommentPath>,
+    form: web::Form<DeleteForm>,
+) -> HttpResponse {
+    let CommentPath {
+        article_id,
+        comment_id,
+    } = path.into_inner();
+
+    let stored_hash = match article_on_board(repo.get_ref(), &board, article_id).await {
+        Ok(true) => repo.comment_password_hash(article_id, comment_id).await,
+        Ok(false) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let stored_hash = match stored_hash {
+        Ok(hash) => hash.flatten(),
+        Err(e) => {
+            error!(article_id, comment_id, error = %e, "Failed to look up comment");
+            return HttpResponse::InternalServerError().body("Failed to delete comment.");
+        }
+    };
+
+    if !password::verify_password(&form.password, stored_hash.as_deref()) {
+        return HttpResponse::Forbidden().body("Incorrect password.");
+    }
+
+    if let Err(e) = soft_delete(repo.get_ref(), &page_cache, article_id, Some(comment_id)).await {
+        error!(article_id, comment_id, error = %e, "Failed to delete comment");
+        return HttpResponse::InternalServerError().body("Failed to delete comment.");
+    }
+
+    HttpResponse::Found()
+        .append_header((
+            "Location",
+            format!("{}/articles/{}", board.base, article_id),
+        ))
+        .finish()
+}
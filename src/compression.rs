@@ -0,0 +1,75 @@
+// Responses are compressed (brotli, gzip or zstd, whichever the client
+// prefers) by actix's Compress, which leaves images other than SVG and videos
+// alone, and audio is marked so it's left alone too: uploads are compressed
+// already. A compressed body is no longer the bytes a strong ETag names, so
+// those become weak ETags. If-None-Match compares weakly, so revalidating
+// still gets a 304.
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, ContentEncoding, HeaderValue},
+    middleware::Next,
+    Error,
+};
+
+// Middleware, wrapped around Compress
+pub async fn weaken_etags(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+    let compressed = headers
+        .get(header::CONTENT_ENCODING)
+        .is_some_and(|encoding| encoding != ContentEncoding::Identity.as_str());
+    if compressed {
+        if let Some(weak) = headers
+            .get(header::ETAG)
+            .and_then(|etag| weaken(etag.as_bytes()))
+        {
+            headers.insert(header::ETAG, weak);
+        }
+    }
+    Ok(res)
+}
+
+// Middleware, wrapped inside Compress, which would compress audio. A
+// response that names its encoding already is sent as it is.
+pub async fn leave_audio_alone(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+    let audio = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("audio/"));
+    if audio && !headers.contains_key(header::CONTENT_ENCODING) {
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(ContentEncoding::Identity.as_str()),
+        );
+    }
+    Ok(res)
+}
+
+// W/"tag" for a strong "tag"; weak ones are left as they are
+fn weaken(etag: &[u8]) -> Option<HeaderValue> {
+    if etag.starts_with(b"W/") {
+        return None;
+    }
+    HeaderValue::from_bytes(&[b"W/", etag].concat()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_strong_etags_are_weakened() {
+        assert_eq!(weaken(b"\"abc\"").unwrap(), "W/\"abc\"");
+        assert_eq!(weaken(b"W/\"abc\""), None);
+    }
+}
@@ -0,0 +1,159 @@
+// Conditional GETs for the article list and article pages. A page's ETag
+// hashes what it's made from, the visitor's own parts (CSRF token, admin
+// controls) included, and says when the page was rendered; Last-Modified is
+// when its articles or comments last changed. A client revalidating its copy
+// gets an empty 304 when neither moved on, but only for REUSE_SECS after the
+// copy was rendered.
+
+use actix_web::http::header::{
+    CacheControl, CacheDirective, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+    CACHE_CONTROL, ETAG, LAST_MODIFIED,
+};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How long a copy of a page is reused at most: its form token and captcha
+// run out (see spam.rs and captcha.rs), and what's left out of its ETag, such
+// as related articles and poster frames, catches up
+const REUSE_SECS: i64 = 5 * 60;
+
+pub struct Validator {
+    hash: u64,
+    last_modified: i64,
+}
+
+impl Validator {
+    pub fn new(parts: impl Hash, last_modified: i64) -> Self {
+        let mut hasher = DefaultHasher::new();
+        parts.hash(&mut hasher);
+        Validator {
+            hash: hasher.finish(),
+            last_modified,
+        }
+    }
+
+    // The same for a page that also shows `parts` that are the visitor's own
+    pub fn with(&self, parts: impl Hash) -> Validator {
+        Validator::new((self.hash, parts), self.last_modified)
+    }
+
+    // An empty 304 when the client's copy is still good. If-Modified-Since
+    // only counts without If-None-Match; the copy was rendered after the
+    // date it sends, which keeps the same limit on its age.
+    pub fn not_modified(&self, req: &HttpRequest, now: i64) -> Option<HttpResponse> {
+        let reusable = |rendered_at: i64| (0..=REUSE_SECS).contains(&(now - rendered_at));
+        let etag = match req.get_header::<IfNoneMatch>() {
+            Some(IfNoneMatch::Items(etags)) => etags
+                .into_iter()
+                .find(|etag| self.rendered_at(etag).is_some_and(reusable))?,
+            Some(IfNoneMatch::Any) => return None,
+            None => {
+                let since = unix_time(req.get_header::<IfModifiedSince>()?.0)?;
+                if since < self.last_modified || !reusable(since) {
+                    return None;
+                }
+                self.etag(since)
+            }
+        };
+        Some(
+            HttpResponse::NotModified()
+                .insert_header((ETAG, etag))
+                .insert_header(self.last_modified())
+                .insert_header(cache_control())
+                .finish(),
+        )
+    }
+
+    // The validators for a page rendered `now`
+    pub fn apply(&self, response: &mut HttpResponse, now: i64) {
+        let headers = [
+            (ETAG, self.etag(now).to_string()),
+            (LAST_MODIFIED, self.last_modified().to_string()),
+            (CACHE_CONTROL, cache_control().to_string()),
+        ];
+        for (name, value) in headers {
+            if let Ok(value) = value.parse() {
+                response.headers_mut().insert(name, value);
+            }
+        }
+    }
+
+    fn etag(&self, rendered_at: i64) -> EntityTag {
+        EntityTag::new_strong(format!("{:016x}.{}", self.hash, rendered_at))
+    }
+
+    // When the page an ETag names was rendered, if it's this page. Weak
+    // ETags count: Compress weakens them (see compression.rs).
+    fn rendered_at(&self, etag: &EntityTag) -> Option<i64> {
+        let (hash, rendered_at) = etag.tag().split_once('.')?;
+        if u64::from_str_radix(hash, 16).ok()? != self.hash {
+            return None;
+        }
+        rendered_at.parse().ok()
+    }
+
+    fn last_modified(&self) -> LastModified {
+        LastModified(HttpDate::from(
+            UNIX_EPOCH + Duration::from_secs(self.last_modified.max(0) as u64),
+        ))
+    }
+}
+
+// Browsers may keep a page but must ask before showing it again; it holds the
+// visitor's CSRF token, so shared caches keep none
+fn cache_control() -> CacheControl {
+    CacheControl(vec![CacheDirective::Private, CacheDirective::NoCache])
+}
+
+fn unix_time(date: HttpDate) -> Option<i64> {
+    SystemTime::from(date)
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn copies_are_reused_while_fresh_and_unchanged() {
+        let validator = Validator::new((1, 2), 1_000);
+        let etag = validator.etag(2_000).to_string();
+        let weak = format!("W/{}", etag);
+        let revalidate = |etag: &str| {
+            TestRequest::get()
+                .insert_header(("If-None-Match", etag))
+                .to_http_request()
+        };
+
+        assert!(validator
+            .not_modified(&revalidate(&etag), 2_000 + REUSE_SECS)
+            .is_some());
+        assert!(validator.not_modified(&revalidate(&weak), 2_010).is_some());
+        assert!(validator
+            .not_modified(&revalidate(&etag), 2_001 + REUSE_SECS)
+            .is_none());
+        let changed = Validator::new((1, 3), 1_000);
+        assert!(changed.not_modified(&revalidate(&etag), 2_010).is_none());
+    }
+
+    #[test]
+    fn if_modified_since_needs_a_recent_unchanged_date() {
+        let validator = Validator::new(1, 1_000);
+        let since = |secs: i64| {
+            let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs as u64));
+            TestRequest::get()
+                .insert_header(IfModifiedSince(date))
+                .to_http_request()
+        };
+
+        assert!(validator.not_modified(&since(1_000), 1_010).is_some());
+        assert!(validator.not_modified(&since(999), 1_010).is_none());
+        assert!(validator
+            .not_modified(&since(1_000), 1_001 + REUSE_SECS)
+            .is_none());
+    }
+}
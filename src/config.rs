@@ -0,0 +1,715 @@
+// Runtime configuration, read once from the environment at startup
+
+use argon2::password_hash::PasswordHash;
+use chrono::{FixedOffset, Offset, Utc};
+use rand::RngCore;
+use std::env;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::board::validate_slug;
+use crate::highlight::{parse_languages, DEFAULT_LANGUAGES};
+use crate::notify;
+use crate::render::parse_timezone;
+
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 20 * 1024 * 1024;
+const DEFAULT_FEED_ITEMS: i64 = 20;
+// Static files, the icon and robots.txt, and the load balancer's probes,
+// which would drown out the rest
+const DEFAULT_ACCESS_LOG_EXCLUDE: &str =
+    "/static/,/favicon.ico,/robots.txt,/healthz,/readyz,/metrics";
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    // BIND_ADDR / PORT: where the HTTP server listens. BIND_ADDR takes IP
+    // addresses or host names, comma separated, all on the one port; PORT=0
+    // has the system pick a free port for each. BIND_ADDR=none listens on no
+    // TCP port, which needs LISTEN_UDS.
+    pub bind_addrs: Vec<String>,
+    pub port: u16,
+    // LISTEN_UDS: a Unix socket to listen on as well as the TCP addresses (or
+    // instead, with BIND_ADDR=none), e.g. for nginx on the same machine. A
+    // socket left at the path by an earlier run is replaced.
+    pub listen_uds: Option<PathBuf>,
+    // LISTEN_UDS_MODE: permissions the socket is given, in octal
+    pub listen_uds_mode: u32,
+    // TLS_CERT_PATH / TLS_KEY_PATH: serve HTTPS on BIND_ADDR:PORT with this
+    // PEM certificate chain and private key; set both or neither. The Unix
+    // socket stays plain.
+    pub tls: Option<TlsConfig>,
+    // TLS_REDIRECT_PORT: with TLS, also listen for plain HTTP on this port of
+    // each BIND_ADDR, redirecting every request to PUBLIC_URL
+    pub tls_redirect_port: Option<u16>,
+    // WORKERS: threads serving requests; 0 starts one per CPU core
+    pub workers: usize,
+    // PUBLIC_URL: scheme and host the site is reached at, used for absolute
+    // links such as those in feeds; defaults to the first bind address
+    pub public_url: String,
+    // DATABASE_URL (required)
+    pub database_url: String,
+    // UPLOADS_DIR: media files live here and are served under /uploads when
+    // STORAGE_BACKEND is local (the default)
+    pub uploads_dir: PathBuf,
+    // STORAGE_BACKEND=local|s3, plus the S3_* settings for s3
+    pub storage: StorageConfig,
+    // DEFAULT_BOARD: slug of the board served at the original routes (/,
+    // /articles, ...); every other board lives under /b/{slug}
+    pub default_board: String,
+    // OG_DEFAULT_IMAGE: og:image for article pages without an image (e.g.
+    // video-only ones); a path or absolute URL. Unset omits og:image there.
+    pub og_default_image: Option<String>,
+    // FEED_ITEMS: number of articles in the RSS and Atom feeds
+    pub feed_items: i64,
+    // TRENDING_CACHE_SECONDS: how long a board's /trending ranking is reused
+    // before it's worked out again; 0 works it out on every request
+    pub trending_cache_secs: u64,
+    // PAGE_CACHE_SECONDS: how long a rendered article list or article page is
+    // reused at most (writes drop it sooner); 0 renders every request
+    pub page_cache_secs: u64,
+    // PAGE_CACHE_ENTRIES: most rendered pages kept at once
+    pub page_cache_entries: usize,
+    // API_KEY_CACHE_SECONDS: how long the API keys not revoked are reused
+    // before they're loaded again, and so how long a revoked key still works
+    pub api_key_cache_secs: u64,
+    // WEBHOOK_ATTEMPTS: how many times a webhook delivery is tried, waiting
+    // twice as long after each failure, before it's given up on
+    pub webhook_attempts: u32,
+    // WEBHOOK_TIMEOUT_SECONDS: how long each try may take
+    pub webhook_timeout_secs: u64,
+    // NOTIFY_WEBHOOK_URL: a Discord or Slack webhook told about new articles,
+    // see notify.rs. NOTIFY_FORMAT=auto|discord|slack picks the shape of the
+    // message (auto goes by the URL's host) and NOTIFY_COMMENTS=true sends
+    // new comments as well.
+    pub notify: Option<NotifyConfig>,
+    // MAX_UPLOAD_BYTES: cap on each uploaded media file
+    pub max_upload_bytes: u64,
+    // MAX_AUDIO_UPLOAD_BYTES: cap on each mp3, ogg or flac file, by default
+    // the same as MAX_UPLOAD_BYTES
+    pub max_audio_upload_bytes: u64,
+    // MAX_IMAGE_PIXELS, MAX_IMAGE_WIDTH / MAX_IMAGE_HEIGHT: largest image
+    // taken, by its header, see media::ImageLimits
+    pub max_image_pixels: u64,
+    pub max_image_width: u32,
+    pub max_image_height: u32,
+    // MAX_GIF_FRAMES: most frames an uploaded GIF may have
+    pub max_gif_frames: usize,
+    // STRIP_EXIF: store JPEG, PNG and WebP uploads without their EXIF, XMP
+    // and text metadata, see media::strip_metadata
+    pub strip_exif: bool,
+    // WEBP_CONVERT: store JPEG and PNG uploads of at least WEBP_MIN_BYTES as
+    // (lossless) WebPs, where that makes them smaller
+    pub webp_convert: bool,
+    pub webp_min_bytes: u64,
+    // UPLOADS_QUOTA_BYTES: once the files under UPLOADS_DIR take this much,
+    // posts with uploads are refused; 0 for no quota
+    pub uploads_quota_bytes: u64,
+    // MAX_TITLE_CHARS / MAX_BODY_CHARS: longest article title (not counting
+    // spaces around it) and body taken from the form or the API
+    pub max_title_chars: usize,
+    pub max_body_chars: usize,
+    // MAX_COMMENT_CHARS: longest comment taken, not counting spaces around it
+    pub max_comment_chars: usize,
+    // RATE_LIMIT_ARTICLES / RATE_LIMIT_COMMENTS: posts allowed per minute
+    // from one IP address; 0 turns the limit off
+    pub article_rate_limit: u32,
+    pub comment_rate_limit: u32,
+    // RATE_LIMIT_REPORTS: reports allowed per minute from one IP address
+    pub report_rate_limit: u32,
+    // DUPLICATE_WINDOW_SECONDS: how long a post is remembered, so the same
+    // one sent again from the same address isn't stored twice; 0 turns it off
+    pub duplicate_window_secs: u64,
+    // RATE_LIMIT_EXEMPT_LOCALHOST: don't limit loopback addresses (for testing)
+    pub rate_limit_exempt_localhost: bool,
+    // TRUSTED_PROXY_HEADER: header a reverse proxy puts the client address in,
+    // e.g. X-Forwarded-For or X-Real-IP. Only set it when every request
+    // arrives through that proxy, or clients can claim any address.
+    pub trusted_proxy_header: Option<String>,
+    // SECRET_KEY: signs the tokens rendered into forms. Without it a random
+    // key is made at startup, so forms opened before a restart stop working.
+    pub secret_key: Vec<u8>,
+    // FORM_MIN_FILL_SECONDS: posts sent sooner than this after the form was
+    // rendered are taken to be from bots
+    pub form_min_fill_secs: i64,
+    // WORD_FILTER_REFRESH_SECONDS: how often the server reloads word filters,
+    // picking up ones added or removed with the CLI
+    pub word_filter_refresh_secs: u64,
+    // CAPTCHA_ENABLED: require a captcha on the submission and comment forms;
+    // turn off for local development
+    pub captcha_enabled: bool,
+    // MAX_ARTICLES: articles a board keeps; past it, posting a new one deletes
+    // the lowest-bumped for good. 0 keeps every article.
+    pub max_articles: i64,
+    // ARCHIVE_AFTER: articles beyond this many on a board, in bump order, are
+    // archived when a new one is posted; 0 never archives
+    pub archive_after: i64,
+    // BUMP_LIMIT: comments after which new ones stop bumping an article;
+    // 0 lets articles be bumped forever
+    pub bump_limit: i64,
+    // DELETED_RETENTION_DAYS: how long deleted articles and comments can be
+    // restored before purge-deleted removes them and their media
+    pub deleted_retention_days: i64,
+    // MEDIA_PRUNE_INTERVAL_HOURS: how often serve removes upload files no
+    // media row refers to, as prune-media does; 0 leaves it to the command
+    pub media_prune_interval_hours: u64,
+    // MEDIA_PRUNE_GRACE_MINUTES: how old such a file must be before it's
+    // removed, so uploads whose post is still being saved are left alone
+    pub media_prune_grace_minutes: u64,
+    // ADMIN_PASSWORD_HASH: Argon2 PHC string of the admin password, as printed
+    // by `articles hash-password`; unset disables admin login
+    pub admin_password_hash: Option<String>,
+    // FFMPEG_PATH: ffmpeg binary used for video poster frames; unset disables
+    // them. The ffprobe beside it checks uploaded videos.
+    pub ffmpeg_path: Option<PathBuf>,
+    // MAX_VIDEO_SECONDS: longest video taken, when ffprobe can tell; 0 for no limit
+    pub max_video_seconds: u64,
+    // MAX_LINKS_PER_POST: bare URLs linked in an article or comment; the rest
+    // stay text. 0 links them all.
+    pub max_links_per_post: usize,
+    // MARKDOWN_IMAGES: show images embedded in article Markdown; off by
+    // default, so pages don't load files from other sites and they show as
+    // links instead
+    pub markdown_images: bool,
+    // HIGHLIGHT_LANGUAGES: languages whose code blocks are syntax highlighted,
+    // comma separated; see highlight::parse_languages. "none" turns it off.
+    pub highlight_languages: Vec<String>,
+    // DISPLAY_TIMEZONE: what times on pages are shown in, "UTC" or a fixed
+    // offset such as "+02:00"; see render::parse_timezone
+    pub display_timezone: FixedOffset,
+    // LOG_FORMAT=pretty|json: how log lines are written to stderr; RUST_LOG
+    // picks which ones are
+    pub log_format: LogFormat,
+    // ACCESS_LOG_EXCLUDE: path prefixes whose requests aren't access logged,
+    // comma separated; "none" logs every request
+    pub access_log_exclude: Vec<String>,
+    // CORS_ALLOWED_ORIGINS: origins such as https://app.example.com, comma
+    // separated, whose pages may call /api from the browser; "*" allows any.
+    // Unset sends no CORS headers, and the HTML pages never do.
+    pub cors_allowed_origins: Vec<String>,
+    // API_DOCS: serve a Swagger UI for the spec at /api/docs. It loads its
+    // scripts from unpkg.com, so it's off by default; /api/openapi.json is
+    // always served.
+    pub api_docs: bool,
+    // ROBOTS_DISALLOW: path prefixes robots.txt asks crawlers to stay out of,
+    // comma separated, such as /admin,/uploads; "none" (the default) allows
+    // everything
+    pub robots_disallow: Vec<String>,
+    // ERROR_LOG_PATH: file errors are also appended to; off by default, as
+    // the server records them in the app_errors table (see app_errors.rs)
+    pub error_log_path: Option<PathBuf>,
+    // ERROR_LOG_ROTATION=never|hourly|daily: how often that file is started
+    // afresh, with the hour or day appended to its name
+    pub error_log_rotation: LogRotation,
+    // ERROR_RETENTION_DAYS: how long recorded errors are kept before
+    // purge-deleted removes them
+    pub error_retention_days: i64,
+    // SHUTDOWN_TIMEOUT_SECONDS: how long requests in flight (such as uploads)
+    // get to finish after SIGTERM or SIGINT before they're cut off
+    pub shutdown_timeout_secs: u64,
+    // SKIP_MIGRATIONS: don't apply pending migrations when the server starts
+    pub skip_migrations: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct NotifyConfig {
+    pub url: String,
+    pub format: NotifyFormat,
+    pub comments: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotifyFormat {
+    Discord,
+    Slack,
+}
+
+#[derive(Clone, Debug)]
+pub enum StorageConfig {
+    Local,
+    S3(S3Config),
+}
+
+#[derive(Clone, Debug)]
+pub struct S3Config {
+    // S3_BUCKET, S3_ACCESS_KEY, S3_SECRET_KEY (required)
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    // S3_REGION, "us-east-1" by default
+    pub region: String,
+    // S3_ENDPOINT: set for S3-compatible services (MinIO, R2, ...); switches
+    // to path-style bucket addressing
+    pub endpoint: Option<String>,
+    // S3_PUBLIC_URL: base URL objects are served from, e.g. a CDN; defaults to
+    // the bucket's own URL
+    pub public_url: Option<String>,
+}
+
+// Every problem found while loading the configuration, reported together
+#[derive(Debug)]
+pub struct ConfigError {
+    pub missing: Vec<&'static str>,
+    pub invalid: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for name in &self.missing {
+            writeln!(f, "  {} must be set in the environment", name)?;
+        }
+        for problem in &self.invalid {
+            writeln!(f, "  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Values given on the command line, which take precedence over the environment
+#[derive(Default)]
+pub struct Overrides {
+    pub database_url: Option<String>,
+    pub bind: Option<(String, u16)>,
+}
+
+impl Config {
+    pub fn load(overrides: Overrides) -> Result<Self, ConfigError> {
+        let mut errors = ConfigError {
+            missing: Vec::new(),
+            invalid: Vec::new(),
+        };
+
+        let database_url = match overrides.database_url {
+            Some(url) => url,
+            None => required("DATABASE_URL", &mut errors),
+        };
+        let (bind_addrs, port) = match overrides.bind {
+            Some((host, port)) => (vec![host], port),
+            None => (
+                bind_addrs(&string_or("BIND_ADDR", "127.0.0.1")),
+                parsed_or("PORT", 8080, &mut errors),
+            ),
+        };
+        let listen_uds = optional("LISTEN_UDS").map(PathBuf::from);
+        match bind_addrs.iter().find(|addr| !valid_bind_addr(addr)) {
+            Some(addr) => errors
+                .invalid
+                .push(format!("BIND_ADDR has an invalid address {:?}", addr)),
+            None if bind_addrs.is_empty() && listen_uds.is_none() => errors
+                .invalid
+                .push("BIND_ADDR names no address, and LISTEN_UDS isn't set".to_string()),
+            None => {}
+        }
+        let tls = tls_config(&mut errors);
+        let tls_redirect_port = match optional("TLS_REDIRECT_PORT") {
+            Some(_) if tls.is_none() => {
+                errors
+                    .invalid
+                    .push("TLS_REDIRECT_PORT needs TLS_CERT_PATH and TLS_KEY_PATH".to_string());
+                None
+            }
+            Some(_) => Some(parsed_or("TLS_REDIRECT_PORT", 80, &mut errors)),
+            None => None,
+        };
+        let public_url = string_or(
+            "PUBLIC_URL",
+            &format!(
+                "{}://{}:{}",
+                if tls.is_some() { "https" } else { "http" },
+                bind_addrs.first().map_or("127.0.0.1", String::as_str),
+                port
+            ),
+        )
+        .trim_end_matches('/')
+        .to_string();
+        let max_upload_bytes = parsed_or("MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES, &mut errors);
+        let config = Config {
+            public_url,
+            bind_addrs,
+            port,
+            listen_uds,
+            listen_uds_mode: listen_uds_mode(&mut errors),
+            tls,
+            tls_redirect_port,
+            workers: parsed_or("WORKERS", 0, &mut errors),
+            database_url,
+            uploads_dir: PathBuf::from(string_or("UPLOADS_DIR", "uploads")),
+            storage: storage_config(&mut errors),
+            default_board: default_board(&mut errors),
+            og_default_image: optional("OG_DEFAULT_IMAGE"),
+            feed_items: parsed_or("FEED_ITEMS", DEFAULT_FEED_ITEMS, &mut errors),
+            trending_cache_secs: parsed_or("TRENDING_CACHE_SECONDS", 60, &mut errors),
+            page_cache_secs: parsed_or("PAGE_CACHE_SECONDS", 30, &mut errors),
+            page_cache_entries: parsed_or("PAGE_CACHE_ENTRIES", 1000, &mut errors),
+            api_key_cache_secs: parsed_or("API_KEY_CACHE_SECONDS", 10, &mut errors),
+            webhook_attempts: parsed_or("WEBHOOK_ATTEMPTS", 5, &mut errors),
+            webhook_timeout_secs: parsed_or("WEBHOOK_TIMEOUT_SECONDS", 10, &mut errors),
+            notify: notify_config(&mut errors),
+            max_upload_bytes,
+            max_audio_upload_bytes: parsed_or(
+                "MAX_AUDIO_UPLOAD_BYTES",
+                max_upload_bytes,
+                &mut errors,
+            ),
+            max_image_pixels: parsed_or("MAX_IMAGE_PIXELS", 50_000_000, &mut errors),
+            max_image_width: parsed_or("MAX_IMAGE_WIDTH", 16_384, &mut errors),
+            max_image_height: parsed_or("MAX_IMAGE_HEIGHT", 16_384, &mut errors),
+            max_gif_frames: parsed_or("MAX_GIF_FRAMES", 1_000, &mut errors),
+            strip_exif: flag_or("STRIP_EXIF", true, &mut errors),
+            webp_convert: flag_or("WEBP_CONVERT", false, &mut errors),
+            webp_min_bytes: parsed_or("WEBP_MIN_BYTES", 100 * 1024, &mut errors),
+            uploads_quota_bytes: parsed_or("UPLOADS_QUOTA_BYTES", 0, &mut errors),
+            max_title_chars: parsed_or("MAX_TITLE_CHARS", 200, &mut errors),
+            max_body_chars: parsed_or("MAX_BODY_CHARS", 50_000, &mut errors),
+            max_comment_chars: parsed_or("MAX_COMMENT_CHARS", 10_000, &mut errors),
+            article_rate_limit: parsed_or("RATE_LIMIT_ARTICLES", 2, &mut errors),
+            comment_rate_limit: parsed_or("RATE_LIMIT_COMMENTS", 6, &mut errors),
+            report_rate_limit: parsed_or("RATE_LIMIT_REPORTS", 5, &mut errors),
+            duplicate_window_secs: parsed_or("DUPLICATE_WINDOW_SECONDS", 300, &mut errors),
+            rate_limit_exempt_localhost: flag_or("RATE_LIMIT_EXEMPT_LOCALHOST", false, &mut errors),
+            trusted_proxy_header: optional("TRUSTED_PROXY_HEADER")
+                .map(|name| name.trim().to_string()),
+            secret_key: secret_key(),
+            form_min_fill_secs: parsed_or("FORM_MIN_FILL_SECONDS", 3, &mut errors),
+            word_filter_refresh_secs: parsed_or("WORD_FILTER_REFRESH_SECONDS", 60, &mut errors),
+            captcha_enabled: flag_or("CAPTCHA_ENABLED", true, &mut errors),
+            max_articles: parsed_or("MAX_ARTICLES", 0, &mut errors),
+            archive_after: parsed_or("ARCHIVE_AFTER", 500, &mut errors),
+            bump_limit: parsed_or("BUMP_LIMIT", 300, &mut errors),
+            deleted_retention_days: parsed_or("DELETED_RETENTION_DAYS", 30, &mut errors),
+            media_prune_interval_hours: parsed_or("MEDIA_PRUNE_INTERVAL_HOURS", 24, &mut errors),
+            media_prune_grace_minutes: parsed_or("MEDIA_PRUNE_GRACE_MINUTES", 60, &mut errors),
+            admin_password_hash: admin_password_hash(&mut errors),
+            ffmpeg_path: optional("FFMPEG_PATH").map(PathBuf::from),
+            max_video_seconds: parsed_or("MAX_VIDEO_SECONDS", 300, &mut errors),
+            max_links_per_post: parsed_or("MAX_LINKS_PER_POST", 10, &mut errors),
+            markdown_images: flag_or("MARKDOWN_IMAGES", false, &mut errors),
+            highlight_languages: parse_languages(&string_or(
+                "HIGHLIGHT_LANGUAGES",
+                DEFAULT_LANGUAGES,
+            )),
+            display_timezone: display_timezone(&mut errors),
+            log_format: log_format(&mut errors),
+            access_log_exclude: path_prefixes(&string_or(
+                "ACCESS_LOG_EXCLUDE",
+                DEFAULT_ACCESS_LOG_EXCLUDE,
+            )),
+            cors_allowed_origins: cors_allowed_origins(&mut errors),
+            api_docs: flag_or("API_DOCS", false, &mut errors),
+            robots_disallow: path_prefixes(&string_or("ROBOTS_DISALLOW", "none")),
+            error_log_path: error_log_path(),
+            error_log_rotation: error_log_rotation(&mut errors),
+            error_retention_days: parsed_or("ERROR_RETENTION_DAYS", 30, &mut errors),
+            shutdown_timeout_secs: parsed_or("SHUTDOWN_TIMEOUT_SECONDS", 30, &mut errors),
+            skip_migrations: flag_or("SKIP_MIGRATIONS", false, &mut errors),
+        };
+
+        if errors.missing.is_empty() && errors.invalid.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Whether cookies should only be sent back over HTTPS
+    pub fn secure_cookies(&self) -> bool {
+        self.tls.is_some() || self.public_url.starts_with("https://")
+    }
+}
+
+fn storage_config(errors: &mut ConfigError) -> StorageConfig {
+    match string_or("STORAGE_BACKEND", "local")
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "local" => StorageConfig::Local,
+        "s3" => StorageConfig::S3(S3Config {
+            bucket: required("S3_BUCKET", errors),
+            access_key: required("S3_ACCESS_KEY", errors),
+            secret_key: required("S3_SECRET_KEY", errors),
+            region: string_or("S3_REGION", "us-east-1"),
+            endpoint: optional("S3_ENDPOINT"),
+            public_url: optional("S3_PUBLIC_URL"),
+        }),
+        other => {
+            errors.invalid.push(format!(
+                "STORAGE_BACKEND should be local or s3, got {:?}",
+                other
+            ));
+            StorageConfig::Local
+        }
+    }
+}
+
+fn log_format(errors: &mut ConfigError) -> LogFormat {
+    match string_or("LOG_FORMAT", "pretty")
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "pretty" => LogFormat::Pretty,
+        "json" => LogFormat::Json,
+        other => {
+            errors.invalid.push(format!(
+                "LOG_FORMAT should be pretty or json, got {:?}",
+                other
+            ));
+            LogFormat::Pretty
+        }
+    }
+}
+
+fn path_prefixes(value: &str) -> Vec<String> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    value
+        .split(',')
+        .map(|prefix| prefix.trim().to_string())
+        .filter(|prefix| !prefix.is_empty())
+        .collect()
+}
+
+// Origins are a scheme and host, with a port perhaps, and nothing after
+fn cors_allowed_origins(errors: &mut ConfigError) -> Vec<String> {
+    let value = string_or("CORS_ALLOWED_ORIGINS", "");
+    let origins: Vec<String> = value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    for origin in &origins {
+        let host = origin
+            .strip_prefix("https://")
+            .or_else(|| origin.strip_prefix("http://"));
+        if origin != "*" && host.is_none_or(|host| host.is_empty() || host.contains('/')) {
+            errors.invalid.push(format!(
+                "CORS_ALLOWED_ORIGINS has an invalid origin {:?}; use * or ones like https://example.com",
+                origin
+            ));
+        }
+    }
+    origins
+}
+
+fn bind_addrs(value: &str) -> Vec<String> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    value
+        .split(',')
+        .map(|addr| addr.trim().trim_matches(['[', ']']).to_string())
+        .filter(|addr| !addr.is_empty())
+        .collect()
+}
+
+// An IP address, or something that could be a host name to look up
+fn valid_bind_addr(addr: &str) -> bool {
+    addr.parse::<IpAddr>().is_ok()
+        || addr.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn tls_config(errors: &mut ConfigError) -> Option<TlsConfig> {
+    match (optional("TLS_CERT_PATH"), optional("TLS_KEY_PATH")) {
+        (Some(cert_path), Some(key_path)) => Some(TlsConfig {
+            cert_path: PathBuf::from(cert_path),
+            key_path: PathBuf::from(key_path),
+        }),
+        (None, None) => None,
+        (Some(_), None) => {
+            errors
+                .invalid
+                .push("TLS_CERT_PATH is set without TLS_KEY_PATH".to_string());
+            None
+        }
+        (None, Some(_)) => {
+            errors
+                .invalid
+                .push("TLS_KEY_PATH is set without TLS_CERT_PATH".to_string());
+            None
+        }
+    }
+}
+
+fn notify_config(errors: &mut ConfigError) -> Option<NotifyConfig> {
+    let url = optional("NOTIFY_WEBHOOK_URL")?.trim().to_string();
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        errors.invalid.push(format!(
+            "NOTIFY_WEBHOOK_URL should start with http:// or https://, got {:?}",
+            url
+        ));
+        return None;
+    }
+    let format = match string_or("NOTIFY_FORMAT", "auto")
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "auto" => notify::format_of(&url),
+        "discord" => NotifyFormat::Discord,
+        "slack" => NotifyFormat::Slack,
+        other => {
+            errors.invalid.push(format!(
+                "NOTIFY_FORMAT should be auto, discord or slack, got {:?}",
+                other
+            ));
+            NotifyFormat::Slack
+        }
+    };
+    Some(NotifyConfig {
+        url,
+        format,
+        comments: flag_or("NOTIFY_COMMENTS", false, errors),
+    })
+}
+
+fn listen_uds_mode(errors: &mut ConfigError) -> u32 {
+    let value = string_or("LISTEN_UDS_MODE", "660");
+    match u32::from_str_radix(value.trim().trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o777 => mode,
+        _ => {
+            errors.invalid.push(format!(
+                "LISTEN_UDS_MODE should be octal permissions like 660, got {:?}",
+                value
+            ));
+            0o660
+        }
+    }
+}
+
+fn error_log_path() -> Option<PathBuf> {
+    let path = string_or("ERROR_LOG_PATH", "off");
+    (!path.trim().eq_ignore_ascii_case("off")).then(|| PathBuf::from(path))
+}
+
+fn error_log_rotation(errors: &mut ConfigError) -> LogRotation {
+    match string_or("ERROR_LOG_ROTATION", "never")
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "never" => LogRotation::Never,
+        "hourly" => LogRotation::Hourly,
+        "daily" => LogRotation::Daily,
+        other => {
+            errors.invalid.push(format!(
+                "ERROR_LOG_ROTATION should be never, hourly or daily, got {:?}",
+                other
+            ));
+            LogRotation::Never
+        }
+    }
+}
+
+fn default_board(errors: &mut ConfigError) -> String {
+    let slug = string_or("DEFAULT_BOARD", "main");
+    if let Err(e) = validate_slug(&slug) {
+        errors
+            .invalid
+            .push(format!("DEFAULT_BOARD {:?}: {}", slug, e));
+    }
+    slug
+}
+
+fn secret_key() -> Vec<u8> {
+    match optional("SECRET_KEY") {
+        Some(key) => key.into_bytes(),
+        None => {
+            let mut key = vec![0; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        }
+    }
+}
+
+fn admin_password_hash(errors: &mut ConfigError) -> Option<String> {
+    let hash = optional("ADMIN_PASSWORD_HASH")?.trim().to_string();
+    if PasswordHash::new(&hash).is_err() {
+        errors.invalid.push(
+            "ADMIN_PASSWORD_HASH is not a password hash; make one with `articles hash-password`"
+                .to_string(),
+        );
+    }
+    Some(hash)
+}
+
+fn display_timezone(errors: &mut ConfigError) -> FixedOffset {
+    let value = string_or("DISPLAY_TIMEZONE", "UTC");
+    parse_timezone(&value).unwrap_or_else(|| {
+        errors.invalid.push(format!(
+            "DISPLAY_TIMEZONE has an invalid value {:?}; use UTC or an offset like +02:00",
+            value
+        ));
+        Utc.fix()
+    })
+}
+
+// Unset and empty variables are treated the same
+fn optional(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn required(name: &'static str, errors: &mut ConfigError) -> String {
+    optional(name).unwrap_or_else(|| {
+        errors.missing.push(name);
+        String::new()
+    })
+}
+
+fn string_or(name: &str, default: &str) -> String {
+    optional(name).unwrap_or_else(|| default.to_string())
+}
+
+fn parsed_or<T: FromStr>(name: &str, default: T, errors: &mut ConfigError) -> T {
+    match optional(name) {
+        Some(value) => value.trim().parse().unwrap_or_else(|_| {
+            errors
+                .invalid
+                .push(format!("{} has an invalid value {:?}", name, value));
+            default
+        }),
+        None => default,
+    }
+}
+
+// Accepts the usual spellings of a yes/no switch
+fn flag_or(name: &str, default: bool, errors: &mut ConfigError) -> bool {
+    match optional(name) {
+        Some(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => {
+                errors
+                    .invalid
+                    .push(format!("{} should be true or false, got {:?}", name, value));
+                default
+            }
+        },
+        None => default,
+    }
+}
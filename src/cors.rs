@@ -0,0 +1,37 @@
+// Cross-origin access to /api for front ends served from elsewhere, allowed
+// for CORS_ALLOWED_ORIGINS only. Preflight requests are answered here, before
+// routing, for the methods and headers the API takes.
+
+use actix_cors::Cors;
+use actix_web::http::{header, Method};
+
+use crate::api;
+use crate::config::Config;
+
+// How long browsers may reuse a preflight answer
+const MAX_AGE_SECS: usize = 3600;
+
+pub fn enabled(config: &Config) -> bool {
+    !config.cors_allowed_origins.is_empty()
+}
+
+pub fn api(config: &Config) -> Cors {
+    // Listings' paging headers, and the request id for reporting problems
+    let mut cors = Cors::default()
+        .allowed_methods([Method::GET, Method::POST, Method::DELETE])
+        .allowed_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+        .expose_headers([
+            header::HeaderName::from_static(api::TOTAL_COUNT),
+            header::LINK,
+            header::HeaderName::from_static("x-request-id"),
+        ])
+        .max_age(MAX_AGE_SECS);
+    for origin in &config.cors_allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
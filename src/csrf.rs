@@ -0,0 +1,260 @@
+// Cross-site request forgery protection for the HTML forms. Each browser gets
+// a signed token in a cookie; pages with forms render the same token into a
+// hidden field, and the middleware below refuses any POST (or other unsafe
+// request) whose field doesn't match the cookie. The JSON API is left alone:
+// browsers won't send a cross-site JSON body without a CORS preflight.
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    cookie::{Cookie, SameSite},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::{ErrorInternalServerError, PayloadError},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    web, Error, FromRequest, HttpMessage, HttpRequest,
+};
+use bytes::{Bytes, BytesMut};
+use chrono::Utc;
+use futures_util::future::{ready, Ready};
+use futures_util::stream::{self, Stream, StreamExt as _};
+use std::pin::Pin;
+
+use crate::assets;
+use crate::config::Config;
+use crate::health;
+use crate::robots;
+use crate::signing;
+use crate::templates::{render_html, MessageContext};
+
+const COOKIE_NAME: &str = "csrf";
+// Name of the hidden input every form must carry
+pub const FIELD_NAME: &str = "csrf_token";
+
+// A token is replaced on the first page view after this long, and refused
+// outright after twice as long, so a form left open across a rotation still
+// works for a while
+const ROTATE_AFTER_SECS: i64 = 24 * 60 * 60;
+const MAX_AGE_SECS: i64 = 2 * ROTATE_AFTER_SECS;
+
+// How much of a request body is read looking for the token. Multipart forms
+// put the field first, so only the start of an upload is buffered.
+const MAX_FORM_BYTES: usize = 1024 * 1024;
+const MAX_MULTIPART_PREFIX_BYTES: usize = 64 * 1024;
+
+// The current request's token, for rendering into forms
+#[derive(Clone)]
+pub struct CsrfToken(pub String);
+
+impl FromRequest for CsrfToken {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<CsrfToken>()
+                .cloned()
+                .ok_or_else(|| ErrorInternalServerError("CSRF protection is not configured")),
+        )
+    }
+}
+
+// "{issued_at}.{nonce}.{signature}"
+fn new_token(secret: &[u8], now: i64) -> String {
+    let message = format!("{}.{}", now, uuid::Uuid::new_v4().simple());
+    let signature = signing::sign(secret, "csrf", &message);
+    format!("{}.{}", message, signature)
+}
+
+// Seconds since a token was issued, or None if it isn't one of ours
+fn token_age(secret: &[u8], token: &str, now: i64) -> Option<i64> {
+    let (message, signature) = token.rsplit_once('.')?;
+    if !signing::verify(secret, "csrf", message, signature) {
+        return None;
+    }
+    let issued_at: i64 = message.split_once('.')?.0.parse().ok()?;
+    Some(now - issued_at)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// The token field of a multipart body, once enough of it has been read
+fn multipart_token(body: &[u8]) -> Option<String> {
+    let name = find(body, format!("name=\"{}\"", FIELD_NAME).as_bytes())?;
+    let start = name + find(&body[name..], b"\r\n\r\n")? + 4;
+    let end = start + find(&body[start..], b"\r\n")?;
+    String::from_utf8(body[start..end].to_vec()).ok()
+}
+
+fn urlencoded_token(body: &[u8]) -> Option<String> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(body).ok()?;
+    pairs
+        .into_iter()
+        .find(|(name, _)| name == FIELD_NAME)
+        .map(|(_, value)| value)
+}
+
+// Read as much of the body as it takes to find the submitted token, then put
+// what was read back in front of the rest for the handler
+async fn submitted_token(req: &mut ServiceRequest) -> Option<String> {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let multipart = content_type.starts_with("multipart/form-data");
+    if !multipart && !content_type.starts_with("application/x-www-form-urlencoded") {
+        return None;
+    }
+    let limit = if multipart {
+        MAX_MULTIPART_PREFIX_BYTES
+    } else {
+        MAX_FORM_BYTES
+    };
+
+    let mut payload = req.take_payload();
+    let mut read = BytesMut::new();
+    let mut failed = None;
+    let mut token = None;
+    while read.len() < limit {
+        match payload.next().await {
+            Some(Ok(chunk)) => {
+                read.extend_from_slice(&chunk);
+                if multipart {
+                    token = multipart_token(&read);
+                    if token.is_some() {
+                        break;
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                failed = Some(e);
+                break;
+            }
+            None => {
+                if !multipart {
+                    token = urlencoded_token(&read);
+                }
+                break;
+            }
+        }
+    }
+
+    let read: Bytes = read.freeze();
+    let rest: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> = match failed {
+        Some(e) => Box::pin(stream::iter([Ok(read), Err(e)])),
+        None => Box::pin(stream::iter([Ok(read)]).chain(payload)),
+    };
+    req.set_payload(Payload::from(rest));
+    token
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+pub async fn protect(
+    config: web::Data<Config>,
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    // Health probes get no cookie, which would only be thrown away, and
+    // neither do the icon and robots.txt, which are cached publicly
+    if req.path().starts_with("/api/")
+        || health::PATHS.contains(&req.path())
+        || [assets::FAVICON, robots::PATH].contains(&req.path())
+    {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let now = Utc::now().timestamp();
+    let secret = &config.secret_key;
+    let current = req.cookie(COOKIE_NAME).and_then(|cookie| {
+        let age = token_age(secret, cookie.value(), now)?;
+        (age <= MAX_AGE_SECS).then(|| (cookie.value().to_string(), age))
+    });
+
+    let safe = is_safe(req.method());
+    if !safe {
+        let submitted = submitted_token(&mut req).await;
+        let matches = match (&current, &submitted) {
+            (Some((expected, _)), Some(submitted)) => {
+                signing::constant_time_eq(expected.as_bytes(), submitted.as_bytes())
+            }
+            _ => false,
+        };
+        if !matches {
+            let response = render_html(
+                StatusCode::FORBIDDEN,
+                &MessageContext {
+                    title: "Form Expired",
+                    message: "This form has expired or was sent from another site. Please reload the page and try again.",
+                    link_href: "/",
+                    link_text: "Home",
+                },
+            );
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    // Rotation only happens on page views, never between rendering a form and
+    // checking it
+    let (token, issued) = match current {
+        Some((token, age)) if !safe || age < ROTATE_AFTER_SECS => (token, false),
+        _ => (new_token(secret, now), true),
+    };
+    req.extensions_mut().insert(CsrfToken(token.clone()));
+
+    let mut res = next.call(req).await?;
+    if issued {
+        let cookie = Cookie::build(COOKIE_NAME, token)
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .secure(config.secure_cookies())
+            .finish();
+        if let Err(e) = res.response_mut().add_cookie(&cookie) {
+            tracing::error!(error = %e, "Failed to set CSRF cookie");
+        }
+    }
+    Ok(res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    #[test]
+    fn tokens_carry_their_age_and_resist_tampering() {
+        let token = new_token(SECRET, 1_000);
+        assert_eq!(token_age(SECRET, &token, 1_060), Some(60));
+        assert_eq!(token_age(b"other secret", &token, 1_060), None);
+        assert_eq!(
+            token_age(SECRET, &token.replacen("1000", "2000", 1), 2_060),
+            None
+        );
+        assert_eq!(token_age(SECRET, "garbage", 0), None);
+    }
+
+    #[test]
+    fn finds_the_token_in_form_bodies() {
+        let body = b"comment=hi&csrf_token=abc.def%2B1";
+        assert_eq!(urlencoded_token(body).as_deref(), Some("abc.def+1"));
+        assert_eq!(urlencoded_token(b"comment=hi"), None);
+
+        let multipart = b"--xyz\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\nabc.def\r\n--xyz\r\n";
+        assert_eq!(multipart_token(multipart).as_deref(), Some("abc.def"));
+        // Not there yet: the value hasn't been terminated
+        assert_eq!(multipart_token(&multipart[..66]), None);
+    }
+}
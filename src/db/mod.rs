@@ -0,0 +1,2180 @@
+// Storage for articles, media and comments. Handlers only see the
+// ArticleRepository trait; DATABASE_URL's scheme picks the backend.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::migrate::{MigrateError, Migration};
+use sqlx::types::Json;
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::{Article, DbArticle, DbBoard, DbComment, Media};
+
+mod postgres;
+mod sqlite;
+
+pub use postgres::PgRepository;
+pub use sqlite::SqliteRepository;
+
+// Columns supplied when an article is created
+pub struct NewArticleRow<'a> {
+    pub board_id: i32,
+    pub title: &'a str,
+    // From slug::slugify; insert_article makes it unique
+    pub slug: Option<&'a str>,
+    pub body: &'a str,
+    // See poster_name
+    pub name: &'a str,
+    // See tripcode::parse
+    pub tripcode: Option<&'a str>,
+    pub created_at: i64,
+    // Only the sort key; equal to created_at until a comment bumps it
+    pub bump_time: i64,
+    pub delete_password_hash: Option<&'a str>,
+    // Already normalized, see normalize_tags
+    pub tags: &'a [String],
+    pub poster_ip: Option<&'a str>,
+    // Uploads already saved to storage, recorded along with the article
+    pub media: &'a [Media],
+}
+
+// Columns supplied when a comment is posted
+pub struct NewCommentRow<'a> {
+    pub article_id: i32,
+    pub comment: &'a str,
+    pub name: &'a str,
+    pub tripcode: Option<&'a str>,
+    pub delete_password_hash: Option<&'a str>,
+    pub poster_ip: Option<&'a str>,
+    pub created_at: i64,
+    // Checked to be on the same article by the caller
+    pub parent_comment_id: Option<i32>,
+    // The article's new bump_time, None for a saged comment. It is left alone
+    // once the article has `bump_limit` comments (0 for no limit).
+    pub bump_to: Option<i64>,
+    pub bump_limit: i64,
+}
+
+// Columns supplied when a reader reports an article (comment_id None) or a
+// comment
+pub struct NewReportRow<'a> {
+    pub article_id: i32,
+    pub comment_id: Option<i32>,
+    pub reason: &'a str,
+    pub reporter_ip: Option<&'a str>,
+    pub created_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchScope {
+    #[default]
+    Articles,
+    Comments,
+}
+
+// How the list_*_articles methods order articles, after any sticky ones.
+// Unknown ?sort= values read as the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ArticleSort {
+    New,
+    Comments,
+    Old,
+    // Last, as serde requires of the variant unknown values read as
+    #[default]
+    #[serde(other)]
+    Bump,
+}
+
+impl ArticleSort {
+    pub const ALL: [ArticleSort; 4] = [
+        ArticleSort::Bump,
+        ArticleSort::New,
+        ArticleSort::Comments,
+        ArticleSort::Old,
+    ];
+
+    // The ?sort= value that picks this order
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArticleSort::Bump => "bump",
+            ArticleSort::New => "new",
+            ArticleSort::Comments => "comments",
+            ArticleSort::Old => "old",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ArticleSort::Bump => "Recently bumped",
+            ArticleSort::New => "Newest",
+            ArticleSort::Comments => "Most comments",
+            ArticleSort::Old => "Oldest",
+        }
+    }
+
+    // Over LISTED_ARTICLE_COLUMNS
+    fn order_by(self) -> &'static str {
+        match self {
+            ArticleSort::Bump => "a.is_sticky DESC, a.bump_time DESC, a.id DESC",
+            ArticleSort::New => "a.is_sticky DESC, a.created_at DESC, a.id DESC",
+            ArticleSort::Comments => {
+                "a.is_sticky DESC, comment_count DESC, a.bump_time DESC, a.id DESC"
+            }
+            ArticleSort::Old => "a.is_sticky DESC, a.created_at, a.id",
+        }
+    }
+}
+
+// A board with the number of articles posted to it
+#[derive(FromRow)]
+pub struct BoardSummary {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub article_count: i64,
+}
+
+// An article linked from another's page, see related_articles
+#[derive(FromRow, Serialize, ToSchema)]
+pub struct RelatedArticle {
+    pub id: i32,
+    pub title: String,
+    pub slug: Option<String>,
+}
+
+// An article as a list shows it, with its tags, how much it has been
+// discussed and the first of its own media, all from the listing query
+#[derive(Clone, FromRow)]
+pub struct ListedArticle {
+    #[sqlx(flatten)]
+    pub article: DbArticle,
+    tags: Option<String>,
+    // Live comments only
+    pub comment_count: i64,
+    pub last_comment_at: Option<i64>,
+    pub media_path: Option<String>,
+    pub thumb_path: Option<String>,
+}
+
+impl ListedArticle {
+    // Tag names in alphabetical order
+    pub fn tags(&self) -> Vec<String> {
+        split_tags(self.tags.as_deref())
+    }
+}
+
+// An article's columns with the names of its tags, for the listing queries
+// below; they add the FROM clause's `m`, the first of the article's media
+const LISTED_ARTICLE_COLUMNS: &str =
+    "a.id, a.board_id, a.title, a.slug, a.body, a.name, a.tripcode, a.created_at, a.bump_time, a.edited_at, \
+     a.archived_at, a.is_sticky, (SELECT string_agg(t.name, ',' ORDER BY t.name) FROM article_tags \
+         JOIN tags t ON t.id = article_tags.tag_id WHERE article_tags.article_id = a.id) AS tags, \
+     (SELECT COUNT(*) FROM comments c WHERE c.article_id = a.id AND c.deleted_at IS NULL) AS comment_count, \
+     (SELECT MAX(c.created_at) FROM comments c WHERE c.article_id = a.id AND c.deleted_at IS NULL) AS last_comment_at, \
+     m.media_path, m.thumb_path";
+const FIRST_MEDIA_JOIN: &str = "LEFT JOIN article_media m ON m.id = \
+     (SELECT MIN(id) FROM article_media WHERE article_id = a.id AND comment_id IS NULL)";
+
+// Tags aggregated by string_agg; normalize_tags keeps commas out of them
+fn split_tags(tags: Option<&str>) -> Vec<String> {
+    tags.map_or_else(Vec::new, |tags| {
+        tags.split(',').map(str::to_string).collect()
+    })
+}
+
+// One of an article's media (if it has any) alongside the article, as
+// get_article_with_media reads them
+#[derive(FromRow)]
+struct ArticleMediaRow {
+    #[sqlx(flatten)]
+    article: DbArticle,
+    tags: Option<String>,
+    last_comment_at: Option<i64>,
+    #[sqlx(flatten)]
+    media: JoinedMedia,
+}
+
+// One of a comment's media (if it has any) alongside the comment, as
+// list_comments_page reads them
+#[derive(FromRow)]
+struct CommentMediaRow {
+    #[sqlx(flatten)]
+    comment: DbComment,
+    #[sqlx(flatten)]
+    media: JoinedMedia,
+}
+
+// One of an article's own media, as media_for_articles reads them
+#[derive(FromRow)]
+struct ListedMediaRow {
+    article_id: i32,
+    #[sqlx(flatten)]
+    media: JoinedMedia,
+}
+
+// A media item from a LEFT JOIN, which has no path when there wasn't one
+#[derive(FromRow)]
+struct JoinedMedia {
+    media_path: Option<String>,
+    original_name: Option<String>,
+    thumb_path: Option<String>,
+    byte_size: Option<i64>,
+    mime_type: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+}
+
+impl JoinedMedia {
+    fn into_media(self) -> Option<Media> {
+        Some(Media {
+            media_path: self.media_path?,
+            original_name: self.original_name,
+            thumb_path: self.thumb_path,
+            byte_size: self.byte_size,
+            mime_type: self.mime_type,
+            width: self.width,
+            height: self.height,
+            ..Default::default()
+        })
+    }
+}
+
+// get_article_with_media's rows, all of one article in media order, back
+// into the article
+fn article_from_rows(rows: Vec<ArticleMediaRow>) -> Result<Article, sqlx::Error> {
+    let mut media = Vec::with_capacity(rows.len());
+    let mut article = None;
+    for row in rows {
+        media.extend(row.media.into_media());
+        article.get_or_insert((row.article, row.tags, row.last_comment_at));
+    }
+    let (a, tags, last_comment_at) = article.ok_or(sqlx::Error::RowNotFound)?;
+    Ok(Article {
+        id: a.id,
+        board_id: a.board_id,
+        title: a.title,
+        slug: a.slug,
+        body: a.body,
+        media,
+        tags: split_tags(tags.as_deref()),
+        name: a.name,
+        tripcode: a.tripcode,
+        created_at: a.created_at,
+        bump_time: a.bump_time,
+        edited_at: a.edited_at,
+        archived_at: a.archived_at,
+        is_sticky: a.is_sticky,
+        last_comment_at,
+    })
+}
+
+// list_comments_page's rows, a comment's next to each other, back into the
+// comments and their media keyed by comment id
+fn comments_from_rows(rows: Vec<CommentMediaRow>) -> (Vec<DbComment>, HashMap<i32, Vec<Media>>) {
+    let mut comments: Vec<DbComment> = Vec::with_capacity(rows.len());
+    let mut media: HashMap<i32, Vec<Media>> = HashMap::new();
+    for row in rows {
+        if let Some(item) = row.media.into_media() {
+            media.entry(row.comment.id).or_default().push(item);
+        }
+        if comments.last().is_none_or(|c| c.id != row.comment.id) {
+            comments.push(row.comment);
+        }
+    }
+    (comments, media)
+}
+
+// A word filter as stored; see word_filter.rs. `action` is "reject" or
+// "replace".
+#[derive(FromRow)]
+pub struct WordFilterRow {
+    pub id: i32,
+    pub pattern: String,
+    pub is_regex: bool,
+    pub action: String,
+    pub replacement: String,
+}
+
+// A posting ban; `cidr` is an address or range as parsed by ban::IpRange,
+// and bans without `expires_at` are permanent
+#[derive(FromRow)]
+pub struct BanRow {
+    pub id: i32,
+    pub cidr: String,
+    pub reason: String,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+// A key for writing through the API; `key_hash` is the SHA-256 of the key,
+// and `scope` one of api_key::Scope's names, see api_key.rs
+#[derive(Clone, FromRow)]
+pub struct ApiKeyRow {
+    pub id: i32,
+    pub key_hash: String,
+    pub label: String,
+    pub scope: String,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+// Everything kept about an article, for export.rs and import.rs: its tags,
+// its own media and its comments (each with theirs) come with it, deleted
+// ones included
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct ExportArticle {
+    pub id: i32,
+    pub board_id: i32,
+    pub title: String,
+    pub slug: Option<String>,
+    pub body: String,
+    pub name: String,
+    pub tripcode: Option<String>,
+    pub created_at: i64,
+    pub bump_time: i64,
+    pub edited_at: Option<i64>,
+    pub archived_at: Option<i64>,
+    pub deleted_at: Option<i64>,
+    pub is_sticky: bool,
+    pub delete_password_hash: Option<String>,
+    pub poster_ip: Option<String>,
+    #[sqlx(skip)]
+    pub tags: Vec<String>,
+    #[sqlx(skip)]
+    pub media: Vec<ExportMedia>,
+    #[sqlx(skip)]
+    pub comments: Vec<ExportComment>,
+}
+
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct ExportComment {
+    pub id: i32,
+    #[serde(skip)]
+    pub article_id: i32,
+    pub parent_comment_id: Option<i32>,
+    pub comment: String,
+    pub name: String,
+    pub tripcode: Option<String>,
+    pub created_at: i64,
+    pub deleted_at: Option<i64>,
+    pub delete_password_hash: Option<String>,
+    pub poster_ip: Option<String>,
+    #[sqlx(skip)]
+    pub media: Vec<ExportMedia>,
+}
+
+// Paths are as stored: under /uploads, or URLs with other storage
+#[derive(FromRow, Serialize, Deserialize)]
+pub struct ExportMedia {
+    pub id: i32,
+    #[serde(skip)]
+    pub article_id: i32,
+    #[serde(skip)]
+    pub comment_id: Option<i32>,
+    pub media_path: String,
+    pub original_name: Option<String>,
+    pub thumb_path: Option<String>,
+    // These are missing from exports made before media had them
+    #[serde(default)]
+    pub byte_size: Option<i64>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub width: Option<i32>,
+    #[serde(default)]
+    pub height: Option<i32>,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+}
+
+impl ExportMedia {
+    fn to_media(&self) -> Media {
+        Media {
+            media_path: self.media_path.clone(),
+            original_name: self.original_name.clone(),
+            thumb_path: self.thumb_path.clone(),
+            byte_size: self.byte_size,
+            mime_type: self.mime_type.clone(),
+            width: self.width,
+            height: self.height,
+            content_hash: self.content_hash.clone(),
+        }
+    }
+}
+
+// A URL told about new posts; `events` is a bitmask of webhook::Event, and
+// `secret` signs what's sent to it
+#[derive(Clone, FromRow)]
+pub struct WebhookRow {
+    pub id: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: i32,
+    pub created_at: i64,
+}
+
+// A delivery given up on after `attempts` tries, with the last error
+#[derive(FromRow)]
+pub struct WebhookFailureRow {
+    pub webhook_id: i32,
+    pub url: String,
+    pub event: String,
+    pub payload: String,
+    pub error: String,
+    pub attempts: i32,
+    pub failed_at: i64,
+}
+
+// A logged error, see app_errors.rs. `route` is the path of the request it
+// happened during, if any; `context` holds the other fields logged with it.
+#[derive(Clone, Debug, FromRow)]
+pub struct AppErrorRow {
+    pub occurred_at: i64,
+    pub route: Option<String>,
+    pub message: String,
+    pub context: Json<serde_json::Value>,
+}
+
+// An open report with enough of its target to link to and show it
+#[derive(FromRow)]
+pub struct ReportRow {
+    pub id: i32,
+    pub article_id: i32,
+    pub comment_id: Option<i32>,
+    pub reason: String,
+    pub created_at: i64,
+    pub board_slug: String,
+    pub article_title: String,
+    pub article_slug: Option<String>,
+    // The reported comment's text, when it's a comment
+    pub comment: Option<String>,
+}
+
+// A deleted article, for the admin list of deleted content
+#[derive(FromRow)]
+pub struct DeletedArticleRow {
+    pub id: i32,
+    pub board_slug: String,
+    pub title: String,
+    pub body: String,
+    pub deleted_at: i64,
+}
+
+// A deleted comment with the article it was on
+#[derive(FromRow)]
+pub struct DeletedCommentRow {
+    pub id: i32,
+    pub article_id: i32,
+    pub board_slug: String,
+    pub article_title: String,
+    pub article_slug: Option<String>,
+    pub comment: String,
+    pub deleted_at: i64,
+}
+
+// One search hit. `snippet` is plain text around the match, with matched
+// words wrapped in render::MATCH_START and render::MATCH_END.
+#[derive(FromRow)]
+pub struct SearchRow {
+    pub article_id: i32,
+    pub title: String,
+    pub slug: Option<String>,
+    pub comment_id: Option<i32>,
+    pub snippet: String,
+    // The article is archived
+    pub archived: bool,
+}
+
+// Lookups of a single row return sqlx::Error::RowNotFound when it doesn't
+// exist. Soft-deleted articles and comments count as not existing everywhere
+// except the methods that deal with deleted content by name.
+#[async_trait]
+pub trait ArticleRepository: Send + Sync {
+    // Apply pending migrations, returning the ones that were applied now
+    async fn run_migrations(&self) -> Result<Vec<&'static Migration>, MigrateError>;
+    // A trivial query, to check the database can be reached
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+    // Close the pool, waiting for queries in progress; any later query fails
+    async fn close(&self);
+
+    async fn insert_board(
+        &self,
+        slug: &str,
+        title: &str,
+        description: &str,
+    ) -> Result<(), sqlx::Error>;
+    async fn get_board(&self, slug: &str) -> Result<DbBoard, sqlx::Error>;
+    // Every board in slug order
+    async fn list_boards(&self) -> Result<Vec<BoardSummary>, sqlx::Error>;
+    // Every board in the order they were made
+    async fn all_boards(&self) -> Result<Vec<DbBoard>, sqlx::Error>;
+    // Up to `limit` articles after `after_id`, deleted or not, in the order
+    // they were posted, with all that belongs to them
+    async fn export_articles(
+        &self,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<ExportArticle>, sqlx::Error>;
+    // An article posted with this title at this time, if there is one
+    async fn find_article(&self, title: &str, created_at: i64) -> Result<Option<i32>, sqlx::Error>;
+    // Store an exported article on `board_id` as it was, with its tags, media
+    // and comments, in one transaction. Everything gets a new id; replies
+    // keep pointing at their parents. Returns the article's id.
+    async fn import_article(
+        &self,
+        board_id: i32,
+        article: &ExportArticle,
+    ) -> Result<i32, sqlx::Error>;
+
+    // The article, its tags and its media go in one transaction, so a failure
+    // leaves none of them. Returns the article's id and its media's, in order,
+    // each with the media as stored (see insert_media).
+    async fn insert_article(
+        &self,
+        article: NewArticleRow<'_>,
+    ) -> Result<(i32, Vec<(i32, Media)>), sqlx::Error>;
+    async fn count_articles(&self, board_id: i32) -> Result<i64, sqlx::Error>;
+    // Articles on a board, or comments on them, matching `query`, best matches first
+    async fn search(
+        &self,
+        board_id: i32,
+        query: &str,
+        scope: SearchScope,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SearchRow>, sqlx::Error>;
+    async fn count_search_results(
+        &self,
+        board_id: i32,
+        query: &str,
+        scope: SearchScope,
+    ) -> Result<i64, sqlx::Error>;
+    // A board's articles in `sort` order, sticky ones first. The
+    // list_*_articles methods each run a single query, however many articles
+    // there are.
+    async fn list_articles(
+        &self,
+        board_id: i32,
+        sort: ArticleSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ListedArticle>, sqlx::Error>;
+    // A board's archived articles, ordered like list_articles
+    async fn count_archived_articles(&self, board_id: i32) -> Result<i64, sqlx::Error>;
+    async fn list_archived_articles(
+        &self,
+        board_id: i32,
+        sort: ArticleSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ListedArticle>, sqlx::Error>;
+    // Archive the board's live articles beyond the `keep` most recently
+    // bumped, returning how many were archived. Sticky articles are neither
+    // archived nor counted.
+    async fn archive_overflow(
+        &self,
+        board_id: i32,
+        keep: i64,
+        archived_at: i64,
+    ) -> Result<u64, sqlx::Error>;
+    async fn count_tagged_articles(&self, board_id: i32, tag: &str) -> Result<i64, sqlx::Error>;
+    // Articles on a board carrying `tag`, ordered like list_articles
+    async fn list_tagged_articles(
+        &self,
+        board_id: i32,
+        tag: &str,
+        sort: ArticleSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ListedArticle>, sqlx::Error>;
+    // A board's current articles with live comments since `since`, most
+    // discussed first: comments since `recent_since` count three times as
+    // much as older ones. A single query, like list_articles.
+    async fn trending_articles(
+        &self,
+        board_id: i32,
+        recent_since: i64,
+        since: i64,
+        limit: i64,
+    ) -> Result<Vec<ListedArticle>, sqlx::Error>;
+    async fn get_article(&self, article_id: i32) -> Result<DbArticle, sqlx::Error>;
+    // Up to `limit` other articles on `article`'s board sharing the most of its
+    // tags or, when it has none, words of its title (see related_by_title).
+    // A single query either way.
+    async fn related_articles(
+        &self,
+        article: &Article,
+        limit: i64,
+    ) -> Result<Vec<RelatedArticle>, sqlx::Error>;
+    // The id and slug of one of a board's current articles, picked at random
+    async fn random_article(
+        &self,
+        board_id: i32,
+    ) -> Result<Option<(i32, Option<String>)>, sqlx::Error>;
+    // The article with its own media and tags, in a single query
+    async fn get_article_with_media(&self, article_id: i32) -> Result<Article, sqlx::Error>;
+    async fn update_article(
+        &self,
+        article_id: i32,
+        title: &str,
+        body: &str,
+        edited_at: i64,
+    ) -> Result<(), sqlx::Error>;
+    // Whether there was an article to set; setting the current value is fine
+    async fn set_sticky(&self, article_id: i32, sticky: bool) -> Result<bool, sqlx::Error>;
+    // None when the article doesn't exist, Some(None) when it has no password
+    async fn article_password_hash(
+        &self,
+        article_id: i32,
+    ) -> Result<Option<Option<String>>, sqlx::Error>;
+    // Hide an article, keeping its row, comments and media for restoring;
+    // whether there was one that wasn't deleted already
+    async fn soft_delete_article(
+        &self,
+        article_id: i32,
+        deleted_at: i64,
+    ) -> Result<bool, sqlx::Error>;
+    // Whether there was a deleted article to restore; it keeps its bump_time
+    async fn restore_article(&self, article_id: i32) -> Result<bool, sqlx::Error>;
+    // Most recently deleted first
+    async fn deleted_articles(&self, limit: i64) -> Result<Vec<DeletedArticleRow>, sqlx::Error>;
+    // Articles soft-deleted at or before `cutoff`, due to be purged
+    async fn article_ids_deleted_before(&self, cutoff: i64) -> Result<Vec<i32>, sqlx::Error>;
+    // Remove an article for good with its media and comment rows, returning
+    // the paths of its media files and thumbnails that no other media row
+    // uses (see media_by_hash)
+    async fn delete_article(&self, article_id: i32) -> Result<Vec<String>, sqlx::Error>;
+    // Delete the board's lowest-bumped articles, sticky ones aside, until at
+    // most `cap` are left (deleted ones don't count), returning their media
+    // paths like delete_article
+    async fn prune_over_cap(&self, board_id: i32, cap: i64) -> Result<Vec<String>, sqlx::Error>;
+    // Every article except the `keep` most recently created, leaving sticky
+    // ones out altogether
+    async fn article_ids_beyond(&self, keep: i64) -> Result<Vec<i32>, sqlx::Error>;
+
+    // Attached to the comment when `comment_id` is set, otherwise to the
+    // article itself. An upload with the content hash of one stored already
+    // takes that one's files, and its own are the caller's to remove once
+    // this returns. Returns its id and the media as stored.
+    async fn insert_media(
+        &self,
+        article_id: i32,
+        comment_id: Option<i32>,
+        media: &Media,
+    ) -> Result<(i32, Media), sqlx::Error>;
+    // The files of an upload already stored with this content hash, if any,
+    // as (media_path, thumb_path); a new upload with the same hash reuses them
+    async fn media_by_hash(
+        &self,
+        content_hash: &str,
+    ) -> Result<Option<(String, Option<String>)>, sqlx::Error>;
+    // The content hashes of an article's media and its comments', by media path
+    async fn media_hashes(&self, article_id: i32) -> Result<HashMap<String, String>, sqlx::Error>;
+    async fn set_media_thumb(&self, media_id: i32, thumb_path: &str) -> Result<(), sqlx::Error>;
+    // Media for a set of articles in a single query, keyed by article id
+    async fn media_for_articles(
+        &self,
+        article_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Media>>, sqlx::Error>;
+    // Every media and thumbnail path any media row refers to, deleted posts' included
+    async fn all_media_paths(&self) -> Result<Vec<String>, sqlx::Error>;
+    // Up to `limit` media rows with no byte_size yet, as (id, media_path),
+    // after the given id
+    async fn media_missing_metadata(
+        &self,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<(i32, String)>, sqlx::Error>;
+    async fn set_media_metadata(
+        &self,
+        media_id: i32,
+        byte_size: i64,
+        mime_type: Option<&str>,
+        dimensions: Option<(i32, i32)>,
+    ) -> Result<(), sqlx::Error>;
+    // Bytes stored under UPLOADS_DIR; None until serve has counted them
+    async fn storage_usage(&self) -> Result<Option<i64>, sqlx::Error>;
+    async fn set_storage_usage(&self, bytes: i64) -> Result<(), sqlx::Error>;
+    // Add `bytes` (negative for files removed), never going below 0
+    async fn add_storage_usage(&self, bytes: i64) -> Result<(), sqlx::Error>;
+
+    // Returns the new comment's id. Bumping happens in the same transaction,
+    // so the comment count it checks includes every comment before this one.
+    async fn insert_comment(&self, comment: NewCommentRow<'_>) -> Result<i32, sqlx::Error>;
+    // An article's comments in the order they were posted, plus deleted ones that still have
+    // live replies, which come back with `deleted` set and no text
+    async fn list_comments(&self, article_id: i32) -> Result<Vec<DbComment>, sqlx::Error>;
+    // One page of list_comments, `limit` live comments after the first
+    // `offset`. A deleted comment goes on the page of the next live one. The
+    // live comments' media come from the same query, keyed by comment id.
+    async fn list_comments_page(
+        &self,
+        article_id: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<DbComment>, HashMap<i32, Vec<Media>>), sqlx::Error>;
+    // Ids of an article's live comments in posting order; a comment's position here
+    // is what puts it on a page
+    async fn live_comment_ids(&self, article_id: i32) -> Result<Vec<i32>, sqlx::Error>;
+    // Same shape as article_password_hash, scoped to the comment's article
+    async fn comment_password_hash(
+        &self,
+        article_id: i32,
+        comment_id: i32,
+    ) -> Result<Option<Option<String>>, sqlx::Error>;
+    // Whether there was such a comment that wasn't deleted already
+    async fn soft_delete_comment(
+        &self,
+        article_id: i32,
+        comment_id: i32,
+        deleted_at: i64,
+    ) -> Result<bool, sqlx::Error>;
+    // The article a comment that isn't deleted is on
+    async fn comment_article_id(&self, comment_id: i32) -> Result<Option<i32>, sqlx::Error>;
+    // Whether there was a deleted comment to restore
+    async fn restore_comment(&self, comment_id: i32) -> Result<bool, sqlx::Error>;
+    // Most recently deleted first
+    async fn deleted_comments(&self, limit: i64) -> Result<Vec<DeletedCommentRow>, sqlx::Error>;
+    // Remove comments soft-deleted at or before `cutoff` for good, returning how
+    // many and the paths of their media files and thumbnails, as delete_article
+    async fn purge_comments_deleted_before(
+        &self,
+        cutoff: i64,
+    ) -> Result<(u64, Vec<String>), sqlx::Error>;
+    async fn comment_exists(&self, article_id: i32, comment_id: i32) -> Result<bool, sqlx::Error>;
+
+    // False when the same address already has an open report on the target
+    async fn insert_report(&self, report: NewReportRow<'_>) -> Result<bool, sqlx::Error>;
+    // Unresolved reports on content that hasn't been deleted, oldest first
+    async fn open_reports(&self) -> Result<Vec<ReportRow>, sqlx::Error>;
+    // The article and comment a report is about
+    async fn report_target(
+        &self,
+        report_id: i32,
+    ) -> Result<Option<(i32, Option<i32>)>, sqlx::Error>;
+    // Resolve every open report on a target
+    async fn resolve_reports(
+        &self,
+        article_id: i32,
+        comment_id: Option<i32>,
+        resolved_at: i64,
+    ) -> Result<(), sqlx::Error>;
+
+    // Every word filter in the order they were added
+    async fn list_word_filters(&self) -> Result<Vec<WordFilterRow>, sqlx::Error>;
+    async fn insert_word_filter(
+        &self,
+        pattern: &str,
+        is_regex: bool,
+        action: &str,
+        replacement: &str,
+    ) -> Result<(), sqlx::Error>;
+    // Whether there was a filter with this id
+    async fn delete_word_filter(&self, filter_id: i32) -> Result<bool, sqlx::Error>;
+
+    async fn insert_ban(
+        &self,
+        cidr: &str,
+        reason: &str,
+        created_at: i64,
+        expires_at: Option<i64>,
+    ) -> Result<i32, sqlx::Error>;
+    // Every ban, expired or not, newest first
+    async fn list_bans(&self) -> Result<Vec<BanRow>, sqlx::Error>;
+    // Bans still in force at `now`
+    async fn active_bans(&self, now: i64) -> Result<Vec<BanRow>, sqlx::Error>;
+    // Whether there was a ban with this id
+    async fn delete_ban(&self, ban_id: i32) -> Result<bool, sqlx::Error>;
+
+    async fn insert_api_key(
+        &self,
+        key_hash: &str,
+        label: &str,
+        scope: &str,
+        created_at: i64,
+    ) -> Result<i32, sqlx::Error>;
+    // Every key, revoked or not, in the order they were made
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, sqlx::Error>;
+    async fn active_api_keys(&self) -> Result<Vec<ApiKeyRow>, sqlx::Error>;
+    // Whether there was a key with this id that wasn't revoked yet
+    async fn revoke_api_key(&self, key_id: i32, revoked_at: i64) -> Result<bool, sqlx::Error>;
+
+    async fn insert_webhook(
+        &self,
+        url: &str,
+        secret: &str,
+        events: i32,
+        created_at: i64,
+    ) -> Result<i32, sqlx::Error>;
+    async fn list_webhooks(&self) -> Result<Vec<WebhookRow>, sqlx::Error>;
+    // Whether there was a webhook with this id
+    async fn delete_webhook(&self, webhook_id: i32) -> Result<bool, sqlx::Error>;
+    async fn insert_webhook_failure(&self, failure: &WebhookFailureRow) -> Result<(), sqlx::Error>;
+    // The latest `limit` failures, newest first
+    async fn recent_webhook_failures(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<WebhookFailureRow>, sqlx::Error>;
+    // Forget failures from before `cutoff`, returning how many there were
+    async fn delete_webhook_failures(&self, cutoff: i64) -> Result<u64, sqlx::Error>;
+
+    async fn insert_app_error(&self, app_error: &AppErrorRow) -> Result<(), sqlx::Error>;
+    // The latest `limit` logged errors, newest first
+    async fn recent_app_errors(&self, limit: i64) -> Result<Vec<AppErrorRow>, sqlx::Error>;
+    // Forget logged errors from before `cutoff`, or all of them without one,
+    // returning how many there were
+    async fn delete_app_errors(&self, cutoff: Option<i64>) -> Result<u64, sqlx::Error>;
+}
+
+// Open the database named by `url`: postgres:// and postgresql:// URLs use
+// Postgres, sqlite: URLs (e.g. sqlite://articles.db, sqlite::memory:) use SQLite
+pub async fn connect(url: &str) -> Result<Arc<dyn ArticleRepository>, String> {
+    if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        let repo = PgRepository::connect(url)
+            .await
+            .map_err(|e| format!("Failed to connect to Postgres: {}", e))?;
+        Ok(Arc::new(repo))
+    } else if url.starts_with("sqlite:") {
+        let repo = SqliteRepository::connect(url)
+            .await
+            .map_err(|e| format!("Failed to open SQLite database: {}", e))?;
+        Ok(Arc::new(repo))
+    } else {
+        Err("DATABASE_URL must start with postgres://, postgresql:// or sqlite:".to_string())
+    }
+}
+
+// The SQL below runs unchanged on both backends, so each one gets the same
+// implementation; `$migrator` is the backend's embedded migrations. Search
+// differs too much between them and is delegated to each backend's own
+// search_rows and count_search_rows. Transactions begin with each backend's
+// begin_write, and rows they rely on staying are read with its FOR_SHARE.
+macro_rules! impl_article_repository {
+    ($repo:ty, $db:ty, $migrator:expr) => {
+        impl $repo {
+            // `base`, or with a number added if another article has it.
+            // Slugs only contain [a-z0-9-], so the base needs no escaping in
+            // the LIKE pattern.
+            async fn unique_slug(tx: &mut sqlx::Transaction<'_, $db>, base: &str) -> Result<String, sqlx::Error> {
+                let taken: Vec<String> = sqlx::query_scalar("SELECT slug FROM articles WHERE slug = $1 OR slug LIKE $2")
+                    .bind(base)
+                    .bind(format!("{}-%", base))
+                    .fetch_all(&mut **tx)
+                    .await?;
+                Ok($crate::slug::dedupe(base, &taken))
+            }
+
+            // Those of `paths` no media row uses any more, each once
+            async fn unreferenced(
+                tx: &mut sqlx::Transaction<'_, $db>,
+                mut paths: Vec<String>,
+            ) -> Result<Vec<String>, sqlx::Error> {
+                paths.sort();
+                paths.dedup();
+                if paths.is_empty() {
+                    return Ok(paths);
+                }
+                let mut query = sqlx::QueryBuilder::<$db>::new("SELECT media_path FROM article_media WHERE media_path IN (");
+                let mut bound = query.separated(", ");
+                for path in &paths {
+                    bound.push_bind(path.clone());
+                }
+                query.push(") UNION SELECT thumb_path FROM article_media WHERE thumb_path IN (");
+                let mut bound = query.separated(", ");
+                for path in &paths {
+                    bound.push_bind(path.clone());
+                }
+                query.push(")");
+                let used: std::collections::HashSet<String> =
+                    query.build_query_scalar().fetch_all(&mut **tx).await?.into_iter().collect();
+                paths.retain(|path| !used.contains(path));
+                Ok(paths)
+            }
+
+            async fn tag_article(
+                tx: &mut sqlx::Transaction<'_, $db>,
+                article_id: i32,
+                tags: &[String],
+            ) -> Result<(), sqlx::Error> {
+                for tag in tags {
+                    // The no-op update makes RETURNING yield the id of an
+                    // existing tag as well as a new one
+                    let tag_id: i32 = sqlx::query_scalar(
+                        "INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO UPDATE SET name = excluded.name RETURNING id",
+                    )
+                    .bind(tag)
+                    .fetch_one(&mut **tx)
+                    .await?;
+                    sqlx::query("INSERT INTO article_tags (article_id, tag_id) VALUES ($1, $2)")
+                        .bind(article_id)
+                        .bind(tag_id)
+                        .execute(&mut **tx)
+                        .await?;
+                }
+                Ok(())
+            }
+
+            // An upload identical to one already stored takes that one's
+            // files, read in the same transaction so a delete can't remove
+            // them before this row is in. Returns the row's id and the media
+            // as stored.
+            async fn insert_media_row(
+                tx: &mut sqlx::Transaction<'_, $db>,
+                article_id: i32,
+                comment_id: Option<i32>,
+                media: &$crate::Media,
+            ) -> Result<(i32, $crate::Media), sqlx::Error> {
+                let mut media = media.clone();
+                if let Some(content_hash) = &media.content_hash {
+                    let sql = format!(
+                        "SELECT media_path, thumb_path FROM article_media WHERE content_hash = $1 ORDER BY id LIMIT 1{}",
+                        Self::FOR_SHARE
+                    );
+                    let earlier: Option<(String, Option<String>)> =
+                        sqlx::query_as(&sql).bind(content_hash).fetch_optional(&mut **tx).await?;
+                    if let Some((media_path, thumb_path)) = earlier {
+                        (media.media_path, media.thumb_path) = (media_path, thumb_path);
+                    }
+                }
+                let media_id = sqlx::query_scalar(
+                    "INSERT INTO article_media (article_id, comment_id, media_path, original_name, thumb_path, \
+                     byte_size, mime_type, width, height, content_hash) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
+                )
+                .bind(article_id)
+                .bind(comment_id)
+                .bind(&media.media_path)
+                .bind(&media.original_name)
+                .bind(&media.thumb_path)
+                .bind(media.byte_size)
+                .bind(&media.mime_type)
+                .bind(media.width)
+                .bind(media.height)
+                .bind(&media.content_hash)
+                .fetch_one(&mut **tx)
+                .await?;
+                Ok((media_id, media))
+            }
+
+            // Remove an article with its media, tag, report and comment rows,
+            // returning the paths of its media files and thumbnails
+            async fn delete_article_rows(
+                tx: &mut sqlx::Transaction<'_, $db>,
+                article_id: i32,
+            ) -> Result<Vec<String>, sqlx::Error> {
+                let media: Vec<(String, Option<String>)> = sqlx::query_as(
+                    "DELETE FROM article_media WHERE article_id = $1 RETURNING media_path, thumb_path",
+                )
+                .bind(article_id)
+                .fetch_all(&mut **tx)
+                .await?;
+                sqlx::query("DELETE FROM article_tags WHERE article_id = $1")
+                    .bind(article_id)
+                    .execute(&mut **tx)
+                    .await?;
+                sqlx::query("DELETE FROM reports WHERE article_id = $1")
+                    .bind(article_id)
+                    .execute(&mut **tx)
+                    .await?;
+                sqlx::query("DELETE FROM comments WHERE article_id = $1")
+                    .bind(article_id)
+                    .execute(&mut **tx)
+                    .await?;
+                sqlx::query("DELETE FROM articles WHERE id = $1")
+                    .bind(article_id)
+                    .execute(&mut **tx)
+                    .await?;
+                Ok(media
+                    .into_iter()
+                    .flat_map(|(media_path, thumb_path)| std::iter::once(media_path).chain(thumb_path))
+                    .collect())
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl $crate::db::ArticleRepository for $repo {
+            async fn run_migrations(
+                &self,
+            ) -> Result<Vec<&'static sqlx::migrate::Migration>, sqlx::migrate::MigrateError> {
+                use sqlx::migrate::Migrate as _;
+                static MIGRATOR: sqlx::migrate::Migrator = $migrator;
+
+                let applied: std::collections::HashSet<i64> = {
+                    let mut conn = self.pool.acquire().await?;
+                    conn.ensure_migrations_table().await?;
+                    conn.list_applied_migrations()
+                        .await?
+                        .into_iter()
+                        .map(|m| m.version)
+                        .collect()
+                };
+                MIGRATOR.run(&self.pool).await?;
+                Ok(MIGRATOR.iter().filter(|m| !applied.contains(&m.version)).collect())
+            }
+
+            async fn ping(&self) -> Result<(), sqlx::Error> {
+                sqlx::query("SELECT 1").execute(&self.pool).await?;
+                Ok(())
+            }
+
+            async fn close(&self) {
+                self.pool.close().await;
+            }
+
+            async fn insert_board(&self, slug: &str, title: &str, description: &str) -> Result<(), sqlx::Error> {
+                sqlx::query("INSERT INTO boards (slug, title, description) VALUES ($1, $2, $3)")
+                    .bind(slug)
+                    .bind(title)
+                    .bind(description)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(())
+            }
+
+            async fn get_board(&self, slug: &str) -> Result<$crate::DbBoard, sqlx::Error> {
+                sqlx::query_as("SELECT id, slug, title, description FROM boards WHERE slug = $1")
+                    .bind(slug)
+                    .fetch_one(&self.pool)
+                    .await
+            }
+
+            async fn list_boards(&self) -> Result<Vec<$crate::db::BoardSummary>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT b.slug, b.title, b.description, COUNT(a.id) AS article_count \
+                     FROM boards b LEFT JOIN articles a ON a.board_id = b.id AND a.deleted_at IS NULL \
+                     GROUP BY b.id, b.slug, b.title, b.description ORDER BY b.slug",
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn all_boards(&self) -> Result<Vec<$crate::DbBoard>, sqlx::Error> {
+                sqlx::query_as("SELECT id, slug, title, description FROM boards ORDER BY id")
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn export_articles(&self, after_id: i32, limit: i64) -> Result<Vec<$crate::db::ExportArticle>, sqlx::Error> {
+                let mut articles: Vec<$crate::db::ExportArticle> = sqlx::query_as(
+                    "SELECT id, board_id, title, slug, body, name, tripcode, created_at, bump_time, edited_at, \
+                     archived_at, deleted_at, is_sticky, delete_password_hash, poster_ip \
+                     FROM articles WHERE id > $1 ORDER BY id LIMIT $2",
+                )
+                .bind(after_id)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?;
+                if articles.is_empty() {
+                    return Ok(articles);
+                }
+                let index: std::collections::HashMap<i32, usize> =
+                    articles.iter().enumerate().map(|(i, a)| (a.id, i)).collect();
+                // The same `article_id IN (...)` for each of the queries below
+                let in_articles = |select: &str, order: &str| {
+                    let mut query = sqlx::QueryBuilder::<$db>::new(select);
+                    let mut ids = query.separated(", ");
+                    for article in &articles {
+                        ids.push_bind(article.id);
+                    }
+                    query.push(order);
+                    query
+                };
+
+                let tags: Vec<(i32, String)> = in_articles(
+                    "SELECT at.article_id, t.name FROM article_tags at JOIN tags t ON t.id = at.tag_id \
+                     WHERE at.article_id IN (",
+                    ") ORDER BY t.name",
+                )
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await?;
+                let comments: Vec<$crate::db::ExportComment> = in_articles(
+                    "SELECT id, article_id, parent_comment_id, comment, name, tripcode, created_at, deleted_at, \
+                     delete_password_hash, poster_ip FROM comments WHERE article_id IN (",
+                    ") ORDER BY id",
+                )
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await?;
+                let media: Vec<$crate::db::ExportMedia> = in_articles(
+                    "SELECT id, article_id, comment_id, media_path, original_name, thumb_path, \
+                     byte_size, mime_type, width, height, content_hash FROM article_media WHERE article_id IN (",
+                    ") ORDER BY id",
+                )
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await?;
+
+                for (article_id, tag) in tags {
+                    articles[index[&article_id]].tags.push(tag);
+                }
+                // Where each comment went, for its media
+                let mut placed = std::collections::HashMap::new();
+                for comment in comments {
+                    let article = &mut articles[index[&comment.article_id]];
+                    placed.insert(comment.id, (index[&comment.article_id], article.comments.len()));
+                    article.comments.push(comment);
+                }
+                for item in media {
+                    match item.comment_id.and_then(|id| placed.get(&id)) {
+                        Some(&(a, c)) => articles[a].comments[c].media.push(item),
+                        None => articles[index[&item.article_id]].media.push(item),
+                    }
+                }
+                Ok(articles)
+            }
+
+            async fn insert_article(
+                &self,
+                article: $crate::db::NewArticleRow<'_>,
+            ) -> Result<(i32, Vec<(i32, $crate::Media)>), sqlx::Error> {
+                let mut tx = self.begin_write().await?;
+
+                let slug = match article.slug {
+                    Some(base) => Some(Self::unique_slug(&mut tx, base).await?),
+                    None => None,
+                };
+
+                let article_id: i32 = sqlx::query_scalar(
+                    "INSERT INTO articles (board_id, title, slug, body, name, tripcode, created_at, bump_time, delete_password_hash, poster_ip) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING id",
+                )
+                .bind(article.board_id)
+                .bind(article.title)
+                .bind(slug)
+                .bind(article.body)
+                .bind(article.name)
+                .bind(article.tripcode)
+                .bind(article.created_at)
+                .bind(article.bump_time)
+                .bind(article.delete_password_hash)
+                .bind(article.poster_ip)
+                .fetch_one(&mut *tx)
+                .await?;
+
+                Self::tag_article(&mut tx, article_id, article.tags).await?;
+
+                let mut media = Vec::with_capacity(article.media.len());
+                for item in article.media {
+                    media.push(Self::insert_media_row(&mut tx, article_id, None, item).await?);
+                }
+
+                tx.commit().await?;
+                Ok((article_id, media))
+            }
+
+            async fn find_article(&self, title: &str, created_at: i64) -> Result<Option<i32>, sqlx::Error> {
+                sqlx::query_scalar("SELECT id FROM articles WHERE title = $1 AND created_at = $2 ORDER BY id LIMIT 1")
+                    .bind(title)
+                    .bind(created_at)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn import_article(&self, board_id: i32, article: &$crate::db::ExportArticle) -> Result<i32, sqlx::Error> {
+                let mut tx = self.begin_write().await?;
+
+                let slug = match article.slug.as_deref() {
+                    Some(base) => Some(Self::unique_slug(&mut tx, base).await?),
+                    None => None,
+                };
+                let article_id: i32 = sqlx::query_scalar(
+                    "INSERT INTO articles (board_id, title, slug, body, name, tripcode, created_at, bump_time, edited_at, \
+                     archived_at, deleted_at, is_sticky, delete_password_hash, poster_ip) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) RETURNING id",
+                )
+                .bind(board_id)
+                .bind(&article.title)
+                .bind(slug)
+                .bind(&article.body)
+                .bind(&article.name)
+                .bind(&article.tripcode)
+                .bind(article.created_at)
+                .bind(article.bump_time)
+                .bind(article.edited_at)
+                .bind(article.archived_at)
+                .bind(article.deleted_at)
+                .bind(article.is_sticky)
+                .bind(&article.delete_password_hash)
+                .bind(&article.poster_ip)
+                .fetch_one(&mut *tx)
+                .await?;
+                Self::tag_article(&mut tx, article_id, &article.tags).await?;
+                for media in &article.media {
+                    Self::insert_media_row(&mut tx, article_id, None, &media.to_media()).await?;
+                }
+
+                // Exported ids to new ones; a reply always comes after its parent
+                let mut comment_ids = std::collections::HashMap::new();
+                for comment in &article.comments {
+                    let comment_id: i32 = sqlx::query_scalar(
+                        "INSERT INTO comments (article_id, parent_comment_id, comment, name, tripcode, created_at, \
+                         deleted_at, delete_password_hash, poster_ip) \
+                         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+                    )
+                    .bind(article_id)
+                    .bind(comment.parent_comment_id.and_then(|id| comment_ids.get(&id).copied()))
+                    .bind(&comment.comment)
+                    .bind(&comment.name)
+                    .bind(&comment.tripcode)
+                    .bind(comment.created_at)
+                    .bind(comment.deleted_at)
+                    .bind(&comment.delete_password_hash)
+                    .bind(&comment.poster_ip)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    comment_ids.insert(comment.id, comment_id);
+                    for media in &comment.media {
+                        Self::insert_media_row(&mut tx, article_id, Some(comment_id), &media.to_media()).await?;
+                    }
+                }
+
+                tx.commit().await?;
+                Ok(article_id)
+            }
+
+            async fn count_articles(&self, board_id: i32) -> Result<i64, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM articles WHERE board_id = $1 AND deleted_at IS NULL AND archived_at IS NULL",
+                )
+                    .bind(board_id)
+                    .fetch_one(&self.pool)
+                    .await
+            }
+
+            async fn search(
+                &self,
+                board_id: i32,
+                query: &str,
+                scope: $crate::db::SearchScope,
+                limit: i64,
+                offset: i64,
+            ) -> Result<Vec<$crate::db::SearchRow>, sqlx::Error> {
+                self.search_rows(board_id, query, scope, limit, offset).await
+            }
+
+            async fn count_search_results(
+                &self,
+                board_id: i32,
+                query: &str,
+                scope: $crate::db::SearchScope,
+            ) -> Result<i64, sqlx::Error> {
+                self.count_search_rows(board_id, query, scope).await
+            }
+
+            async fn list_articles(
+                &self,
+                board_id: i32,
+                sort: $crate::db::ArticleSort,
+                limit: i64,
+                offset: i64,
+            ) -> Result<Vec<$crate::db::ListedArticle>, sqlx::Error> {
+                sqlx::query_as(&format!(
+                    "SELECT {} FROM articles a {} \
+                     WHERE a.board_id = $1 AND a.deleted_at IS NULL AND a.archived_at IS NULL \
+                     ORDER BY {} LIMIT $2 OFFSET $3",
+                    $crate::db::LISTED_ARTICLE_COLUMNS,
+                    $crate::db::FIRST_MEDIA_JOIN,
+                    sort.order_by(),
+                ))
+                .bind(board_id)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn count_archived_articles(&self, board_id: i32) -> Result<i64, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM articles WHERE board_id = $1 AND deleted_at IS NULL AND archived_at IS NOT NULL",
+                )
+                .bind(board_id)
+                .fetch_one(&self.pool)
+                .await
+            }
+
+            async fn list_archived_articles(
+                &self,
+                board_id: i32,
+                sort: $crate::db::ArticleSort,
+                limit: i64,
+                offset: i64,
+            ) -> Result<Vec<$crate::db::ListedArticle>, sqlx::Error> {
+                sqlx::query_as(&format!(
+                    "SELECT {} FROM articles a {} \
+                     WHERE a.board_id = $1 AND a.deleted_at IS NULL AND a.archived_at IS NOT NULL \
+                     ORDER BY {} LIMIT $2 OFFSET $3",
+                    $crate::db::LISTED_ARTICLE_COLUMNS,
+                    $crate::db::FIRST_MEDIA_JOIN,
+                    sort.order_by(),
+                ))
+                .bind(board_id)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn archive_overflow(&self, board_id: i32, keep: i64, archived_at: i64) -> Result<u64, sqlx::Error> {
+                let result = sqlx::query(
+                    "UPDATE articles SET archived_at = $3 \
+                     WHERE board_id = $1 AND deleted_at IS NULL AND archived_at IS NULL AND NOT is_sticky AND id NOT IN ( \
+                         SELECT id FROM articles \
+                         WHERE board_id = $1 AND deleted_at IS NULL AND archived_at IS NULL AND NOT is_sticky \
+                         ORDER BY bump_time DESC, id DESC LIMIT $2)",
+                )
+                .bind(board_id)
+                .bind(keep)
+                .bind(archived_at)
+                .execute(&self.pool)
+                .await?;
+                Ok(result.rows_affected())
+            }
+
+            async fn count_tagged_articles(&self, board_id: i32, tag: &str) -> Result<i64, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM articles a \
+                     JOIN article_tags ON article_tags.article_id = a.id JOIN tags t ON t.id = article_tags.tag_id \
+                     WHERE a.board_id = $1 AND a.deleted_at IS NULL AND a.archived_at IS NULL AND t.name = $2",
+                )
+                .bind(board_id)
+                .bind(tag)
+                .fetch_one(&self.pool)
+                .await
+            }
+
+            async fn list_tagged_articles(
+                &self,
+                board_id: i32,
+                tag: &str,
+                sort: $crate::db::ArticleSort,
+                limit: i64,
+                offset: i64,
+            ) -> Result<Vec<$crate::db::ListedArticle>, sqlx::Error> {
+                sqlx::query_as(&format!(
+                    "SELECT {} FROM articles a {} \
+                     JOIN article_tags ON article_tags.article_id = a.id JOIN tags tagged ON tagged.id = article_tags.tag_id \
+                     WHERE a.board_id = $1 AND a.deleted_at IS NULL AND a.archived_at IS NULL AND tagged.name = $2 \
+                     ORDER BY {} LIMIT $3 OFFSET $4",
+                    $crate::db::LISTED_ARTICLE_COLUMNS,
+                    $crate::db::FIRST_MEDIA_JOIN,
+                    sort.order_by(),
+                ))
+                .bind(board_id)
+                .bind(tag)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn trending_articles(
+                &self,
+                board_id: i32,
+                recent_since: i64,
+                since: i64,
+                limit: i64,
+            ) -> Result<Vec<$crate::db::ListedArticle>, sqlx::Error> {
+                sqlx::query_as(&format!(
+                    "SELECT {} FROM articles a {} \
+                     JOIN (SELECT article_id, SUM(CASE WHEN created_at >= $2 THEN 3 ELSE 1 END) AS score FROM comments \
+                         WHERE deleted_at IS NULL AND created_at >= $3 GROUP BY article_id) activity ON activity.article_id = a.id \
+                     WHERE a.board_id = $1 AND a.deleted_at IS NULL AND a.archived_at IS NULL \
+                     ORDER BY activity.score DESC, a.bump_time DESC, a.id DESC LIMIT $4",
+                    $crate::db::LISTED_ARTICLE_COLUMNS,
+                    $crate::db::FIRST_MEDIA_JOIN,
+                ))
+                .bind(board_id)
+                .bind(recent_since)
+                .bind(since)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn get_article(&self, article_id: i32) -> Result<$crate::DbArticle, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT id, board_id, title, slug, body, name, tripcode, created_at, bump_time, edited_at, archived_at, is_sticky FROM articles WHERE id = $1 AND deleted_at IS NULL",
+                )
+                .bind(article_id)
+                .fetch_one(&self.pool)
+                .await
+            }
+
+            async fn related_articles(
+                &self,
+                article: &$crate::Article,
+                limit: i64,
+            ) -> Result<Vec<$crate::db::RelatedArticle>, sqlx::Error> {
+                if article.tags.is_empty() {
+                    return self.related_by_title(article.board_id, article.id, &article.title, limit).await;
+                }
+                sqlx::query_as(
+                    "SELECT a.id, a.title, a.slug FROM articles a \
+                     JOIN article_tags shared ON shared.article_id = a.id \
+                     WHERE shared.tag_id IN (SELECT tag_id FROM article_tags WHERE article_id = $1) \
+                     AND a.board_id = $2 AND a.id <> $1 AND a.deleted_at IS NULL \
+                     GROUP BY a.id, a.title, a.slug, a.bump_time \
+                     ORDER BY COUNT(*) DESC, a.bump_time DESC, a.id DESC LIMIT $3",
+                )
+                .bind(article.id)
+                .bind(article.board_id)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn random_article(&self, board_id: i32) -> Result<Option<(i32, Option<String>)>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT id, slug FROM articles WHERE board_id = $1 AND deleted_at IS NULL AND archived_at IS NULL \
+                     ORDER BY random() LIMIT 1",
+                )
+                .bind(board_id)
+                .fetch_optional(&self.pool)
+                .await
+            }
+
+            async fn get_article_with_media(&self, article_id: i32) -> Result<$crate::Article, sqlx::Error> {
+                let rows: Vec<$crate::db::ArticleMediaRow> = sqlx::query_as(
+                    "SELECT a.id, a.board_id, a.title, a.slug, a.body, a.name, a.tripcode, a.created_at, a.bump_time, \
+                     a.edited_at, a.archived_at, a.is_sticky, (SELECT string_agg(t.name, ',' ORDER BY t.name) FROM article_tags \
+                         JOIN tags t ON t.id = article_tags.tag_id WHERE article_tags.article_id = a.id) AS tags, \
+                     (SELECT MAX(c.created_at) FROM comments c WHERE c.article_id = a.id AND c.deleted_at IS NULL) AS last_comment_at, \
+                     m.media_path, m.original_name, m.thumb_path, m.byte_size, m.mime_type, m.width, m.height \
+                     FROM articles a \
+                     LEFT JOIN article_media m ON m.article_id = a.id AND m.comment_id IS NULL \
+                     WHERE a.id = $1 AND a.deleted_at IS NULL ORDER BY m.id",
+                )
+                .bind(article_id)
+                .fetch_all(&self.pool)
+                .await?;
+                $crate::db::article_from_rows(rows)
+            }
+
+            async fn update_article(
+                &self,
+                article_id: i32,
+                title: &str,
+                body: &str,
+                edited_at: i64,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query("UPDATE articles SET title = $1, body = $2, edited_at = $3 WHERE id = $4")
+                    .bind(title)
+                    .bind(body)
+                    .bind(edited_at)
+                    .bind(article_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(())
+            }
+
+            async fn set_sticky(&self, article_id: i32, sticky: bool) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("UPDATE articles SET is_sticky = $1 WHERE id = $2 AND deleted_at IS NULL")
+                    .bind(sticky)
+                    .bind(article_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn article_password_hash(
+                &self,
+                article_id: i32,
+            ) -> Result<Option<Option<String>>, sqlx::Error> {
+                sqlx::query_scalar("SELECT delete_password_hash FROM articles WHERE id = $1 AND deleted_at IS NULL")
+                    .bind(article_id)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn soft_delete_article(&self, article_id: i32, deleted_at: i64) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("UPDATE articles SET deleted_at = $1 WHERE id = $2 AND deleted_at IS NULL")
+                    .bind(deleted_at)
+                    .bind(article_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn restore_article(&self, article_id: i32) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("UPDATE articles SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL")
+                    .bind(article_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn deleted_articles(&self, limit: i64) -> Result<Vec<$crate::db::DeletedArticleRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT a.id, b.slug AS board_slug, a.title, a.body, a.deleted_at \
+                     FROM articles a JOIN boards b ON b.id = a.board_id \
+                     WHERE a.deleted_at IS NOT NULL ORDER BY a.deleted_at DESC, a.id DESC LIMIT $1",
+                )
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn article_ids_deleted_before(&self, cutoff: i64) -> Result<Vec<i32>, sqlx::Error> {
+                sqlx::query_scalar("SELECT id FROM articles WHERE deleted_at <= $1 ORDER BY id")
+                    .bind(cutoff)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn delete_article(&self, article_id: i32) -> Result<Vec<String>, sqlx::Error> {
+                let mut tx = self.begin_write().await?;
+                let media_paths = Self::delete_article_rows(&mut tx, article_id).await?;
+                let media_paths = Self::unreferenced(&mut tx, media_paths).await?;
+                tx.commit().await?;
+                Ok(media_paths)
+            }
+
+            async fn prune_over_cap(&self, board_id: i32, cap: i64) -> Result<Vec<String>, sqlx::Error> {
+                let mut tx = self.begin_write().await?;
+                let count: i64 =
+                    sqlx::query_scalar("SELECT COUNT(*) FROM articles WHERE board_id = $1 AND deleted_at IS NULL")
+                        .bind(board_id)
+                        .fetch_one(&mut *tx)
+                        .await?;
+                if count <= cap {
+                    return Ok(Vec::new());
+                }
+                let article_ids: Vec<i32> = sqlx::query_scalar(
+                    "SELECT id FROM articles WHERE board_id = $1 AND deleted_at IS NULL AND NOT is_sticky \
+                     ORDER BY bump_time, id LIMIT $2",
+                )
+                .bind(board_id)
+                .bind(count - cap)
+                .fetch_all(&mut *tx)
+                .await?;
+
+                let mut media_paths = Vec::new();
+                for article_id in article_ids {
+                    media_paths.extend(Self::delete_article_rows(&mut tx, article_id).await?);
+                }
+                let media_paths = Self::unreferenced(&mut tx, media_paths).await?;
+                tx.commit().await?;
+                Ok(media_paths)
+            }
+
+            async fn article_ids_beyond(&self, keep: i64) -> Result<Vec<i32>, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT id FROM articles WHERE NOT is_sticky \
+                     AND id NOT IN (SELECT id FROM articles WHERE NOT is_sticky \
+                     ORDER BY created_at DESC, id DESC LIMIT $1) ORDER BY created_at, id",
+                )
+                .bind(keep)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn insert_media(
+                &self,
+                article_id: i32,
+                comment_id: Option<i32>,
+                media: &$crate::Media,
+            ) -> Result<(i32, $crate::Media), sqlx::Error> {
+                let mut tx = self.begin_write().await?;
+                let stored = Self::insert_media_row(&mut tx, article_id, comment_id, media).await?;
+                tx.commit().await?;
+                Ok(stored)
+            }
+
+            async fn media_by_hash(&self, content_hash: &str) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+                sqlx::query_as("SELECT media_path, thumb_path FROM article_media WHERE content_hash = $1 ORDER BY id LIMIT 1")
+                    .bind(content_hash)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn media_hashes(
+                &self,
+                article_id: i32,
+            ) -> Result<std::collections::HashMap<String, String>, sqlx::Error> {
+                let rows: Vec<(String, String)> = sqlx::query_as(
+                    "SELECT media_path, content_hash FROM article_media WHERE article_id = $1 AND content_hash IS NOT NULL",
+                )
+                .bind(article_id)
+                .fetch_all(&self.pool)
+                .await?;
+                Ok(rows.into_iter().collect())
+            }
+
+            async fn set_media_thumb(&self, media_id: i32, thumb_path: &str) -> Result<(), sqlx::Error> {
+                sqlx::query("UPDATE article_media SET thumb_path = $1 WHERE id = $2")
+                    .bind(thumb_path)
+                    .bind(media_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(())
+            }
+
+            async fn media_for_articles(
+                &self,
+                article_ids: &[i32],
+            ) -> Result<std::collections::HashMap<i32, Vec<$crate::Media>>, sqlx::Error> {
+                let mut media: std::collections::HashMap<i32, Vec<$crate::Media>> =
+                    std::collections::HashMap::new();
+                if article_ids.is_empty() {
+                    return Ok(media);
+                }
+
+                let mut query = sqlx::QueryBuilder::<$db>::new(
+                    "SELECT article_id, media_path, original_name, thumb_path, byte_size, mime_type, width, height \
+                     FROM article_media WHERE article_id IN (",
+                );
+                let mut ids = query.separated(", ");
+                for &id in article_ids {
+                    ids.push_bind(id);
+                }
+                query.push(") AND comment_id IS NULL ORDER BY id");
+
+                let rows: Vec<$crate::db::ListedMediaRow> = query.build_query_as().fetch_all(&self.pool).await?;
+                for row in rows {
+                    media.entry(row.article_id).or_default().extend(row.media.into_media());
+                }
+                Ok(media)
+            }
+
+            async fn all_media_paths(&self) -> Result<Vec<String>, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT media_path FROM article_media \
+                     UNION SELECT thumb_path FROM article_media WHERE thumb_path IS NOT NULL",
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn media_missing_metadata(
+                &self,
+                after_id: i32,
+                limit: i64,
+            ) -> Result<Vec<(i32, String)>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT id, media_path FROM article_media WHERE byte_size IS NULL AND id > $1 ORDER BY id LIMIT $2",
+                )
+                .bind(after_id)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn set_media_metadata(
+                &self,
+                media_id: i32,
+                byte_size: i64,
+                mime_type: Option<&str>,
+                dimensions: Option<(i32, i32)>,
+            ) -> Result<(), sqlx::Error> {
+                let (width, height) = dimensions.unzip();
+                sqlx::query(
+                    "UPDATE article_media SET byte_size = $1, mime_type = COALESCE($2, mime_type), width = $3, height = $4 \
+                     WHERE id = $5",
+                )
+                .bind(byte_size)
+                .bind(mime_type)
+                .bind(width)
+                .bind(height)
+                .bind(media_id)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+
+            async fn storage_usage(&self) -> Result<Option<i64>, sqlx::Error> {
+                sqlx::query_scalar("SELECT bytes FROM storage_usage WHERE id = 1")
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn set_storage_usage(&self, bytes: i64) -> Result<(), sqlx::Error> {
+                sqlx::query(
+                    "INSERT INTO storage_usage (id, bytes) VALUES (1, $1) \
+                     ON CONFLICT (id) DO UPDATE SET bytes = excluded.bytes",
+                )
+                .bind(bytes)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+
+            async fn add_storage_usage(&self, bytes: i64) -> Result<(), sqlx::Error> {
+                sqlx::query("UPDATE storage_usage SET bytes = CASE WHEN bytes + $1 < 0 THEN 0 ELSE bytes + $1 END WHERE id = 1")
+                    .bind(bytes)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(())
+            }
+
+            async fn insert_comment(&self, comment: $crate::db::NewCommentRow<'_>) -> Result<i32, sqlx::Error> {
+                // Committed explicitly for the same reason as insert_ban
+                let mut tx = self.begin_write().await?;
+                let comment_id = sqlx::query_scalar(
+                    "INSERT INTO comments (article_id, comment, name, tripcode, delete_password_hash, poster_ip, created_at, parent_comment_id) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+                )
+                .bind(comment.article_id)
+                .bind(comment.comment)
+                .bind(comment.name)
+                .bind(comment.tripcode)
+                .bind(comment.delete_password_hash)
+                .bind(comment.poster_ip)
+                .bind(comment.created_at)
+                .bind(comment.parent_comment_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                if let Some(bump_time) = comment.bump_to {
+                    // The count includes the new comment, so with a limit of
+                    // 300 the 299th comment bumps and the 300th doesn't
+                    sqlx::query(
+                        "UPDATE articles SET bump_time = $1 WHERE id = $2 AND ($3 = 0 OR \
+                         (SELECT COUNT(*) FROM comments WHERE article_id = $2 AND deleted_at IS NULL) < $3)",
+                    )
+                    .bind(bump_time)
+                    .bind(comment.article_id)
+                    .bind(comment.bump_limit)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                tx.commit().await?;
+                Ok(comment_id)
+            }
+
+            async fn list_comments(&self, article_id: i32) -> Result<Vec<$crate::DbComment>, sqlx::Error> {
+                let comments = sqlx::query_as(
+                    "SELECT id, CASE WHEN deleted_at IS NULL THEN comment ELSE '' END AS comment, \
+                     CASE WHEN deleted_at IS NULL THEN name ELSE '' END AS name, \
+                     CASE WHEN deleted_at IS NULL THEN tripcode END AS tripcode, created_at, \
+                     parent_comment_id, deleted_at IS NOT NULL AS deleted \
+                     FROM comments WHERE article_id = $1 ORDER BY created_at, id",
+                )
+                .bind(article_id)
+                .fetch_all(&self.pool)
+                .await?;
+                Ok($crate::thread::prune_deleted(comments))
+            }
+
+            async fn list_comments_page(
+                &self,
+                article_id: i32,
+                limit: i64,
+                offset: i64,
+            ) -> Result<(Vec<$crate::DbComment>, std::collections::HashMap<i32, Vec<$crate::Media>>), sqlx::Error> {
+                // Each comment is numbered by the live comments before it, which
+                // for a live one is its position and for a deleted one is the
+                // position of the next live one
+                let rows: Vec<$crate::db::CommentMediaRow> = sqlx::query_as(
+                    "SELECT n.id, n.comment, n.name, n.tripcode, n.created_at, n.parent_comment_id, n.deleted, \
+                     m.media_path, m.original_name, m.thumb_path, m.byte_size, m.mime_type, m.width, m.height FROM ( \
+                         SELECT id, CASE WHEN deleted_at IS NULL THEN comment ELSE '' END AS comment, \
+                         CASE WHEN deleted_at IS NULL THEN name ELSE '' END AS name, \
+                         CASE WHEN deleted_at IS NULL THEN tripcode END AS tripcode, created_at, \
+                         parent_comment_id, deleted_at IS NOT NULL AS deleted, \
+                         COALESCE(SUM(CASE WHEN deleted_at IS NULL THEN 1 ELSE 0 END) OVER ( \
+                             ORDER BY created_at, id ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING), 0) AS live_before \
+                         FROM comments WHERE article_id = $1) n \
+                     LEFT JOIN article_media m ON m.comment_id = n.id AND NOT n.deleted \
+                     WHERE n.live_before >= $2 AND n.live_before < $3 \
+                     ORDER BY n.created_at, n.id, m.id",
+                )
+                .bind(article_id)
+                .bind(offset)
+                .bind(offset + limit)
+                .fetch_all(&self.pool)
+                .await?;
+                let (comments, media) = $crate::db::comments_from_rows(rows);
+                Ok(($crate::thread::prune_deleted(comments), media))
+            }
+
+            async fn live_comment_ids(&self, article_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT id FROM comments WHERE article_id = $1 AND deleted_at IS NULL ORDER BY created_at, id",
+                )
+                    .bind(article_id)
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn comment_password_hash(
+                &self,
+                article_id: i32,
+                comment_id: i32,
+            ) -> Result<Option<Option<String>>, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT delete_password_hash FROM comments WHERE id = $1 AND article_id = $2 AND deleted_at IS NULL",
+                )
+                .bind(comment_id)
+                .bind(article_id)
+                .fetch_optional(&self.pool)
+                .await
+            }
+
+            async fn soft_delete_comment(
+                &self,
+                article_id: i32,
+                comment_id: i32,
+                deleted_at: i64,
+            ) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query(
+                    "UPDATE comments SET deleted_at = $1 WHERE id = $2 AND article_id = $3 AND deleted_at IS NULL",
+                )
+                .bind(deleted_at)
+                .bind(comment_id)
+                .bind(article_id)
+                .execute(&self.pool)
+                .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn comment_article_id(&self, comment_id: i32) -> Result<Option<i32>, sqlx::Error> {
+                sqlx::query_scalar(
+                    "SELECT c.article_id FROM comments c JOIN articles a ON a.id = c.article_id \
+                     WHERE c.id = $1 AND c.deleted_at IS NULL AND a.deleted_at IS NULL",
+                )
+                .bind(comment_id)
+                .fetch_optional(&self.pool)
+                .await
+            }
+
+            async fn restore_comment(&self, comment_id: i32) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("UPDATE comments SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL")
+                    .bind(comment_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn deleted_comments(&self, limit: i64) -> Result<Vec<$crate::db::DeletedCommentRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT c.id, c.article_id, b.slug AS board_slug, a.title AS article_title, a.slug AS article_slug, \
+                     c.comment, c.deleted_at \
+                     FROM comments c JOIN articles a ON a.id = c.article_id JOIN boards b ON b.id = a.board_id \
+                     WHERE c.deleted_at IS NOT NULL ORDER BY c.deleted_at DESC, c.id DESC LIMIT $1",
+                )
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn purge_comments_deleted_before(&self, cutoff: i64) -> Result<(u64, Vec<String>), sqlx::Error> {
+                let mut tx = self.begin_write().await?;
+                sqlx::query(
+                    "DELETE FROM reports WHERE comment_id IN (SELECT id FROM comments WHERE deleted_at <= $1)",
+                )
+                .bind(cutoff)
+                .execute(&mut *tx)
+                .await?;
+                let media: Vec<(String, Option<String>)> = sqlx::query_as(
+                    "DELETE FROM article_media WHERE comment_id IN (SELECT id FROM comments WHERE deleted_at <= $1) \
+                     RETURNING media_path, thumb_path",
+                )
+                .bind(cutoff)
+                .fetch_all(&mut *tx)
+                .await?;
+                let result = sqlx::query("DELETE FROM comments WHERE deleted_at <= $1")
+                    .bind(cutoff)
+                    .execute(&mut *tx)
+                    .await?;
+                let media_paths = media
+                    .into_iter()
+                    .flat_map(|(media_path, thumb_path)| std::iter::once(media_path).chain(thumb_path))
+                    .collect();
+                let media_paths = Self::unreferenced(&mut tx, media_paths).await?;
+                tx.commit().await?;
+                Ok((result.rows_affected(), media_paths))
+            }
+
+            async fn comment_exists(&self, article_id: i32, comment_id: i32) -> Result<bool, sqlx::Error> {
+                let found: Option<i32> = sqlx::query_scalar(
+                    "SELECT id FROM comments WHERE id = $1 AND article_id = $2 AND deleted_at IS NULL",
+                )
+                .bind(comment_id)
+                .bind(article_id)
+                .fetch_optional(&self.pool)
+                .await?;
+                Ok(found.is_some())
+            }
+
+            async fn insert_report(&self, report: $crate::db::NewReportRow<'_>) -> Result<bool, sqlx::Error> {
+                // Duplicates run into reports_open_dedupe_idx
+                let result = sqlx::query(
+                    "INSERT INTO reports (article_id, comment_id, reason, reporter_ip, created_at) \
+                     VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+                )
+                .bind(report.article_id)
+                .bind(report.comment_id)
+                .bind(report.reason)
+                .bind(report.reporter_ip)
+                .bind(report.created_at)
+                .execute(&self.pool)
+                .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn open_reports(&self) -> Result<Vec<$crate::db::ReportRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT r.id, r.article_id, r.comment_id, r.reason, r.created_at, b.slug AS board_slug, \
+                     a.title AS article_title, a.slug AS article_slug, c.comment \
+                     FROM reports r JOIN articles a ON a.id = r.article_id JOIN boards b ON b.id = a.board_id \
+                     LEFT JOIN comments c ON c.id = r.comment_id \
+                     WHERE r.resolved_at IS NULL AND a.deleted_at IS NULL AND c.deleted_at IS NULL \
+                     ORDER BY r.created_at, r.id",
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn report_target(&self, report_id: i32) -> Result<Option<(i32, Option<i32>)>, sqlx::Error> {
+                sqlx::query_as("SELECT article_id, comment_id FROM reports WHERE id = $1")
+                    .bind(report_id)
+                    .fetch_optional(&self.pool)
+                    .await
+            }
+
+            async fn resolve_reports(
+                &self,
+                article_id: i32,
+                comment_id: Option<i32>,
+                resolved_at: i64,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query(
+                    "UPDATE reports SET resolved_at = $1 \
+                     WHERE article_id = $2 AND COALESCE(comment_id, 0) = COALESCE($3, 0) AND resolved_at IS NULL",
+                )
+                .bind(resolved_at)
+                .bind(article_id)
+                .bind(comment_id)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+
+            async fn list_word_filters(&self) -> Result<Vec<$crate::db::WordFilterRow>, sqlx::Error> {
+                sqlx::query_as("SELECT id, pattern, is_regex, action, replacement FROM word_filters ORDER BY id")
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn insert_word_filter(
+                &self,
+                pattern: &str,
+                is_regex: bool,
+                action: &str,
+                replacement: &str,
+            ) -> Result<(), sqlx::Error> {
+                sqlx::query(
+                    "INSERT INTO word_filters (pattern, is_regex, action, replacement) VALUES ($1, $2, $3, $4)",
+                )
+                .bind(pattern)
+                .bind(is_regex)
+                .bind(action)
+                .bind(replacement)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+
+            async fn delete_word_filter(&self, filter_id: i32) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("DELETE FROM word_filters WHERE id = $1")
+                    .bind(filter_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn insert_ban(
+                &self,
+                cidr: &str,
+                reason: &str,
+                created_at: i64,
+                expires_at: Option<i64>,
+            ) -> Result<i32, sqlx::Error> {
+                // Committed explicitly: SQLite only finishes a RETURNING
+                // statement when it's stepped again, which a CLI command that
+                // exits right after never does
+                let mut tx = self.begin_write().await?;
+                let ban_id = sqlx::query_scalar(
+                    "INSERT INTO bans (cidr, reason, created_at, expires_at) VALUES ($1, $2, $3, $4) RETURNING id",
+                )
+                .bind(cidr)
+                .bind(reason)
+                .bind(created_at)
+                .bind(expires_at)
+                .fetch_one(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                Ok(ban_id)
+            }
+
+            async fn list_bans(&self) -> Result<Vec<$crate::db::BanRow>, sqlx::Error> {
+                sqlx::query_as("SELECT id, cidr, reason, created_at, expires_at FROM bans ORDER BY id DESC")
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn active_bans(&self, now: i64) -> Result<Vec<$crate::db::BanRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT id, cidr, reason, created_at, expires_at FROM bans \
+                     WHERE expires_at IS NULL OR expires_at > $1 ORDER BY id DESC",
+                )
+                .bind(now)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn delete_ban(&self, ban_id: i32) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("DELETE FROM bans WHERE id = $1")
+                    .bind(ban_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn insert_api_key(
+                &self,
+                key_hash: &str,
+                label: &str,
+                scope: &str,
+                created_at: i64,
+            ) -> Result<i32, sqlx::Error> {
+                // Committed explicitly for the same reason as insert_ban
+                let mut tx = self.begin_write().await?;
+                let key_id = sqlx::query_scalar(
+                    "INSERT INTO api_keys (key_hash, label, scope, created_at) VALUES ($1, $2, $3, $4) RETURNING id",
+                )
+                .bind(key_hash)
+                .bind(label)
+                .bind(scope)
+                .bind(created_at)
+                .fetch_one(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                Ok(key_id)
+            }
+
+            async fn list_api_keys(&self) -> Result<Vec<$crate::db::ApiKeyRow>, sqlx::Error> {
+                sqlx::query_as("SELECT id, key_hash, label, scope, created_at, revoked_at FROM api_keys ORDER BY id")
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn active_api_keys(&self) -> Result<Vec<$crate::db::ApiKeyRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT id, key_hash, label, scope, created_at, revoked_at FROM api_keys \
+                     WHERE revoked_at IS NULL ORDER BY id",
+                )
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn revoke_api_key(&self, key_id: i32, revoked_at: i64) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("UPDATE api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL")
+                    .bind(revoked_at)
+                    .bind(key_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn insert_webhook(&self, url: &str, secret: &str, events: i32, created_at: i64) -> Result<i32, sqlx::Error> {
+                // Committed explicitly for the same reason as insert_ban
+                let mut tx = self.begin_write().await?;
+                let webhook_id = sqlx::query_scalar(
+                    "INSERT INTO webhooks (url, secret, events, created_at) VALUES ($1, $2, $3, $4) RETURNING id",
+                )
+                .bind(url)
+                .bind(secret)
+                .bind(events)
+                .bind(created_at)
+                .fetch_one(&mut *tx)
+                .await?;
+                tx.commit().await?;
+                Ok(webhook_id)
+            }
+
+            async fn list_webhooks(&self) -> Result<Vec<$crate::db::WebhookRow>, sqlx::Error> {
+                sqlx::query_as("SELECT id, url, secret, events, created_at FROM webhooks ORDER BY id")
+                    .fetch_all(&self.pool)
+                    .await
+            }
+
+            async fn delete_webhook(&self, webhook_id: i32) -> Result<bool, sqlx::Error> {
+                let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+                    .bind(webhook_id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            }
+
+            async fn insert_webhook_failure(&self, failure: &$crate::db::WebhookFailureRow) -> Result<(), sqlx::Error> {
+                sqlx::query(
+                    "INSERT INTO webhook_failures (webhook_id, url, event, payload, error, attempts, failed_at) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                )
+                .bind(failure.webhook_id)
+                .bind(&failure.url)
+                .bind(&failure.event)
+                .bind(&failure.payload)
+                .bind(&failure.error)
+                .bind(failure.attempts)
+                .bind(failure.failed_at)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            }
+
+            async fn recent_webhook_failures(&self, limit: i64) -> Result<Vec<$crate::db::WebhookFailureRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT webhook_id, url, event, payload, error, attempts, failed_at FROM webhook_failures \
+                     ORDER BY failed_at DESC, id DESC LIMIT $1",
+                )
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn delete_webhook_failures(&self, cutoff: i64) -> Result<u64, sqlx::Error> {
+                let result = sqlx::query("DELETE FROM webhook_failures WHERE failed_at < $1")
+                    .bind(cutoff)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected())
+            }
+
+            async fn insert_app_error(&self, app_error: &$crate::db::AppErrorRow) -> Result<(), sqlx::Error> {
+                sqlx::query("INSERT INTO app_errors (occurred_at, route, message, context) VALUES ($1, $2, $3, $4)")
+                    .bind(app_error.occurred_at)
+                    .bind(&app_error.route)
+                    .bind(&app_error.message)
+                    .bind(&app_error.context)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(())
+            }
+
+            async fn recent_app_errors(&self, limit: i64) -> Result<Vec<$crate::db::AppErrorRow>, sqlx::Error> {
+                sqlx::query_as(
+                    "SELECT occurred_at, route, message, context FROM app_errors ORDER BY occurred_at DESC, id DESC LIMIT $1",
+                )
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await
+            }
+
+            async fn delete_app_errors(&self, cutoff: Option<i64>) -> Result<u64, sqlx::Error> {
+                let result = sqlx::query("DELETE FROM app_errors WHERE $1 IS NULL OR occurred_at < $1")
+                    .bind(cutoff)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(result.rows_affected())
+            }
+        }
+    };
+}
+
+use impl_article_repository;
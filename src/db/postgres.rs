@@ -0,0 +1,186 @@
+// Postgres backend
+
+use sqlx::PgPool;
+
+use super::{RelatedArticle, SearchRow, SearchScope};
+
+// ts_headline wraps matches in the same markers render::highlight_html expects
+const HEADLINE_OPTIONS: &str = "StartSel=\u{1}, StopSel=\u{2}, MaxWords=35, MinWords=15";
+
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        Ok(PgRepository {
+            pool: PgPool::connect(url).await?,
+        })
+    }
+
+    async fn begin_write(&self) -> Result<sqlx::Transaction<'_, sqlx::Postgres>, sqlx::Error> {
+        self.pool.begin().await
+    }
+
+    // Ends a write transaction's SELECT of rows it relies on, so a delete of
+    // them waits until it commits
+    const FOR_SHARE: &'static str = " FOR SHARE";
+
+    // Full-text search with English stemming, ranked by ts_rank
+    async fn search_rows(
+        &self,
+        board_id: i32,
+        query: &str,
+        scope: SearchScope,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SearchRow>, sqlx::Error> {
+        let sql = match scope {
+            SearchScope::Articles => {
+                "SELECT a.id AS article_id, a.title, a.slug, NULL::INT AS comment_id, a.archived_at IS NOT NULL AS archived, ts_headline('english', a.body, q, $2) AS snippet \
+                 FROM articles a CROSS JOIN plainto_tsquery('english', $1) q \
+                 WHERE a.board_id = $5 AND a.deleted_at IS NULL AND a.search_vector @@ q \
+                 ORDER BY ts_rank(a.search_vector, q) DESC, a.bump_time DESC, a.id DESC LIMIT $3 OFFSET $4"
+            }
+            SearchScope::Comments => {
+                "SELECT c.article_id, a.title, a.slug, c.id AS comment_id, a.archived_at IS NOT NULL AS archived, ts_headline('english', c.comment, q, $2) AS snippet \
+                 FROM comments c JOIN articles a ON a.id = c.article_id CROSS JOIN plainto_tsquery('english', $1) q \
+                 WHERE a.board_id = $5 AND a.deleted_at IS NULL AND c.deleted_at IS NULL AND c.search_vector @@ q \
+                 ORDER BY ts_rank(c.search_vector, q) DESC, c.id DESC LIMIT $3 OFFSET $4"
+            }
+        };
+        sqlx::query_as(sql)
+            .bind(query)
+            .bind(HEADLINE_OPTIONS)
+            .bind(limit)
+            .bind(offset)
+            .bind(board_id)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn count_search_rows(
+        &self,
+        board_id: i32,
+        query: &str,
+        scope: SearchScope,
+    ) -> Result<i64, sqlx::Error> {
+        let sql = match scope {
+            SearchScope::Articles => {
+                "SELECT COUNT(*) FROM articles \
+                 WHERE board_id = $2 AND deleted_at IS NULL AND search_vector @@ plainto_tsquery('english', $1)"
+            }
+            SearchScope::Comments => {
+                "SELECT COUNT(*) FROM comments c JOIN articles a ON a.id = c.article_id \
+                 WHERE a.board_id = $2 AND a.deleted_at IS NULL AND c.deleted_at IS NULL \
+                 AND c.search_vector @@ plainto_tsquery('english', $1)"
+            }
+        };
+        sqlx::query_scalar(sql)
+            .bind(query)
+            .bind(board_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    // Articles matching any word of `title` in the full-text index, best
+    // matches first. plainto_tsquery drops stop words and joins the rest with
+    // &, which becomes | so one shared word is enough.
+    async fn related_by_title(
+        &self,
+        board_id: i32,
+        article_id: i32,
+        title: &str,
+        limit: i64,
+    ) -> Result<Vec<RelatedArticle>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT a.id, a.title, a.slug \
+             FROM articles a CROSS JOIN (SELECT replace(plainto_tsquery('english', $1)::text, '&', '|')::tsquery AS q) words \
+             WHERE a.board_id = $2 AND a.id <> $3 AND a.deleted_at IS NULL AND a.search_vector @@ words.q \
+             ORDER BY ts_rank(a.search_vector, words.q) DESC, a.bump_time DESC, a.id DESC LIMIT $4",
+        )
+        .bind(title)
+        .bind(board_id)
+        .bind(article_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+super::impl_article_repository!(
+    PgRepository,
+    sqlx::Postgres,
+    sqlx::migrate!("migrations/postgres")
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ArticleRepository, NewArticleRow};
+
+    // Only run against a Postgres named by DATABASE_URL, with articles of
+    // their own that are removed again
+    #[tokio::test]
+    async fn search_matches_other_forms_of_a_word() {
+        let Some(url) = std::env::var("DATABASE_URL")
+            .ok()
+            .filter(|url| url.starts_with("postgres"))
+        else {
+            return;
+        };
+        let repo = PgRepository::connect(&url).await.unwrap();
+        repo.run_migrations().await.unwrap();
+        let board = repo.get_board("main").await.unwrap().id;
+        // A word of its own keeps other articles in the database out of the results
+        let word = format!("stem{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+        let mut ids = Vec::new();
+        for (title, body) in [
+            ("Weekend", "I went for a run by the river"),
+            ("Cooking", "Nothing to do with it"),
+        ] {
+            let body = format!("{} {}", body, word);
+            let (id, _) = repo
+                .insert_article(NewArticleRow {
+                    board_id: board,
+                    title,
+                    slug: None,
+                    body: &body,
+                    name: crate::DEFAULT_NAME,
+                    tripcode: None,
+                    created_at: 1,
+                    bump_time: 1,
+                    delete_password_hash: None,
+                    tags: &[],
+                    poster_ip: None,
+                    media: &[],
+                })
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+
+        let query = format!("running {}", word);
+        let found = repo
+            .search(board, &query, SearchScope::Articles, 10, 0)
+            .await;
+        let counted = repo
+            .count_search_results(board, &query, SearchScope::Articles)
+            .await;
+        for id in &ids {
+            repo.delete_article(*id).await.unwrap();
+        }
+
+        let found = found.unwrap();
+        assert_eq!(
+            found.iter().map(|row| row.article_id).collect::<Vec<_>>(),
+            [ids[0]]
+        );
+        assert!(
+            found[0].snippet.contains("\u{1}run\u{2}"),
+            "{:?}",
+            found[0].snippet
+        );
+        assert_eq!(counted.unwrap(), 1);
+    }
+}
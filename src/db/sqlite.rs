@@ -0,0 +1,730 @@
+// SQLite backend, for small deployments without a database server
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use std::str::FromStr;
+
+use super::{RelatedArticle, SearchRow, SearchScope};
+use crate::render::{MATCH_END, MATCH_START};
+
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    // The database file is created on first use. An in-memory database only
+    // lives as long as its connection, so it gets exactly one that is never
+    // recycled.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+        SqliteRepository::connect_with(SqliteConnectOptions::from_str(url)?, in_memory).await
+    }
+
+    // connect with options already parsed, e.g. to change how statements are
+    // logged
+    async fn connect_with(
+        options: SqliteConnectOptions,
+        in_memory: bool,
+    ) -> Result<Self, sqlx::Error> {
+        let options = options.create_if_missing(true).foreign_keys(true);
+        let pool_options = if in_memory {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            SqlitePoolOptions::new()
+        };
+        let pool = pool_options.connect_with(options).await?;
+        Ok(SqliteRepository { pool })
+    }
+
+    // A transaction that writes. SQLite's BEGIN only takes the write lock at
+    // the first write, and one that has read by then fails with "database is
+    // locked" when another commits first, rather than waiting its turn. A
+    // write that changes nothing takes the lock up front instead, as BEGIN
+    // IMMEDIATE would, which sqlx can't issue.
+    async fn begin_write(&self) -> Result<sqlx::Transaction<'_, sqlx::Sqlite>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE boards SET id = id WHERE 0")
+            .execute(&mut *tx)
+            .await?;
+        Ok(tx)
+    }
+
+    // begin_write holds the whole database already, so rows a write
+    // transaction reads need no lock of their own
+    const FOR_SHARE: &'static str = "";
+
+    // Full-text search with English stemming (see the full_text_search
+    // migration), ranked by bm25 with titles weighing more than bodies
+    async fn search_rows(
+        &self,
+        board_id: i32,
+        query: &str,
+        scope: SearchScope,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SearchRow>, sqlx::Error> {
+        let Some(terms) = match_terms(query) else {
+            return Ok(Vec::new());
+        };
+        let sql = match scope {
+            SearchScope::Articles => {
+                "SELECT a.id AS article_id, a.title, a.slug, NULL AS comment_id, a.archived_at IS NOT NULL AS archived, \
+                 snippet(articles_fts, 1, $5, $6, '…', $7) AS snippet \
+                 FROM articles_fts JOIN articles a ON a.id = articles_fts.rowid \
+                 WHERE articles_fts MATCH $1 AND a.board_id = $4 AND a.deleted_at IS NULL \
+                 ORDER BY bm25(articles_fts, 1.0, 0.4), a.bump_time DESC, a.id DESC LIMIT $2 OFFSET $3"
+            }
+            SearchScope::Comments => {
+                "SELECT c.article_id, a.title, a.slug, c.id AS comment_id, a.archived_at IS NOT NULL AS archived, \
+                 snippet(comments_fts, 0, $5, $6, '…', $7) AS snippet \
+                 FROM comments_fts JOIN comments c ON c.id = comments_fts.rowid JOIN articles a ON a.id = c.article_id \
+                 WHERE comments_fts MATCH $1 AND a.board_id = $4 AND a.deleted_at IS NULL AND c.deleted_at IS NULL \
+                 ORDER BY bm25(comments_fts), c.id DESC LIMIT $2 OFFSET $3"
+            }
+        };
+        sqlx::query_as(sql)
+            .bind(terms)
+            .bind(limit)
+            .bind(offset)
+            .bind(board_id)
+            .bind(MATCH_START.to_string())
+            .bind(MATCH_END.to_string())
+            .bind(SNIPPET_TOKENS)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn count_search_rows(
+        &self,
+        board_id: i32,
+        query: &str,
+        scope: SearchScope,
+    ) -> Result<i64, sqlx::Error> {
+        let Some(terms) = match_terms(query) else {
+            return Ok(0);
+        };
+        let sql = match scope {
+            SearchScope::Articles => {
+                "SELECT COUNT(*) FROM articles_fts JOIN articles a ON a.id = articles_fts.rowid \
+                 WHERE articles_fts MATCH $1 AND a.board_id = $2 AND a.deleted_at IS NULL"
+            }
+            SearchScope::Comments => {
+                "SELECT COUNT(*) FROM comments_fts JOIN comments c ON c.id = comments_fts.rowid JOIN articles a ON a.id = c.article_id \
+                 WHERE comments_fts MATCH $1 AND a.board_id = $2 AND a.deleted_at IS NULL AND c.deleted_at IS NULL"
+            }
+        };
+        sqlx::query_scalar(sql)
+            .bind(terms)
+            .bind(board_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    // Without a full-text index, articles whose titles contain the most of the
+    // longer words of `title`
+    async fn related_by_title(
+        &self,
+        board_id: i32,
+        article_id: i32,
+        title: &str,
+        limit: i64,
+    ) -> Result<Vec<RelatedArticle>, sqlx::Error> {
+        let words = title_words(title);
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let matches: Vec<String> = (0..words.len())
+            .map(|i| format!("(title LIKE ${} ESCAPE '\\')", i + 4))
+            .collect();
+        let sql = format!(
+            "SELECT id, title, slug FROM articles \
+             WHERE board_id = $1 AND id <> $2 AND deleted_at IS NULL AND ({}) \
+             ORDER BY {} DESC, bump_time DESC, id DESC LIMIT $3",
+            matches.join(" OR "),
+            matches.join(" + "),
+        );
+        let mut query = sqlx::query_as(&sql)
+            .bind(board_id)
+            .bind(article_id)
+            .bind(limit);
+        for word in &words {
+            query = query.bind(contains_pattern(word));
+        }
+        query.fetch_all(&self.pool).await
+    }
+}
+
+// Words either side of a match in a search snippet, as ts_headline's MaxWords
+const SNIPPET_TOKENS: i64 = 24;
+
+// `query` as an FTS5 query matching rows with all of its words, like
+// plainto_tsquery: each is quoted so none is read as an operator. None when
+// it has no words.
+fn match_terms(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"", word))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+// Words this short are mostly ones like "the" and "and", which would relate
+// everything
+const MIN_TITLE_WORD_CHARS: usize = 4;
+const MAX_TITLE_WORDS: usize = 8;
+
+// The distinct words of `title` worth matching, lowercased
+fn title_words(title: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in title.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() >= MIN_TITLE_WORD_CHARS && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words.truncate(MAX_TITLE_WORDS);
+    words
+}
+
+// A LIKE pattern matching `query` anywhere, with its own wildcards escaped
+fn contains_pattern(query: &str) -> String {
+    let mut pattern = String::with_capacity(query.len() + 2);
+    pattern.push('%');
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+super::impl_article_repository!(
+    SqliteRepository,
+    sqlx::Sqlite,
+    sqlx::migrate!("migrations/sqlite")
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ArticleRepository, ArticleSort, NewArticleRow, NewCommentRow, SearchScope};
+    use crate::Media;
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use sqlx::{ConnectOptions, Connection};
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Statements sqlx has logged at trace level. Only counting_repo's
+    // repository logs them there; every other one logs at debug.
+    static STATEMENTS: AtomicUsize = AtomicUsize::new(0);
+
+    struct StatementCounter;
+
+    impl Log for StatementCounter {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "sqlx::query" && metadata.level() == Level::Trace
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                STATEMENTS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    async fn counting_repo() -> SqliteRepository {
+        let _ = log::set_logger(&StatementCounter);
+        log::set_max_level(LevelFilter::Trace);
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")
+            .unwrap()
+            .log_statements(LevelFilter::Trace);
+        let repo = SqliteRepository::connect_with(options, true).await.unwrap();
+        repo.run_migrations().await.unwrap();
+        repo
+    }
+
+    // What `query` returns and how many statements it ran. The connection's
+    // worker thread logs a statement once it's done with it, which can be
+    // after its rows are returned; a ping is only answered after that.
+    async fn count_statements<T>(
+        repo: &SqliteRepository,
+        query: impl Future<Output = Result<T, sqlx::Error>>,
+    ) -> (T, usize) {
+        repo.pool.acquire().await.unwrap().ping().await.unwrap();
+        let before = STATEMENTS.load(Ordering::SeqCst);
+        let result = query.await.unwrap();
+        repo.pool.acquire().await.unwrap().ping().await.unwrap();
+        (result, STATEMENTS.load(Ordering::SeqCst) - before)
+    }
+
+    fn media(path: &str) -> Media {
+        Media {
+            media_path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Pages take a fixed number of queries, however many articles, comments
+    // and media there are on them
+    #[tokio::test]
+    async fn pages_load_in_a_fixed_number_of_queries() {
+        let repo = counting_repo().await;
+        let board = repo.get_board("main").await.unwrap();
+        let tags = ["news".to_string(), "rust".to_string()];
+        let mut article_ids = Vec::new();
+        for n in 0..3 {
+            let files = [media(&format!("{}a.png", n)), media(&format!("{}b.png", n))];
+            let (article_id, _) = repo
+                .insert_article(NewArticleRow {
+                    board_id: board.id,
+                    title: "Title",
+                    slug: None,
+                    body: "Body",
+                    name: crate::DEFAULT_NAME,
+                    tripcode: None,
+                    created_at: n,
+                    bump_time: n,
+                    delete_password_hash: None,
+                    tags: &tags,
+                    poster_ip: None,
+                    media: &files,
+                })
+                .await
+                .unwrap();
+            article_ids.push(article_id);
+        }
+        let article_id = article_ids[0];
+        for n in 0..3 {
+            let comment_id = repo
+                .insert_comment(NewCommentRow {
+                    article_id,
+                    comment: "Comment",
+                    name: crate::DEFAULT_NAME,
+                    tripcode: None,
+                    delete_password_hash: None,
+                    poster_ip: None,
+                    created_at: 10 + n,
+                    parent_comment_id: None,
+                    bump_to: None,
+                    bump_limit: 0,
+                })
+                .await
+                .unwrap();
+            repo.insert_media(article_id, Some(comment_id), &media(&format!("c{}.png", n)))
+                .await
+                .unwrap();
+            repo.insert_media(article_id, Some(comment_id), &media(&format!("d{}.png", n)))
+                .await
+                .unwrap();
+        }
+
+        let (listed, statements) = count_statements(
+            &repo,
+            repo.list_articles(board.id, ArticleSort::Bump, 10, 0),
+        )
+        .await;
+        assert_eq!(statements, 1);
+        assert_eq!(listed.len(), 3);
+        let first = listed.iter().find(|a| a.article.id == article_id).unwrap();
+        assert_eq!(
+            (
+                first.media_path.as_deref(),
+                first.comment_count,
+                first.tags()
+            ),
+            (Some("0a.png"), 3, tags.to_vec())
+        );
+
+        let (article, statements) =
+            count_statements(&repo, repo.get_article_with_media(article_id)).await;
+        assert_eq!(statements, 1);
+        assert_eq!(
+            article
+                .media
+                .iter()
+                .map(|m| m.media_path.as_str())
+                .collect::<Vec<_>>(),
+            ["0a.png", "0b.png"]
+        );
+        assert_eq!(article.tags, tags);
+
+        let ((comments, comment_media), statements) =
+            count_statements(&repo, repo.list_comments_page(article_id, 10, 0)).await;
+        assert_eq!(statements, 1);
+        assert_eq!(comments.len(), 3);
+        assert!(comments.iter().all(|c| comment_media[&c.id].len() == 2));
+    }
+
+    async fn memory_repo() -> SqliteRepository {
+        let repo = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        repo.run_migrations().await.unwrap();
+        repo
+    }
+
+    async fn post(
+        repo: &SqliteRepository,
+        board_id: i32,
+        title: &str,
+        body: &str,
+        bump_time: i64,
+        files: &[Media],
+    ) -> i32 {
+        repo.insert_article(NewArticleRow {
+            board_id,
+            title,
+            slug: None,
+            body,
+            name: crate::DEFAULT_NAME,
+            tripcode: None,
+            created_at: bump_time,
+            bump_time,
+            delete_password_hash: None,
+            tags: &[],
+            poster_ip: None,
+            media: files,
+        })
+        .await
+        .unwrap()
+        .0
+    }
+
+    #[tokio::test]
+    async fn articles_are_created_read_updated_and_deleted() {
+        let repo = memory_repo().await;
+        let board = repo.get_board("main").await.unwrap().id;
+        let article_id = post(&repo, board, "First", "Body", 1, &[media("/uploads/a.png")]).await;
+
+        let article = repo.get_article(article_id).await.unwrap();
+        assert_eq!(
+            (
+                article.title.as_str(),
+                article.body.as_str(),
+                article.edited_at
+            ),
+            ("First", "Body", None)
+        );
+        assert_eq!(repo.count_articles(board).await.unwrap(), 1);
+
+        repo.update_article(article_id, "Renamed", "New body", 5)
+            .await
+            .unwrap();
+        let article = repo.get_article(article_id).await.unwrap();
+        assert_eq!(
+            (
+                article.title.as_str(),
+                article.body.as_str(),
+                article.edited_at
+            ),
+            ("Renamed", "New body", Some(5))
+        );
+        let media = repo.get_article_with_media(article_id).await.unwrap().media;
+        assert_eq!(
+            media
+                .iter()
+                .map(|m| m.media_path.as_str())
+                .collect::<Vec<_>>(),
+            ["/uploads/a.png"]
+        );
+
+        repo.insert_comment(NewCommentRow {
+            article_id,
+            comment: "Comment",
+            name: crate::DEFAULT_NAME,
+            tripcode: None,
+            delete_password_hash: None,
+            poster_ip: None,
+            created_at: 2,
+            parent_comment_id: None,
+            bump_to: None,
+            bump_limit: 0,
+        })
+        .await
+        .unwrap();
+        assert_eq!(repo.list_comments(article_id).await.unwrap().len(), 1);
+
+        repo.soft_delete_article(article_id, 6).await.unwrap();
+        assert_eq!(repo.count_articles(board).await.unwrap(), 0);
+        assert!(repo.restore_article(article_id).await.unwrap());
+        assert!(!repo.restore_article(article_id).await.unwrap());
+        assert_eq!(repo.count_articles(board).await.unwrap(), 1);
+
+        assert_eq!(
+            repo.delete_article(article_id).await.unwrap(),
+            ["/uploads/a.png"]
+        );
+        assert!(matches!(
+            repo.get_article(article_id).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+        assert!(repo.list_comments(article_id).await.unwrap().is_empty());
+        assert_eq!(repo.count_articles(board).await.unwrap(), 0);
+    }
+
+    // An upload with a stored one's hash takes its files, which stay until
+    // neither uses them
+    #[tokio::test]
+    async fn identical_uploads_share_the_stored_files() {
+        let repo = memory_repo().await;
+        let board = repo.get_board("main").await.unwrap().id;
+        let upload = |path: &str| Media {
+            thumb_path: Some(format!("{}.thumb", path)),
+            content_hash: Some("abc".to_string()),
+            ..media(path)
+        };
+        let first = post(
+            &repo,
+            board,
+            "First",
+            "Body",
+            1,
+            &[upload("/uploads/a.png")],
+        )
+        .await;
+        let second = post(&repo, board, "Second", "Body", 2, &[]).await;
+        let (_, stored) = repo
+            .insert_media(second, None, &upload("/uploads/b.png"))
+            .await
+            .unwrap();
+        assert_eq!(
+            (stored.media_path.as_str(), stored.thumb_path.as_deref()),
+            ("/uploads/a.png", Some("/uploads/a.png.thumb"))
+        );
+
+        assert!(repo.delete_article(first).await.unwrap().is_empty());
+        assert_eq!(
+            repo.delete_article(second).await.unwrap(),
+            ["/uploads/a.png", "/uploads/a.png.thumb"]
+        );
+    }
+
+    #[tokio::test]
+    async fn articles_and_comments_come_a_page_at_a_time() {
+        let repo = memory_repo().await;
+        let board = repo.get_board("main").await.unwrap().id;
+        for n in 1..=5 {
+            post(&repo, board, &format!("Article {}", n), "Body", n, &[]).await;
+        }
+        let titles = |listed: Vec<crate::db::ListedArticle>| {
+            listed
+                .into_iter()
+                .map(|a| a.article.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            titles(
+                repo.list_articles(board, ArticleSort::Bump, 2, 0)
+                    .await
+                    .unwrap()
+            ),
+            ["Article 5", "Article 4"]
+        );
+        assert_eq!(
+            titles(
+                repo.list_articles(board, ArticleSort::Bump, 2, 2)
+                    .await
+                    .unwrap()
+            ),
+            ["Article 3", "Article 2"]
+        );
+        assert_eq!(
+            titles(
+                repo.list_articles(board, ArticleSort::Bump, 2, 4)
+                    .await
+                    .unwrap()
+            ),
+            ["Article 1"]
+        );
+        assert!(repo
+            .list_articles(board, ArticleSort::Bump, 2, 6)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let article_id = post(&repo, board, "Discussed", "Body", 10, &[]).await;
+        for n in 0..3 {
+            repo.insert_comment(NewCommentRow {
+                article_id,
+                comment: &format!("Comment {}", n),
+                name: crate::DEFAULT_NAME,
+                tripcode: None,
+                delete_password_hash: None,
+                poster_ip: None,
+                created_at: 20 + n,
+                parent_comment_id: None,
+                bump_to: None,
+                bump_limit: 0,
+            })
+            .await
+            .unwrap();
+        }
+        let (first, _) = repo.list_comments_page(article_id, 2, 0).await.unwrap();
+        let (second, _) = repo.list_comments_page(article_id, 2, 2).await.unwrap();
+        assert_eq!(
+            first.iter().map(|c| c.comment.as_str()).collect::<Vec<_>>(),
+            ["Comment 0", "Comment 1"]
+        );
+        assert_eq!(
+            second
+                .iter()
+                .map(|c| c.comment.as_str())
+                .collect::<Vec<_>>(),
+            ["Comment 2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn search_matches_other_forms_of_a_word() {
+        let repo = memory_repo().await;
+        let board = repo.get_board("main").await.unwrap().id;
+        let ran = post(
+            &repo,
+            board,
+            "Weekend",
+            "I went for a run by the river",
+            1,
+            &[],
+        )
+        .await;
+        post(&repo, board, "Cooking", "Nothing to do with it", 2, &[]).await;
+        repo.insert_comment(NewCommentRow {
+            article_id: ran,
+            comment: "She runs there daily",
+            name: crate::DEFAULT_NAME,
+            tripcode: None,
+            delete_password_hash: None,
+            poster_ip: None,
+            created_at: 2,
+            parent_comment_id: None,
+            bump_to: None,
+            bump_limit: 0,
+        })
+        .await
+        .unwrap();
+
+        let found = repo
+            .search(board, "running", SearchScope::Articles, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            found.iter().map(|row| row.article_id).collect::<Vec<_>>(),
+            [ran]
+        );
+        assert_eq!(found[0].snippet, "I went for a \u{1}run\u{2} by the river");
+        assert_eq!(
+            repo.count_search_results(board, "running", SearchScope::Articles)
+                .await
+                .unwrap(),
+            1
+        );
+        let found = repo
+            .search(board, "running", SearchScope::Comments, 10, 0)
+            .await
+            .unwrap();
+        let comment_id = repo.list_comments(ran).await.unwrap()[0].id;
+        assert_eq!(
+            found.iter().map(|row| row.comment_id).collect::<Vec<_>>(),
+            [Some(comment_id)]
+        );
+
+        // Every word has to match, and query syntax is taken as words
+        assert_eq!(
+            repo.count_search_results(board, "running cooking", SearchScope::Articles)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            repo.count_search_results(board, "run* \"river", SearchScope::Articles)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(repo
+            .search(board, "?!", SearchScope::Articles, 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Edits and deletions reach the index
+        repo.update_article(ran, "Weekend", "A quiet walk", 4)
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.count_search_results(board, "running", SearchScope::Articles)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            repo.count_search_results(board, "walking", SearchScope::Articles)
+                .await
+                .unwrap(),
+            1
+        );
+        repo.delete_article(ran).await.unwrap();
+        assert_eq!(
+            repo.count_search_results(board, "walking", SearchScope::Articles)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            repo.count_search_results(board, "running", SearchScope::Comments)
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    // Each insert reads the slugs already taken before it writes. Run at the
+    // same time on a database file, with a connection each, none of them may
+    // fail with "database is locked" or take a slug another one got.
+    #[tokio::test]
+    async fn articles_posted_at_once_get_slugs_of_their_own() {
+        let path =
+            std::env::temp_dir().join(format!("articles-{}.db", uuid::Uuid::new_v4().simple()));
+        let repo = SqliteRepository::connect(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+        repo.run_migrations().await.unwrap();
+        let board = repo.get_board("main").await.unwrap().id;
+        let inserts = (0..8).map(|n| {
+            repo.insert_article(NewArticleRow {
+                board_id: board,
+                title: "Same title",
+                slug: Some("same-title"),
+                body: "Body",
+                name: crate::DEFAULT_NAME,
+                tripcode: None,
+                created_at: n,
+                bump_time: n,
+                delete_password_hash: None,
+                tags: &[],
+                poster_ip: None,
+                media: &[],
+            })
+        });
+        let inserted = futures_util::future::join_all(inserts).await;
+        let mut slugs = Vec::new();
+        for (article_id, _) in inserted.iter().flatten() {
+            slugs.push(repo.get_article(*article_id).await.unwrap().slug.unwrap());
+        }
+        repo.pool.close().await;
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert!(inserted.iter().all(Result::is_ok), "{:?}", inserted);
+        slugs.sort();
+        slugs.dedup();
+        assert_eq!(slugs.len(), 8, "{:?}", slugs);
+    }
+}
@@ -0,0 +1,195 @@
+// Deleted articles and comments stay in the database, hidden, until
+// purge-deleted removes them; admins can list them here and bring them back.
+// prune removes the oldest articles for good, deleted or not.
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpResponse,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::admin::AdminUser;
+use crate::board::board_base;
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::csrf::CsrfToken;
+use crate::db::{self, ArticleRepository};
+use crate::markdown;
+use crate::page_cache::PageCache;
+use crate::render::{format_timestamp, summary};
+use crate::slug::article_path;
+use crate::storage;
+use crate::templates::{render_html, AdminDeletedContext, DeletedItem};
+use crate::upload::remove_media_files;
+use crate::EXCERPT_CHARS;
+
+// How many of each are listed, most recently deleted first
+const MAX_LISTED: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct RestorePath {
+    id: i32,
+}
+
+// GET /admin/deleted
+pub async fn deleted_page(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+    csrf: CsrfToken,
+) -> HttpResponse {
+    let (articles, comments) = match (
+        repo.deleted_articles(MAX_LISTED).await,
+        repo.deleted_comments(MAX_LISTED).await,
+    ) {
+        (Ok(articles), Ok(comments)) => (articles, comments),
+        (Err(e), _) | (_, Err(e)) => {
+            error!(error = %e, "Failed to fetch deleted content");
+            return HttpResponse::InternalServerError().body("Failed to load deleted content");
+        }
+    };
+
+    render_html(
+        StatusCode::OK,
+        &AdminDeletedContext {
+            csrf_token: &csrf.0,
+            articles: articles
+                .into_iter()
+                .map(|a| DeletedItem {
+                    id: a.id,
+                    heading: a.title,
+                    // The article itself 404s while it's deleted
+                    href: None,
+                    board: a.board_slug,
+                    excerpt: summary(&markdown::to_plain_text(&a.body), EXCERPT_CHARS),
+                    deleted_at: format_timestamp(a.deleted_at),
+                })
+                .collect(),
+            comments: comments
+                .into_iter()
+                .map(|c| DeletedItem {
+                    id: c.id,
+                    heading: c.article_title,
+                    href: Some(format!(
+                        "{}{}",
+                        board_base(&c.board_slug, &config.default_board),
+                        article_path(c.article_id, c.article_slug.as_deref())
+                    )),
+                    board: c.board_slug,
+                    excerpt: summary(&c.comment, EXCERPT_CHARS),
+                    deleted_at: format_timestamp(c.deleted_at),
+                })
+                .collect(),
+        },
+    )
+}
+
+fn back_to_deleted() -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, "/admin/deleted"))
+        .finish()
+}
+
+fn restore_result(kind: &str, id: i32, result: Result<bool, sqlx::Error>) -> HttpResponse {
+    match result {
+        Ok(true) => back_to_deleted(),
+        Ok(false) => HttpResponse::NotFound().body(format!("There is no deleted {} {}", kind, id)),
+        Err(e) => {
+            error!(kind, id, error = %e, "Failed to restore deleted content");
+            HttpResponse::InternalServerError().body(format!("Failed to restore {}", kind))
+        }
+    }
+}
+
+// POST /admin/deleted/articles/{id}/restore
+pub async fn restore_article(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<RestorePath>,
+) -> HttpResponse {
+    let restored = repo.restore_article(path.id).await;
+    page_cache.invalidate_article(path.id);
+    restore_result("article", path.id, restored)
+}
+
+// POST /admin/deleted/comments/{id}/restore
+pub async fn restore_comment(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<RestorePath>,
+) -> HttpResponse {
+    let restored = repo.restore_comment(path.id).await;
+    // Which article it's on isn't known here
+    page_cache.clear();
+    restore_result("comment", path.id, restored)
+}
+
+// `articles prune --keep N`: delete every article except the N most recently
+// created, along with their comments, media rows and files
+pub async fn prune(config: &Config, keep: i64) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let storage = storage::from_config(config, repo.clone())?;
+    let article_ids = repo
+        .article_ids_beyond(keep)
+        .await
+        .map_err(|e| format!("Failed to find articles to prune: {}", e))?;
+
+    for &article_id in &article_ids {
+        let media_paths = repo
+            .delete_article(article_id)
+            .await
+            .map_err(|e| format!("Failed to delete article {}: {}", article_id, e))?;
+        remove_media_files(storage.as_ref(), &media_paths).await;
+    }
+
+    println!("Pruned {} article(s)", article_ids.len());
+    Ok(())
+}
+
+// `articles purge-deleted`: remove what was deleted longer ago than the
+// retention window for good, including media files, and old recorded errors
+pub async fn purge_deleted(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let storage = storage::from_config(config, repo.clone())?;
+    let cutoff = Utc::now().timestamp() - config.deleted_retention_days.max(0) * 24 * 60 * 60;
+
+    let article_ids = repo
+        .article_ids_deleted_before(cutoff)
+        .await
+        .map_err(|e| format!("Failed to find deleted articles: {}", e))?;
+    for &article_id in &article_ids {
+        let media_paths = repo
+            .delete_article(article_id)
+            .await
+            .map_err(|e| format!("Failed to purge article {}: {}", article_id, e))?;
+        remove_media_files(storage.as_ref(), &media_paths).await;
+    }
+    let (comments, media_paths) = repo
+        .purge_comments_deleted_before(cutoff)
+        .await
+        .map_err(|e| format!("Failed to purge deleted comments: {}", e))?;
+    remove_media_files(storage.as_ref(), &media_paths).await;
+
+    let error_cutoff = Utc::now().timestamp() - config.error_retention_days.max(0) * 24 * 60 * 60;
+    let errors = repo
+        .delete_app_errors(Some(error_cutoff))
+        .await
+        .map_err(|e| format!("Failed to purge old errors: {}", e))?;
+    let failures = repo
+        .delete_webhook_failures(error_cutoff)
+        .await
+        .map_err(|e| format!("Failed to purge old webhook failures: {}", e))?;
+
+    println!(
+        "Purged {} article(s), {} comment(s), {} error(s) and {} webhook failure(s)",
+        article_ids.len(),
+        comments,
+        errors,
+        failures
+    );
+    Ok(())
+}
@@ -0,0 +1,165 @@
+// A post sent twice, by a double click on the submit button or a browser
+// retrying over a bad connection, is only stored once: the copy is answered
+// with the redirect the original got. A copy is the same text (and file
+// names) sent to the same place from the same address within the window.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+// Past this many remembered posts, expired ones are forgotten
+const MAX_TRACKED_POSTS: usize = 10_000;
+
+pub struct RecentPosts {
+    window: Duration,
+    hasher: RandomState,
+    // When each post was claimed, and its id once stored
+    posts: Mutex<HashMap<u64, (Instant, Option<i32>)>>,
+}
+
+pub enum Seen<'a> {
+    // Not sent before; store it, then report its id to the claim
+    New(Claim<'a>),
+    // Stored already, under this id
+    Posted(i32),
+    // Being stored by another request right now
+    Pending,
+}
+
+impl RecentPosts {
+    // A window of 0 remembers nothing
+    pub fn new(window: Duration) -> Self {
+        RecentPosts {
+            window,
+            hasher: RandomState::new(),
+            posts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        RecentPosts::new(Duration::from_secs(config.duplicate_window_secs))
+    }
+
+    // Check for a copy of `post` (whatever identifies it, e.g. the article id
+    // and text of a comment) from `addr`, claiming it if there isn't one
+    pub fn claim(&self, addr: Option<IpAddr>, post: impl Hash, now: Instant) -> Seen<'_> {
+        let key = self.hasher.hash_one((addr, post));
+        if self.window.is_zero() {
+            return Seen::New(Claim { recent: self, key });
+        }
+
+        let mut posts = self.posts.lock().unwrap_or_else(|e| e.into_inner());
+        if posts.len() >= MAX_TRACKED_POSTS {
+            posts.retain(|_, (at, _)| now.duration_since(*at) < self.window);
+        }
+        match posts.get(&key) {
+            Some(&(at, Some(id))) if now.duration_since(at) < self.window => Seen::Posted(id),
+            Some(&(at, None)) if now.duration_since(at) < self.window => Seen::Pending,
+            _ => {
+                posts.insert(key, (now, None));
+                Seen::New(Claim { recent: self, key })
+            }
+        }
+    }
+}
+
+// A post being stored. Dropped without `posted`, as when storing it fails,
+// it's forgotten so the post can be sent again.
+pub struct Claim<'a> {
+    recent: &'a RecentPosts,
+    key: u64,
+}
+
+impl Claim<'_> {
+    pub fn posted(self, id: i32) {
+        let mut posts = self.recent.posts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(post) = posts.get_mut(&self.key) {
+            post.1 = Some(id);
+        }
+    }
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        let mut posts = self.recent.posts.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, None)) = posts.get(&self.key) {
+            posts.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: Option<IpAddr> = Some(IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1)));
+    const WINDOW: Duration = Duration::from_secs(300);
+
+    #[test]
+    fn copies_get_the_original() {
+        let recent = RecentPosts::new(WINDOW);
+        let start = Instant::now();
+        let Seen::New(claim) = recent.claim(ADDR, (1, "text"), start) else {
+            panic!("first post is new");
+        };
+        assert!(matches!(
+            recent.claim(ADDR, (1, "text"), start),
+            Seen::Pending
+        ));
+        claim.posted(7);
+        assert!(matches!(
+            recent.claim(ADDR, (1, "text"), start + Duration::from_secs(60)),
+            Seen::Posted(7)
+        ));
+
+        // Other text, another place, or another address is a new post
+        assert!(matches!(
+            recent.claim(ADDR, (1, "other"), start),
+            Seen::New(_)
+        ));
+        assert!(matches!(
+            recent.claim(ADDR, (2, "text"), start),
+            Seen::New(_)
+        ));
+        assert!(matches!(
+            recent.claim(None, (1, "text"), start),
+            Seen::New(_)
+        ));
+    }
+
+    #[test]
+    fn posts_can_be_repeated_after_the_window() {
+        let recent = RecentPosts::new(WINDOW);
+        let start = Instant::now();
+        if let Seen::New(claim) = recent.claim(ADDR, "text", start) {
+            claim.posted(1);
+        }
+        assert!(matches!(
+            recent.claim(ADDR, "text", start + WINDOW),
+            Seen::New(_)
+        ));
+    }
+
+    #[test]
+    fn failed_posts_are_forgotten() {
+        let recent = RecentPosts::new(WINDOW);
+        let start = Instant::now();
+        drop(recent.claim(ADDR, "text", start));
+        assert!(matches!(recent.claim(ADDR, "text", start), Seen::New(_)));
+    }
+
+    #[test]
+    fn zero_disables_the_check() {
+        let recent = RecentPosts::new(Duration::ZERO);
+        let start = Instant::now();
+        if let Seen::New(claim) = recent.claim(ADDR, "text", start) {
+            claim.posted(1);
+        }
+        assert!(matches!(recent.claim(ADDR, "text", start), Seen::New(_)));
+    }
+}
@@ -0,0 +1,246 @@
+// Errors that handlers surface as HTTP responses. Pages get a styled error
+// page; under /api, json_errors swaps it for the usual {"error": "..."} body.
+// Server-side failures are logged in full but only described vaguely to the
+// client.
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    middleware::Next,
+    Error, HttpRequest, HttpResponse, ResponseError,
+};
+use serde_json::json;
+use std::fmt;
+use tracing::error;
+
+use crate::render::format_size;
+use crate::templates::{render_html, ErrorPageContext};
+
+#[derive(Debug)]
+pub enum AppError {
+    // Any query failure but RowNotFound, which converts to NotFound
+    Database(sqlx::Error),
+    // The thing asked for doesn't exist (or isn't on this board)
+    NotFound,
+    // A submitted field that can't be used, and a message saying why
+    Validation {
+        field: &'static str,
+        message: String,
+    },
+    Io(std::io::Error),
+    // A multipart form body that couldn't be read
+    Multipart(actix_multipart::MultipartError),
+    // An upload over the limit, which is given in bytes
+    PayloadTooLarge(u64),
+    // An image over the pixel limits, and which one
+    ImageTooLarge(String),
+    // What the client isn't allowed to do
+    Forbidden(String),
+    // An upload refused because UPLOADS_QUOTA_BYTES is reached
+    InsufficientStorage,
+    Template(askama::Error),
+    Password(argon2::password_hash::Error),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "Database error: {}", e),
+            AppError::NotFound => write!(f, "Not found"),
+            AppError::Validation { field, message } => write!(f, "Invalid {}: {}", field, message),
+            AppError::Io(e) => write!(f, "I/O error: {}", e),
+            AppError::Multipart(e) => write!(f, "Unreadable form: {}", e),
+            AppError::PayloadTooLarge(limit) => write!(f, "Upload over the {} byte limit", limit),
+            AppError::ImageTooLarge(reason) => write!(f, "Image too large: {}", reason),
+            AppError::Forbidden(message) => write!(f, "Forbidden: {}", message),
+            AppError::InsufficientStorage => write!(f, "The uploads quota is reached"),
+            AppError::Template(e) => write!(f, "Failed to render template: {}", e),
+            AppError::Password(e) => write!(f, "Failed to hash password: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound,
+            e => AppError::Database(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e)
+    }
+}
+
+impl From<actix_multipart::MultipartError> for AppError {
+    fn from(e: actix_multipart::MultipartError) -> Self {
+        AppError::Multipart(e)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        AppError::Password(e)
+    }
+}
+
+impl From<askama::Error> for AppError {
+    fn from(e: askama::Error) -> Self {
+        AppError::Template(e)
+    }
+}
+
+impl AppError {
+    // The page title and message shown to the client
+    fn public_message(&self) -> (&'static str, String) {
+        match self {
+            AppError::NotFound => (
+                "Not Found",
+                "There's nothing here; it may have been deleted.".to_string(),
+            ),
+            AppError::Validation { message, .. } => ("Invalid Submission", message.clone()),
+            AppError::Multipart(_) => (
+                "Invalid Submission",
+                "The form could not be read. Please try again.".to_string(),
+            ),
+            AppError::PayloadTooLarge(limit) => (
+                "File Too Large",
+                format!("Uploads are limited to {}.", format_size(*limit)),
+            ),
+            AppError::ImageTooLarge(reason) => ("Image Too Large", reason.clone()),
+            AppError::Forbidden(message) => ("Forbidden", message.clone()),
+            AppError::InsufficientStorage => (
+                "Out of Space",
+                "The site can't take any more files for now. Posts without files still work."
+                    .to_string(),
+            ),
+            AppError::Database(_)
+            | AppError::Io(_)
+            | AppError::Template(_)
+            | AppError::Password(_) => (
+                "Server Error",
+                "Something went wrong on our end. Please try again later.".to_string(),
+            ),
+        }
+    }
+
+    // The same error as a JSON body, for API clients
+    pub fn json_response(&self) -> HttpResponse {
+        let (_, message) = self.public_message();
+        let body = match self {
+            AppError::Validation { field, .. } => json!({ "error": message, "field": field }),
+            _ => json!({ "error": message }),
+        };
+        HttpResponse::build(self.status_code()).json(body)
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Validation { .. } | AppError::Multipart(_) => StatusCode::BAD_REQUEST,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::ImageTooLarge(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::Database(_)
+            | AppError::Io(_)
+            | AppError::Template(_)
+            | AppError::Password(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // A full quota is logged as a warning where it's found
+        if self.status_code().is_server_error() && !matches!(self, AppError::InsufficientStorage) {
+            error!(error = %self, "Request failed");
+        }
+        // render_html reports its own failures through here, so a broken
+        // template can't use the message page
+        if let AppError::Template(_) = self {
+            return HttpResponse::build(self.status_code()).body("Failed to render page");
+        }
+        let (title, message) = self.public_message();
+        render_html(
+            self.status_code(),
+            &ErrorPageContext {
+                status: self.status_code().as_u16(),
+                title,
+                message: &message,
+            },
+        )
+    }
+}
+
+// The default service, for paths no route matches
+pub async fn not_found(req: HttpRequest) -> HttpResponse {
+    if req.path().starts_with("/api/") {
+        AppError::NotFound.json_response()
+    } else {
+        AppError::NotFound.error_response()
+    }
+}
+
+// Middleware for /api: an AppError from a handler is answered with JSON
+// rather than the HTML page
+pub async fn json_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let res = next.call(req).await?;
+    let json = res
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<AppError>())
+        .map(AppError::json_response);
+    Ok(match json {
+        Some(response) => res.into_response(response).map_into_right_body(),
+        None => res.map_into_left_body(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[test]
+    fn missing_rows_are_not_found() {
+        assert!(matches!(
+            AppError::from(sqlx::Error::RowNotFound),
+            AppError::NotFound
+        ));
+        assert_eq!(
+            AppError::from(sqlx::Error::RowNotFound).status_code(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            AppError::from(sqlx::Error::PoolTimedOut).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn json_names_the_field_but_hides_server_details() {
+        let invalid = AppError::Validation {
+            field: "name",
+            message: "Name must be at most 50 characters".to_string(),
+        };
+        let response = invalid.json_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            json!({ "error": "Name must be at most 50 characters", "field": "name" })
+        );
+
+        let failed = AppError::from(sqlx::Error::Protocol("secret detail".to_string()));
+        let body = to_bytes(failed.json_response().into_body()).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains("secret detail"));
+    }
+}
@@ -0,0 +1,185 @@
+// Backups as one JSON document, from GET /admin/export or `articles export`:
+//
+//   {"format": "articles-export", "version": 1, "exported_at": ...,
+//    "boards": [...], "articles": [{..., "tags", "media", "comments"}, ...]}
+//
+// Articles come in the order they were posted, each with its tags, its own
+// media and its comments, and each comment with its media. Deleted ones are
+// kept too, with deleted_at set. Media are named by path, not included; with
+// --include-files the command writes a tar of the document and uploads/.
+// The document is written a batch of articles at a time, so neither the
+// server nor the command holds more than that at once. VERSION changes
+// whenever the shape does, so an import can tell what it's reading.
+
+use actix_web::{error::ErrorInternalServerError, http::header, web, HttpResponse};
+use bytes::Bytes;
+use chrono::Utc;
+use futures_util::stream::{self, Stream, StreamExt as _, TryStreamExt as _};
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt as _;
+use tracing::error;
+
+use crate::admin::AdminUser;
+use crate::cli::CommandResult;
+use crate::config::{Config, StorageConfig};
+use crate::db::{self, ArticleRepository};
+
+pub const FORMAT: &str = "articles-export";
+pub const VERSION: u32 = 1;
+// Articles read and written at a time
+const BATCH: i64 = 100;
+// The document's name in a tar made with --include-files
+pub const TAR_DOCUMENT: &str = "export.json";
+
+enum Step {
+    Start,
+    // After the last article written, if any
+    Articles { after_id: i32, first: bool },
+    Done,
+}
+
+// The document in pieces, each a batch of articles
+pub fn document(
+    repo: Arc<dyn ArticleRepository>,
+) -> impl Stream<Item = Result<Bytes, sqlx::Error>> {
+    stream::try_unfold(Step::Start, move |step| {
+        let repo = repo.clone();
+        async move {
+            match step {
+                Step::Start => {
+                    let boards = repo.all_boards().await?;
+                    // Written out by hand so the format and version come first
+                    let head = format!(
+                        "{{\"format\":{},\"version\":{},\"exported_at\":{},\"boards\":{},\"articles\":[",
+                        json!(FORMAT),
+                        VERSION,
+                        Utc::now().timestamp(),
+                        json!(boards),
+                    );
+                    Ok(Some((
+                        Bytes::from(head),
+                        Step::Articles {
+                            after_id: 0,
+                            first: true,
+                        },
+                    )))
+                }
+                Step::Articles { after_id, first } => {
+                    let articles = repo.export_articles(after_id, BATCH).await?;
+                    let Some(last) = articles.last() else {
+                        return Ok(Some((Bytes::from_static(b"]}\n"), Step::Done)));
+                    };
+                    let after_id = last.id;
+                    let mut chunk = Vec::new();
+                    for (i, article) in articles.iter().enumerate() {
+                        if !(first && i == 0) {
+                            chunk.push(b',');
+                        }
+                        serde_json::to_writer(&mut chunk, article).expect("articles serialize");
+                    }
+                    Ok(Some((
+                        Bytes::from(chunk),
+                        Step::Articles {
+                            after_id,
+                            first: false,
+                        },
+                    )))
+                }
+                Step::Done => Ok(None),
+            }
+        }
+    })
+}
+
+// GET /admin/export, as a download. A failure partway through cuts the
+// response off, so a broken backup can't pass for a whole one.
+pub async fn export(_admin: AdminUser, repo: web::Data<dyn ArticleRepository>) -> HttpResponse {
+    let filename = format!("articles-{}.json", Utc::now().format("%Y%m%d-%H%M%S"));
+    let body = document(repo.into_inner()).map_err(|e| {
+        error!(error = %e, "Failed to export articles");
+        ErrorInternalServerError("Export failed")
+    });
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .streaming(body)
+}
+
+// Write the document to `out`, returning how many bytes it took
+pub async fn write_document(
+    repo: Arc<dyn ArticleRepository>,
+    out: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(out).await?);
+    let mut written = 0;
+    let mut pieces = Box::pin(document(repo));
+    while let Some(piece) = pieces.next().await {
+        let piece = piece?;
+        file.write_all(&piece).await?;
+        written += piece.len() as u64;
+    }
+    file.flush().await?;
+    Ok(written)
+}
+
+// A tar of the document at `document` as TAR_DOCUMENT, and everything under
+// `uploads_dir` as uploads/, written to `out`
+pub fn write_tar(document: &Path, uploads_dir: &Path, out: &Path) -> std::io::Result<()> {
+    let mut tar = tar::Builder::new(std::fs::File::create(out)?);
+    tar.append_path_with_name(document, TAR_DOCUMENT)?;
+    if uploads_dir.is_dir() {
+        tar.append_dir_all("uploads", uploads_dir)?;
+    }
+    tar.into_inner()?.sync_all()
+}
+
+// `articles export --out PATH [--include-files]`
+pub async fn export_data(
+    config: &Config,
+    out: &std::path::Path,
+    include_files: bool,
+) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    if !include_files {
+        let written = write_document(repo, out)
+            .await
+            .map_err(|e| format!("Failed to export to {}: {}", out.display(), e))?;
+        println!("Exported {} bytes to {}", written, out.display());
+        return Ok(());
+    }
+    // Other backends keep the files themselves, and are backed up there
+    if !matches!(config.storage, StorageConfig::Local) {
+        return Err("--include-files only works with STORAGE_BACKEND=local".into());
+    }
+    let document = env::temp_dir().join(format!("articles-export-{}.json", std::process::id()));
+    let written = write_document(repo, &document).await;
+    let tarred = match written {
+        Ok(_) => {
+            let (document, uploads_dir, out) = (
+                document.clone(),
+                config.uploads_dir.clone(),
+                out.to_path_buf(),
+            );
+            tokio::task::spawn_blocking(move || write_tar(&document, &uploads_dir, &out))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|tarred| tarred.map_err(|e| e.to_string()))
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    let _ = fs::remove_file(&document);
+    tarred.map_err(|e| format!("Failed to export to {}: {}", out.display(), e))?;
+    println!(
+        "Exported the data and {} to {}",
+        config.uploads_dir.display(),
+        out.display()
+    );
+    Ok(())
+}
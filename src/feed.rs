@@ -0,0 +1,218 @@
+// RSS 2.0 and Atom feeds of a board's most recently bumped articles
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use std::fmt::Write as _;
+use tracing::error;
+
+use crate::board::Board;
+use crate::config::Config;
+use crate::db::{ArticleRepository, ArticleSort};
+use crate::markdown;
+use crate::render::excerpt;
+use crate::slug::article_path;
+use crate::{DbArticle, EXCERPT_CHARS, MAX_PER_PAGE};
+
+// Escape text for XML element content and attribute values. Characters XML
+// 1.0 doesn't allow at all (most control characters) are dropped, since no
+// escape can represent them.
+pub fn escape_xml(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' || c == '\u{FFFE}' || c == '\u{FFFF}' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn timestamp(epoch_seconds: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(epoch_seconds, 0).unwrap_or_default()
+}
+
+// What both feed formats need to know about the board
+struct FeedInfo<'a> {
+    title: &'a str,
+    description: &'a str,
+    // The board's article list
+    link: String,
+    // Prefix of every article URL; see slug::article_path
+    article_link: String,
+}
+
+impl<'a> FeedInfo<'a> {
+    fn new(board: &'a Board, public_url: &str) -> Self {
+        FeedInfo {
+            title: &board.title,
+            description: if board.description.is_empty() {
+                &board.title
+            } else {
+                &board.description
+            },
+            link: format!("{}{}/articles", public_url, board.base),
+            article_link: format!("{}{}", public_url, board.base),
+        }
+    }
+}
+
+fn rss(info: &FeedInfo, articles: &[DbArticle]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\">\n<channel>\n");
+    let _ = writeln!(xml, "<title>{}</title>", escape_xml(info.title));
+    let _ = writeln!(xml, "<link>{}</link>", escape_xml(&info.link));
+    let _ = writeln!(
+        xml,
+        "<description>{}</description>",
+        escape_xml(info.description)
+    );
+    if let Some(latest) = articles.iter().map(|a| a.created_at).max() {
+        let _ = writeln!(
+            xml,
+            "<lastBuildDate>{}</lastBuildDate>",
+            timestamp(latest).to_rfc2822()
+        );
+    }
+    for article in articles {
+        let link = format!(
+            "{}{}",
+            info.article_link,
+            article_path(article.id, article.slug.as_deref())
+        );
+        xml.push_str("<item>\n");
+        let _ = writeln!(xml, "<title>{}</title>", escape_xml(&article.title));
+        let _ = writeln!(xml, "<link>{}</link>", escape_xml(&link));
+        let _ = writeln!(
+            xml,
+            "<guid isPermaLink=\"true\">{}</guid>",
+            escape_xml(&link)
+        );
+        let _ = writeln!(
+            xml,
+            "<description>{}</description>",
+            escape_xml(&excerpt(
+                &markdown::to_plain_text(&article.body),
+                "",
+                EXCERPT_CHARS
+            ))
+        );
+        let _ = writeln!(
+            xml,
+            "<pubDate>{}</pubDate>",
+            timestamp(article.created_at).to_rfc2822()
+        );
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn atom(info: &FeedInfo, articles: &[DbArticle]) -> String {
+    // An empty feed still needs an <updated>
+    let updated = articles
+        .iter()
+        .map(|a| a.edited_at.unwrap_or(a.created_at))
+        .max()
+        .map_or_else(Utc::now, timestamp);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(xml, "<id>{}</id>", escape_xml(&info.link));
+    let _ = writeln!(xml, "<title>{}</title>", escape_xml(info.title));
+    let _ = writeln!(xml, "<subtitle>{}</subtitle>", escape_xml(info.description));
+    let _ = writeln!(xml, "<link href=\"{}\"/>", escape_xml(&info.link));
+    let _ = writeln!(xml, "<updated>{}</updated>", updated.to_rfc3339());
+    for article in articles {
+        let link = format!(
+            "{}{}",
+            info.article_link,
+            article_path(article.id, article.slug.as_deref())
+        );
+        xml.push_str("<entry>\n");
+        let _ = writeln!(xml, "<id>{}</id>", escape_xml(&link));
+        let _ = writeln!(xml, "<title>{}</title>", escape_xml(&article.title));
+        let _ = writeln!(xml, "<link href=\"{}\"/>", escape_xml(&link));
+        let _ = writeln!(
+            xml,
+            "<published>{}</published>",
+            timestamp(article.created_at).to_rfc3339()
+        );
+        let _ = writeln!(
+            xml,
+            "<updated>{}</updated>",
+            timestamp(article.edited_at.unwrap_or(article.created_at)).to_rfc3339()
+        );
+        let _ = writeln!(
+            xml,
+            "<summary>{}</summary>",
+            escape_xml(&excerpt(
+                &markdown::to_plain_text(&article.body),
+                "",
+                EXCERPT_CHARS
+            ))
+        );
+        // Atom requires an author on every entry unless the feed has one
+        xml.push_str("<author><name>Anonymous</name></author>\n");
+        xml.push_str("</entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+async fn latest_articles(
+    repo: &dyn ArticleRepository,
+    board: &Board,
+    config: &Config,
+) -> Option<Vec<DbArticle>> {
+    match repo
+        .list_articles(
+            board.id,
+            ArticleSort::Bump,
+            config.feed_items.clamp(1, MAX_PER_PAGE),
+            0,
+        )
+        .await
+    {
+        Ok(articles) => Some(articles.into_iter().map(|a| a.article).collect()),
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to fetch articles for feed");
+            None
+        }
+    }
+}
+
+// GET /feed.rss
+pub async fn rss_feed(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let Some(articles) = latest_articles(repo.get_ref(), &board, &config).await else {
+        return HttpResponse::InternalServerError().body("Failed to load feed");
+    };
+    HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(rss(&FeedInfo::new(&board, &config.public_url), &articles))
+}
+
+// GET /feed.atom
+pub async fn atom_feed(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let Some(articles) = latest_articles(repo.get_ref(), &board, &config).await else {
+        return HttpResponse::InternalServerError().body("Failed to load feed");
+    };
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(atom(&FeedInfo::new(&board, &config.public_url), &articles))
+}
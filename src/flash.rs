@@ -0,0 +1,18 @@
+// One-off messages carried across a redirect in the session cookie, shown by
+// the page the redirect leads to and then forgotten
+
+use actix_session::Session;
+use tracing::error;
+
+const FLASH: &str = "flash";
+
+pub fn set(session: &Session, message: &str) {
+    if let Err(e) = session.insert(FLASH, message) {
+        error!(error = %e, "Failed to store flash message");
+    }
+}
+
+// The message left for this page, if any, removing it
+pub fn take(session: &Session) -> Option<String> {
+    session.remove_as::<String>(FLASH).and_then(Result::ok)
+}
@@ -0,0 +1,135 @@
+// Probes for a load balancer. /healthz only says the process is serving;
+// /readyz also checks the database answers and uploads can be stored, and
+// names what doesn't with a 503. /metrics gives gauges in Prometheus's text
+// format. None of them sets cookies (see csrf::protect) or is rate limited.
+
+use actix_web::{web, HttpResponse};
+use serde_json::json;
+use std::time::Duration;
+use tracing::error;
+
+use crate::config::Config;
+use crate::db::ArticleRepository;
+use crate::storage::MediaStorage;
+
+pub const PATHS: [&str; 3] = ["/healthz", "/readyz", "/metrics"];
+
+// A probe that waits on a stuck pool is as bad as one that fails
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+pub async fn readyz(
+    repo: web::Data<dyn ArticleRepository>,
+    storage: web::Data<dyn MediaStorage>,
+) -> HttpResponse {
+    let mut failing = Vec::new();
+    match tokio::time::timeout(DATABASE_TIMEOUT, repo.ping()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            error!(error = %e, "Readiness check: database query failed");
+            failing.push("database");
+        }
+        Err(_) => {
+            error!("Readiness check: database query timed out");
+            failing.push("database");
+        }
+    }
+    if let Err(e) = storage.check_writable().await {
+        error!(error = %e, "Readiness check: uploads aren't writable");
+        failing.push("uploads");
+    }
+
+    if failing.is_empty() {
+        HttpResponse::Ok().json(json!({ "status": "ok" }))
+    } else {
+        HttpResponse::ServiceUnavailable()
+            .json(json!({ "status": "unavailable", "failing": failing }))
+    }
+}
+
+pub async fn metrics(
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+) -> HttpResponse {
+    let mut body = String::new();
+    let mut gauge = |name: &str, help: &str, value: u64| {
+        body.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+            name, help, name, name, value
+        ));
+    };
+    // Not counted with other storage backends
+    match repo.storage_usage().await {
+        Ok(Some(used)) => gauge(
+            "articles_uploads_bytes",
+            "Bytes of media files under UPLOADS_DIR.",
+            used.max(0) as u64,
+        ),
+        Ok(None) => {}
+        Err(e) => error!(error = %e, "Failed to load storage usage for /metrics"),
+    }
+    gauge(
+        "articles_uploads_quota_bytes",
+        "UPLOADS_QUOTA_BYTES, past which uploads are refused; 0 for no quota.",
+        config.uploads_quota_bytes,
+    );
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use std::sync::Arc;
+
+    use crate::db;
+    use crate::storage::LocalStorage;
+
+    async fn check(
+        repo: Arc<dyn ArticleRepository>,
+        storage: Arc<dyn MediaStorage>,
+    ) -> (u16, serde_json::Value) {
+        let response = readyz(web::Data::from(repo), web::Data::from(storage)).await;
+        let status = response.status().as_u16();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn names_what_is_not_ready() {
+        let dir =
+            std::env::temp_dir().join(format!("articles-readyz-{}", uuid::Uuid::new_v4().simple()));
+        let storage: Arc<dyn MediaStorage> = Arc::new(LocalStorage::new(dir.clone()).unwrap());
+        let repo = db::connect("sqlite::memory:").await.unwrap();
+
+        assert_eq!(
+            check(repo.clone(), storage.clone()).await,
+            (200, json!({ "status": "ok" }))
+        );
+        // The check leaves nothing behind; the one entry is thumbs/
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        repo.close().await;
+        assert_eq!(
+            check(repo.clone(), storage.clone()).await,
+            (
+                503,
+                json!({ "status": "unavailable", "failing": ["database"] })
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            check(repo, storage).await,
+            (
+                503,
+                json!({ "status": "unavailable", "failing": ["database", "uploads"] })
+            )
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Fenced code blocks (```lang) in articles and comments, highlighted on the
+// server with syntect for the languages in HIGHLIGHT_LANGUAGES. syntect
+// escapes the code itself, token by token, and only adds colour spans around
+// it, so nothing in a block can end the <pre> it sits in. Other languages,
+// and blocks too big to be worth the CPU, are shown as plain monospace.
+
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{append_highlighted_html_for_styled_line, IncludeBackground};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::render::escape_html;
+
+// Blocks longer than this are shown without highlighting
+const MAX_HIGHLIGHT_BYTES: usize = 50 * 1024;
+// One of syntect's default themes, picked for the light page background
+const THEME: &str = "InspiredGitHub";
+
+pub const DEFAULT_LANGUAGES: &str =
+    "rust,python,javascript,go,c,cpp,java,ruby,php,sh,sql,html,css,json,yaml";
+
+// Set once at startup from Config::highlight_languages; DEFAULT_LANGUAGES
+// until then
+static LANGUAGES: OnceLock<Vec<String>> = OnceLock::new();
+
+pub fn set_languages(languages: Vec<String>) {
+    let _ = LANGUAGES.set(languages);
+}
+
+// A HIGHLIGHT_LANGUAGES value: syntect language names or file extensions,
+// comma separated. A language named either way is highlighted however a block
+// names it ("rust" also covers ```rs).
+pub fn parse_languages(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|language| language.trim().to_ascii_lowercase())
+        .filter(|language| !language.is_empty())
+        .collect()
+}
+
+// Whether `syntax` is one of the configured languages, by any of its names
+fn highlighted(syntax: &SyntaxReference) -> bool {
+    let is_syntax = |language: &String| {
+        syntax.name.eq_ignore_ascii_case(language) || syntax.file_extensions.contains(language)
+    };
+    match LANGUAGES.get() {
+        Some(languages) => languages.iter().any(is_syntax),
+        None => parse_languages(DEFAULT_LANGUAGES).iter().any(is_syntax),
+    }
+}
+
+// Loading these takes a few milliseconds, so it's done on first use
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+    &THEME_SET.get_or_init(ThemeSet::load_defaults).themes[THEME]
+}
+
+// `code` as a <pre><code> block. `info` is what followed the opening fence;
+// its first word names the language.
+pub fn code_block(info: &str, code: &str) -> String {
+    let language = info.split_whitespace().next().unwrap_or("");
+    let body = highlight(language, code).unwrap_or_else(|| escape_html(code));
+    format!("<pre><code>{}</code></pre>", body)
+}
+
+fn highlight(language: &str, code: &str) -> Option<String> {
+    if code.len() > MAX_HIGHLIGHT_BYTES || language.is_empty() {
+        return None;
+    }
+    let syntaxes = syntaxes();
+    let syntax = syntaxes
+        .find_syntax_by_token(language)
+        .filter(|syntax| highlighted(syntax))?;
+    let mut highlighter = HighlightLines::new(syntax, theme());
+    let mut html = String::with_capacity(code.len() * 4);
+    for line in LinesWithEndings::from(code) {
+        let regions = highlighter.highlight_line(line, syntaxes).ok()?;
+        append_highlighted_html_for_styled_line(&regions, IncludeBackground::No, &mut html).ok()?;
+    }
+    Some(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_known_languages() {
+        let html = code_block("rust", "fn main() {}\n");
+        assert!(html.starts_with("<pre><code><span style=\""), "{}", html);
+        assert!(html.contains(">main</span>"), "{}", html);
+        assert!(html.ends_with("</span></code></pre>"), "{}", html);
+        assert_eq!(code_block("rs extra words", "fn main() {}\n"), html);
+    }
+
+    #[test]
+    fn shows_other_code_plain() {
+        assert_eq!(
+            code_block("", "<b>x</b>\n"),
+            "<pre><code>&lt;b&gt;x&lt;/b&gt;\n</code></pre>"
+        );
+        assert_eq!(
+            code_block("brainfuck", "+[>]\n"),
+            "<pre><code>+[&gt;]\n</code></pre>"
+        );
+        let big = "let x = 1;\n".repeat(MAX_HIGHLIGHT_BYTES / 10);
+        assert_eq!(
+            code_block("rust", &big),
+            format!("<pre><code>{}</code></pre>", big)
+        );
+    }
+
+    #[test]
+    fn code_cannot_end_the_block() {
+        for language in ["html", "rust", ""] {
+            let html = code_block(language, "</code></pre><script>alert(1)</script>\n");
+            assert!(
+                !html.contains("<script") && !html.contains("</pre><"),
+                "{}",
+                html
+            );
+            assert_eq!(html.matches("</pre>").count(), 1, "{}", html);
+        }
+    }
+}
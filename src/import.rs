@@ -0,0 +1,463 @@
+// Restores what export.rs writes, with `articles import PATH`: the JSON
+// document, or the tar `export --include-files` makes. The whole document is
+// checked before anything is written: its format and version, that each
+// article's board is in it, and that each reply's parent comes before it on
+// the same article. Boards are matched to this site's by slug, and made if
+// missing. Each article then goes in with its tags, media and comments in one
+// transaction, under new ids. One with the same title and created_at as an
+// article already here is skipped, unless forced. Media files that come with
+// the document (the tar's uploads/, or --files) are checked and stripped as
+// uploads are, and stored again under new names with the paths rewritten to
+// match, unless an identical file is already stored here, which is used
+// instead; the rest keep their paths. A file that couldn't have been
+// uploaded stops the import.
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+
+use crate::board::validate_slug;
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::db::{self, ArticleRepository, ExportArticle, ExportMedia};
+use crate::export::{self, FORMAT, VERSION};
+use crate::media::{self, image_dimensions, thumb_key, MediaType, UploadOptions};
+use crate::storage::{self, MediaStorage};
+use crate::DbBoard;
+
+// What's read first, so a file that isn't an export, or is from a newer
+// version, is told apart from a broken one
+#[derive(Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+#[derive(Deserialize)]
+pub struct Document {
+    boards: Vec<DbBoard>,
+    articles: Vec<ExportArticle>,
+}
+
+pub struct Options<'a> {
+    // Where the exported uploads/ directory's files are
+    pub files: Option<&'a Path>,
+    pub force: bool,
+    pub dry_run: bool,
+    // The limits and rework that uploads get
+    pub upload: UploadOptions,
+}
+
+// What was imported, or with dry_run would be
+#[derive(Default)]
+pub struct Report {
+    pub boards: Vec<String>,
+    pub articles: usize,
+    pub comments: usize,
+    pub files: usize,
+    // Media under /uploads whose files weren't found, which keep their paths
+    pub missing: usize,
+    // The titles of articles already here
+    pub skipped: Vec<String>,
+}
+
+pub fn parse(json: &[u8]) -> Result<Document, String> {
+    let header: Header =
+        serde_json::from_slice(json).map_err(|e| format!("Not an export: {}", e))?;
+    if header.format != FORMAT {
+        return Err(format!(
+            "Not an export: the format is {:?}, not {:?}",
+            header.format, FORMAT
+        ));
+    }
+    if header.version == 0 || header.version > VERSION {
+        return Err(format!(
+            "The export is version {}, and this version of articles reads up to version {}",
+            header.version, VERSION
+        ));
+    }
+    let document: Document =
+        serde_json::from_slice(json).map_err(|e| format!("The export is malformed: {}", e))?;
+    validate(&document)?;
+    Ok(document)
+}
+
+fn validate(document: &Document) -> Result<(), String> {
+    for board in &document.boards {
+        validate_slug(&board.slug).map_err(|e| format!("Board {}: {}", board.slug, e))?;
+    }
+    for article in &document.articles {
+        if !document
+            .boards
+            .iter()
+            .any(|board| board.id == article.board_id)
+        {
+            return Err(format!(
+                "Article {}: board {} isn't in the export",
+                article.id, article.board_id
+            ));
+        }
+        for (i, comment) in article.comments.iter().enumerate() {
+            let Some(parent) = comment.parent_comment_id else {
+                continue;
+            };
+            if !article.comments[..i].iter().any(|c| c.id == parent) {
+                return Err(format!(
+                    "Article {}: comment {} replies to {}, which doesn't come before it",
+                    article.id, comment.id, parent
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn import(
+    repo: &dyn ArticleRepository,
+    storage: &dyn MediaStorage,
+    document: Document,
+    options: &Options<'_>,
+) -> Result<Report, String> {
+    let mut report = Report::default();
+
+    // Exported board ids to this site's; None for boards a dry run would make
+    let mut boards = HashMap::new();
+    for board in &document.boards {
+        let id = match repo.get_board(&board.slug).await {
+            Ok(existing) => Some(existing.id),
+            Err(sqlx::Error::RowNotFound) => {
+                report.boards.push(board.slug.clone());
+                if options.dry_run {
+                    None
+                } else {
+                    repo.insert_board(&board.slug, &board.title, &board.description)
+                        .await
+                        .and(repo.get_board(&board.slug).await)
+                        .map(|created| Some(created.id))
+                        .map_err(|e| format!("Failed to create board {}: {}", board.slug, e))?
+                }
+            }
+            Err(e) => return Err(format!("Failed to load board {}: {}", board.slug, e)),
+        };
+        boards.insert(board.id, id);
+    }
+
+    for mut article in document.articles {
+        if !options.force {
+            let existing = repo
+                .find_article(&article.title, article.created_at)
+                .await
+                .map_err(|e| format!("Failed to look for article {}: {}", article.id, e))?;
+            if existing.is_some() {
+                report.skipped.push(article.title);
+                continue;
+            }
+        }
+        report.articles += 1;
+        report.comments += article.comments.len();
+
+        let media = article.media.iter_mut().chain(
+            article
+                .comments
+                .iter_mut()
+                .flat_map(|comment| comment.media.iter_mut()),
+        );
+        let mut stored = Vec::new();
+        for item in media {
+            let Some(file) = upload_file(options.files, &item.media_path) else {
+                continue;
+            };
+            if !file.is_file() {
+                report.missing += 1;
+                continue;
+            }
+            report.files += 1;
+            if options.dry_run {
+                continue;
+            }
+            if let Err(e) = store(repo, storage, options, item, &file, &mut stored).await {
+                discard(storage, &stored).await;
+                return Err(format!("Failed to store {}: {}", file.display(), e));
+            }
+        }
+        if options.dry_run {
+            continue;
+        }
+
+        let board_id = boards[&article.board_id].expect("boards are made unless it's a dry run");
+        if let Err(e) = repo.import_article(board_id, &article).await {
+            discard(storage, &stored).await;
+            return Err(format!(
+                "Failed to import article {} ({:?}): {}; the {} before it were imported",
+                article.id,
+                article.title,
+                e,
+                report.articles - 1
+            ));
+        }
+    }
+    Ok(report)
+}
+
+// The file behind a media path under /uploads, if files came with the
+// export; paths that would leave the directory have none
+fn upload_file(files: Option<&Path>, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.strip_prefix("/uploads/")?);
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(files?.join(relative))
+}
+
+// Store `file`, as media::rework leaves it, and its thumbnail anew,
+// pointing `media` at them, or at the files of an identical upload. Files
+// that aren't a supported type, or are over the limits, are an error.
+async fn store(
+    repo: &dyn ArticleRepository,
+    storage: &dyn MediaStorage,
+    options: &Options<'_>,
+    media: &mut ExportMedia,
+    file: &Path,
+    stored: &mut Vec<String>,
+) -> io::Result<()> {
+    let data = tokio::fs::read(file).await?;
+    let media_type = MediaType::detect(&data).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a type of media that can be uploaded",
+        )
+    })?;
+    let upload = options.upload;
+    if let Some(reason) = upload.limits.exceeded(&data, media_type) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, reason));
+    }
+    let (data, media_type) =
+        tokio::task::spawn_blocking(move || media::rework(data, media_type, upload))
+            .await
+            .map_err(io::Error::other)?
+            .map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("the image doesn't decode: {}", e),
+                )
+            })?;
+    // Whatever the export said, these are now known from the file
+    let (width, height) = image_dimensions(&data)
+        .map(|(w, h)| (w as i32, h as i32))
+        .unzip();
+    media.byte_size = Some(data.len() as i64);
+    media.mime_type = Some(media_type.mime().to_string());
+    (media.width, media.height) = (width, height);
+    let content_hash: String = Sha256::digest(&data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if let Some((media_path, thumb_path)) = repo
+        .media_by_hash(&content_hash)
+        .await
+        .map_err(io::Error::other)?
+    {
+        media.media_path = media_path;
+        media.thumb_path = thumb_path;
+        media.content_hash = Some(content_hash);
+        return Ok(());
+    }
+    media.content_hash = Some(content_hash);
+    let key = format!(
+        "article_{}.{}",
+        Uuid::new_v4().simple(),
+        media_type.extension()
+    );
+    let url = storage
+        .put(&key, media_type.mime(), storage::once(data))
+        .await?;
+    stored.push(url.clone());
+    media.media_path = url;
+
+    let thumb = media
+        .thumb_path
+        .as_deref()
+        .and_then(|path| upload_file(options.files, path));
+    if let Some(thumb) = thumb.filter(|thumb| thumb.is_file()) {
+        let data = tokio::fs::read(&thumb).await?;
+        if let Some(thumb_type) = MediaType::detect(&data) {
+            let url = storage
+                .put(
+                    &thumb_key(&key, thumb_type),
+                    thumb_type.mime(),
+                    storage::once(data),
+                )
+                .await?;
+            stored.push(url.clone());
+            media.thumb_path = Some(url);
+        }
+    }
+    Ok(())
+}
+
+// Remove what was stored for an article that couldn't be imported
+async fn discard(storage: &dyn MediaStorage, stored: &[String]) {
+    for url in stored {
+        let _ = storage.delete(url).await;
+    }
+}
+
+// `articles import PATH [--files DIR] [--force] [--dry-run]`
+pub async fn import_data(
+    config: &Config,
+    path: &std::path::Path,
+    files: Option<&std::path::Path>,
+    force: bool,
+    dry_run: bool,
+) -> CommandResult {
+    let contents =
+        fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    // Anything but JSON is taken for a tar from `export --include-files`,
+    // unpacked somewhere it can be read from
+    let is_json = contents.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    let unpacked =
+        (!is_json).then(|| env::temp_dir().join(format!("articles-import-{}", std::process::id())));
+    let result = async {
+        let (json, files) = match &unpacked {
+            None => (contents, files.map(|dir| dir.to_path_buf())),
+            Some(dir) => {
+                let dir = dir.clone();
+                let unpack = tokio::task::spawn_blocking({
+                    let dir = dir.clone();
+                    move || tar::Archive::new(contents.as_slice()).unpack(&dir)
+                });
+                unpack
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|unpacked| unpacked.map_err(|e| e.to_string()))
+                    .map_err(|e| format!("{} is neither JSON nor a tar: {}", path.display(), e))?;
+                let json = fs::read(dir.join(export::TAR_DOCUMENT)).map_err(|e| {
+                    format!("No {} in {}: {}", export::TAR_DOCUMENT, path.display(), e)
+                })?;
+                (
+                    json,
+                    Some(files.map_or_else(|| dir.join("uploads"), |dir| dir.to_path_buf())),
+                )
+            }
+        };
+        let document = parse(&json)?;
+        let repo = db::connect(&config.database_url).await?;
+        let storage = storage::from_config(config, repo.clone())?;
+        let options = Options {
+            files: files.as_deref(),
+            force,
+            dry_run,
+            upload: UploadOptions::from_config(config),
+        };
+        import(repo.as_ref(), storage.as_ref(), document, &options).await
+    }
+    .await;
+    if let Some(dir) = &unpacked {
+        let _ = fs::remove_dir_all(dir);
+    }
+    let report = result?;
+
+    for title in &report.skipped {
+        println!("Skipped {:?}: an article with that title and time is already here (--force imports it anyway)", title);
+    }
+    let verb = if dry_run { "Would import" } else { "Imported" };
+    println!(
+        "{} {} articles with {} comments and {} media files",
+        verb, report.articles, report.comments, report.files
+    );
+    if !report.boards.is_empty() {
+        let verb = if dry_run { "Would create" } else { "Created" };
+        println!("{} boards: {}", verb, report.boards.join(", "));
+    }
+    if report.missing > 0 {
+        println!(
+            "{} media files weren't with the export; their paths were kept",
+            report.missing
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(body: &str) -> String {
+        format!(
+            r#"{{"format":"articles-export","version":1,"exported_at":0,"boards":[{{"id":1,"slug":"main","title":"Main","description":""}}],"articles":[{}]}}"#,
+            body
+        )
+    }
+
+    fn article(board_id: i32, comments: &str) -> String {
+        format!(
+            r#"{{"id":7,"board_id":{},"title":"T","slug":null,"body":"B","name":"Anonymous","tripcode":null,"created_at":1,"bump_time":1,"edited_at":null,"archived_at":null,"deleted_at":null,"is_sticky":false,"delete_password_hash":null,"poster_ip":null,"tags":[],"media":[],"comments":[{}]}}"#,
+            board_id, comments
+        )
+    }
+
+    fn comment(id: i32, parent: Option<i32>) -> String {
+        format!(
+            r#"{{"id":{},"parent_comment_id":{},"comment":"C","name":"Anonymous","tripcode":null,"created_at":1,"deleted_at":null,"delete_password_hash":null,"poster_ip":null,"media":[]}}"#,
+            id,
+            parent.map_or("null".to_string(), |p| p.to_string())
+        )
+    }
+
+    #[test]
+    fn exports_are_checked_before_anything_is_read_from_them() {
+        let replies = format!("{},{}", comment(1, None), comment(2, Some(1)));
+        assert!(parse(document(&article(1, &replies)).as_bytes()).is_ok());
+
+        let newer = document("").replace("\"version\":1", "\"version\":2");
+        assert!(parse(newer.as_bytes())
+            .err()
+            .unwrap()
+            .contains("reads up to version 1"));
+        let other = document("").replace("articles-export", "something-else");
+        assert!(parse(other.as_bytes())
+            .err()
+            .unwrap()
+            .starts_with("Not an export"));
+        assert!(parse(b"[1, 2]").is_err());
+
+        let elsewhere = parse(document(&article(2, "")).as_bytes()).err().unwrap();
+        assert!(
+            elsewhere.contains("board 2 isn't in the export"),
+            "{}",
+            elsewhere
+        );
+        let orphan = format!("{},{}", comment(1, Some(2)), comment(2, None));
+        let orphaned = parse(document(&article(1, &orphan)).as_bytes())
+            .err()
+            .unwrap();
+        assert!(orphaned.contains("comment 1 replies to 2"), "{}", orphaned);
+    }
+
+    #[test]
+    fn only_files_under_the_uploads_directory_are_read() {
+        let files = Path::new("/tmp/export/uploads");
+        assert_eq!(
+            upload_file(Some(files), "/uploads/a.png"),
+            Some(files.join("a.png"))
+        );
+        assert_eq!(
+            upload_file(Some(files), "/uploads/thumbs/b.png"),
+            Some(files.join("thumbs/b.png"))
+        );
+        assert_eq!(upload_file(Some(files), "/uploads/../etc/passwd"), None);
+        assert_eq!(
+            upload_file(Some(files), "https://cdn.example.com/a.png"),
+            None
+        );
+        assert_eq!(upload_file(None, "/uploads/a.png"), None);
+    }
+}
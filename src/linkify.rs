@@ -0,0 +1,211 @@
+// Bare http(s) URLs in posts become links. URLs are found in the raw text and
+// every piece is escaped on its own, so a URL can't carry quotes or angle
+// brackets into the markup.
+
+use crate::render::escape_html;
+
+// Longer URLs are shortened to this many characters (plus an ellipsis) in the
+// link text; the href is always the whole URL
+const MAX_SHOWN_CHARS: usize = 60;
+
+// Characters that end a URL outright
+fn ends_url(c: char) -> bool {
+    c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | '"' | '`')
+}
+
+// The byte length of the URL starting at the beginning of `text`, with
+// trailing punctuation left out: sentence punctuation, and closing brackets
+// the URL doesn't open itself
+fn url_len(text: &str) -> usize {
+    let mut url = &text[..text.find(ends_url).unwrap_or(text.len())];
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '*']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if inner.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url.len();
+        }
+        url = trimmed;
+    }
+}
+
+// Where the next URL in `text` starts, and its length
+fn next_url(text: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(i) = text[from..].find("http").map(|i| from + i) {
+        let rest = &text[i..];
+        let scheme = ["https://", "http://"]
+            .into_iter()
+            .find(|s| rest.starts_with(s));
+        // Not in the middle of a word, like "xhttp://"
+        let at_boundary = !text[..i].ends_with(|c: char| c.is_alphanumeric());
+        if let (Some(scheme), true) = (scheme, at_boundary) {
+            let len = url_len(rest);
+            if len > scheme.len() {
+                return Some((i, len));
+            }
+        }
+        from = i + "http".len();
+    }
+    None
+}
+
+// Turns the URLs in one post into links, up to the post's limit
+pub struct Linkifier {
+    max_links: usize,
+    links: usize,
+}
+
+impl Linkifier {
+    // A `max_links` of 0 links every URL
+    pub fn new(max_links: usize) -> Self {
+        Linkifier {
+            max_links,
+            links: 0,
+        }
+    }
+
+    // Escape `text` for HTML, linking its URLs while the post has links to
+    // spare; later URLs stay text
+    pub fn linkify(&mut self, text: &str) -> String {
+        let mut html = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some((start, len)) = next_url(rest) {
+            if self.max_links > 0 && self.links >= self.max_links {
+                break;
+            }
+            self.links += 1;
+            let url = &rest[start..start + len];
+            html.push_str(&escape_html(&rest[..start]));
+            html.push_str(&format!(
+                "<a href=\"{}\" rel=\"nofollow noopener\" target=\"_blank\">{}</a>",
+                escape_html(url),
+                escape_html(&shorten(url))
+            ));
+            rest = &rest[start + len..];
+        }
+        html.push_str(&escape_html(rest));
+        html
+    }
+}
+
+fn shorten(url: &str) -> String {
+    match url.char_indices().nth(MAX_SHOWN_CHARS) {
+        Some((cut, _)) => format!("{}…", &url[..cut]),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str) -> String {
+        format!(
+            "<a href=\"{}\" rel=\"nofollow noopener\" target=\"_blank\">{}</a>",
+            url, url
+        )
+    }
+
+    fn linkify(text: &str) -> String {
+        Linkifier::new(0).linkify(text)
+    }
+
+    #[test]
+    fn leaves_punctuation_after_urls() {
+        assert_eq!(
+            linkify("see https://example.com/a, or http://example.org."),
+            format!(
+                "see {}, or {}.",
+                link("https://example.com/a"),
+                link("http://example.org")
+            )
+        );
+        assert_eq!(
+            linkify("https://example.com/?q=1!"),
+            format!("{}!", link("https://example.com/?q=1"))
+        );
+        assert_eq!(
+            linkify("'https://example.com'"),
+            format!("&#39;{}&#39;", link("https://example.com"))
+        );
+    }
+
+    #[test]
+    fn balances_parentheses() {
+        assert_eq!(
+            linkify("(see https://example.com/page)"),
+            format!("(see {})", link("https://example.com/page"))
+        );
+        assert_eq!(
+            linkify("https://en.wikipedia.org/wiki/Rust_(programming_language)."),
+            format!(
+                "{}.",
+                link("https://en.wikipedia.org/wiki/Rust_(programming_language)")
+            )
+        );
+        assert_eq!(
+            linkify("(https://en.wikipedia.org/wiki/Rust_(game))"),
+            format!("({})", link("https://en.wikipedia.org/wiki/Rust_(game)"))
+        );
+    }
+
+    #[test]
+    fn urls_cannot_break_out_of_the_link() {
+        assert_eq!(
+            linkify("https://example.com/\"onmouseover=\"alert(1)"),
+            format!(
+                "{}&quot;onmouseover=&quot;alert(1)",
+                link("https://example.com/")
+            )
+        );
+        assert_eq!(
+            linkify("https://example.com/<script>"),
+            format!("{}&lt;script&gt;", link("https://example.com/"))
+        );
+        assert_eq!(
+            linkify("https://example.com/?a=1&b=2"),
+            link("https://example.com/?a=1&amp;b=2")
+        );
+    }
+
+    #[test]
+    fn needs_a_scheme_at_a_word_boundary() {
+        assert_eq!(
+            linkify("https:// and xhttp://example.com"),
+            "https:// and xhttp://example.com"
+        );
+        assert_eq!(
+            linkify("ftp://example.com www.example.com"),
+            "ftp://example.com www.example.com"
+        );
+    }
+
+    #[test]
+    fn shortens_long_urls() {
+        let url = format!("https://example.com/{}", "a".repeat(100));
+        let html = linkify(&url);
+        assert!(
+            html.starts_with(&format!("<a href=\"{}\"", url)),
+            "{}",
+            html
+        );
+        assert!(
+            html.ends_with(&format!(">{}…</a>", &url[..MAX_SHOWN_CHARS])),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn stops_linking_at_the_limit() {
+        let mut linkifier = Linkifier::new(2);
+        assert_eq!(
+            linkifier.linkify("http://a.example http://b.example"),
+            format!("{} {}", link("http://a.example"), link("http://b.example"))
+        );
+        assert_eq!(linkifier.linkify("http://c.example"), "http://c.example");
+    }
+}
@@ -0,0 +1,499 @@
+// A board's article lists: current, tagged, archived and the catalog, a page
+// at a time, and search
+
+use actix_session::Session;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use askama::Template as _;
+use chrono::Utc;
+use serde::Deserialize;
+use std::time::Instant;
+use tracing::error;
+
+use crate::admin::AdminUser;
+use crate::board::Board;
+use crate::conditional::Validator;
+use crate::db::{ArticleRepository, ArticleSort, ListedArticle, SearchScope};
+use crate::error::AppError;
+use crate::flash;
+use crate::markdown;
+use crate::page_cache::{CachedPage, PageCache, PageKey};
+use crate::render::{display_time, highlight_html, summary};
+use crate::slug::article_path;
+use crate::templates::{
+    render_html, ArticleListContext, ArticleListItem, CatalogContext, MessageContext,
+    SearchContext, SearchResult,
+};
+use crate::{is_audio_path, CATALOG_EXCERPT_CHARS, DEFAULT_PER_PAGE, EXCERPT_CHARS, MAX_PER_PAGE};
+
+const MAX_SEARCH_CHARS: usize = 100;
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+    #[serde(default)]
+    sort: ArticleSort,
+}
+
+impl ListQuery {
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn per_page(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TagPath {
+    tag: String,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: Option<String>,
+    #[serde(default)]
+    scope: SearchScope,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+// Which articles an article list page shows
+#[derive(Clone, Copy)]
+enum Listing<'a> {
+    Current,
+    Tagged(&'a str),
+    Archived,
+    // The current articles as a grid of cards
+    Catalog,
+}
+
+// How a GET's page may be reused: the client's copy revalidated (see
+// conditional.rs) and, with `cache`, the rendering kept (see page_cache.rs)
+pub struct Reuse<'a> {
+    pub request: &'a HttpRequest,
+    // The page's key, and PageCache::generation from before its reads
+    pub cache: Option<(&'a PageCache, PageKey, u64)>,
+}
+
+// List articles one page at a time
+#[allow(clippy::too_many_arguments)]
+pub async fn list_articles(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    query: web::Query<ListQuery>,
+    session: Session,
+    admin: Option<AdminUser>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let flash = flash::take(&session);
+    // A flash is shown once, so that page is never reused
+    if flash.is_some() {
+        return article_list_page(
+            repo.get_ref(),
+            &board,
+            &query,
+            Listing::Current,
+            flash.as_deref(),
+            None,
+        )
+        .await;
+    }
+    // Moderators get the list as it is
+    let cache = (admin.is_none() && page_cache.enabled()).then_some(page_cache.get_ref());
+    let key = PageKey::List {
+        board_id: board.id,
+        page: query.page(),
+        per_page: query.per_page(),
+        sort: query.sort,
+    };
+    if let Some(page) = cache.and_then(|cache| cache.get(&key, Instant::now())) {
+        return Ok(send_cached_list(&page, &req));
+    }
+    let reuse = Reuse {
+        request: &req,
+        cache: cache.map(|cache| (cache, key, cache.generation())),
+    };
+    article_list_page(
+        repo.get_ref(),
+        &board,
+        &query,
+        Listing::Current,
+        None,
+        Some(reuse),
+    )
+    .await
+}
+
+// A kept list page: a 304 when the client's copy is still good
+fn send_cached_list(page: &CachedPage, req: &HttpRequest) -> HttpResponse {
+    let now = Utc::now().timestamp();
+    if let Some(not_modified) = page.validator.not_modified(req, now) {
+        return not_modified;
+    }
+    let mut response = HttpResponse::Ok()
+        .content_type("text/html")
+        .body(page.html.clone());
+    page.validator.apply(&mut response, now);
+    response
+}
+
+// Redirect to a random current article, or back to the list when there are none
+pub async fn random_article(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    session: Session,
+) -> Result<HttpResponse, AppError> {
+    let location = match repo.random_article(board.id).await? {
+        Some((article_id, slug)) => format!(
+            "{}{}",
+            board.base,
+            article_path(article_id, slug.as_deref())
+        ),
+        None => {
+            flash::set(&session, "There are no articles to pick from yet.");
+            format!("{}/articles", board.base)
+        }
+    };
+    Ok(HttpResponse::Found()
+        .append_header(("Location", location))
+        .finish())
+}
+
+// List archived articles, paginated like the main list
+pub async fn list_archived_articles(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    query: web::Query<ListQuery>,
+) -> Result<HttpResponse, AppError> {
+    article_list_page(
+        repo.get_ref(),
+        &board,
+        &query,
+        Listing::Archived,
+        None,
+        None,
+    )
+    .await
+}
+
+// Show the current articles as a grid of cards, paginated like the main list
+pub async fn catalog(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    query: web::Query<ListQuery>,
+) -> Result<HttpResponse, AppError> {
+    article_list_page(repo.get_ref(), &board, &query, Listing::Catalog, None, None).await
+}
+
+// List the articles carrying a tag, paginated like the main list
+pub async fn list_tagged_articles(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    path: web::Path<TagPath>,
+    query: web::Query<ListQuery>,
+) -> Result<HttpResponse, AppError> {
+    // Match the normalization applied when tags are stored, so /tags/Rust
+    // finds articles tagged "rust"
+    let tag = path.tag.trim().to_lowercase();
+    article_list_page(
+        repo.get_ref(),
+        &board,
+        &query,
+        Listing::Tagged(&tag),
+        None,
+        None,
+    )
+    .await
+}
+
+// Render one page of an article list, with `flash` (see flash.rs) above it
+async fn article_list_page(
+    repo: &dyn ArticleRepository,
+    board: &Board,
+    query: &ListQuery,
+    listing: Listing<'_>,
+    flash: Option<&str>,
+    reuse: Option<Reuse<'_>>,
+) -> Result<HttpResponse, AppError> {
+    let (per_page, page) = (query.per_page(), query.page());
+    let offset = (page - 1).saturating_mul(per_page);
+
+    let total = match listing {
+        Listing::Current | Listing::Catalog => repo.count_articles(board.id).await?,
+        Listing::Tagged(tag) => repo.count_tagged_articles(board.id, tag).await?,
+        Listing::Archived => repo.count_archived_articles(board.id).await?,
+    };
+    // Tags only exist through the articles that carry them
+    if let (Listing::Tagged(tag), 0) = (listing, total) {
+        return Ok(render_html(
+            StatusCode::NOT_FOUND,
+            &MessageContext {
+                title: "Tag Not Found",
+                message: &format!("No articles are tagged \"{}\".", tag),
+                link_href: &format!("{}/articles", board.base),
+                link_text: "View all articles",
+            },
+        ));
+    }
+    let total_pages = ((total + per_page - 1) / per_page).max(1);
+
+    let articles_db = match listing {
+        Listing::Current | Listing::Catalog => {
+            repo.list_articles(board.id, query.sort, per_page, offset)
+                .await?
+        }
+        Listing::Tagged(tag) => {
+            repo.list_tagged_articles(board.id, tag, query.sort, per_page, offset)
+                .await?
+        }
+        Listing::Archived => {
+            repo.list_archived_articles(board.id, query.sort, per_page, offset)
+                .await?
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let validator = Validator::new(
+        (
+            page,
+            per_page,
+            total,
+            query.sort,
+            articles_db
+                .iter()
+                .map(|listed| {
+                    let a = &listed.article;
+                    (
+                        a.id,
+                        a.bump_time,
+                        a.edited_at,
+                        a.archived_at,
+                        a.is_sticky,
+                        listed.comment_count,
+                        &listed.thumb_path,
+                    )
+                })
+                .collect::<Vec<_>>(),
+        ),
+        articles_db
+            .iter()
+            .flat_map(|listed| {
+                [
+                    Some(listed.article.bump_time),
+                    listed.last_comment_at,
+                    listed.article.edited_at,
+                    listed.article.archived_at,
+                ]
+            })
+            .flatten()
+            .max()
+            .unwrap_or(0),
+    );
+    if let Some(not_modified) = reuse
+        .as_ref()
+        .and_then(|reuse| validator.not_modified(reuse.request, now))
+    {
+        return Ok(not_modified);
+    }
+    let excerpt_chars = match listing {
+        Listing::Catalog => CATALOG_EXCERPT_CHARS,
+        _ => EXCERPT_CHARS,
+    };
+    let articles = articles_db
+        .into_iter()
+        .map(|listed| list_item(listed, excerpt_chars, now))
+        .collect();
+
+    if let Listing::Catalog = listing {
+        return Ok(render_html(
+            StatusCode::OK,
+            &CatalogContext {
+                board,
+                sort: query.sort,
+                articles,
+                page,
+                total_pages,
+                per_page,
+            },
+        ));
+    }
+    let context = ArticleListContext {
+        board,
+        tag: match listing {
+            Listing::Tagged(tag) => Some(tag),
+            _ => None,
+        },
+        archived: matches!(listing, Listing::Archived),
+        flash,
+        sort: query.sort,
+        articles,
+        page,
+        total_pages,
+        per_page,
+    };
+    match reuse {
+        Some(Reuse {
+            request,
+            cache: Some((cache, key, generation)),
+        }) => {
+            let page = CachedPage {
+                html: context.render()?,
+                validator,
+            };
+            Ok(send_cached_list(
+                &cache.put(key, page, generation, Instant::now()),
+                request,
+            ))
+        }
+        reuse => {
+            let mut response = render_html(StatusCode::OK, &context);
+            if reuse.is_some() {
+                validator.apply(&mut response, now);
+            }
+            Ok(response)
+        }
+    }
+}
+
+// An article as a list or catalog shows it, with an excerpt of up to
+// `excerpt_chars`
+pub fn list_item(listed: ListedArticle, excerpt_chars: usize, now: i64) -> ArticleListItem {
+    let tags = listed.tags();
+    let a = listed.article;
+    // Videos only have a picture once their poster frame is made; audio
+    // never has one
+    let (thumb, video, audio) = match (listed.thumb_path, listed.media_path) {
+        (Some(thumb), _) => (Some(thumb), false, false),
+        (None, Some(path)) if path.ends_with(".mp4") => (None, true, false),
+        (None, Some(path)) if is_audio_path(&path) => (None, false, true),
+        (None, path) => (path, false, false),
+    };
+    ArticleListItem {
+        path: article_path(a.id, a.slug.as_deref()),
+        tags,
+        excerpt: summary(&markdown::to_plain_text(&a.body), excerpt_chars),
+        title: a.title,
+        thumb,
+        video,
+        audio,
+        sticky: a.is_sticky,
+        comment_count: listed.comment_count,
+        last_activity: display_time(listed.last_comment_at.unwrap_or(a.created_at), now),
+    }
+}
+
+// The trimmed search query, or None when it is blank. Overlong queries are
+// rejected with a message for the client.
+pub fn search_query(q: Option<&str>) -> Result<Option<&str>, String> {
+    let q = q.unwrap_or_default().trim();
+    if q.is_empty() {
+        Ok(None)
+    } else if q.chars().count() > MAX_SEARCH_CHARS {
+        Err(format!(
+            "Search queries are limited to {} characters",
+            MAX_SEARCH_CHARS
+        ))
+    } else {
+        Ok(Some(q))
+    }
+}
+
+// Search articles (or their comments with scope=comments); a blank query just
+// shows the form
+pub async fn search(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    query: web::Query<SearchQuery>,
+) -> HttpResponse {
+    let q = match search_query(query.q.as_deref()) {
+        Ok(Some(q)) => q,
+        Ok(None) => {
+            return render_html(
+                StatusCode::OK,
+                &SearchContext {
+                    board: &board,
+                    query: "",
+                    scope: query.scope,
+                    results: Vec::new(),
+                    total: 0,
+                    page: 1,
+                    total_pages: 1,
+                    per_page: DEFAULT_PER_PAGE,
+                },
+            )
+        }
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let page = query.page.unwrap_or(1).max(1);
+
+    let total = match repo.count_search_results(board.id, q, query.scope).await {
+        Ok(n) => n,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to count search results");
+            return HttpResponse::InternalServerError().body("Search failed");
+        }
+    };
+    let rows = match repo
+        .search(
+            board.id,
+            q,
+            query.scope,
+            per_page,
+            (page - 1).saturating_mul(per_page),
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!(board_id = board.id, error = %e, "Failed to search articles");
+            return HttpResponse::InternalServerError().body("Search failed");
+        }
+    };
+
+    render_html(
+        StatusCode::OK,
+        &SearchContext {
+            board: &board,
+            query: q,
+            scope: query.scope,
+            results: rows
+                .into_iter()
+                .map(|row| SearchResult {
+                    snippet_html: highlight_html(&row.snippet),
+                    article_id: row.article_id,
+                    path: article_path(row.article_id, row.slug.as_deref()),
+                    title: row.title,
+                    comment_id: row.comment_id,
+                    archived: row.archived,
+                })
+                .collect(),
+            total,
+            page,
+            total_pages: ((total + per_page - 1) / per_page).max(1),
+            per_page,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_sorts_fall_back_to_bump() {
+        let sort = |query: &str| serde_urlencoded::from_str::<ListQuery>(query).unwrap().sort;
+        assert_eq!(sort("sort=comments"), ArticleSort::Comments);
+        assert_eq!(sort("sort=sideways"), ArticleSort::Bump);
+        assert_eq!(sort("sort="), ArticleSort::Bump);
+        assert_eq!(sort("page=2"), ArticleSort::Bump);
+    }
+}
@@ -0,0 +1,159 @@
+// Logging through tracing. Lines go to stderr, laid out for reading or as one
+// JSON object each (LOG_FORMAT), for whatever RUST_LOG lets through: warnings
+// and errors by default. The server records errors in the database (see
+// app_errors.rs), and they can also be appended to ERROR_LOG_PATH. Records
+// dependencies such as sqlx make with the log crate come through as well.
+//
+// Every request runs in a span carrying a fresh id, which is sent back in
+// X-Request-Id so a report from a user can be matched to its log lines. Once
+// answered it is logged at info level with the target "access", unless its
+// path is one of ACCESS_LOG_EXCLUDE.
+
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web, Error,
+};
+use std::path::Path;
+use std::time::Instant;
+use tokio::sync::mpsc::Receiver;
+use tracing::{field::Empty, info, info_span, Instrument, Metadata};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{filter_fn, EnvFilter, FilterExt as _, FilterFn, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
+use tracing_subscriber::{fmt, Layer};
+
+use crate::app_errors::{self, ErrorLayer};
+use crate::client_ip::client_ip;
+use crate::config::{Config, LogFormat, LogRotation};
+use crate::db::AppErrorRow;
+
+const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const ACCESS: &str = "access";
+
+// Request spans are kept whatever the level filters say, so the lines that
+// are written carry the request id
+fn request_spans() -> FilterFn<impl Fn(&Metadata<'_>) -> bool> {
+    filter_fn(|meta| meta.is_span() && meta.target() == module_path!())
+}
+
+pub struct Logging {
+    // Flushes the error file when dropped, so it has to live as long as
+    // anything might log
+    pub guard: Option<WorkerGuard>,
+    // Errors to write to the database, when they're recorded
+    pub errors: Option<Receiver<AppErrorRow>>,
+}
+
+// Install the subscriber, queueing errors for the database if `record_errors`
+pub fn init(config: &Config, record_errors: bool) -> Result<Logging, String> {
+    let stderr = match config.log_format {
+        LogFormat::Pretty => fmt::layer().pretty().with_writer(std::io::stderr).boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(std::io::stderr)
+            .boxed(),
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,{}=info", ACCESS)));
+
+    let (file, guard) = match &config.error_log_path {
+        Some(path) => {
+            let rotation = match config.error_log_rotation {
+                LogRotation::Never => Rotation::NEVER,
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+            };
+            let name = path
+                .file_name()
+                .ok_or_else(|| format!("ERROR_LOG_PATH {} names no file", path.display()))?;
+            let appender = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(name.to_string_lossy())
+                .build(
+                    path.parent()
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .unwrap_or(Path::new(".")),
+                )
+                .map_err(|e| format!("Failed to open ERROR_LOG_PATH {}: {}", path.display(), e))?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer)
+                .with_filter(LevelFilter::ERROR.or(request_spans()));
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let (recorder, errors) = match record_errors {
+        true => {
+            let (sender, errors) = app_errors::channel();
+            (
+                Some(ErrorLayer::new(sender).with_filter(LevelFilter::ERROR.or(request_spans()))),
+                Some(errors),
+            )
+        }
+        false => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr.with_filter(filter.or(request_spans())))
+        .with(file)
+        .with(recorder)
+        .try_init()
+        .map_err(|e| format!("Failed to set up logging: {}", e))?;
+    Ok(Logging { guard, errors })
+}
+
+// Middleware: run the request in a span with its id, return the id and log
+// how it was answered
+pub async fn request_span(
+    config: web::Data<Config>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = uuid::Uuid::new_v4().simple().to_string();
+    // api_key is filled in by api_key::ApiKey
+    let span = info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.path(),
+        api_key = Empty,
+    );
+    let logged = !config
+        .access_log_exclude
+        .iter()
+        .any(|prefix| req.path().starts_with(prefix.as_str()));
+    let client = client_ip(req.request());
+    let started = Instant::now();
+
+    let mut res = next.call(req).instrument(span.clone()).await?;
+    if logged {
+        let bytes = match res.response().body().size() {
+            BodySize::Sized(bytes) => Some(bytes),
+            BodySize::None | BodySize::Stream => None,
+        };
+        span.in_scope(|| {
+            info!(
+                target: ACCESS,
+                status = res.status().as_u16(),
+                latency_ms = started.elapsed().as_secs_f64() * 1000.0,
+                bytes,
+                client_ip = client.map(display),
+                "Request answered",
+            )
+        });
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID, value);
+    }
+    Ok(res)
+}
@@ -1,386 +1,1277 @@
-use actix_files::Files;
-use actix_multipart::Multipart;
-use actix_web::{error::ErrorInternalServerError, web, App, Error, HttpResponse, HttpServer};
-use chrono::Utc;
-use futures_util::stream::StreamExt as _;
-use sanitize_filename::sanitize;
+use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::{Key, SameSite},
+    middleware::{from_fn, Compress, Condition},
+    web, App, HttpServer,
+};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
-use std::fs::{self, File, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use std::env;
+use sqlx::FromRow;
+use std::fs;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tracing::{error, warn};
+use utoipa::ToSchema;
 
-const MAIN_PAGE_TITLE: &str = "All Articles";
+mod admin;
+mod api;
+mod api_key;
+mod app_errors;
+mod article;
+mod assets;
+mod ban;
+mod board;
+mod captcha;
+mod cli;
+mod client_ip;
+mod comment;
+mod compression;
+mod conditional;
+mod config;
+mod cors;
+mod csrf;
+mod db;
+mod deleted;
+mod duplicate;
+mod error;
+mod export;
+mod feed;
+mod flash;
+mod health;
+mod highlight;
+mod import;
+mod linkify;
+mod listing;
+mod logging;
+mod markdown;
+mod media;
+mod notify;
+mod openapi;
+mod orphans;
+mod page_cache;
+mod password;
+mod post;
+mod quote;
+mod rate_limit;
+mod render;
+mod report;
+mod robots;
+mod signing;
+mod slug;
+mod spam;
+mod spoiler;
+mod storage;
+mod templates;
+mod thread;
+mod tls;
+mod trending;
+mod tripcode;
+mod upload;
+mod webhook;
+mod word_filter;
 
-#[derive(Serialize, Deserialize)]
-struct CommentForm {
-    comment: String,
+use api_key::ApiKeys;
+use captcha::Captchas;
+use clap::Parser;
+use cli::{ApiKeyCommand, Cli, Command, CommandResult, WebhookCommand};
+use config::{Config, Overrides, StorageConfig};
+use db::{AppErrorRow, ArticleRepository};
+use duplicate::RecentPosts;
+use notify::Notifier;
+use page_cache::PageCache;
+use render::format_size;
+use trending::TrendingCache;
+use webhook::Webhooks;
+use word_filter::WordFilters;
+
+const DEFAULT_PER_PAGE: i64 = 25;
+const MAX_PER_PAGE: i64 = 100;
+const COMMENTS_PER_PAGE: i64 = 100;
+// What posts with the name left blank are shown under
+const DEFAULT_NAME: &str = "Anonymous";
+const EXCERPT_CHARS: usize = 200;
+// Catalog cards are small, so their excerpts are shorter
+const CATALOG_EXCERPT_CHARS: usize = 100;
+// Related articles listed under an article, see ArticleRepository::related_articles
+const RELATED_ARTICLES: i64 = 5;
+const CAPTCHA_ERROR: &str = "The captcha answer was wrong or has expired. Please try again.";
+
+// Path parameters are matched by name so the same handlers serve both the
+// original routes and the /b/{board} ones
+#[derive(Deserialize)]
+struct ArticlePath {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+struct CommentPath {
+    article_id: i32,
+    comment_id: i32,
+}
+
+#[derive(Deserialize)]
+struct DeleteForm {
+    password: String,
+}
+
+#[derive(FromRow, Serialize, Deserialize)]
+struct DbBoard {
+    id: i32,
+    slug: String,
+    title: String,
+    description: String,
 }
 
-#[derive(Serialize, FromRow)]
+#[derive(Clone, Serialize, FromRow)]
 struct DbArticle {
     id: i32,
+    board_id: i32,
     title: String,
+    slug: Option<String>,
     body: String,
+    name: String,
+    tripcode: Option<String>,
+    created_at: i64,
     bump_time: i64,
+    edited_at: Option<i64>,
+    archived_at: Option<i64>,
+    is_sticky: bool,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+struct DbComment {
+    id: i32,
+    comment: String,
+    name: String,
+    tripcode: Option<String>,
+    created_at: i64,
+    parent_comment_id: Option<i32>,
+    // A deleted comment kept as a placeholder for its replies
+    deleted: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Debug, Default, Serialize, FromRow, ToSchema)]
+struct Media {
+    media_path: String,
+    original_name: Option<String>,
+    thumb_path: Option<String>,
+    // Unknown for files stored before these were kept, until backfill-media
+    // reads them
+    byte_size: Option<i64>,
+    mime_type: Option<String>,
+    // Of images, in pixels
+    width: Option<i32>,
+    height: Option<i32>,
+    // The SHA-256 of the file, for uploads since hashes were kept
+    #[serde(skip)]
+    #[sqlx(default)]
+    content_hash: Option<String>,
+}
+
+impl Media {
+    // What's known of a file just stored, before it has a name or thumbnail
+    fn uploaded(stored: media::StoredUpload) -> Self {
+        let (width, height) = stored.dimensions.map(|(w, h)| (w as i32, h as i32)).unzip();
+        Media {
+            media_path: stored.url,
+            byte_size: Some(stored.byte_size as i64),
+            mime_type: Some(stored.media_type.mime().to_string()),
+            width,
+            height,
+            content_hash: Some(stored.sha256),
+            ..Default::default()
+        }
+    }
+
+    fn is_video(&self) -> bool {
+        self.media_path.ends_with(".mp4")
+    }
+
+    fn is_audio(&self) -> bool {
+        is_audio_path(&self.media_path)
+    }
+
+    // Its size and dimensions as shown beside its name, e.g. "245 KB, 1024×768"
+    fn details(&self) -> Option<String> {
+        let size = self.byte_size.map(|bytes| format_size(bytes as u64));
+        let dimensions = self
+            .width
+            .zip(self.height)
+            .map(|(width, height)| format!("{}×{}", width, height));
+        let details: Vec<String> = size.into_iter().chain(dimensions).collect();
+        (!details.is_empty()).then(|| details.join(", "))
+    }
+
+    // Its file and thumbnail
+    fn file_paths(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.media_path).chain(self.thumb_path.as_ref())
+    }
+}
+
+// Audio is stored as .mp3, .ogg or .flac, which is all a list has to go by
+fn is_audio_path(path: &str) -> bool {
+    [".mp3", ".ogg", ".flac"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+#[derive(Serialize, ToSchema)]
 struct Article {
     id: i32,
+    board_id: i32,
     title: String,
+    slug: Option<String>,
     body: String,
-    media_paths: Vec<String>,
+    media: Vec<Media>,
+    tags: Vec<String>,
+    name: String,
+    tripcode: Option<String>,
+    created_at: i64,
+    // Only for ordering; bumped by comments
     bump_time: i64,
+    edited_at: Option<i64>,
+    // Archived articles are read-only
+    archived_at: Option<i64>,
+    is_sticky: bool,
+    // Of its live comments
+    last_comment_at: Option<i64>,
 }
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve { bind: None });
+    // Needs no configuration, and is how ADMIN_PASSWORD_HASH gets made
+    if let Command::HashPassword = command {
+        return exit_code(admin::hash_admin_password());
+    }
+    let overrides = Overrides {
+        database_url: cli.database_url,
+        bind: match &command {
+            Command::Serve { bind } => bind.clone(),
+            _ => None,
+        },
+    };
 
-    create_and_set_permissions("uploads")?;
+    // Report every missing or malformed variable at once rather than failing
+    // on the first one
+    let config = match Config::load(overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            eprint!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Held until exit, so the error log is flushed
+    let logging::Logging {
+        guard: _log_guard,
+        errors,
+    } = match logging::init(&config, matches!(command, Command::Serve { .. })) {
+        Ok(logging) => logging,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    render::set_display_offset(config.display_timezone);
+    highlight::set_languages(config.highlight_languages.clone());
 
-    // Retrieve DATABASE_URL from environment
-    let database_url = env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set in the environment before running");
+    let result = match command {
+        Command::Serve { .. } => serve(config, errors).await,
+        Command::Migrate => migrate(&config).await,
+        Command::Prune { keep } => deleted::prune(&config, keep).await,
+        Command::PurgeDeleted => deleted::purge_deleted(&config).await,
+        Command::PruneMedia { dry_run } => orphans::prune_media(&config, dry_run).await,
+        Command::BackfillMedia => media::backfill_media(&config).await,
+        Command::AddBoard {
+            slug,
+            title,
+            description,
+        } => board::add_board(&config, &slug, &title, &description).await,
+        Command::AddFilter {
+            pattern,
+            regex,
+            replace,
+        } => word_filter::add_filter(&config, &pattern, regex, replace.as_deref()).await,
+        Command::RemoveFilter { id } => word_filter::remove_filter(&config, id).await,
+        Command::ListFilters => word_filter::list_filters(&config).await,
+        Command::Ban {
+            target,
+            reason,
+            duration,
+        } => ban::ban(&config, &target, &reason, duration).await,
+        Command::Unban { id } => ban::unban(&config, id).await,
+        Command::ListBans => ban::list_bans(&config).await,
+        Command::Export { out, include_files } => {
+            export::export_data(&config, &out, include_files).await
+        }
+        Command::Import {
+            path,
+            files,
+            force,
+            dry_run,
+        } => import::import_data(&config, &path, files.as_deref(), force, dry_run).await,
+        Command::ApiKey { command } => match command {
+            ApiKeyCommand::Create { label, admin } => {
+                let scope = if admin {
+                    api_key::Scope::Admin
+                } else {
+                    api_key::Scope::Write
+                };
+                api_key::create_api_key(&config, &label, scope).await
+            }
+            ApiKeyCommand::Revoke { id } => api_key::revoke_api_key(&config, id).await,
+            ApiKeyCommand::List => api_key::list_api_keys(&config).await,
+        },
+        Command::Webhook { command } => match command {
+            WebhookCommand::Add {
+                url,
+                events,
+                secret,
+                test,
+            } => webhook::add_webhook(&config, &url, &events, secret, test).await,
+            WebhookCommand::Remove { id } => webhook::remove_webhook(&config, id).await,
+            WebhookCommand::List => webhook::list_webhooks(&config).await,
+            WebhookCommand::Test { id } => webhook::test_webhook(&config, id).await,
+            WebhookCommand::Failures => webhook::list_webhook_failures(&config).await,
+        },
+        Command::HashPassword => unreachable!("handled before loading the configuration"),
+    };
+    exit_code(result)
+}
 
-    let pool = PgPool::connect(&database_url)
+fn exit_code(result: CommandResult) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Count what's under UPLOADS_DIR afresh, for UPLOADS_QUOTA_BYTES, in case
+// files were added or removed other than through storage
+async fn count_uploads(config: &Config, repo: &dyn ArticleRepository) -> CommandResult {
+    let uploads_dir = config.uploads_dir.clone();
+    let bytes = tokio::task::spawn_blocking(move || orphans::total_bytes(&uploads_dir))
         .await
-        .expect("Failed to connect to Postgres");
+        .map_err(|e| e.to_string())
+        .and_then(|counted| counted.map_err(|e| e.to_string()))
+        .map_err(|e| {
+            format!(
+                "Failed to count the files in {}: {}",
+                config.uploads_dir.display(),
+                e
+            )
+        })?;
+    repo.set_storage_usage(bytes as i64)
+        .await
+        .map_err(|e| format!("Failed to record storage usage: {}", e))?;
+    Ok(())
+}
+
+// `articles serve`: run the web server until it is shut down, writing the
+// errors logged meanwhile to the database
+async fn serve(config: Config, errors: Option<Receiver<AppErrorRow>>) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    if !config.skip_migrations {
+        run_migrations(repo.as_ref()).await?;
+    }
+    let storage = storage::from_config(&config, repo.clone())?;
+    if matches!(config.storage, StorageConfig::Local) {
+        count_uploads(&config, repo.as_ref()).await?;
+    }
+    match repo.get_board(&config.default_board).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(format!(
+                "DEFAULT_BOARD {:?} does not exist; create it with `articles add-board`",
+                config.default_board
+            )
+            .into())
+        }
+        Err(e) => return Err(format!("Failed to look up the default board: {}", e).into()),
+    }
+
+    if let Some(errors) = errors {
+        app_errors::spawn_writer(errors, repo.clone());
+    }
+
+    let rate_limits = web::Data::new(rate_limit::RateLimits::from_config(&config));
+    let captchas = web::Data::new(Captchas::new());
+    let recent_posts = web::Data::new(RecentPosts::from_config(&config));
+    let trending_cache = web::Data::new(TrendingCache::from_config(&config));
+    let static_files = assets::init();
+    let page_cache = web::Data::new(PageCache::from_config(&config));
+    let api_keys = web::Data::new(ApiKeys::from_config(&config));
+    let webhooks = web::Data::new(Webhooks::spawn(repo.clone(), &config));
+    let notifier = web::Data::new(Notifier::spawn(repo.clone(), &config));
+    let word_filters = web::Data::new(WordFilters::new());
+    word_filters
+        .reload(repo.as_ref())
+        .await
+        .map_err(|e| format!("Failed to load word filters: {}", e))?;
+    word_filter::spawn_refresh(
+        word_filters.clone(),
+        web::Data::from(repo.clone()),
+        Duration::from_secs(config.word_filter_refresh_secs.max(1)),
+    );
+    // Bucket backends keep files elsewhere, and their own lifecycle rules
+    if config.media_prune_interval_hours > 0 && matches!(config.storage, StorageConfig::Local) {
+        orphans::spawn(
+            repo.clone(),
+            config.uploads_dir.clone(),
+            Duration::from_secs(config.media_prune_interval_hours * 60 * 60),
+            Duration::from_secs(config.media_prune_grace_minutes * 60),
+        );
+    }
+    let session_key = Key::from(&signing::derive_key(&config.secret_key, "session"));
+    let (bind_addrs, port, workers) = (config.bind_addrs.clone(), config.port, config.workers);
+    let listen_uds = config
+        .listen_uds
+        .clone()
+        .map(|path| (path, config.listen_uds_mode));
+    let certificates = match &config.tls {
+        Some(tls) => {
+            let certificates = Arc::new(tls::Certificates::load(tls)?);
+            tls::spawn_reload_on_hangup(certificates.clone());
+            Some(certificates)
+        }
+        None => None,
+    };
+    let shutdown_timeout = config.shutdown_timeout_secs;
+    let redirect_port = config.tls_redirect_port;
+    let config = web::Data::new(config);
+    // Kept to close the pool once the server stops
+    let shutdown_repo = repo.clone();
+    let redirect_config = config.clone();
+
+    let mut server = HttpServer::new(move || {
+        let sessions =
+            SessionMiddleware::builder(CookieSessionStore::default(), session_key.clone())
+                .cookie_name("session".to_string())
+                .cookie_secure(config.secure_cookies())
+                .cookie_same_site(SameSite::Lax)
+                .build();
 
-    HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(pool.clone()))
-            .route("/", web::get().to(new_article_form))
-            .route("/submit", web::post().to(submit_article))
-            .route("/articles", web::get().to(list_articles))
-            .route("/articles/{id}", web::get().to(view_article))
-            .route("/articles/{id}/comment", web::post().to(submit_comment))
-            .service(Files::new("/static", "./static"))
-            .service(Files::new("/uploads", "./uploads"))
+            .app_data(web::Data::from(repo.clone()))
+            .app_data(config.clone())
+            .app_data(rate_limits.clone())
+            .app_data(captchas.clone())
+            .app_data(recent_posts.clone())
+            .app_data(trending_cache.clone())
+            .app_data(page_cache.clone())
+            .app_data(api_keys.clone())
+            .app_data(webhooks.clone())
+            .app_data(notifier.clone())
+            .app_data(word_filters.clone())
+            .wrap(from_fn(csrf::protect))
+            .wrap(sessions)
+            .wrap(from_fn(logging::request_span))
+            .wrap(from_fn(compression::leave_audio_alone))
+            .wrap(Compress::default())
+            .wrap(from_fn(compression::weaken_etags))
+            .app_data(web::Data::from(storage.clone()))
+            .route("/healthz", web::get().to(health::healthz))
+            .route("/readyz", web::get().to(health::readyz))
+            .route("/metrics", web::get().to(health::metrics))
+            .route(robots::PATH, web::get().to(robots::robots_txt))
+            .route("/boards", web::get().to(board::board_index))
+            .route("/captcha", web::get().to(captcha::captcha_image))
+            .route("/admin", web::get().to(admin::index))
+            .route("/admin/login", web::get().to(admin::login_form))
+            .route("/admin/login", web::post().to(admin::login))
+            .route("/admin/logout", web::post().to(admin::logout))
+            .route(
+                "/admin/articles/{id}/sticky",
+                web::post().to(admin::set_sticky),
+            )
+            .route("/admin/reports", web::get().to(report::reports_page))
+            .route(
+                "/admin/reports/{id}/dismiss",
+                web::post().to(report::dismiss_report),
+            )
+            .route(
+                "/admin/reports/{id}/delete",
+                web::post().to(report::delete_reported),
+            )
+            .route("/admin/deleted", web::get().to(deleted::deleted_page))
+            .route(
+                "/admin/deleted/articles/{id}/restore",
+                web::post().to(deleted::restore_article),
+            )
+            .route(
+                "/admin/deleted/comments/{id}/restore",
+                web::post().to(deleted::restore_comment),
+            )
+            .route("/admin/errors", web::get().to(app_errors::errors_page))
+            .route(
+                "/admin/errors/clear",
+                web::post().to(app_errors::clear_errors),
+            )
+            .route("/admin/export", web::get().to(export::export))
+            // The default board keeps the original routes
+            .configure(board_routes)
+            .service(
+                web::scope("/b/{board}")
+                    .route("", web::get().to(article::new_article_form))
+                    .configure(board_routes),
+            )
+            .service(
+                web::scope("/api")
+                    .wrap(from_fn(error::json_errors))
+                    // Outermost, so errors carry the headers too
+                    .wrap(Condition::new(cors::enabled(&config), cors::api(&config)))
+                    .app_data(api::path_config())
+                    .app_data(api::query_config())
+                    .app_data(api::json_config())
+                    .route("/articles", web::get().to(api::list_articles))
+                    .route(
+                        "/articles",
+                        web::post()
+                            .to(api::create_article)
+                            .wrap(from_fn(rate_limit::limit_articles))
+                            .wrap(from_fn(ban::reject_banned)),
+                    )
+                    .route("/articles/{id}", web::get().to(api::get_article))
+                    .route("/articles/{id}", web::delete().to(api::delete_article))
+                    .route("/comments/{id}", web::delete().to(api::delete_comment))
+                    .route(
+                        "/articles/{id}/related",
+                        web::get().to(api::related_articles),
+                    )
+                    .route("/search", web::get().to(api::search))
+                    .route("/openapi.json", web::get().to(openapi::openapi_json))
+                    .route("/docs", web::get().to(openapi::docs)),
+            )
+            .configure(|cfg| {
+                // Other backends hand out URLs that are served elsewhere
+                let uploads_dir = matches!(config.storage, StorageConfig::Local)
+                    .then_some(config.uploads_dir.as_path());
+                assets::configure(cfg, static_files, uploads_dir);
+            })
+            .default_service(web::to(error::not_found))
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
-}
+    .shutdown_timeout(shutdown_timeout)
+    .disable_signals();
+    if workers > 0 {
+        server = server.workers(workers);
+    }
+    for addr in &bind_addrs {
+        server = match &certificates {
+            Some(certificates) => server.bind_rustls_0_23(
+                (addr.as_str(), port),
+                tls::server_config(certificates.clone())?,
+            ),
+            None => server.bind((addr.as_str(), port)),
+        }
+        .map_err(|e| format!("Failed to bind {} port {}: {}", addr, port, e))?;
+    }
+    // With PORT=0 this is the only place the chosen ports show up. Taken
+    // before binding the socket, which actix lists with a made-up address.
+    let scheme = if certificates.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let mut listening: Vec<_> = server
+        .addrs()
+        .iter()
+        .map(|addr| format!("{}://{}", scheme, addr))
+        .collect();
+    let redirect = match redirect_port {
+        Some(redirect_port) => {
+            let mut redirect = HttpServer::new(move || {
+                App::new()
+                    .app_data(redirect_config.clone())
+                    .wrap(from_fn(logging::request_span))
+                    .default_service(web::to(tls::redirect_to_https))
+            })
+            .workers(1)
+            .shutdown_timeout(shutdown_timeout)
+            .disable_signals();
+            for addr in &bind_addrs {
+                redirect = redirect.bind((addr.as_str(), redirect_port)).map_err(|e| {
+                    format!("Failed to bind {} port {}: {}", addr, redirect_port, e)
+                })?;
+            }
+            listening.extend(
+                redirect
+                    .addrs()
+                    .iter()
+                    .map(|addr| format!("http://{}", addr)),
+            );
+            Some(redirect.run())
+        }
+        None => None,
+    };
+    if let Some((path, mode)) = &listen_uds {
+        listening.push(format!("unix:{}", path.display()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
 
-// Function to create a directory and set permissions
-fn create_and_set_permissions(dir: &str) -> std::io::Result<()> {
-    if !Path::new(dir).exists() {
-        fs::create_dir(dir)?;
+            remove_stale_socket(path)?;
+            server = server
+                .bind_uds(path)
+                .map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+            fs::set_permissions(path, fs::Permissions::from_mode(*mode)).map_err(|e| {
+                format!("Failed to set the permissions of {}: {}", path.display(), e)
+            })?;
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, mode);
+            return Err("LISTEN_UDS needs a system with Unix sockets".into());
+        }
     }
-    Ok(())
-}
+    println!("Listening on {}", listening.join(", "));
 
-// Utility function to log errors to "error.txt"
-fn log_error(error_message: &str) {
-    if let Ok(file) = OpenOptions::new().create(true).append(true).open("error.txt") {
-        let mut writer = BufWriter::new(file);
-        let _ = writeln!(writer, "ERROR: {}", error_message);
+    let server = server.run();
+    match redirect {
+        Some(redirect) => {
+            tokio::spawn(stop_on_signal(vec![server.handle(), redirect.handle()]));
+            futures_util::future::try_join(server, redirect).await?;
+        }
+        None => {
+            tokio::spawn(stop_on_signal(vec![server.handle()]));
+            server.await?;
+        }
     }
+    shutdown_repo.close().await;
+    if let Some((path, _)) = &listen_uds {
+        let _ = fs::remove_file(path);
+    }
+    Ok(())
 }
 
-// Route to display the article submission form
-async fn new_article_form() -> HttpResponse {
-    let html = format!(
-        r#"
-    <!DOCTYPE html>
-    <html lang="en">
-    <head>
-        <meta charset="UTF-8">
-        <title>Submit a New Article</title>
-        <link rel="stylesheet" href="/static/style.css">
-        <style>
-            .post-form-box {{
-                background: #fff;
-                padding: 20px;
-                border-radius: 8px;
-                box-shadow: 0 0 10px rgba(0, 0, 0, 0.1);
-                margin: 50px auto;
-                max-width: 400px;
-                text-align: center;
-            }}
-            form input[type="text"], form textarea {{
-                width: 100%;
-                padding: 10px;
-                margin-top: 10px;
-                margin-bottom: 15px;
-                border: 1px solid #ccc;
-                border-radius: 4px;
-                box-sizing: border-box;
-            }}
-            form input[type="file"] {{
-                margin-bottom: 15px;
-            }}
-            form input[type="submit"] {{
-                background: #333;
-                color: #fff;
-                padding: 10px 20px;
-                border: none;
-                border-radius: 4px;
-                cursor: pointer;
-            }}
-            form input[type="submit"]:hover {{
-                background: #555;
-            }}
-        </style>
-    </head>
-    <body>
-        <div class="post-form-box">
-            <h1>Submit a New Article</h1>
-            <form action="/submit" method="POST" enctype="multipart/form-data">
-                <input type="text" name="title" placeholder="Title" required><br>
-                <textarea name="body" rows="10" placeholder="Body" required></textarea><br>
-                <input type="file" name="media" accept=".jpg,.jpeg,.png,.gif,.webp,.mp4" required><br><br>
-                <label>jpg, png, gif, webp, or MP4</label><br><br>
-                <input type="submit" value="Submit Article">
-            </form>
-        </div>
-        <br>
-        <a href="/articles" style="display: block; text-align: center;">View All Articles</a>
-    </body>
-    </html>
-    "#
-    );
+// Clear the way for a Unix socket at `path`, removing one left there by an
+// earlier run
+#[cfg(unix)]
+fn remove_stale_socket(path: &std::path::Path) -> Result<(), String> {
+    use std::os::unix::fs::FileTypeExt as _;
 
-    HttpResponse::Ok().content_type("text/html").body(html)
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove the old socket {}: {}", path.display(), e)),
+        Ok(_) => Err(format!(
+            "LISTEN_UDS {} exists and isn't a socket",
+            path.display()
+        )),
+        Err(_) => Ok(()),
+    }
 }
 
-// Handle submission of new articles
-async fn submit_article(
-    pool: web::Data<PgPool>,
-    mut payload: Multipart,
-) -> Result<HttpResponse, Error> {
-    let mut title = String::new();
-    let mut body = String::new();
-    let mut media_paths = Vec::new();
-
-    create_and_set_permissions("uploads").expect("Failed to create or set permissions for uploads directory");
-
-    while let Some(item) = payload.next().await {
-        let mut field = item?;
-        let content_disposition = field.content_disposition().unwrap();
-        let field_name = content_disposition.get_name().unwrap();
-
-        if field_name == "title" {
-            let mut value = Vec::new();
-            while let Some(chunk) = field.next().await {
-                value.extend_from_slice(&chunk?);
-            }
-            title = String::from_utf8(value).unwrap_or_default();
-        } else if field_name == "body" {
-            let mut value = Vec::new();
-            while let Some(chunk) = field.next().await {
-                value.extend_from_slice(&chunk?);
-            }
-            body = String::from_utf8(value).unwrap_or_default();
-        } else if field_name == "media" {
-            if let Some(filename) = content_disposition.get_filename() {
-                let sanitized_filename = sanitize(&filename);
-                let filepath = format!("./uploads/article_{}", sanitized_filename);
-                let mut f = File::create(&filepath)
-                    .map_err(|e| ErrorInternalServerError(format!("Failed to create file: {}", e)))?;
-                while let Some(chunk) = field.next().await {
-                    f.write_all(&chunk?)
-                        .map_err(|e| ErrorInternalServerError(format!("Failed to write file: {}", e)))?;
-                }
-                media_paths.push(format!("/uploads/article_{}", sanitized_filename));
+// Stop taking connections at SIGTERM or SIGINT, and let the requests in
+// flight finish within SHUTDOWN_TIMEOUT_SECONDS. Any cut off then have their
+// half-written uploads removed by the storage backend.
+async fn stop_on_signal(servers: Vec<actix_web::dev::ServerHandle>) {
+    #[cfg(unix)]
+    let signal = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = terminate.recv() => "SIGTERM",
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+            },
+            Err(e) => {
+                error!(error = %e, "Failed to listen for SIGTERM");
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
             }
         }
+    };
+    #[cfg(not(unix))]
+    let signal = async {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    };
+
+    let signal = signal.await;
+    warn!(signal, "Shutting down; finishing requests in flight");
+    futures_util::future::join_all(servers.iter().map(|server| server.stop(true))).await;
+}
+
+// Routes for one board's pages, mounted at the root for the default board and
+// under /b/{board} for every board
+fn board_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::get().to(article::new_article_form))
+        .route(
+            "/submit",
+            web::post()
+                .to(article::submit_article)
+                .wrap(from_fn(rate_limit::limit_articles))
+                .wrap(from_fn(ban::reject_banned)),
+        )
+        .route("/articles", web::get().to(listing::list_articles))
+        .route("/tags/{tag}", web::get().to(listing::list_tagged_articles))
+        .route("/archive", web::get().to(listing::list_archived_articles))
+        .route("/catalog", web::get().to(listing::catalog))
+        .route("/trending", web::get().to(trending::trending))
+        .route("/search", web::get().to(listing::search))
+        .route("/feed.rss", web::get().to(feed::rss_feed))
+        .route("/feed.atom", web::get().to(feed::atom_feed))
+        .route("/articles/random", web::get().to(listing::random_article))
+        .route("/articles/{id}", web::get().to(article::view_article))
+        .route(
+            "/articles/{id}/comment",
+            web::post()
+                .to(comment::submit_comment)
+                .wrap(from_fn(rate_limit::limit_comments))
+                .wrap(from_fn(ban::reject_banned)),
+        )
+        .route(
+            "/articles/{id}/delete",
+            web::post().to(article::delete_article),
+        )
+        .route(
+            "/articles/{id}/report",
+            web::post()
+                .to(report::report_article)
+                .wrap(from_fn(rate_limit::limit_reports)),
+        )
+        .route(
+            "/articles/{id}/edit",
+            web::get().to(article::edit_article_form),
+        )
+        .route("/articles/{id}/edit", web::post().to(article::edit_article))
+        // After /edit, which slugs never take (see slug::dedupe)
+        .route(
+            "/articles/{id}/{slug}",
+            web::get().to(article::view_article),
+        )
+        .route(
+            "/articles/{article_id}/comments/{comment_id}",
+            web::get().to(comment::view_comment),
+        )
+        .route(
+            "/articles/{article_id}/comments/{comment_id}/delete",
+            web::post().to(comment::delete_comment),
+        )
+        .route(
+            "/articles/{article_id}/comments/{comment_id}/report",
+            web::post()
+                .to(report::report_comment)
+                .wrap(from_fn(rate_limit::limit_reports)),
+        );
+}
+
+// `articles migrate`: apply pending migrations and exit
+async fn migrate(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    run_migrations(repo.as_ref()).await?;
+    Ok(())
+}
+
+// Apply the backend's embedded migrations, reporting each one that wasn't
+// already recorded in the database
+async fn run_migrations(repo: &dyn ArticleRepository) -> Result<(), String> {
+    let applied = repo
+        .run_migrations()
+        .await
+        .map_err(|e| format!("Failed to run migrations: {}", e))?;
+    if applied.is_empty() {
+        println!("Database schema is up to date");
+    }
+    for m in applied {
+        println!("Applied migration {} ({})", m.version, m.description);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ArticleSort, NewArticleRow, NewCommentRow};
+
+    async fn add_article(repo: &dyn ArticleRepository, bump_time: i64) -> i32 {
+        let board = repo.get_board("main").await.unwrap();
+        repo.insert_article(NewArticleRow {
+            board_id: board.id,
+            title: "Title",
+            slug: None,
+            body: "Body",
+            name: DEFAULT_NAME,
+            tripcode: None,
+            created_at: bump_time,
+            bump_time,
+            delete_password_hash: None,
+            tags: &[],
+            poster_ip: None,
+            media: &[],
+        })
+        .await
+        .unwrap()
+        .0
+    }
+
+    async fn repo_with_article(bump_time: i64) -> (std::sync::Arc<dyn ArticleRepository>, i32) {
+        let repo = db::connect("sqlite::memory:").await.unwrap();
+        repo.run_migrations().await.unwrap();
+        let article_id = add_article(repo.as_ref(), bump_time).await;
+        (repo, article_id)
+    }
+
+    async fn post_comment(
+        repo: &dyn ArticleRepository,
+        article_id: i32,
+        now: i64,
+        sage: bool,
+        bump_limit: i64,
+    ) {
+        repo.insert_comment(NewCommentRow {
+            article_id,
+            comment: "Comment",
+            name: DEFAULT_NAME,
+            tripcode: None,
+            delete_password_hash: None,
+            poster_ip: None,
+            created_at: now,
+            parent_comment_id: None,
+            bump_to: if sage { None } else { Some(now) },
+            bump_limit,
+        })
+        .await
+        .unwrap();
     }
 
-    if media_paths.is_empty() {
-        return Ok(HttpResponse::BadRequest().body("Media file is required"));
+    async fn bump_time(repo: &dyn ArticleRepository, article_id: i32) -> i64 {
+        repo.get_article(article_id).await.unwrap().bump_time
     }
 
-    let bump_time = Utc::now().timestamp();
+    #[tokio::test]
+    async fn saged_comments_leave_bump_time_alone() {
+        let (repo, article_id) = repo_with_article(100).await;
+        post_comment(repo.as_ref(), article_id, 200, true, 0).await;
+        assert_eq!(bump_time(repo.as_ref(), article_id).await, 100);
+    }
 
-    let article_id: i32 = sqlx::query_scalar(
-        "INSERT INTO articles (title, body, bump_time) VALUES ($1, $2, $3) RETURNING id"
-    )
-    .bind(&title)
-    .bind(&body)
-    .bind(bump_time)
-    .fetch_one(pool.get_ref())
-    .await
-    .map_err(|e| {
-        log_error(&format!("Failed to store article: {}", e));
-        ErrorInternalServerError("Database insert failed")
-    })?;
+    #[tokio::test]
+    async fn other_comments_bump_the_article() {
+        let (repo, article_id) = repo_with_article(100).await;
+        post_comment(repo.as_ref(), article_id, 200, false, 0).await;
+        assert_eq!(bump_time(repo.as_ref(), article_id).await, 200);
+        // Still posted when it was
+        assert_eq!(repo.get_article(article_id).await.unwrap().created_at, 100);
+    }
 
-    // Insert media
-    for path in media_paths {
-        sqlx::query("INSERT INTO article_media (article_id, media_path) VALUES ($1, $2)")
-            .bind(article_id)
-            .bind(path)
-            .execute(pool.get_ref())
+    #[tokio::test]
+    async fn bumping_stops_at_the_bump_limit() {
+        let (repo, article_id) = repo_with_article(100).await;
+        for _ in 1..=298 {
+            post_comment(repo.as_ref(), article_id, 100, true, 300).await;
+        }
+        // Comment 299 still bumps
+        post_comment(repo.as_ref(), article_id, 299, false, 300).await;
+        assert_eq!(bump_time(repo.as_ref(), article_id).await, 299);
+        // Comments 300 and 301 don't
+        post_comment(repo.as_ref(), article_id, 300, false, 300).await;
+        assert_eq!(bump_time(repo.as_ref(), article_id).await, 299);
+        post_comment(repo.as_ref(), article_id, 301, false, 300).await;
+        assert_eq!(bump_time(repo.as_ref(), article_id).await, 299);
+    }
+
+    #[tokio::test]
+    async fn archives_articles_past_the_newest() {
+        let (repo, oldest) = repo_with_article(100).await;
+        let bumped = add_article(repo.as_ref(), 300).await;
+        let newest = add_article(repo.as_ref(), 200).await;
+        let board_id = repo.get_board("main").await.unwrap().id;
+
+        assert_eq!(repo.archive_overflow(board_id, 2, 400).await.unwrap(), 1);
+        assert_eq!(repo.archive_overflow(board_id, 2, 500).await.unwrap(), 0);
+        assert_eq!(
+            repo.get_article(oldest).await.unwrap().archived_at,
+            Some(400)
+        );
+        let current: Vec<i32> = repo
+            .list_articles(board_id, ArticleSort::Bump, 10, 0)
             .await
-            .map_err(|e| {
-                log_error(&format!("Failed to store media: {}", e));
-                ErrorInternalServerError("Failed to store media")
-            })?;
+            .unwrap()
+            .iter()
+            .map(|a| a.article.id)
+            .collect();
+        assert_eq!(current, vec![bumped, newest]);
+        let archived: Vec<i32> = repo
+            .list_archived_articles(board_id, ArticleSort::Bump, 10, 0)
+            .await
+            .unwrap()
+            .iter()
+            .map(|a| a.article.id)
+            .collect();
+        assert_eq!(archived, vec![oldest]);
     }
 
-    Ok(HttpResponse::Found()
-        .append_header(("Location", "/articles"))
-        .finish())
-}
+    #[tokio::test]
+    async fn sticky_articles_stay_on_top_and_out_of_the_archive() {
+        let (repo, sticky) = repo_with_article(100).await;
+        let newer = add_article(repo.as_ref(), 200).await;
+        let newest = add_article(repo.as_ref(), 300).await;
+        let board_id = repo.get_board("main").await.unwrap().id;
 
-// List all articles
-async fn list_articles(pool: web::Data<PgPool>) -> HttpResponse {
-    let articles_db = match sqlx::query_as::<_, DbArticle>("SELECT id, title, body, bump_time FROM articles ORDER BY bump_time DESC")
-        .fetch_all(pool.get_ref())
-        .await {
-            Ok(a) => a,
-            Err(e) => {
-                log_error(&format!("Failed to fetch articles: {}", e));
-                return HttpResponse::InternalServerError().body("Failed to load articles");
-            }
-        };
+        assert!(repo.set_sticky(sticky, true).await.unwrap());
+        assert!(repo.set_sticky(sticky, true).await.unwrap());
+        assert!(!repo.set_sticky(9999, true).await.unwrap());
+
+        let current: Vec<i32> = repo
+            .list_articles(board_id, ArticleSort::Bump, 10, 0)
+            .await
+            .unwrap()
+            .iter()
+            .map(|a| a.article.id)
+            .collect();
+        assert_eq!(current, vec![sticky, newest, newer]);
+        assert_eq!(repo.archive_overflow(board_id, 1, 400).await.unwrap(), 1);
+        assert_eq!(repo.get_article(sticky).await.unwrap().archived_at, None);
+        assert_eq!(
+            repo.get_article(newer).await.unwrap().archived_at,
+            Some(400)
+        );
+        assert!(repo
+            .article_ids_beyond(0)
+            .await
+            .unwrap()
+            .iter()
+            .all(|&id| id != sticky));
+    }
+
+    #[tokio::test]
+    async fn pruning_spares_sticky_articles() {
+        let (repo, sticky) = repo_with_article(100).await;
+        let old = add_article(repo.as_ref(), 200).await;
+        let newer = add_article(repo.as_ref(), 300).await;
+        let newest = add_article(repo.as_ref(), 400).await;
+        let board_id = repo.get_board("main").await.unwrap().id;
+        repo.set_sticky(sticky, true).await.unwrap();
 
-    let mut articles_html = format!(r#"
-    <!DOCTYPE html>
-    <html lang="en">
-    <head>
-        <meta charset="UTF-8">
-        <title>{}</title>
-        <link rel="stylesheet" href="/static/style.css">
-    </head>
-    <body>
-        <h1>{}</h1>
-        <div style="text-align: center; margin-bottom: 20px;">
-            <a href="/">Submit a New Article</a>
-        </div>
-    "#, MAIN_PAGE_TITLE, MAIN_PAGE_TITLE);
-
-    for article in &articles_db {
-        articles_html.push_str(&format!(
-            r#"<div class="article-link">
-            <h2><a href="/articles/{}">{}</a></h2>
-            </div>"#,
-            article.id, article.title
+        assert!(repo.prune_over_cap(board_id, 4).await.unwrap().is_empty());
+        repo.prune_over_cap(board_id, 2).await.unwrap();
+        let left: Vec<i32> = repo
+            .list_articles(board_id, ArticleSort::Bump, 10, 0)
+            .await
+            .unwrap()
+            .iter()
+            .map(|a| a.article.id)
+            .collect();
+        assert_eq!(left, vec![sticky, newest]);
+        assert!(matches!(
+            repo.get_article(old).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+        assert!(matches!(
+            repo.get_article(newer).await,
+            Err(sqlx::Error::RowNotFound)
         ));
     }
 
-    articles_html.push_str("</body></html>");
+    #[tokio::test]
+    async fn the_oldest_created_articles_are_beyond_the_kept_ones() {
+        let (repo, older) = repo_with_article(200).await;
+        let newest = add_article(repo.as_ref(), 300).await;
+        // Imported with an earlier date, so created after the others yet older
+        let oldest = add_article(repo.as_ref(), 100).await;
 
-    HttpResponse::Ok().content_type("text/html").body(articles_html)
-}
+        assert_eq!(repo.article_ids_beyond(2).await.unwrap(), vec![oldest]);
+        assert_eq!(
+            repo.article_ids_beyond(0).await.unwrap(),
+            vec![oldest, older, newest]
+        );
+        assert!(repo.article_ids_beyond(3).await.unwrap().is_empty());
+    }
 
-// View an article by ID
-async fn view_article(pool: web::Data<PgPool>, path: web::Path<i32>) -> HttpResponse {
-    let article_id = path.into_inner();
-
-    let article_db = match sqlx::query_as::<_, DbArticle>(
-        "SELECT id, title, body, bump_time FROM articles WHERE id = $1",
-    )
-    .bind(article_id)
-    .fetch_one(pool.get_ref())
-    .await
-    {
-        Ok(a) => a,
-        Err(_) => return HttpResponse::NotFound().body("Article not found"),
-    };
+    #[tokio::test]
+    async fn deleted_comments_go_on_the_page_of_the_next_live_one() {
+        let (repo, article_id) = repo_with_article(100).await;
+        let mut ids = Vec::new();
+        for parent in [None, None, None, None, Some(2), None] {
+            let parent_comment_id = parent.map(|i: usize| ids[i]);
+            ids.push(
+                repo.insert_comment(NewCommentRow {
+                    article_id,
+                    comment: "Comment",
+                    name: DEFAULT_NAME,
+                    tripcode: None,
+                    delete_password_hash: None,
+                    poster_ip: None,
+                    created_at: 200,
+                    parent_comment_id,
+                    bump_to: None,
+                    bump_limit: 0,
+                })
+                .await
+                .unwrap(),
+            );
+        }
+        // The third has a live reply and stays as a placeholder, the last doesn't
+        repo.soft_delete_comment(article_id, ids[2], 300)
+            .await
+            .unwrap();
+        repo.soft_delete_comment(article_id, ids[5], 300)
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.live_comment_ids(article_id).await.unwrap(),
+            vec![ids[0], ids[1], ids[3], ids[4]]
+        );
 
-    let media_paths = sqlx::query!("SELECT media_path FROM article_media WHERE article_id = $1", article_db.id)
-        .fetch_all(pool.get_ref())
-        .await
-        .map(|rows| rows.into_iter().map(|r| r.media_path).collect::<Vec<_>>())
-        .unwrap_or_default();
-
-    let article = Article {
-        id: article_db.id,
-        title: article_db.title,
-        body: article_db.body,
-        bump_time: article_db.bump_time,
-        media_paths,
-    };
+        let page = |offset| {
+            let repo = repo.clone();
+            async move {
+                let (comments, _) = repo
+                    .list_comments_page(article_id, 2, offset)
+                    .await
+                    .unwrap();
+                comments.iter().map(|c| c.id).collect::<Vec<i32>>()
+            }
+        };
+        assert_eq!(page(0).await, vec![ids[0], ids[1]]);
+        assert_eq!(page(2).await, vec![ids[2], ids[3], ids[4]]);
+        assert!(page(4).await.is_empty());
+    }
 
-    let comments = sqlx::query!("SELECT comment FROM comments WHERE article_id = $1", article.id)
-        .fetch_all(pool.get_ref())
-        .await
-        .map(|rows| rows.into_iter().map(|r| r.comment).collect::<Vec<_>>())
-        .unwrap_or_default();
-
-    let mut article_html = String::new();
-    article_html.push_str(r#"<!DOCTYPE html><html lang="en"><head><meta charset="UTF-8">"#);
-    article_html.push_str(&format!("<title>{}</title>", article.title));
-    article_html.push_str(r#"<link rel="stylesheet" href="/static/style.css"></head><body>"#);
-    article_html.push_str(
-        r#"<div style="text-align: center; margin-bottom: 20px;"><a href="/articles">← Back to All Articles</a></div>"#,
-    );
-    article_html.push_str(&format!("<h1>{}</h1>", article.title));
-
-    for media in &article.media_paths {
-        if media.ends_with(".mp4") {
-            article_html.push_str(&format!(
-                r#"<video controls width="600">
-                    <source src="{}" type="video/mp4">
-                    Your browser does not support the video tag.
-                </video><br>"#,
-                media
-            ));
-        } else {
-            article_html.push_str(&format!(
-                r#"<img src="{}" alt="Article Image" style="max-width: 100%; height: auto;"><br>"#,
-                media
-            ));
+    #[tokio::test]
+    async fn listing_counts_live_comments_and_shows_the_first_media() {
+        let (repo, busy) = repo_with_article(100).await;
+        let quiet = add_article(repo.as_ref(), 100).await;
+        post_comment(repo.as_ref(), busy, 200, true, 0).await;
+        post_comment(repo.as_ref(), busy, 300, true, 0).await;
+        post_comment(repo.as_ref(), busy, 400, true, 0).await;
+        let last = *repo.live_comment_ids(busy).await.unwrap().last().unwrap();
+        repo.soft_delete_comment(busy, last, 500).await.unwrap();
+        for path in ["first.png", "second.png"] {
+            let media = Media {
+                media_path: path.to_string(),
+                ..Default::default()
+            };
+            repo.insert_media(busy, None, &media).await.unwrap();
         }
+
+        let board = repo.get_board("main").await.unwrap();
+        let listed = repo
+            .list_articles(board.id, ArticleSort::Bump, 10, 0)
+            .await
+            .unwrap();
+        let busy = listed.iter().find(|a| a.article.id == busy).unwrap();
+        assert_eq!((busy.comment_count, busy.last_comment_at), (2, Some(300)));
+        assert_eq!(busy.media_path.as_deref(), Some("first.png"));
+        let quiet = listed.iter().find(|a| a.article.id == quiet).unwrap();
+        assert_eq!((quiet.comment_count, quiet.last_comment_at), (0, None));
+        assert_eq!(quiet.media_path, None);
     }
 
-    article_html.push_str(&format!(
-        r#"
-        <p>{}</p>
-        <h3>Leave a Comment</h3>
-        <form action="/articles/{}/comment" method="POST">
-            <textarea name="comment" rows="4" required></textarea><br>
-            <input type="submit" value="Submit Comment">
-        </form>
-        <h3>Comments</h3>
-    "#,
-        article.body, article.id
-    ));
+    #[tokio::test]
+    async fn articles_can_be_sorted() {
+        let (repo, a) = repo_with_article(100).await;
+        let b = add_article(repo.as_ref(), 200).await;
+        let c = add_article(repo.as_ref(), 300).await;
+        post_comment(repo.as_ref(), a, 400, false, 0).await;
+        post_comment(repo.as_ref(), b, 410, true, 0).await;
+        post_comment(repo.as_ref(), b, 420, true, 0).await;
 
-    for comment in comments {
-        article_html.push_str(&format!(r#"<div class="comment"><p>{}</p></div>"#, comment));
+        let board_id = repo.get_board("main").await.unwrap().id;
+        for (sort, expected) in [
+            (ArticleSort::Bump, [a, c, b]),
+            (ArticleSort::New, [c, b, a]),
+            (ArticleSort::Comments, [b, a, c]),
+            (ArticleSort::Old, [a, b, c]),
+        ] {
+            let listed = repo.list_articles(board_id, sort, 10, 0).await.unwrap();
+            assert_eq!(
+                listed.iter().map(|l| l.article.id).collect::<Vec<_>>(),
+                expected,
+                "{:?}",
+                sort
+            );
+        }
     }
 
-    article_html.push_str("</body></html>");
+    #[tokio::test]
+    async fn trending_weights_recent_comments() {
+        const DAY: i64 = 24 * 60 * 60;
+        let now = 10 * DAY;
+        let (repo, older) = repo_with_article(0).await;
+        let recent = add_article(repo.as_ref(), 0).await;
+        let stale = add_article(repo.as_ref(), 0).await;
+        add_article(repo.as_ref(), 0).await;
+        post_comment(repo.as_ref(), older, now - 2 * DAY, true, 0).await;
+        post_comment(repo.as_ref(), older, now - 2 * DAY, true, 0).await;
+        post_comment(repo.as_ref(), recent, now - 60, true, 0).await;
+        post_comment(repo.as_ref(), stale, now - 8 * DAY, true, 0).await;
+        // Deleted comments don't count
+        post_comment(repo.as_ref(), older, now - 60, true, 0).await;
+        let deleted = *repo.live_comment_ids(older).await.unwrap().last().unwrap();
+        repo.soft_delete_comment(older, deleted, now).await.unwrap();
 
-    HttpResponse::Ok().content_type("text/html").body(article_html)
-}
+        let board_id = repo.get_board("main").await.unwrap().id;
+        let trending = repo
+            .trending_articles(board_id, now - DAY, now - 7 * DAY, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            trending.iter().map(|l| l.article.id).collect::<Vec<_>>(),
+            [recent, older]
+        );
+    }
 
-// Submit comment
-async fn submit_comment(
-    pool: web::Data<PgPool>,
-    path: web::Path<i32>,
-    form: web::Form<CommentForm>,
-) -> HttpResponse {
-    let article_id = path.into_inner();
-
-    if let Err(e) = sqlx::query("INSERT INTO comments (article_id, comment) VALUES ($1, $2)")
-        .bind(article_id)
-        .bind(&form.comment)
-        .execute(pool.get_ref())
-        .await
-    {
-        log_error(&format!("Failed to store comment: {}", e));
-        return HttpResponse::InternalServerError().body("Failed to store comment.");
+    #[tokio::test]
+    async fn related_articles_share_tags_or_title_words() {
+        let repo = db::connect("sqlite::memory:").await.unwrap();
+        repo.run_migrations().await.unwrap();
+        let board_id = repo.get_board("main").await.unwrap().id;
+        let add = |title: &'static str, tags: &'static [&'static str]| {
+            let repo = repo.clone();
+            async move {
+                let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+                let row = NewArticleRow {
+                    board_id,
+                    title,
+                    slug: None,
+                    body: "Body",
+                    name: DEFAULT_NAME,
+                    tripcode: None,
+                    created_at: 0,
+                    bump_time: 0,
+                    delete_password_hash: None,
+                    tags: &tags,
+                    poster_ip: None,
+                    media: &[],
+                };
+                let id = repo.insert_article(row).await.unwrap().0;
+                repo.get_article_with_media(id).await.unwrap()
+            }
+        };
+        let related = |article: Article| {
+            let repo = repo.clone();
+            async move {
+                repo.related_articles(&article, 5)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|r| r.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let tagged = add("Garden notes", &["plants", "soil"]).await;
+        let both = add("Unrelated", &["plants", "soil"]).await;
+        let one = add("Garden again", &["soil"]).await;
+        let deleted = add("Gone", &["plants"]).await;
+        repo.soft_delete_article(deleted.id, 0).await.unwrap();
+        let untagged = add("Watering the garden", &[]).await;
+        add("Nothing alike", &[]).await;
+
+        // Tags win over title words, most shared first
+        let tagged_id = tagged.id;
+        assert_eq!(related(tagged).await, [both.id, one.id]);
+        // Without tags, titles are matched; short words like "the" aren't
+        assert_eq!(related(untagged).await, [one.id, tagged_id]);
+        assert!(related(add("The and", &[]).await).await.is_empty());
     }
 
-    let new_bump_time = Utc::now().timestamp();
-    if let Err(e) = sqlx::query("UPDATE articles SET bump_time = $1 WHERE id = $2")
-        .bind(new_bump_time)
-        .bind(article_id)
-        .execute(pool.get_ref())
-        .await
-    {
-        log_error(&format!("Failed to bump article: {}", e));
-        return HttpResponse::InternalServerError().body("Failed to bump article.");
+    #[tokio::test]
+    async fn app_errors_are_listed_newest_first_and_purged() {
+        let repo = db::connect("sqlite::memory:").await.unwrap();
+        repo.run_migrations().await.unwrap();
+        for (occurred_at, message) in [(100, "Old"), (300, "New"), (200, "Middle")] {
+            let row = AppErrorRow {
+                occurred_at,
+                route: Some("/articles".to_string()),
+                message: message.to_string(),
+                context: sqlx::types::Json(serde_json::json!({ "request_id": "abc" })),
+            };
+            repo.insert_app_error(&row).await.unwrap();
+        }
+        let messages =
+            |rows: Vec<AppErrorRow>| rows.into_iter().map(|r| r.message).collect::<Vec<_>>();
+
+        let listed = repo.recent_app_errors(10).await.unwrap();
+        assert_eq!(listed[0].context.0["request_id"], "abc");
+        assert_eq!(messages(listed), ["New", "Middle", "Old"]);
+        assert_eq!(messages(repo.recent_app_errors(1).await.unwrap()), ["New"]);
+
+        assert_eq!(repo.delete_app_errors(Some(200)).await.unwrap(), 1);
+        assert_eq!(
+            messages(repo.recent_app_errors(10).await.unwrap()),
+            ["New", "Middle"]
+        );
+        assert_eq!(repo.delete_app_errors(None).await.unwrap(), 2);
+        assert!(repo.recent_app_errors(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn articles_without_media_or_tags_are_found() {
+        let (repo, article_id) = repo_with_article(100).await;
+        let article = repo.get_article_with_media(article_id).await.unwrap();
+        assert!(article.media.is_empty() && article.tags.is_empty());
+        assert!(matches!(
+            repo.get_article_with_media(article_id + 1).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
     }
 
-    HttpResponse::Found()
-        .append_header(("Location", format!("/articles/{}", article_id)))
-        .finish()
+    #[tokio::test]
+    async fn comments_are_listed_in_posting_order() {
+        let (repo, article_id) = repo_with_article(100).await;
+        post_comment(repo.as_ref(), article_id, 300, true, 0).await;
+        post_comment(repo.as_ref(), article_id, 200, true, 0).await;
+        let times: Vec<i64> = repo
+            .list_comments(article_id)
+            .await
+            .unwrap()
+            .iter()
+            .map(|c| c.created_at)
+            .collect();
+        assert_eq!(times, vec![200, 300]);
+        let ids = repo.live_comment_ids(article_id).await.unwrap();
+        assert!(ids[0] > ids[1]);
+    }
+
+    #[tokio::test]
+    async fn comment_media_is_kept_apart_and_purged_with_its_comment() {
+        let (repo, article_id) = repo_with_article(100).await;
+        post_comment(repo.as_ref(), article_id, 200, false, 0).await;
+        let comment_id = repo.live_comment_ids(article_id).await.unwrap()[0];
+        let file = |path: &str| Media {
+            media_path: path.to_string(),
+            ..Default::default()
+        };
+        repo.insert_media(article_id, None, &file("article.png"))
+            .await
+            .unwrap();
+        repo.insert_media(article_id, Some(comment_id), &file("comment.png"))
+            .await
+            .unwrap();
+
+        let article = repo.get_article_with_media(article_id).await.unwrap();
+        assert_eq!(
+            article
+                .media
+                .iter()
+                .map(|m| m.media_path.as_str())
+                .collect::<Vec<_>>(),
+            ["article.png"]
+        );
+        assert_eq!(
+            repo.media_for_articles(&[article_id]).await.unwrap()[&article_id].len(),
+            1
+        );
+        let (_, comment_media) = repo.list_comments_page(article_id, 10, 0).await.unwrap();
+        assert_eq!(comment_media[&comment_id][0].media_path, "comment.png");
+
+        repo.soft_delete_comment(article_id, comment_id, 300)
+            .await
+            .unwrap();
+        let (purged, paths) = repo.purge_comments_deleted_before(300).await.unwrap();
+        assert_eq!((purged, paths), (1, vec!["comment.png".to_string()]));
+        assert_eq!(
+            repo.get_article_with_media(article_id)
+                .await
+                .unwrap()
+                .media
+                .len(),
+            1
+        );
+    }
 }
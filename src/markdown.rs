@@ -0,0 +1,286 @@
+// Article bodies are written in Markdown (CommonMark, plus tables and
+// strikethrough), with bare URLs linked, spoilers hidden and code blocks
+// highlighted as in comments. Raw HTML in the source is shown as text rather
+// than passed through, and the rendered HTML is run through ammonia as well,
+// so nothing a poster writes can come out as a script, an event handler or a
+// javascript: link.
+
+use pulldown_cmark::{
+    html, CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd, TextMergeStream,
+};
+use std::collections::HashSet;
+
+use crate::highlight;
+use crate::linkify::Linkifier;
+use crate::spoiler;
+
+fn parser(source: &str) -> Parser<'_> {
+    Parser::new_ext(
+        source,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+}
+
+// Render `source` to HTML that is safe to insert into a page, linking up to
+// `max_links` bare URLs. Without `images`, images become links to the image
+// instead, so pages don't load files from other sites.
+pub fn to_html(source: &str, images: bool, max_links: usize) -> String {
+    let mut linkifier = Linkifier::new(max_links);
+    // Text already inside a link or image is left alone
+    let mut nested = 0;
+    // The info string and text of the code block being read, highlighted
+    // (see highlight.rs) as a whole once it ends
+    let mut code: Option<(String, String)> = None;
+    let mut events = Vec::new();
+    for event in TextMergeStream::new(parser(source)) {
+        let event = match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => info.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code = Some((info, String::new()));
+                continue;
+            }
+            Event::Text(text) if code.is_some() => {
+                code.as_mut().unwrap().1.push_str(&text);
+                continue;
+            }
+            Event::End(TagEnd::CodeBlock) => match code.take() {
+                Some((info, text)) => {
+                    Event::Html(CowStr::from(highlight::code_block(&info, &text) + "\n"))
+                }
+                None => continue,
+            },
+            Event::Start(Tag::Link { .. } | Tag::Image { .. }) => {
+                nested += 1;
+                convert(event, images)
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                nested -= 1;
+                convert(event, images)
+            }
+            Event::Text(text) if nested == 0 => {
+                Event::InlineHtml(CowStr::from(spoiler::render(&text, |t| {
+                    linkifier.linkify(t)
+                })))
+            }
+            event => convert(event, images),
+        };
+        events.push(event);
+    }
+    let mut unsafe_html = String::with_capacity(source.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, events.into_iter());
+
+    let mut cleaner = ammonia::Builder::default();
+    cleaner
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("nofollow noopener"))
+        .add_tag_attribute_values("a", "target", ["_blank"])
+        .add_tag_attribute_values("span", "class", ["spoiler"])
+        // Colours from the highlighter
+        .add_tag_attributes("span", ["style"])
+        .filter_style_properties(HashSet::from([
+            "color",
+            "font-weight",
+            "font-style",
+            "text-decoration",
+        ]));
+    if !images {
+        cleaner.rm_tags(["img"]);
+    }
+    cleaner.clean(&unsafe_html).to_string()
+}
+
+fn convert(event: Event<'_>, images: bool) -> Event<'_> {
+    match event {
+        Event::Html(text) | Event::InlineHtml(text) => Event::Text(text),
+        Event::Start(Tag::HtmlBlock) => Event::Start(Tag::Paragraph),
+        Event::End(TagEnd::HtmlBlock) => Event::End(TagEnd::Paragraph),
+        // Bodies written before Markdown rely on their line breaks showing
+        Event::SoftBreak => Event::HardBreak,
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !images => Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }),
+        Event::End(TagEnd::Image) if !images => Event::End(TagEnd::Link),
+        event => event,
+    }
+}
+
+// The text of `source` without its Markdown syntax, one line per block, for
+// excerpts and descriptions
+pub fn to_plain_text(source: &str) -> String {
+    let mut text = String::with_capacity(source.len());
+    for event in parser(source) {
+        match event {
+            Event::Text(t) | Event::Code(t) | Event::Html(t) | Event::InlineHtml(t) => {
+                text.push_str(&t)
+            }
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::Item
+                | TagEnd::CodeBlock
+                | TagEnd::TableCell
+                | TagEnd::HtmlBlock,
+            ) => text.push('\n'),
+            _ => {}
+        }
+    }
+    text.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_common_formatting() {
+        assert_eq!(
+            to_html(
+                "# Title\n\nSome *emphasis* and `code`,\nnext line",
+                false,
+                0
+            ),
+            "<h1>Title</h1>\n<p>Some <em>emphasis</em> and <code>code</code>,<br>\nnext line</p>\n"
+        );
+        assert_eq!(
+            to_html("- one\n- ~~two~~", false, 0),
+            "<ul>\n<li>one</li>\n<li><del>two</del></li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn scripts_never_reach_the_output() {
+        for source in [
+            "<script>alert(1)</script>",
+            "text <script>alert(1)</script> more",
+            "<div onclick=\"alert(1)\">x</div>",
+            "<img src=x onerror=alert(1)>",
+            "[click](javascript:alert(1))",
+            "![x](javascript:alert(1))",
+            "```\n</code><script>alert(1)</script>\n```",
+            "```html\n</code></pre><script>alert(1)</script>\n```",
+        ] {
+            for images in [false, true] {
+                let html = to_html(source, images, 0);
+                assert!(!html.contains("javascript:"), "{} gave {}", source, html);
+                // Every tag left is one Markdown made, without handlers
+                for tag in html
+                    .split('<')
+                    .skip(1)
+                    .map(|rest| &rest[..rest.find('>').unwrap()])
+                {
+                    let name = tag
+                        .trim_start_matches('/')
+                        .split([' ', '>'])
+                        .next()
+                        .unwrap();
+                    assert!(
+                        ["p", "code", "pre", "a", "img", "br", "span"].contains(&name),
+                        "{} gave {}",
+                        source,
+                        html
+                    );
+                    assert!(!tag.contains(" on"), "{} gave {}", source, html);
+                }
+            }
+        }
+        assert_eq!(
+            to_html("<script>alert(1)</script>", false, 0),
+            "<p>&lt;script&gt;alert(1)&lt;/script&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn links_are_marked_nofollow() {
+        assert_eq!(
+            to_html("[site](https://example.com)", false, 0),
+            "<p><a href=\"https://example.com\" rel=\"nofollow noopener\">site</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn links_bare_urls_outside_code_and_links() {
+        assert_eq!(
+            to_html("see https://example.com. `https://a.example` [https://b.example](https://c.example)", false, 0),
+            "<p>see <a href=\"https://example.com\" target=\"_blank\" rel=\"nofollow noopener\">https://example.com</a>. \
+             <code>https://a.example</code> <a href=\"https://c.example\" rel=\"nofollow noopener\">https://b.example</a></p>\n"
+        );
+        assert_eq!(
+            to_html("```\nhttps://example.com\n```", false, 0),
+            "<pre><code>https://example.com\n</code></pre>\n"
+        );
+        assert_eq!(
+            to_html("http://a.example http://b.example", false, 1),
+            "<p><a href=\"http://a.example\" target=\"_blank\" rel=\"nofollow noopener\">http://a.example</a> http://b.example</p>\n"
+        );
+    }
+
+    #[test]
+    fn hides_spoilers_outside_code() {
+        assert_eq!(
+            to_html("the ||butler|| *did* it, `||not code||`", false, 0),
+            "<p>the <span class=\"spoiler\">butler</span> <em>did</em> it, <code>||not code||</code></p>\n"
+        );
+        assert_eq!(
+            to_html("[spoiler]the butler[/spoiler] <span class=\"x\">", false, 0),
+            "<p><span class=\"spoiler\">the butler</span> &lt;span class=\"x\"&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn highlights_fenced_code() {
+        let html = to_html(
+            "```rust\nlet x = \"</pre><script>alert(1)</script>\";\n```",
+            false,
+            0,
+        );
+        assert!(html.starts_with("<pre><code><span style=\""), "{}", html);
+        assert!(html.contains("&lt;/pre&gt;&lt;script&gt;"), "{}", html);
+        assert!(
+            !html.contains("<script") && html.matches("</pre>").count() == 1,
+            "{}",
+            html
+        );
+        assert_eq!(
+            to_html(
+                "```nonsense\n</pre><script>alert(1)</script>\n```",
+                false,
+                0
+            ),
+            "<pre><code>&lt;/pre&gt;&lt;script&gt;alert(1)&lt;/script&gt;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn images_become_links_unless_enabled() {
+        assert_eq!(
+            to_html("![cat](https://example.com/cat.png)", false, 0),
+            "<p><a href=\"https://example.com/cat.png\" rel=\"nofollow noopener\">cat</a></p>\n"
+        );
+        assert_eq!(
+            to_html("![cat](https://example.com/cat.png)", true, 0),
+            "<p><img src=\"https://example.com/cat.png\" alt=\"cat\"></p>\n"
+        );
+    }
+
+    #[test]
+    fn plain_text_drops_the_syntax() {
+        assert_eq!(
+            to_plain_text(
+                "# Title\n\nSome *emphasis*\nand [a link](https://example.com).\n\n- one\n- two"
+            ),
+            "Title\nSome emphasis and a link.\none\ntwo"
+        );
+    }
+}
@@ -0,0 +1,1259 @@
+// Detection and storage of uploaded media files, and `articles backfill-media`
+// for rows stored before their size, type and dimensions were kept
+
+use actix_multipart::{Field, MultipartError};
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt as _};
+use image::ImageDecoder as _;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::db;
+use crate::storage::{self, MediaStorage};
+
+// Number of leading bytes needed to recognise every supported format
+const SNIFF_LEN: usize = 12;
+// Longest edge, in pixels, of generated thumbnails
+const THUMB_MAX_EDGE: u32 = 250;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaType {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Mp4,
+    Mp3,
+    Ogg,
+    Flac,
+}
+
+impl MediaType {
+    // Identify a file from its leading bytes rather than trusting its name
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(MediaType::Jpeg)
+        } else if head.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+            Some(MediaType::Png)
+        } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+            Some(MediaType::Gif)
+        } else if head.len() >= 12 && &head[0..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+            Some(MediaType::Webp)
+        } else if head.len() >= 8 && &head[4..8] == b"ftyp" {
+            Some(MediaType::Mp4)
+        } else if head.starts_with(b"ID3") || is_mp3_frame(head) {
+            Some(MediaType::Mp3)
+        } else if head.starts_with(b"OggS") {
+            Some(MediaType::Ogg)
+        } else if head.starts_with(b"fLaC") {
+            Some(MediaType::Flac)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_image(self) -> bool {
+        !matches!(self, MediaType::Mp4) && !self.is_audio()
+    }
+
+    pub fn is_audio(self) -> bool {
+        matches!(self, MediaType::Mp3 | MediaType::Ogg | MediaType::Flac)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MediaType::Jpeg => "jpg",
+            MediaType::Png => "png",
+            MediaType::Gif => "gif",
+            MediaType::Webp => "webp",
+            MediaType::Mp4 => "mp4",
+            MediaType::Mp3 => "mp3",
+            MediaType::Ogg => "ogg",
+            MediaType::Flac => "flac",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            MediaType::Jpeg => "image/jpeg",
+            MediaType::Png => "image/png",
+            MediaType::Gif => "image/gif",
+            MediaType::Webp => "image/webp",
+            MediaType::Mp4 => "video/mp4",
+            MediaType::Mp3 => "audio/mpeg",
+            MediaType::Ogg => "audio/ogg",
+            MediaType::Flac => "audio/flac",
+        }
+    }
+}
+
+// An MP3 without an ID3 tag starts straight off with a frame header: eleven
+// set sync bits, an MPEG version that isn't the reserved one, and layer III.
+// JPEGs (FF D8) and ADTS AAC (layer bits 00) don't pass.
+fn is_mp3_frame(head: &[u8]) -> bool {
+    match head {
+        [0xFF, second, ..] => {
+            second & 0xE0 == 0xE0 && second & 0x18 != 0x08 && second & 0x06 == 0x02
+        }
+        _ => false,
+    }
+}
+
+// A media file that has been handed to the storage backend
+pub struct StoredUpload {
+    // Server-generated name the file was stored under
+    pub key: String,
+    pub url: String,
+    pub media_type: MediaType,
+    // Full contents of image uploads, kept for thumbnailing
+    pub image_data: Option<Vec<u8>>,
+    // Of the whole file, hex encoded, for finding identical uploads
+    pub sha256: String,
+    pub byte_size: u64,
+    // Of images, in pixels
+    pub dimensions: Option<(u32, u32)>,
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    UnsupportedType,
+    // Over the limit for its type, in bytes
+    TooLarge(u64),
+    // An image that didn't decode, so its metadata couldn't be stripped
+    Corrupt,
+    // An image over ImageLimits, and why
+    ImageTooLarge(String),
+    // A video ffprobe found too long or in the wrong codecs, and why
+    InvalidVideo(String),
+    Io(io::Error),
+    Multipart(MultipartError),
+}
+
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+impl From<MultipartError> for UploadError {
+    fn from(e: MultipartError) -> Self {
+        UploadError::Multipart(e)
+    }
+}
+
+// How large an image may be. Checked against its header before anything
+// decodes it, as a small file can claim enough pixels to exhaust memory; the
+// decoders are held to the same size in case a header lied.
+#[derive(Clone, Copy)]
+pub struct ImageLimits {
+    pub max_pixels: u64,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_gif_frames: usize,
+}
+
+impl ImageLimits {
+    pub fn from_config(config: &Config) -> Self {
+        ImageLimits {
+            max_pixels: config.max_image_pixels,
+            max_width: config.max_image_width,
+            max_height: config.max_image_height,
+            max_gif_frames: config.max_gif_frames,
+        }
+    }
+
+    // Why a file read whole is over the limits, if it is
+    pub fn exceeded(&self, data: &[u8], media_type: MediaType) -> Option<String> {
+        header_dimensions(data, media_type)
+            .and_then(|dimensions| self.dimensions_exceeded(dimensions))
+            .or_else(|| self.frames_exceeded(data, media_type))
+    }
+
+    // Why a GIF has too many frames, if it does, which takes all of it to tell
+    pub fn frames_exceeded(&self, data: &[u8], media_type: MediaType) -> Option<String> {
+        (media_type == MediaType::Gif
+            && gif_frames(data, self.max_gif_frames + 1) > self.max_gif_frames)
+            .then(|| format!("GIFs can have at most {} frames.", self.max_gif_frames))
+    }
+
+    // Why an image of this size is over the limits, if it is. Ones whose
+    // header can't be read are left to the decoder to refuse.
+    pub fn dimensions_exceeded(&self, (width, height): (u32, u32)) -> Option<String> {
+        if width > self.max_width || height > self.max_height {
+            Some(format!(
+                "Images can be at most {}×{} pixels; this one is {}×{}.",
+                self.max_width, self.max_height, width, height
+            ))
+        } else if u64::from(width) * u64::from(height) > self.max_pixels {
+            Some(format!(
+                "Images can have at most {} pixels; this one has {}.",
+                self.max_pixels,
+                u64::from(width) * u64::from(height)
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn decoding(&self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits
+    }
+
+    fn reader<'a>(
+        &self,
+        data: &'a [u8],
+        format: image::ImageFormat,
+    ) -> image::ImageReader<Cursor<&'a [u8]>> {
+        let mut reader = image::ImageReader::with_format(Cursor::new(data), format);
+        reader.limits(self.decoding());
+        reader
+    }
+}
+
+// An image's width and height as its header gives them, without decoding
+// anything; None until enough of `data` is there, or if it isn't the header
+// of a `media_type` image
+pub fn header_dimensions(data: &[u8], media_type: MediaType) -> Option<(u32, u32)> {
+    let u16_le = |at: usize| {
+        Some(u32::from(u16::from_le_bytes(
+            data.get(at..at + 2)?.try_into().ok()?,
+        )))
+    };
+    let u16_be = |at: usize| {
+        Some(u32::from(u16::from_be_bytes(
+            data.get(at..at + 2)?.try_into().ok()?,
+        )))
+    };
+    let u24_le = |at: usize| {
+        Some(u32::from_le_bytes([
+            *data.get(at)?,
+            *data.get(at + 1)?,
+            *data.get(at + 2)?,
+            0,
+        ]))
+    };
+    match media_type {
+        MediaType::Png => {
+            if data.get(12..16)? != b"IHDR" {
+                return None;
+            }
+            let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+            Some((width, height))
+        }
+        // The logical screen, which is what the decoder gives too
+        MediaType::Gif => Some((u16_le(6)?, u16_le(8)?)),
+        MediaType::Webp => match data.get(12..16)? {
+            b"VP8X" => Some((u24_le(24)? + 1, u24_le(27)? + 1)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8 " => {
+                if data.get(23..26)? != [0x9D, 0x01, 0x2A] {
+                    return None;
+                }
+                Some((u16_le(26)? & 0x3FFF, u16_le(28)? & 0x3FFF))
+            }
+            _ => None,
+        },
+        // The frame header, after however many other segments
+        MediaType::Jpeg => {
+            let mut at = 2;
+            loop {
+                if *data.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *data.get(at + 1)?;
+                match marker {
+                    // Fill bytes before a marker
+                    0xFF => at += 1,
+                    // Markers without a length
+                    0x01 | 0xD0..=0xD7 => at += 2,
+                    0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                        return Some((u16_be(at + 7)?, u16_be(at + 5)?));
+                    }
+                    _ => at += 2 + u16_be(at + 2)? as usize,
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+// How many frames a GIF has, counting no further than `up_to`; a truncated
+// one counts as far as it goes
+fn gif_frames(data: &[u8], up_to: usize) -> usize {
+    // The size of a color table, from the packed byte that says if there is one
+    let color_table = |packed: u8| {
+        if packed & 0x80 != 0 {
+            3 << ((packed & 0x07) + 1)
+        } else {
+            0
+        }
+    };
+    // Past a run of data sub-blocks, which ends with an empty one
+    let skip_sub_blocks = |mut at: usize| {
+        while let Some(&len) = data.get(at) {
+            at += 1 + len as usize;
+            if len == 0 {
+                break;
+            }
+        }
+        at
+    };
+
+    // The header and logical screen descriptor
+    let Some(&packed) = data.get(10) else {
+        return 0;
+    };
+    let mut at = 13 + color_table(packed);
+    let mut frames = 0;
+    while frames < up_to {
+        match data.get(at) {
+            // An extension: its label, then sub-blocks
+            Some(0x21) => at = skip_sub_blocks(at + 2),
+            // An image descriptor, then its LZW code size and sub-blocks
+            Some(0x2c) => {
+                frames += 1;
+                let Some(&packed) = data.get(at + 9) else {
+                    break;
+                };
+                at = skip_sub_blocks(at + 10 + color_table(packed) + 1);
+            }
+            _ => break,
+        }
+    }
+    frames
+}
+
+// What save_upload does with an upload besides storing it
+#[derive(Clone, Copy)]
+pub struct UploadOptions {
+    pub max_bytes: u64,
+    // In place of max_bytes for audio
+    pub max_audio_bytes: u64,
+    pub limits: ImageLimits,
+    pub strip_metadata: bool,
+    // JPEGs and PNGs of at least this many bytes are stored as WebPs
+    // instead, when that makes them smaller
+    pub webp_min_bytes: Option<u64>,
+}
+
+impl UploadOptions {
+    pub fn from_config(config: &Config) -> Self {
+        UploadOptions {
+            max_bytes: config.max_upload_bytes,
+            max_audio_bytes: config.max_audio_upload_bytes,
+            limits: ImageLimits::from_config(config),
+            strip_metadata: config.strip_exif,
+            webp_min_bytes: config.webp_convert.then_some(config.webp_min_bytes),
+        }
+    }
+
+    // Whether an upload of this type is read whole and reworked before it's
+    // stored, rather than streamed as it comes
+    fn reworks(&self, media_type: MediaType) -> bool {
+        match media_type {
+            MediaType::Jpeg | MediaType::Png => {
+                self.strip_metadata || self.webp_min_bytes.is_some()
+            }
+            MediaType::Webp => self.strip_metadata,
+            _ => false,
+        }
+    }
+}
+
+// Stream a multipart field into `storage` under a server-generated name.
+// Nothing is stored unless the content sniffs as an allowed type, and reading
+// stops as soon as the field exceeds the size limit. Images over the pixel
+// limits are refused once their header is in, before the rest is read.
+// Images that are stripped of their metadata or converted to WebP are read
+// whole, and stored as `rework` leaves them, instead.
+pub async fn save_upload(
+    field: &mut Field,
+    storage: &dyn MediaStorage,
+    options: UploadOptions,
+) -> Result<StoredUpload, UploadError> {
+    let UploadOptions { limits, .. } = options;
+    // Fused, as a small file can be read to its end before the rest of it is
+    // asked for
+    let mut field = field.fuse();
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    while head.len() < SNIFF_LEN {
+        match field.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    let media_type = MediaType::detect(&head).ok_or(UploadError::UnsupportedType)?;
+    let max_bytes = if media_type.is_audio() {
+        options.max_audio_bytes
+    } else {
+        options.max_bytes
+    };
+    if head.len() as u64 > max_bytes {
+        return Err(UploadError::TooLarge(max_bytes));
+    }
+
+    // Read as far as an image's header, and refuse one over the limits
+    // before any more of it is read or stored
+    if media_type.is_image() {
+        let dimensions = loop {
+            if let Some(dimensions) = header_dimensions(&head, media_type) {
+                break Some(dimensions);
+            }
+            match field.next().await {
+                Some(chunk) => head.extend_from_slice(&chunk?),
+                None => break None,
+            }
+            if head.len() as u64 > max_bytes {
+                return Err(UploadError::TooLarge(max_bytes));
+            }
+        };
+        if let Some(reason) =
+            dimensions.and_then(|dimensions| limits.dimensions_exceeded(dimensions))
+        {
+            return Err(UploadError::ImageTooLarge(reason));
+        }
+    }
+
+    if options.reworks(media_type) {
+        let mut data = head;
+        while let Some(chunk) = field.next().await {
+            data.extend_from_slice(&chunk?);
+            if data.len() as u64 > max_bytes {
+                return Err(UploadError::TooLarge(max_bytes));
+            }
+        }
+        let (data, media_type) =
+            tokio::task::spawn_blocking(move || rework(data, media_type, options))
+                .await
+                .map_err(io::Error::other)?
+                .map_err(|_| UploadError::Corrupt)?;
+        let key = format!(
+            "article_{}.{}",
+            Uuid::new_v4().simple(),
+            media_type.extension()
+        );
+        let url = storage
+            .put(&key, media_type.mime(), storage::once(data.clone()))
+            .await?;
+        return Ok(StoredUpload {
+            key,
+            url,
+            media_type,
+            dimensions: image_dimensions(&data),
+            sha256: hex(&Sha256::digest(&data)),
+            byte_size: data.len() as u64,
+            image_data: Some(data),
+        });
+    }
+
+    let key = format!(
+        "article_{}.{}",
+        Uuid::new_v4().simple(),
+        media_type.extension()
+    );
+    // The backend only sees io errors; the real reason a stream was cut short
+    // is kept here so it can be reported
+    let failure = RefCell::new(None);
+    let written = Cell::new(head.len() as u64);
+    let image_data = RefCell::new(media_type.is_image().then(|| head.clone()));
+    let hasher = RefCell::new(Sha256::new_with_prefix(&head));
+
+    let rest = field.map(|chunk| {
+        let chunk = chunk.map_err(|e| {
+            *failure.borrow_mut() = Some(UploadError::Multipart(e));
+            io::Error::other("upload stream failed")
+        })?;
+        written.set(written.get() + chunk.len() as u64);
+        if written.get() > max_bytes {
+            *failure.borrow_mut() = Some(UploadError::TooLarge(max_bytes));
+            return Err(io::Error::other("upload exceeds the size limit"));
+        }
+        if let Some(data) = image_data.borrow_mut().as_mut() {
+            data.extend_from_slice(&chunk);
+        }
+        hasher.borrow_mut().update(&chunk);
+        Ok(chunk)
+    });
+    let body = stream::once(async { Ok(Bytes::from(head)) }).chain(rest);
+
+    match storage.put(&key, media_type.mime(), Box::pin(body)).await {
+        Ok(url) => {
+            let image_data = image_data.into_inner();
+            // A GIF's frames are only counted once it's all in, so it's
+            // removed again
+            if let Some(reason) = image_data
+                .as_deref()
+                .and_then(|data| limits.frames_exceeded(data, media_type))
+            {
+                storage.delete(&url).await?;
+                return Err(UploadError::ImageTooLarge(reason));
+            }
+            Ok(StoredUpload {
+                key,
+                url,
+                media_type,
+                dimensions: image_data.as_deref().and_then(image_dimensions),
+                image_data,
+                sha256: hex(&hasher.into_inner().finalize()),
+                byte_size: written.get(),
+            })
+        }
+        Err(e) => Err(failure.into_inner().unwrap_or(UploadError::Io(e))),
+    }
+}
+
+// An upload as it's to be stored, with its type: stripped of its metadata
+// and converted to WebP, as `options` say. An image that doesn't decode is an
+// error; one that can't be converted is kept as it was. Imported files are
+// reworked too, so nothing is stored that couldn't have been uploaded.
+pub fn rework(
+    data: Vec<u8>,
+    media_type: MediaType,
+    options: UploadOptions,
+) -> image::ImageResult<(Vec<u8>, MediaType)> {
+    let data = if options.strip_metadata {
+        strip_metadata(&data, media_type, options.limits)?
+    } else {
+        data
+    };
+    let convert = options
+        .webp_min_bytes
+        .is_some_and(|min_bytes| data.len() as u64 >= min_bytes)
+        && matches!(media_type, MediaType::Jpeg | MediaType::Png)
+        && !is_animated_png(&data);
+    if !convert {
+        return Ok((data, media_type));
+    }
+    match to_webp(&data, media_type, options.limits) {
+        Ok(webp) if webp.len() < data.len() => {
+            info!(
+                from = media_type.mime(),
+                original_bytes = data.len(),
+                webp_bytes = webp.len(),
+                saved_bytes = data.len() - webp.len(),
+                "Converted an upload to WebP"
+            );
+            Ok((webp, MediaType::Webp))
+        }
+        Ok(webp) => {
+            debug!(
+                original_bytes = data.len(),
+                webp_bytes = webp.len(),
+                "Kept an upload that's smaller than its WebP"
+            );
+            Ok((data, media_type))
+        }
+        Err(e) => {
+            warn!(from = media_type.mime(), error = %e, "Failed to convert an upload to WebP; it's kept as it was");
+            Ok((data, media_type))
+        }
+    }
+}
+
+// Whether a PNG has an animation control chunk, which comes before the
+// image data; converting would keep only its first frame
+fn is_animated_png(data: &[u8]) -> bool {
+    let mut rest = data.get(8..).unwrap_or_default();
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        match &rest[4..8] {
+            b"acTL" => return true,
+            b"IDAT" => return false,
+            _ => rest = rest.get(12 + len..).unwrap_or_default(),
+        }
+    }
+    false
+}
+
+// A JPEG or PNG as a WebP, turned upright. The encoder at hand only writes
+// lossless WebPs, which suit PNGs' flat artwork and screenshots best.
+fn to_webp(data: &[u8], media_type: MediaType, limits: ImageLimits) -> image::ImageResult<Vec<u8>> {
+    let format = match media_type {
+        MediaType::Jpeg => image::ImageFormat::Jpeg,
+        _ => image::ImageFormat::Png,
+    };
+    let mut decoder = limits.reader(data, format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    let mut encoded = Cursor::new(Vec::new());
+    if img.color().has_alpha() {
+        img.to_rgba8()
+            .write_to(&mut encoded, image::ImageFormat::WebP)?;
+    } else {
+        img.to_rgb8()
+            .write_to(&mut encoded, image::ImageFormat::WebP)?;
+    }
+    Ok(encoded.into_inner())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// PNG chunks that can say where, when or on what an image was made
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+// A copy of an image without its EXIF, XMP and text metadata, which can give
+// away where a photo was taken and with what. JPEGs are decoded and encoded
+// again; PNGs and WebPs lose just those chunks, unless their EXIF orientation
+// turns them, which is then applied to the pixels and the image encoded
+// again (losslessly). Anything that doesn't decode is an error, so nothing
+// unchecked is kept. Other types come back as they are.
+pub fn strip_metadata(
+    data: &[u8],
+    media_type: MediaType,
+    limits: ImageLimits,
+) -> image::ImageResult<Vec<u8>> {
+    let format = match media_type {
+        MediaType::Jpeg => image::ImageFormat::Jpeg,
+        MediaType::Png => image::ImageFormat::Png,
+        MediaType::Webp => image::ImageFormat::WebP,
+        _ => return Ok(data.to_vec()),
+    };
+    let mut decoder = limits.reader(data, format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    if orientation == image::metadata::Orientation::NoTransforms {
+        match media_type {
+            MediaType::Png => return Ok(strip_png_chunks(data)),
+            MediaType::Webp => return Ok(strip_webp_chunks(data)),
+            _ => {}
+        }
+    }
+    img.apply_orientation(orientation);
+
+    let mut encoded = Cursor::new(Vec::new());
+    match media_type {
+        MediaType::Jpeg => {
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, 90);
+            match img {
+                image::DynamicImage::ImageLuma8(gray) => encoder.encode_image(&gray)?,
+                img => encoder.encode_image(&img.to_rgb8())?,
+            }
+        }
+        MediaType::Webp => img.to_rgba8().write_to(&mut encoded, format)?,
+        _ => img.write_to(&mut encoded, format)?,
+    }
+    Ok(encoded.into_inner())
+}
+
+// A PNG's chunks up to IEND, less PNG_METADATA_CHUNKS; `data` has decoded
+fn strip_png_chunks(data: &[u8]) -> Vec<u8> {
+    let (signature, mut rest) = data.split_at(8);
+    let mut stripped = signature.to_vec();
+    // Length, type, data and CRC
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let Some(chunk) = rest.get(..12 + len) else {
+            break;
+        };
+        let kind = &chunk[4..8];
+        if !PNG_METADATA_CHUNKS.iter().any(|dropped| kind == *dropped) {
+            stripped.extend_from_slice(chunk);
+        }
+        if kind == b"IEND" {
+            break;
+        }
+        rest = &rest[chunk.len()..];
+    }
+    stripped
+}
+
+// A WebP without its EXIF and XMP chunks, and the VP8X flags saying it has
+// them; `data` has decoded
+fn strip_webp_chunks(data: &[u8]) -> Vec<u8> {
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+    // "RIFF", the size of what follows, "WEBP"
+    let mut stripped = data[..12].to_vec();
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        // Chunks are padded to an even length
+        let end = (8 + len + len % 2).min(rest.len());
+        let chunk = &rest[..end];
+        match &chunk[..4] {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if chunk.len() > 8 => {
+                stripped.extend_from_slice(&chunk[..8]);
+                stripped.push(chunk[8] & !(EXIF_FLAG | XMP_FLAG));
+                stripped.extend_from_slice(&chunk[9..]);
+            }
+            _ => stripped.extend_from_slice(chunk),
+        }
+        rest = &rest[end..];
+    }
+    let riff_size = (stripped.len() - 8) as u32;
+    stripped[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    stripped
+}
+
+// Width and height in pixels, from an image's header
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut reader = image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    // However large they are; nothing is decoded
+    reader.no_limits();
+    reader.into_dimensions().ok()
+}
+
+// Storage key for the thumbnail or poster of the upload stored as `upload_key`
+pub fn thumb_key(upload_key: &str, thumb_type: MediaType) -> String {
+    let stem = Path::new(upload_key)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("upload");
+    format!("thumbs/thumb_{}.{}", stem, thumb_type.extension())
+}
+
+// Encode a scaled-down copy of an uploaded image, returning its bytes and
+// format. JPEGs stay JPEGs; everything else becomes a PNG so transparency
+// survives.
+pub fn generate_thumbnail(
+    data: &[u8],
+    media_type: MediaType,
+    limits: ImageLimits,
+) -> image::ImageResult<(Vec<u8>, MediaType)> {
+    let format = image::guess_format(data)?;
+    let img = limits.reader(data, format).decode()?;
+    let thumb = if img.width() <= THUMB_MAX_EDGE && img.height() <= THUMB_MAX_EDGE {
+        img
+    } else {
+        img.thumbnail(THUMB_MAX_EDGE, THUMB_MAX_EDGE)
+    };
+
+    let (thumb_type, thumb, format) = match media_type {
+        MediaType::Jpeg => (
+            MediaType::Jpeg,
+            image::DynamicImage::ImageRgb8(thumb.to_rgb8()),
+            image::ImageFormat::Jpeg,
+        ),
+        _ => (MediaType::Png, thumb, image::ImageFormat::Png),
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    thumb.write_to(&mut encoded, format)?;
+    Ok((encoded.into_inner(), thumb_type))
+}
+
+// Extract the first frame of a video as a JPEG by running ffmpeg, returning
+// the poster's bytes
+pub async fn extract_poster_frame(ffmpeg: &Path, source: &Path) -> io::Result<Vec<u8>> {
+    let target = env::temp_dir().join(format!("poster_{}.jpg", Uuid::new_v4().simple()));
+
+    let scale = format!(
+        "scale={0}:{0}:force_original_aspect_ratio=decrease",
+        THUMB_MAX_EDGE
+    );
+    let output = Command::new(ffmpeg)
+        .args(["-nostdin", "-y", "-loglevel", "error", "-i"])
+        .arg(source)
+        .args(["-frames:v", "1", "-vf", &scale])
+        .arg(&target)
+        .stdin(Stdio::null())
+        .output()
+        .await;
+
+    let result = match output {
+        Ok(output) if output.status.success() => fs::read(&target),
+        Ok(output) => Err(io::Error::other(format!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&target);
+    result
+}
+
+// ffprobe, taken to be beside the configured ffmpeg
+pub fn ffprobe_beside(ffmpeg: &Path) -> PathBuf {
+    ffmpeg.with_file_name(match ffmpeg.extension() {
+        Some(extension) => format!("ffprobe.{}", extension.to_string_lossy()),
+        None => "ffprobe".to_string(),
+    })
+}
+
+// What ffprobe says of a video
+#[derive(Debug, Default)]
+pub struct VideoProbe {
+    pub duration_secs: Option<f64>,
+    pub video_codecs: Vec<String>,
+    pub audio_codecs: Vec<String>,
+}
+
+impl VideoProbe {
+    // Why a video won't play everywhere or is too long, if it is: browsers
+    // play H.264 with AAC sound in MP4. `max_secs` of 0 allows any length.
+    pub fn problem(&self, max_secs: u64) -> Option<String> {
+        let minutes = |secs: f64| format!("{}:{:02}", secs as u64 / 60, secs as u64 % 60);
+        match self.duration_secs {
+            Some(secs) if max_secs > 0 && secs > max_secs as f64 => {
+                return Some(format!(
+                    "Videos can be at most {} long; this one is {}.",
+                    minutes(max_secs as f64),
+                    minutes(secs.ceil())
+                ));
+            }
+            _ => {}
+        }
+        if !self.video_codecs.iter().any(|codec| codec == "h264") {
+            return Some(match self.video_codecs.first() {
+                Some(codec) => format!("Videos need to be H.264; this one is {}.", codec),
+                None => "Videos need an H.264 video track; this one has none.".to_string(),
+            });
+        }
+        self.audio_codecs
+            .iter()
+            .find(|codec| *codec != "aac")
+            .map(|codec| format!("A video's sound needs to be AAC; this one's is {}.", codec))
+    }
+}
+
+// Ask ffprobe about the video at `source`. A file ffprobe can't read is an
+// InvalidData error; other errors are about running it.
+pub async fn probe_video(ffprobe: &Path, source: &Path) -> io::Result<VideoProbe> {
+    #[derive(Deserialize)]
+    struct Probed {
+        #[serde(default)]
+        streams: Vec<Stream>,
+        format: Option<Format>,
+    }
+    #[derive(Deserialize)]
+    struct Stream {
+        codec_type: Option<String>,
+        codec_name: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Format {
+        // A decimal string
+        duration: Option<String>,
+    }
+
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration:stream=codec_type,codec_name",
+            "-of",
+            "json",
+        ])
+        .arg(source)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "ffprobe exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    let probed: Probed = serde_json::from_slice(&output.stdout)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut probe = VideoProbe {
+        duration_secs: probed
+            .format
+            .and_then(|format| format.duration?.parse().ok()),
+        ..Default::default()
+    };
+    for stream in probed.streams {
+        let (Some(kind), Some(codec)) = (stream.codec_type, stream.codec_name) else {
+            continue;
+        };
+        match kind.as_str() {
+            "video" => probe.video_codecs.push(codec),
+            "audio" => probe.audio_codecs.push(codec),
+            _ => {}
+        }
+    }
+    Ok(probe)
+}
+
+// `articles backfill-media`. Rows whose file is gone are left as they are.
+pub async fn backfill_media(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let storage = storage::from_config(config, repo.clone())?;
+    let (mut filled, mut missing, mut after_id) = (0, 0, 0);
+    loop {
+        let rows = repo.media_missing_metadata(after_id, 100).await?;
+        let Some(&(last, _)) = rows.last() else {
+            break;
+        };
+        after_id = last;
+        for (media_id, media_path) in rows {
+            let data = match storage.get(&media_path).await {
+                Ok(data) => data,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    missing += 1;
+                    continue;
+                }
+                Err(e) => return Err(format!("Failed to read {}: {}", media_path, e).into()),
+            };
+            let dimensions = image_dimensions(&data).map(|(w, h)| (w as i32, h as i32));
+            let mime_type = MediaType::detect(&data).map(MediaType::mime);
+            repo.set_media_metadata(media_id, data.len() as i64, mime_type, dimensions)
+                .await?;
+            filled += 1;
+        }
+    }
+    println!(
+        "Filled in {} media row(s); {} file(s) were missing",
+        filled, missing
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use actix_multipart::Multipart;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::web::Bytes;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    const OPTIONS: UploadOptions = UploadOptions {
+        max_bytes: 1024,
+        max_audio_bytes: 1024,
+        limits: ImageLimits {
+            max_pixels: 50_000_000,
+            max_width: 16_384,
+            max_height: 16_384,
+            max_gif_frames: 1_000,
+        },
+        strip_metadata: false,
+        webp_min_bytes: None,
+    };
+
+    // A multipart form holding a single file field
+    fn upload(filename: &str, content: &[u8]) -> Multipart {
+        let mut body = format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"media\"; filename=\"{}\"\r\n\r\n",
+            filename
+        )
+        .into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n--boundary--\r\n");
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; boundary=boundary"),
+        );
+        Multipart::new(
+            &headers,
+            futures_util::stream::once(async move { Ok(Bytes::from(body)) }),
+        )
+    }
+
+    // A scratch uploads directory, removed when dropped
+    struct Dir(std::path::PathBuf);
+
+    impl Dir {
+        fn new() -> Dir {
+            let dir =
+                std::env::temp_dir().join(format!("articles-uploads-{}", Uuid::new_v4().simple()));
+            fs::create_dir_all(&dir).unwrap();
+            Dir(dir)
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[actix_web::test]
+    async fn same_named_uploads_are_kept_apart() {
+        let dir = Dir::new();
+        let storage = LocalStorage::new(dir.0.clone()).unwrap();
+        let files = [
+            [PNG, b"first photo"].concat(),
+            [PNG, b"second photo"].concat(),
+        ];
+        // Both are streamed at once, as two posts arriving together would be
+        let stored = futures_util::future::join_all(files.iter().map(|content| {
+            let storage = &storage;
+            async move {
+                let mut form = upload("photo.png", content);
+                let mut field = form.next().await.unwrap().unwrap();
+                save_upload(&mut field, storage, OPTIONS).await.unwrap().key
+            }
+        }))
+        .await;
+
+        assert_ne!(stored[0], stored[1]);
+        for (name, content) in stored.iter().zip(&files) {
+            assert_eq!(
+                &fs::read(dir.0.join(name)).unwrap(),
+                content,
+                "{} has another upload's file",
+                name
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn uploads_at_the_limit_are_taken_and_over_it_refused() {
+        let dir = Dir::new();
+        let storage = LocalStorage::new(dir.0.clone()).unwrap();
+        let file = [PNG, &[0; 4088]].concat();
+        assert_eq!(file.len(), 4096);
+
+        let mut form = upload("photo.png", &file);
+        let mut field = form.next().await.unwrap().unwrap();
+        let name = save_upload(
+            &mut field,
+            &storage,
+            UploadOptions {
+                max_bytes: 4096,
+                ..OPTIONS
+            },
+        )
+        .await
+        .unwrap()
+        .key;
+        assert_eq!(fs::read(dir.0.join(name)).unwrap(), file);
+
+        let mut form = upload("photo.png", &file);
+        let mut field = form.next().await.unwrap().unwrap();
+        let result = save_upload(
+            &mut field,
+            &storage,
+            UploadOptions {
+                max_bytes: 4095,
+                ..OPTIONS
+            },
+        )
+        .await;
+        assert!(
+            matches!(result, Err(UploadError::TooLarge(4095))),
+            "{:?}",
+            result.err()
+        );
+        // Nothing of the refused upload is left behind
+        assert_eq!(
+            fs::read_dir(&dir.0)
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().path().is_file())
+                .count(),
+            1
+        );
+    }
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        chunk.extend((data.len() as u32).to_be_bytes());
+        chunk.extend(kind);
+        chunk.extend(data);
+        // The CRC isn't checked here
+        chunk.extend([0; 4]);
+        chunk
+    }
+
+    #[test]
+    fn headers_give_the_dimensions_the_decoder_does() {
+        let img = image::DynamicImage::from(image::RgbImage::new(300, 17));
+        for (format, media_type) in [
+            (image::ImageFormat::Png, MediaType::Png),
+            (image::ImageFormat::Jpeg, MediaType::Jpeg),
+            (image::ImageFormat::Gif, MediaType::Gif),
+            (image::ImageFormat::WebP, MediaType::Webp),
+        ] {
+            let mut data = Vec::new();
+            img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+            assert_eq!(
+                header_dimensions(&data, media_type),
+                Some((300, 17)),
+                "{:?}",
+                format
+            );
+            assert_eq!(image_dimensions(&data), Some((300, 17)), "{:?}", format);
+            // Nothing short of the header will do
+            let header = (1..=data.len())
+                .find(|&len| header_dimensions(&data[..len], media_type).is_some())
+                .unwrap();
+            assert!(header < 200, "{:?} needed {} bytes", format, header);
+        }
+
+        // A JPEG's frame header can come after EXIF and other segments
+        let mut jpeg = Vec::new();
+        img.write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let mut exif = vec![0xFF, 0xE1, 0x10, 0x02];
+        exif.resize(4 + 0x1000, b'x');
+        let tagged = [&jpeg[..2], &exif[..], &jpeg[2..]].concat();
+        assert_eq!(header_dimensions(&tagged, MediaType::Jpeg), Some((300, 17)));
+        assert_eq!(header_dimensions(&tagged[..4096], MediaType::Jpeg), None);
+
+        // A lossy WebP's frame header, and the extended format's canvas
+        let mut vp8 = b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\x9D\x01\x2A".to_vec();
+        vp8.extend([0x2C, 0x01, 0x11, 0x00]);
+        assert_eq!(header_dimensions(&vp8, MediaType::Webp), Some((300, 17)));
+        let mut vp8x = b"RIFF\0\0\0\0WEBPVP8X\x0A\0\0\0\0\0\0\0".to_vec();
+        vp8x.extend([0x2B, 0x01, 0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(header_dimensions(&vp8x, MediaType::Webp), Some((300, 17)));
+    }
+
+    #[test]
+    fn audio_is_known_by_its_container() {
+        assert_eq!(
+            MediaType::detect(b"ID3\x04\0\0\0\0\x01\x00TIT2"),
+            Some(MediaType::Mp3)
+        );
+        // MPEG-1 and MPEG-2 layer III frame headers, with no ID3 tag
+        assert_eq!(
+            MediaType::detect(&[0xFF, 0xFB, 0x90, 0x64, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Some(MediaType::Mp3)
+        );
+        assert_eq!(
+            MediaType::detect(&[0xFF, 0xF3, 0x48, 0xC4, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Some(MediaType::Mp3)
+        );
+        assert_eq!(
+            MediaType::detect(b"OggS\0\x02\0\0\0\0\0\0"),
+            Some(MediaType::Ogg)
+        );
+        assert_eq!(
+            MediaType::detect(b"fLaC\0\0\0\x22\x10\0\x10\0"),
+            Some(MediaType::Flac)
+        );
+        for audio in [MediaType::Mp3, MediaType::Ogg, MediaType::Flac] {
+            assert!(audio.is_audio() && !audio.is_image());
+            assert!(audio.mime().starts_with("audio/"));
+        }
+
+        // Not layer III: ADTS AAC, and MPEG layer II
+        assert_eq!(
+            MediaType::detect(&[0xFF, 0xF1, 0x50, 0x80, 0, 0, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+        assert_eq!(
+            MediaType::detect(&[0xFF, 0xFD, 0x90, 0x64, 0, 0, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+        // The reserved MPEG version
+        assert_eq!(
+            MediaType::detect(&[0xFF, 0xEB, 0x90, 0x64, 0, 0, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+        assert_eq!(
+            MediaType::detect(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F', 0, 1]),
+            Some(MediaType::Jpeg)
+        );
+        assert_eq!(MediaType::detect(b"ID"), None);
+    }
+
+    #[test]
+    fn png_text_chunks_are_dropped() {
+        let mut png = Vec::new();
+        image::RgbImage::new(2, 2)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let mut tagged = png.clone();
+        // After the signature and IHDR
+        tagged.splice(33..33, chunk(b"tEXt", b"Comment\0taken at home"));
+        tagged.splice(33..33, chunk(b"eXIf", b"MM\0*"));
+        tagged.extend(b"trailing");
+        assert_eq!(strip_png_chunks(&tagged), png);
+    }
+
+    #[test]
+    fn webp_exif_and_xmp_are_dropped() {
+        let mut webp = Vec::new();
+        image::RgbaImage::new(2, 2)
+            .write_to(&mut Cursor::new(&mut webp), image::ImageFormat::WebP)
+            .unwrap();
+        let image = &webp[12..];
+
+        let riff = |chunks: &[&[u8]]| {
+            let body: Vec<u8> = chunks.concat();
+            let mut riff = b"RIFF".to_vec();
+            riff.extend((4 + body.len() as u32).to_le_bytes());
+            riff.extend(b"WEBP");
+            riff.extend(body);
+            riff
+        };
+        // Flags, then the canvas's width and height less one
+        let vp8x = |flags: u8| {
+            [
+                b"VP8X".as_slice(),
+                &[10, 0, 0, 0, flags, 0, 0, 0, 1, 0, 0, 1, 0, 0],
+            ]
+            .concat()
+        };
+        let exif = [b"EXIF".as_slice(), &[3, 0, 0, 0], b"MM*", &[0]].concat();
+        let xmp = [b"XMP ".as_slice(), &[2, 0, 0, 0], b"<x"].concat();
+
+        let tagged = riff(&[&vp8x(0x08 | 0x04 | 0x10), image, &exif, &xmp]);
+        let stripped = strip_webp_chunks(&tagged);
+        assert_eq!(stripped, riff(&[&vp8x(0x10), image]));
+        assert!(image::load_from_memory(&stripped).is_ok());
+    }
+
+    #[test]
+    fn videos_must_be_short_h264_with_aac() {
+        let probe = |secs: f64, video: &[&str], audio: &[&str]| VideoProbe {
+            duration_secs: Some(secs),
+            video_codecs: video.iter().map(|c| c.to_string()).collect(),
+            audio_codecs: audio.iter().map(|c| c.to_string()).collect(),
+        };
+        assert_eq!(probe(299.5, &["h264"], &["aac"]).problem(300), None);
+        assert_eq!(probe(30.0, &["h264"], &[]).problem(300), None);
+        assert_eq!(probe(3.0 * 3600.0, &["h264"], &["aac"]).problem(0), None);
+        assert_eq!(
+            probe(754.2, &["h264"], &["aac"]).problem(300).unwrap(),
+            "Videos can be at most 5:00 long; this one is 12:35."
+        );
+        assert_eq!(
+            probe(10.0, &["hevc"], &["aac"]).problem(300).unwrap(),
+            "Videos need to be H.264; this one is hevc."
+        );
+        assert_eq!(
+            probe(10.0, &[], &["aac"]).problem(300).unwrap(),
+            "Videos need an H.264 video track; this one has none."
+        );
+        assert_eq!(
+            probe(10.0, &["h264"], &["opus"]).problem(300).unwrap(),
+            "A video's sound needs to be AAC; this one's is opus."
+        );
+    }
+
+    #[test]
+    fn ffprobe_is_found_beside_ffmpeg() {
+        assert_eq!(
+            ffprobe_beside(Path::new("/usr/bin/ffmpeg")),
+            Path::new("/usr/bin/ffprobe")
+        );
+        assert_eq!(ffprobe_beside(Path::new("ffmpeg")), Path::new("ffprobe"));
+        assert_eq!(
+            ffprobe_beside(Path::new("C:/ffmpeg/ffmpeg.exe")),
+            Path::new("C:/ffmpeg/ffprobe.exe")
+        );
+    }
+}
@@ -0,0 +1,401 @@
+// Chat notifications: with NOTIFY_WEBHOOK_URL set, each new article (and
+// with NOTIFY_COMMENTS each new comment) is posted there as a short message
+// with its title, the start of its text, a link and its first image, shaped
+// for a Discord webhook or a Slack incoming webhook. Messages wait in a small
+// queue and go out one at a time, a second apart, as both services answer
+// bursts with 429s; a 429's Retry-After is waited out. One that still can't
+// be sent after a few tries is dropped with a warning. Unlike webhooks (see
+// webhook.rs) these are only a convenience, so nothing is kept of them.
+
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::{error, warn};
+
+use crate::config::{Config, NotifyConfig, NotifyFormat};
+use crate::db::ArticleRepository;
+use crate::markdown;
+use crate::render::{absolute_url, summary};
+use crate::slug::article_path;
+use crate::EXCERPT_CHARS;
+
+// Messages waiting to be sent; past this many, new ones are dropped
+const QUEUE_CAPACITY: usize = 32;
+// Between messages, and doubled after each failed try
+const INTERVAL: Duration = Duration::from_secs(1);
+const ATTEMPTS: u32 = 3;
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Longer waits asked for by a 429 are cut to this
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// Discord's webhooks are on discord.com (or the older discordapp.com); most
+// other chat services take Slack's shape
+pub fn format_of(url: &str) -> NotifyFormat {
+    let host = url
+        .split("://")
+        .nth(1)
+        .unwrap_or_default()
+        .split(['/', '?'])
+        .next()
+        .unwrap_or_default();
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let discord = ["discord.com", "discordapp.com"]
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+    if discord {
+        NotifyFormat::Discord
+    } else {
+        NotifyFormat::Slack
+    }
+}
+
+// What a notification says, whichever shape it's sent in
+#[derive(Debug)]
+pub struct Message {
+    pub title: String,
+    pub excerpt: String,
+    // Absolute, as are thumbnails
+    pub link: String,
+    pub thumbnail: Option<String>,
+}
+
+pub fn payload(format: NotifyFormat, message: &Message) -> Value {
+    match format {
+        NotifyFormat::Discord => {
+            let mut embed = Map::new();
+            embed.insert("title".to_string(), json!(message.title));
+            embed.insert("description".to_string(), json!(message.excerpt));
+            embed.insert("url".to_string(), json!(message.link));
+            if let Some(thumbnail) = &message.thumbnail {
+                embed.insert("thumbnail".to_string(), json!({ "url": thumbnail }));
+            }
+            // Posts can't ping anyone with @everyone and the like
+            json!({ "embeds": [embed], "allowed_mentions": { "parse": [] } })
+        }
+        NotifyFormat::Slack => {
+            let title = slack_escape(&message.title);
+            let mut section = Map::new();
+            section.insert("type".to_string(), json!("section"));
+            section.insert(
+                "text".to_string(),
+                json!({
+                    "type": "mrkdwn",
+                    "text": format!("*<{}|{}>*\n{}", message.link, title, slack_escape(&message.excerpt)),
+                }),
+            );
+            if let Some(thumbnail) = &message.thumbnail {
+                section.insert(
+                    "accessory".to_string(),
+                    json!({ "type": "image", "image_url": thumbnail, "alt_text": message.title }),
+                );
+            }
+            // `text` is what notifications and older clients show
+            json!({ "text": format!("<{}|{}>", message.link, title), "blocks": [section] })
+        }
+    }
+}
+
+// Slack reads these three as markup in message text
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// A post a handler stored, with the base of its board's URLs
+enum Posted {
+    Article {
+        base: String,
+        article_id: i32,
+    },
+    Comment {
+        base: String,
+        article_id: i32,
+        comment_id: i32,
+    },
+}
+
+// Taken by the handlers that store posts, as web::Data; does nothing without
+// NOTIFY_WEBHOOK_URL
+pub struct Notifier {
+    queue: Option<Sender<Posted>>,
+    comments: bool,
+}
+
+impl Notifier {
+    // Start the task that sends notifications, if they're configured
+    pub fn spawn(repo: Arc<dyn ArticleRepository>, config: &Config) -> Self {
+        let Some(notify) = config.notify.clone() else {
+            return Notifier {
+                queue: None,
+                comments: false,
+            };
+        };
+        let (queue, received) = mpsc::channel(QUEUE_CAPACITY);
+        let comments = notify.comments;
+        tokio::spawn(run(repo, notify, config.public_url.clone(), received));
+        Notifier {
+            queue: Some(queue),
+            comments,
+        }
+    }
+
+    pub fn article_created(&self, base: &str, article_id: i32) {
+        self.queue(Posted::Article {
+            base: base.to_string(),
+            article_id,
+        });
+    }
+
+    pub fn comment_created(&self, base: &str, article_id: i32, comment_id: i32) {
+        if self.comments {
+            self.queue(Posted::Comment {
+                base: base.to_string(),
+                article_id,
+                comment_id,
+            });
+        }
+    }
+
+    fn queue(&self, posted: Posted) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        if let Err(TrySendError::Full(_)) = queue.try_send(posted) {
+            warn!("Too many chat notifications waiting; one was dropped");
+        }
+    }
+}
+
+async fn run(
+    repo: Arc<dyn ArticleRepository>,
+    notify: NotifyConfig,
+    public_url: String,
+    mut received: Receiver<Posted>,
+) {
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .expect("the notification client builds");
+    while let Some(posted) = received.recv().await {
+        let Some(message) = message(repo.as_ref(), &public_url, &posted).await else {
+            continue;
+        };
+        let body = payload(notify.format, &message);
+        if let Err(e) = send_with_retries(&client, &notify.url, &body).await {
+            warn!(link = %message.link, error = %e, "Dropped a chat notification");
+        }
+        tokio::time::sleep(INTERVAL).await;
+    }
+}
+
+// None when the post is gone again, or can't be loaded
+async fn message(
+    repo: &dyn ArticleRepository,
+    public_url: &str,
+    posted: &Posted,
+) -> Option<Message> {
+    let loaded = match *posted {
+        Posted::Article {
+            ref base,
+            article_id,
+        } => repo
+            .get_article_with_media(article_id)
+            .await
+            .map(|article| {
+                let path = format!(
+                    "{}{}",
+                    base,
+                    article_path(article.id, article.slug.as_deref())
+                );
+                // The first image, or a video's poster frame
+                let thumbnail = article
+                    .media
+                    .iter()
+                    .find_map(|m| {
+                        let image = !m.is_video() && !m.is_audio();
+                        m.thumb_path
+                            .as_deref()
+                            .or(image.then_some(m.media_path.as_str()))
+                    })
+                    .map(|path| absolute_url(public_url, path));
+                Some(Message {
+                    excerpt: summary(&markdown::to_plain_text(&article.body), EXCERPT_CHARS),
+                    title: article.title,
+                    link: absolute_url(public_url, &path),
+                    thumbnail,
+                })
+            }),
+        Posted::Comment {
+            ref base,
+            article_id,
+            comment_id,
+        } => {
+            match (
+                repo.get_article(article_id).await,
+                repo.list_comments(article_id).await,
+            ) {
+                (Ok(article), Ok(comments)) => Ok(comments
+                    .into_iter()
+                    .find(|c| c.id == comment_id)
+                    .map(|comment| {
+                        let path = format!(
+                            "{}{}#c{}",
+                            base,
+                            article_path(article.id, article.slug.as_deref()),
+                            comment.id
+                        );
+                        Message {
+                            title: format!("Comment on {}", article.title),
+                            excerpt: summary(&comment.comment, EXCERPT_CHARS),
+                            link: absolute_url(public_url, &path),
+                            thumbnail: None,
+                        }
+                    })),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            }
+        }
+    };
+    match loaded {
+        Ok(message) => message,
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(e) => {
+            error!(error = %e, "Failed to load post for a chat notification");
+            None
+        }
+    }
+}
+
+async fn send_with_retries(
+    client: &reqwest::Client,
+    url: &str,
+    body: &Value,
+) -> Result<(), String> {
+    let mut wait = INTERVAL;
+    let mut attempt = 1;
+    loop {
+        let response = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await;
+        let error = match response {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                // Seconds, which Discord may give with a fraction
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok()?.trim().parse::<f64>().ok())
+                    .map_or(wait, |secs| {
+                        Duration::from_secs_f64(secs.clamp(0.0, MAX_RETRY_AFTER.as_secs_f64()))
+                    });
+                wait = retry_after;
+                "HTTP 429".to_string()
+            }
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= ATTEMPTS {
+            return Err(error);
+        }
+        tokio::time::sleep(wait).await;
+        wait = (wait * 2).min(MAX_RETRY_AFTER);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(thumbnail: Option<&str>) -> Message {
+        Message {
+            title: "Cats & <dogs>".to_string(),
+            excerpt: "All about them".to_string(),
+            link: "https://example.com/articles/1/cats-dogs".to_string(),
+            thumbnail: thumbnail.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn discord_gets_an_embed() {
+        let with = payload(
+            NotifyFormat::Discord,
+            &message(Some("https://example.com/uploads/thumb.jpg")),
+        );
+        assert_eq!(
+            with,
+            json!({
+                "embeds": [{
+                    "title": "Cats & <dogs>",
+                    "description": "All about them",
+                    "url": "https://example.com/articles/1/cats-dogs",
+                    "thumbnail": { "url": "https://example.com/uploads/thumb.jpg" },
+                }],
+                "allowed_mentions": { "parse": [] },
+            })
+        );
+        let without = payload(NotifyFormat::Discord, &message(None));
+        assert!(without["embeds"][0].get("thumbnail").is_none());
+    }
+
+    #[test]
+    fn slack_gets_a_section_block() {
+        let with = payload(
+            NotifyFormat::Slack,
+            &message(Some("https://example.com/uploads/thumb.jpg")),
+        );
+        assert_eq!(
+            with,
+            json!({
+                "text": "<https://example.com/articles/1/cats-dogs|Cats &amp; &lt;dogs&gt;>",
+                "blocks": [{
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": "*<https://example.com/articles/1/cats-dogs|Cats &amp; &lt;dogs&gt;>*\nAll about them",
+                    },
+                    "accessory": {
+                        "type": "image",
+                        "image_url": "https://example.com/uploads/thumb.jpg",
+                        "alt_text": "Cats & <dogs>",
+                    },
+                }],
+            })
+        );
+        let without = payload(NotifyFormat::Slack, &message(None));
+        assert!(without["blocks"][0].get("accessory").is_none());
+    }
+
+    #[test]
+    fn the_format_goes_by_the_host() {
+        assert_eq!(
+            format_of("https://discord.com/api/webhooks/1/abc"),
+            NotifyFormat::Discord
+        );
+        assert_eq!(
+            format_of("https://canary.discordapp.com/api/webhooks/1/abc"),
+            NotifyFormat::Discord
+        );
+        assert_eq!(
+            format_of("https://hooks.slack.com/services/T0/B0/xyz"),
+            NotifyFormat::Slack
+        );
+        assert_eq!(
+            format_of("https://discord.com.example.net/hook"),
+            NotifyFormat::Slack
+        );
+        assert_eq!(
+            format_of("https://chat.example.com:8065/hooks/xyz"),
+            NotifyFormat::Slack
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// The OpenAPI spec for /api, served at /api/openapi.json and built from the
+// #[utoipa::path] on each handler in api.rs; a route added there needs adding
+// to `paths` here too. With API_DOCS=true, /api/docs shows it in Swagger UI.
+
+use actix_web::{http::StatusCode, web, HttpResponse};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+use crate::api;
+use crate::config::Config;
+use crate::error::AppError;
+use crate::templates::{render_html, ApiDocsContext};
+
+pub const SPEC: &str = "/api/openapi.json";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Articles", description = "Reading is open to everyone. Posting takes an API key, and deleting an admin key or an admin's login."),
+    paths(
+        api::list_articles,
+        api::create_article,
+        api::get_article,
+        api::related_articles,
+        api::delete_article,
+        api::delete_comment,
+        api::search,
+    ),
+    modifiers(&Security),
+)]
+pub struct ApiDoc;
+
+// Every error under /api has this shape, with the other fields only where they
+// apply. Only described here; handlers build it with json_error.
+#[derive(Serialize, ToSchema)]
+#[allow(dead_code)]
+pub struct ErrorBody {
+    error: String,
+    // The form field a 400 is about
+    field: Option<String>,
+    // Each field a 422 rejects, with why
+    fields: Option<BTreeMap<String, String>>,
+    // Why a banned client was banned, and until when
+    reason: Option<String>,
+    expires_at: Option<i64>,
+}
+
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("A key made with `articles api-key create`; deleting needs one made with --admin"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "admin_session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                "session",
+                "The session of an admin logged in at /admin/login, used when no key is sent",
+            ))),
+        );
+    }
+}
+
+// Built once, as it never changes while the server runs
+fn spec() -> &'static str {
+    static SPEC_JSON: OnceLock<String> = OnceLock::new();
+    SPEC_JSON.get_or_init(|| {
+        ApiDoc::openapi()
+            .to_json()
+            .expect("the OpenAPI spec serializes")
+    })
+}
+
+// GET /api/openapi.json
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(spec())
+}
+
+// GET /api/docs, a 404 unless API_DOCS is set
+pub async fn docs(config: web::Data<Config>) -> Result<HttpResponse, AppError> {
+    if !config.api_docs {
+        return Err(AppError::NotFound);
+    }
+    Ok(render_html(
+        StatusCode::OK,
+        &ApiDocsContext { spec_url: SPEC },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_PER_PAGE, MAX_PER_PAGE};
+    use serde_json::Value;
+
+    #[test]
+    fn page_sizes_match_the_handlers() {
+        let spec: Value = serde_json::from_str(spec()).unwrap();
+        for path in ["/api/articles", "/api/search"] {
+            let params = spec["paths"][path]["get"]["parameters"].as_array().unwrap();
+            let limit = params
+                .iter()
+                .find(|param| param["name"] == "limit")
+                .unwrap();
+            assert_eq!(
+                limit["schema"]["maximum"].as_f64(),
+                Some(MAX_PER_PAGE as f64),
+                "{}",
+                path
+            );
+            assert_eq!(
+                limit["schema"]["default"].as_i64(),
+                Some(DEFAULT_PER_PAGE),
+                "{}",
+                path
+            );
+        }
+    }
+}
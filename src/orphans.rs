@@ -0,0 +1,213 @@
+// Upload files no media row refers to, left behind by posts that failed after
+// their files were stored, or by rows deleted by hand. `articles prune-media`
+// removes them, and so does serve every MEDIA_PRUNE_INTERVAL_HOURS. A post's
+// files are stored before its rows, so only files older than
+// MEDIA_PRUNE_GRACE_MINUTES go. Only regular files under UPLOADS_DIR are
+// looked at: symlinks aren't followed and dotfiles are left alone. The same
+// files are what count against UPLOADS_QUOTA_BYTES.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info, warn};
+
+use crate::cli::CommandResult;
+use crate::config::{Config, StorageConfig};
+use crate::db::{self, ArticleRepository};
+
+// What was removed, or with dry_run would be
+#[derive(Default)]
+pub struct Report {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+// A file under the uploads directory
+struct Upload {
+    path: PathBuf,
+    // As media rows refer to it
+    url: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+fn list(uploads_dir: &Path) -> io::Result<Vec<Upload>> {
+    let mut uploads = Vec::new();
+    if !uploads_dir.is_dir() {
+        return Ok(uploads);
+    }
+    let mut dirs = vec![(uploads_dir.to_path_buf(), "/uploads".to_string())];
+    while let Some((dir, url)) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            // Names that aren't UTF-8 can't be in a media row, or be told apart
+            // from a mangled one, so they're left too
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let url = format!("{}/{}", url, name);
+            // Of the entry itself, not what a symlink points to
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push((entry.path(), url));
+            } else if file_type.is_file() {
+                let metadata = entry.metadata()?;
+                uploads.push(Upload {
+                    path: entry.path(),
+                    url,
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+    }
+    Ok(uploads)
+}
+
+// What the files under the uploads directory take, for storage_usage
+pub fn total_bytes(uploads_dir: &Path) -> io::Result<u64> {
+    Ok(list(uploads_dir)?.iter().map(|upload| upload.size).sum())
+}
+
+pub async fn prune(
+    repo: &dyn ArticleRepository,
+    uploads_dir: &Path,
+    grace: Duration,
+    dry_run: bool,
+) -> Result<Report, String> {
+    let cutoff = SystemTime::now() - grace;
+    // Listed before the rows are loaded, so a file can't be stored and
+    // referenced in between and still be taken for an orphan
+    let dir = uploads_dir.to_path_buf();
+    let uploads = tokio::task::spawn_blocking(move || list(&dir))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|listed| listed.map_err(|e| e.to_string()))
+        .map_err(|e| format!("Failed to list {}: {}", uploads_dir.display(), e))?;
+    let referenced: HashSet<String> = repo
+        .all_media_paths()
+        .await
+        .map_err(|e| format!("Failed to load media paths: {}", e))?
+        .into_iter()
+        .collect();
+
+    let mut report = Report::default();
+    for upload in uploads {
+        if upload.modified > cutoff || referenced.contains(&upload.url) {
+            continue;
+        }
+        if !dry_run {
+            match fs::remove_file(&upload.path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    warn!(path = %upload.path.display(), error = %e, "Failed to remove orphaned upload");
+                    continue;
+                }
+            }
+        }
+        report.files += 1;
+        report.bytes += upload.size;
+    }
+    if !dry_run && report.bytes > 0 {
+        if let Err(e) = repo.add_storage_usage(-(report.bytes as i64)).await {
+            error!(error = %e, "Failed to update storage usage");
+        }
+    }
+    Ok(report)
+}
+
+// Prune every `interval` for as long as the server runs
+pub fn spawn(
+    repo: Arc<dyn ArticleRepository>,
+    uploads_dir: PathBuf,
+    interval: Duration,
+    grace: Duration,
+) {
+    actix_web::rt::spawn(async move {
+        let mut ticks = actix_web::rt::time::interval(interval);
+        // The first tick completes immediately; a restart shouldn't prune
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            match prune(repo.as_ref(), &uploads_dir, grace, false).await {
+                Ok(report) if report.files > 0 => {
+                    info!(
+                        files = report.files,
+                        bytes = report.bytes,
+                        "Removed orphaned uploads"
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => error!(error = %e, "Failed to prune orphaned uploads"),
+            }
+        }
+    });
+}
+
+// `articles prune-media [--dry-run]`
+pub async fn prune_media(config: &Config, dry_run: bool) -> CommandResult {
+    if !matches!(config.storage, StorageConfig::Local) {
+        return Err("prune-media only works with STORAGE_BACKEND=local".into());
+    }
+    let repo = db::connect(&config.database_url).await?;
+    let grace = Duration::from_secs(config.media_prune_grace_minutes * 60);
+    let report = prune(repo.as_ref(), &config.uploads_dir, grace, dry_run).await?;
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    println!(
+        "{} {} orphaned file(s), {} bytes",
+        verb, report.files, report.bytes
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_regular_files_under_the_directory_are_listed() {
+        let dir = std::env::temp_dir().join(format!(
+            "articles-orphans-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        fs::create_dir_all(dir.join("thumbs")).unwrap();
+        fs::write(dir.join("article_a.png"), b"a").unwrap();
+        fs::write(dir.join("thumbs/thumb_a.png"), b"thumb").unwrap();
+        fs::write(dir.join(".keep"), b"").unwrap();
+        let outside = std::env::temp_dir().join(format!(
+            "articles-outside-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret"), b"s").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, dir.join("linked")).unwrap();
+            std::os::unix::fs::symlink(outside.join("secret"), dir.join("secret")).unwrap();
+        }
+
+        let mut listed: Vec<_> = list(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|u| (u.url, u.size))
+            .collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            [
+                ("/uploads/article_a.png".to_string(), 1),
+                ("/uploads/thumbs/thumb_a.png".to_string(), 5)
+            ]
+        );
+        assert!(list(&dir.join("missing")).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+}
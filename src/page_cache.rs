@@ -0,0 +1,272 @@
+// Rendered article lists and article pages, reused for PAGE_CACHE_SECONDS
+// rather than built from the database on every hit. Posting, commenting and
+// moderation drop the pages they change straight away. Moderators are never
+// served from here. Article pages hold the visitor's own tokens, so they're
+// kept with placeholders that are filled in for each visitor.
+
+use rand::RngCore;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::conditional::Validator;
+use crate::config::Config;
+use crate::db::ArticleSort;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum PageKey {
+    // One page of a board's current articles
+    List {
+        board_id: i32,
+        page: i64,
+        per_page: i64,
+        sort: ArticleSort,
+    },
+    // An article's page as asked for; only ones with the canonical slug are
+    // kept, the others being redirects
+    Article {
+        board_id: i32,
+        article_id: i32,
+        slug: Option<String>,
+        comments_page: Option<i64>,
+        reply_to: Option<i32>,
+    },
+}
+
+impl PageKey {
+    fn article_id(&self) -> Option<i32> {
+        match self {
+            PageKey::List { .. } => None,
+            PageKey::Article { article_id, .. } => Some(*article_id),
+        }
+    }
+}
+
+pub struct CachedPage {
+    pub html: String,
+    // Without the visitor's parts, see Validator::with
+    pub validator: Validator,
+}
+
+struct Pages {
+    // Counts the writes, so pages read before one aren't kept after it
+    generation: u64,
+    entries: HashMap<PageKey, (Instant, Arc<CachedPage>)>,
+}
+
+pub struct PageCache {
+    ttl: Duration,
+    capacity: usize,
+    // Random, so placeholders can't be typed into a post
+    marker: String,
+    pages: Mutex<Pages>,
+}
+
+impl PageCache {
+    // A ttl or capacity of 0 keeps nothing
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        let mut marker = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut marker);
+        PageCache {
+            ttl,
+            capacity,
+            marker: marker.iter().map(|b| format!("{:02x}", b)).collect(),
+            pages: Mutex::new(Pages {
+                generation: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        PageCache::new(
+            Duration::from_secs(config.page_cache_secs),
+            config.page_cache_entries,
+        )
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.capacity > 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pages> {
+        self.pages.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get(&self, key: &PageKey, now: Instant) -> Option<Arc<CachedPage>> {
+        match self.lock().entries.get(key) {
+            Some((at, page)) if now.duration_since(*at) < self.ttl => Some(page.clone()),
+            _ => None,
+        }
+    }
+
+    // Taken before reading what a page is made from, for put
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    // Keep `page`, unless something was written since `generation` was taken;
+    // it would be stale already. When full, expired pages go first, then the
+    // oldest.
+    pub fn put(
+        &self,
+        key: PageKey,
+        page: CachedPage,
+        generation: u64,
+        now: Instant,
+    ) -> Arc<CachedPage> {
+        let page = Arc::new(page);
+        let mut pages = self.lock();
+        if !self.enabled() || pages.generation != generation {
+            return page;
+        }
+        if pages.entries.len() >= self.capacity && !pages.entries.contains_key(&key) {
+            pages
+                .entries
+                .retain(|_, (at, _)| now.duration_since(*at) < self.ttl);
+            if pages.entries.len() >= self.capacity {
+                let oldest = pages
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (at, _))| *at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    pages.entries.remove(&oldest);
+                }
+            }
+        }
+        pages.entries.insert(key, (now, page.clone()));
+        page
+    }
+
+    // After a new article: every list
+    pub fn invalidate_lists(&self) {
+        self.invalidate(|key| key.article_id().is_none());
+    }
+
+    // After an article or its comments change: its pages and every list,
+    // which show its comment count and order it by its bump time
+    pub fn invalidate_article(&self, article_id: i32) {
+        self.invalidate(|key| key.article_id().is_none_or(|id| id == article_id));
+    }
+
+    // When what changed can't be told, such as a restored comment
+    pub fn clear(&self) {
+        self.invalidate(|_| true);
+    }
+
+    fn invalidate(&self, stale: impl Fn(&PageKey) -> bool) {
+        let mut pages = self.lock();
+        pages.generation += 1;
+        pages.entries.retain(|key, _| !stale(key));
+    }
+
+    // What stands in for one of the visitor's tokens, such as "csrf", in a
+    // kept page
+    pub fn placeholder(&self, name: &str) -> String {
+        format!("{}-{}", self.marker, name)
+    }
+
+    // `html` with each of the `tokens`' placeholders replaced by its value
+    pub fn fill(&self, html: &str, tokens: &[(&str, &str)]) -> String {
+        tokens.iter().fold(html.to_string(), |html, (name, value)| {
+            html.replace(&self.placeholder(name), value)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> CachedPage {
+        CachedPage {
+            html: String::new(),
+            validator: Validator::new(0, 0),
+        }
+    }
+
+    fn article(article_id: i32) -> PageKey {
+        PageKey::Article {
+            board_id: 1,
+            article_id,
+            slug: None,
+            comments_page: None,
+            reply_to: None,
+        }
+    }
+
+    fn list(page: i64) -> PageKey {
+        PageKey::List {
+            board_id: 1,
+            page,
+            per_page: 25,
+            sort: ArticleSort::Bump,
+        }
+    }
+
+    #[test]
+    fn pages_are_reused_until_they_expire() {
+        let cache = PageCache::new(Duration::from_secs(30), 10);
+        let start = Instant::now();
+        assert!(cache.get(&article(1), start).is_none());
+        cache.put(article(1), page(), cache.generation(), start);
+        assert!(cache
+            .get(&article(1), start + Duration::from_secs(29))
+            .is_some());
+        assert!(cache.get(&article(2), start).is_none());
+        assert!(cache
+            .get(&article(1), start + Duration::from_secs(30))
+            .is_none());
+    }
+
+    #[test]
+    fn writes_drop_the_pages_they_change() {
+        let cache = PageCache::new(Duration::from_secs(30), 10);
+        let now = Instant::now();
+        for key in [article(1), article(2), list(1)] {
+            cache.put(key, page(), cache.generation(), now);
+        }
+        cache.invalidate_article(1);
+        assert!(cache.get(&article(1), now).is_none());
+        assert!(cache.get(&list(1), now).is_none());
+        assert!(cache.get(&article(2), now).is_some());
+
+        cache.put(list(1), page(), cache.generation(), now);
+        cache.invalidate_lists();
+        assert!(cache.get(&list(1), now).is_none());
+        assert!(cache.get(&article(2), now).is_some());
+    }
+
+    #[test]
+    fn pages_read_before_a_write_are_not_kept() {
+        let cache = PageCache::new(Duration::from_secs(30), 10);
+        let now = Instant::now();
+        let generation = cache.generation();
+        cache.invalidate_article(1);
+        cache.put(article(1), page(), generation, now);
+        assert!(cache.get(&article(1), now).is_none());
+    }
+
+    #[test]
+    fn the_oldest_page_makes_room() {
+        let cache = PageCache::new(Duration::from_secs(30), 2);
+        let start = Instant::now();
+        cache.put(article(1), page(), 0, start);
+        cache.put(article(2), page(), 0, start + Duration::from_secs(1));
+        cache.put(article(3), page(), 0, start + Duration::from_secs(2));
+        assert!(cache.get(&article(1), start).is_none());
+        assert!(cache.get(&article(2), start).is_some());
+        assert!(cache.get(&article(3), start).is_some());
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let cache = PageCache::new(Duration::from_secs(30), 10);
+        let html = format!("<input value=\"{}\">", cache.placeholder("csrf"));
+        assert_eq!(
+            cache.fill(&html, &[("csrf", "token")]),
+            "<input value=\"token\">"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+// Salted hashing for the deletion passwords posters can attach to their content
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use std::sync::OnceLock;
+
+// Hash a password into a PHC string that embeds its own random salt
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+// Check a password against a stored hash; a missing hash never matches
+pub fn verify_password(password: &str, stored_hash: Option<&str>) -> bool {
+    // Without a stored hash we still pay for one verification so response
+    // timing doesn't reveal whether the content had a password at all
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let dummy = DUMMY_HASH.get_or_init(|| hash_password("dummy password").unwrap_or_default());
+
+    let matches = |hash: &str| {
+        PasswordHash::new(hash)
+            .map(|parsed| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &parsed)
+                    .is_ok()
+            })
+            .unwrap_or(false)
+    };
+
+    match stored_hash {
+        Some(hash) => matches(hash),
+        None => {
+            matches(dummy);
+            false
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Checks of what's posted, shared by the forms and the API
+
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::DEFAULT_NAME;
+
+const MAX_NAME_CHARS: usize = 50;
+const MAX_TAGS: usize = 5;
+const MAX_TAG_CHARS: usize = 32;
+
+// Check an article's title and body against the configured lengths,
+// collecting one message per offending field
+pub fn validate_article(
+    config: &Config,
+    title: &str,
+    body: &str,
+) -> BTreeMap<&'static str, String> {
+    let mut errors = BTreeMap::new();
+
+    let title = title.trim();
+    if title.is_empty() {
+        errors.insert("title", "must not be empty".to_string());
+    } else if title.chars().count() > config.max_title_chars {
+        errors.insert(
+            "title",
+            format!("must be at most {} characters", config.max_title_chars),
+        );
+    }
+
+    if body.trim().is_empty() {
+        errors.insert("body", "must not be empty".to_string());
+    } else if body.chars().count() > config.max_body_chars {
+        errors.insert(
+            "body",
+            format!("must be at most {} characters", config.max_body_chars),
+        );
+    }
+
+    errors
+}
+
+// validate_article's messages as sentences, for a form's error line
+pub fn describe_errors(errors: &BTreeMap<&'static str, String>) -> String {
+    let sentences: Vec<String> = errors
+        .iter()
+        .map(|(field, message)| {
+            let mut field = field.to_string();
+            field[..1].make_ascii_uppercase();
+            format!("{} {}.", field, message)
+        })
+        .collect();
+    sentences.join(" ")
+}
+
+// The name a post is stored under: trimmed, and DEFAULT_NAME when blank.
+// Overlong names are rejected with a message for the client.
+pub fn poster_name(input: &str) -> Result<&str, String> {
+    let name = input.trim();
+    if name.is_empty() {
+        Ok(DEFAULT_NAME)
+    } else if name.chars().count() > MAX_NAME_CHARS {
+        Err(format!("must be at most {} characters", MAX_NAME_CHARS))
+    } else {
+        Ok(name)
+    }
+}
+
+// Split a comma-separated tag list into trimmed, lowercased, deduplicated
+// tags. Blank entries are skipped; anything else that can't be used as a tag
+// is rejected with a message for the client.
+pub fn normalize_tags(input: &str) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = Vec::new();
+    for raw in input.split(',') {
+        let tag = raw
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        if tag.is_empty() || tags.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_CHARS {
+            return Err(format!("Tags must be at most {} characters", MAX_TAG_CHARS));
+        }
+        if !tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        {
+            return Err("Tags may only contain letters, numbers, spaces, - and _".to_string());
+        }
+        tags.push(tag);
+    }
+    if tags.len() > MAX_TAGS {
+        return Err(format!("Articles can have at most {} tags", MAX_TAGS));
+    }
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_names_post_anonymously() {
+        assert_eq!(poster_name("  alice \t"), Ok("alice"));
+        assert_eq!(poster_name(" \n "), Ok(DEFAULT_NAME));
+        assert_eq!(
+            poster_name(&"ü".repeat(MAX_NAME_CHARS)).map(|n| n.chars().count()),
+            Ok(MAX_NAME_CHARS)
+        );
+        assert!(poster_name(&"x".repeat(MAX_NAME_CHARS + 1)).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+// Imageboard-style references between comments: ">>45" in a comment links to
+// comment 45 of the same article, and comment 45 lists the comments quoting it
+
+use std::collections::{HashMap, HashSet};
+
+// What ">>" looks like once escape_html has run
+const ESCAPED_QUOTE: &str = "&gt;&gt;";
+
+enum Piece<'a> {
+    Text(&'a str),
+    // A referenced id and the digits it was written with
+    Quote(i32, &'a str),
+}
+
+// Split `text` at each `marker` (">>" or its escaped form) followed by digits.
+// A marker without digits (or with too many to be an id) stays text; only its
+// first half is skipped, so the ">>4" in ">>>4" is still found.
+fn scan<'a>(text: &'a str, marker: &'a str) -> Vec<Piece<'a>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(i) = rest.find(marker) {
+        pieces.push(Piece::Text(&rest[..i]));
+        let after = &rest[i + marker.len()..];
+        let digits =
+            &after[..after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len()];
+        match digits.parse::<i32>() {
+            Ok(id) => {
+                pieces.push(Piece::Quote(id, digits));
+                rest = &after[digits.len()..];
+            }
+            Err(_) => {
+                let half = marker.len() / 2;
+                pieces.push(Piece::Text(&marker[..half]));
+                rest = &rest[i + half..];
+            }
+        }
+    }
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+// Turn >>N in comment HTML that has already been escaped into a link to
+// `href(N)`, for the ids it returns one for. Working on escaped text means
+// nothing a poster writes can come out as markup; the only tags added are
+// these links.
+pub fn link_quotes(escaped: &str, href: impl Fn(i32) -> Option<String>) -> String {
+    let mut html = String::with_capacity(escaped.len());
+    for piece in scan(escaped, ESCAPED_QUOTE) {
+        match piece {
+            Piece::Quote(id, digits) => match href(id) {
+                Some(href) => html.push_str(&format!(
+                    "<a href=\"{}\" class=\"quote-link\">&gt;&gt;{}</a>",
+                    href, digits
+                )),
+                None => {
+                    html.push_str(ESCAPED_QUOTE);
+                    html.push_str(digits);
+                }
+            },
+            Piece::Text(text) => html.push_str(text),
+        }
+    }
+    html
+}
+
+// The ids `text` quotes, in order of first mention
+pub fn quoted_ids(text: &str) -> Vec<i32> {
+    let mut ids = Vec::new();
+    for piece in scan(text, ">>") {
+        if let Piece::Quote(id, _) = piece {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+    ids
+}
+
+// For each comment, the other comments in `comments` quoting it, in comment
+// order. Quotes of missing comments and self-quotes aren't listed.
+pub fn backlinks(comments: &[(i32, &str)]) -> HashMap<i32, Vec<i32>> {
+    let ids: HashSet<i32> = comments.iter().map(|&(id, _)| id).collect();
+    let mut replies: HashMap<i32, Vec<i32>> = HashMap::new();
+    for &(id, text) in comments {
+        for quoted in quoted_ids(text) {
+            if quoted != id && ids.contains(&quoted) {
+                replies.entry(quoted).or_default().push(id);
+            }
+        }
+    }
+    replies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::escape_comment;
+
+    fn link(text: &str, existing: &[i32]) -> String {
+        link_quotes(&escape_comment(text, 0), |id| {
+            existing.contains(&id).then(|| format!("#c{}", id))
+        })
+    }
+
+    #[test]
+    fn links_every_reference() {
+        assert_eq!(
+            link("agree with >>4 and >>7, not >>9", &[4, 7]),
+            "agree with <a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a> and \
+             <a href=\"#c7\" class=\"quote-link\">&gt;&gt;7</a>, not &gt;&gt;9"
+        );
+        assert_eq!(quoted_ids(">>4 >>7 >>4"), vec![4, 7]);
+    }
+
+    #[test]
+    fn links_references_at_the_edges() {
+        assert_eq!(
+            link(">>4", &[4]),
+            "<a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a>"
+        );
+        assert_eq!(
+            link(">>4\n>>5", &[4, 5]),
+            "<a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a><br><a href=\"#c5\" class=\"quote-link\">&gt;&gt;5</a>"
+        );
+        assert_eq!(link("trailing >>", &[4]), "trailing &gt;&gt;");
+        assert_eq!(
+            link(">>>4", &[4]),
+            "<span class=\"quote\">&gt;<a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a></span>"
+        );
+        assert_eq!(quoted_ids(">>>4"), vec![4]);
+        assert_eq!(link(">>99999999999", &[4]), "&gt;&gt;99999999999");
+    }
+
+    #[test]
+    fn never_lets_markup_through() {
+        assert_eq!(
+            link("<b>>>4</b>", &[4]),
+            "&lt;b&gt;<a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a>&lt;/b&gt;"
+        );
+        assert_eq!(
+            link(">>4\"onclick=\"x", &[4]),
+            "<a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a>&quot;onclick=&quot;x"
+        );
+    }
+
+    #[test]
+    fn greentext_lines_sit_beside_quote_links() {
+        assert_eq!(
+            link(
+                ">>4\r\n>be me\nnormal line\n>\n>>4 again\n >not at the start\n>>x",
+                &[4]
+            ),
+            "<a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a><br>\
+             <span class=\"quote\">&gt;be me</span><br>\
+             normal line<br>\
+             <span class=\"quote\">&gt;</span><br>\
+             <a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a> again<br>\
+             \x20&gt;not at the start<br>\
+             <span class=\"quote\">&gt;&gt;x</span>"
+        );
+        assert_eq!(
+            link("></span><script>x</script>", &[]),
+            "<span class=\"quote\">&gt;&lt;/span&gt;&lt;script&gt;x&lt;/script&gt;</span>"
+        );
+    }
+
+    #[test]
+    fn spoilers_hide_greentext_and_quote_links() {
+        assert_eq!(
+            link(">it was ||>>4||\n[spoiler]twist\nending[/spoiler]", &[4]),
+            "<span class=\"quote\">&gt;it was <span class=\"spoiler\">\
+             <a href=\"#c4\" class=\"quote-link\">&gt;&gt;4</a></span></span><br>\
+             [spoiler]twist<br>ending[/spoiler]"
+        );
+    }
+
+    #[test]
+    fn code_blocks_stay_inert() {
+        assert_eq!(
+            link("look:\n```\n>be code\n</pre><script>x</script>\n```\nafter", &[]),
+            "look:<pre><code>&gt;be code\n&lt;/pre&gt;&lt;script&gt;x&lt;/script&gt;\n</code></pre>after"
+        );
+        let html = link("```html\n</pre><script>x</script>\n```", &[]);
+        assert!(
+            html.starts_with("<pre><code><span style=") && html.ends_with("</code></pre>"),
+            "{}",
+            html
+        );
+        assert!(
+            !html.contains("<script") && html.matches("</pre>").count() == 1,
+            "{}",
+            html
+        );
+        assert_eq!(link("```\nnever closed", &[]), "```<br>never closed");
+    }
+
+    #[test]
+    fn backlinks_skip_self_references() {
+        let comments = [(4, "first"), (5, ">>4 >>5"), (6, ">>4 >>4 >>1"), (7, ">>7")];
+        let replies = backlinks(&comments);
+        assert_eq!(replies[&4], vec![5, 6]);
+        assert!(!replies.contains_key(&5));
+        assert!(!replies.contains_key(&7));
+        assert!(!replies.contains_key(&1));
+        // A self-reference still links, it just isn't a reply
+        assert_eq!(
+            link(">>7", &[7]),
+            "<a href=\"#c7\" class=\"quote-link\">&gt;&gt;7</a>"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+// Per-IP rate limits on posting. Each route that creates something is wrapped
+// in one of the middleware functions below; the buckets live in a single
+// RateLimits shared by every worker.
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, StatusCode},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::client_ip::client_ip;
+use crate::config::Config;
+use crate::templates::{render_html, MessageContext};
+
+// Past this many tracked addresses, idle ones are forgotten
+const MAX_TRACKED_ADDRS: usize = 10_000;
+
+// A token bucket per address: up to `per_minute` requests at once, refilling
+// at `per_minute` per minute
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    // A limit of 0 disables the limiter
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    // Take a token for `addr`, or say how long until one is available
+    pub fn check(&self, addr: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(self.per_minute);
+        let rate = self.refill_rate();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED_ADDRS {
+            buckets.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < capacity
+            });
+        }
+
+        let bucket = buckets.entry(addr).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+pub struct RateLimits {
+    pub articles: RateLimiter,
+    pub comments: RateLimiter,
+    pub reports: RateLimiter,
+    pub exempt_localhost: bool,
+}
+
+impl RateLimits {
+    pub fn from_config(config: &Config) -> Self {
+        RateLimits {
+            articles: RateLimiter::new(config.article_rate_limit),
+            comments: RateLimiter::new(config.comment_rate_limit),
+            reports: RateLimiter::new(config.report_rate_limit),
+            exempt_localhost: config.rate_limit_exempt_localhost,
+        }
+    }
+}
+
+// Counts against the article budget (form and API submissions)
+pub async fn limit_articles(
+    limits: web::Data<RateLimits>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    enforce(&limits.articles, limits.exempt_localhost, req, next).await
+}
+
+// Counts against the comment budget
+pub async fn limit_comments(
+    limits: web::Data<RateLimits>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    enforce(&limits.comments, limits.exempt_localhost, req, next).await
+}
+
+// Counts against the report budget
+pub async fn limit_reports(
+    limits: web::Data<RateLimits>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    enforce(&limits.reports, limits.exempt_localhost, req, next).await
+}
+
+async fn enforce<B: MessageBody>(
+    limiter: &RateLimiter,
+    exempt_localhost: bool,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let Some(addr) = client_ip(req.request()) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if exempt_localhost && addr.is_loopback() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    match limiter.check(addr, Instant::now()) {
+        Ok(()) => Ok(next.call(req).await?.map_into_left_body()),
+        Err(wait) => {
+            let response = too_many_requests(req.path(), (wait.as_secs_f64().ceil() as u64).max(1));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+// JSON for the API, otherwise a page linking back to where the form was
+fn too_many_requests(path: &str, retry_after: u64) -> HttpResponse {
+    let mut response = if path.starts_with("/api/") {
+        HttpResponse::TooManyRequests().json(json!({ "error": "Too many requests" }))
+    } else {
+        render_html(
+            StatusCode::TOO_MANY_REQUESTS,
+            &MessageContext {
+                title: "Slow Down",
+                message: &format!(
+                    "You're posting too quickly. Please wait {} second{} and try again.",
+                    retry_after,
+                    if retry_after == 1 { "" } else { "s" }
+                ),
+                link_href: &back_link(path),
+                link_text: "Go back",
+            },
+        )
+    };
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, header::HeaderValue::from(retry_after));
+    response
+}
+
+// Where the form posted to `path` lives: /b/tech/submit goes back to /b/tech/,
+// .../articles/5/comment to .../articles/5
+pub fn back_link(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((parent, _)) if path.ends_with("/submit") => format!("{}/", parent),
+        Some((parent, _)) if !parent.is_empty() => parent.to_string(),
+        _ => "/".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    #[test]
+    fn allows_a_burst_then_reports_the_wait() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.check(ADDR, start).is_ok());
+        assert!(limiter.check(ADDR, start).is_ok());
+        assert_eq!(limiter.check(ADDR, start), Err(Duration::from_secs(30)));
+
+        // Other addresses have their own budget
+        assert!(limiter
+            .check(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), start)
+            .is_ok());
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimiter::new(6);
+        let start = Instant::now();
+        for _ in 0..6 {
+            assert!(limiter.check(ADDR, start).is_ok());
+        }
+        assert!(limiter.check(ADDR, start + Duration::from_secs(5)).is_err());
+        assert!(limiter.check(ADDR, start + Duration::from_secs(10)).is_ok());
+        assert!(limiter
+            .check(ADDR, start + Duration::from_secs(10))
+            .is_err());
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let limiter = RateLimiter::new(0);
+        let start = Instant::now();
+        assert!((0..100).all(|_| limiter.check(ADDR, start).is_ok()));
+    }
+}
@@ -0,0 +1,392 @@
+// Helpers for turning user-supplied text into HTML that is safe to interpolate
+
+use chrono::{DateTime, FixedOffset, Offset, Utc};
+use std::sync::OnceLock;
+
+use crate::highlight;
+use crate::linkify::Linkifier;
+use crate::spoiler;
+
+// Escape the characters that are significant in HTML text and attribute values
+pub fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Escape a comment and keep its line breaks visible, linking up to
+// `max_links` URLs (see linkify.rs). Lines starting with ">" are greentext,
+// wrapped in a span; ">>123" quote links (see quote.rs) don't count. Each line
+// is escaped before it is wrapped, so nothing in it can close the span.
+// Spoilers (see spoiler.rs) open and close within a line. Lines between a
+// "```lang" fence and a closing "```" are a code block (see highlight.rs); a
+// fence that is never closed is just text.
+pub fn escape_comment(input: &str, max_links: usize) -> String {
+    let mut linkifier = Linkifier::new(max_links);
+    let lines: Vec<&str> = input
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    let mut html = String::with_capacity(input.len());
+    // Lines are separated by <br>, but blocks bring their own line breaks
+    let mut need_break = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let fence_end = line.strip_prefix("```").and_then(|info| {
+            lines[i + 1..]
+                .iter()
+                .position(|l| l.trim_end() == "```")
+                .map(|len| (info, len))
+        });
+        if let Some((info, len)) = fence_end {
+            let code: String = lines[i + 1..i + 1 + len]
+                .iter()
+                .map(|l| format!("{}\n", l))
+                .collect();
+            html.push_str(&highlight::code_block(info, &code));
+            need_break = false;
+            i += len + 2;
+            continue;
+        }
+        if need_break {
+            html.push_str("<br>");
+        }
+        need_break = true;
+        let mut format = |text: &str| spoiler::render(text, |t| linkifier.linkify(t));
+        let quote_link = line
+            .strip_prefix(">>")
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+        if line.starts_with('>') && !quote_link {
+            html.push_str(&format!("<span class=\"quote\">{}</span>", format(line)));
+        } else {
+            html.push_str(&format(line));
+        }
+        i += 1;
+    }
+    html
+}
+
+// Set once at startup from Config::display_timezone; times are shown in UTC
+// until then
+static DISPLAY_OFFSET: OnceLock<FixedOffset> = OnceLock::new();
+
+pub fn set_display_offset(offset: FixedOffset) {
+    let _ = DISPLAY_OFFSET.set(offset);
+}
+
+fn display_offset() -> FixedOffset {
+    DISPLAY_OFFSET.get().copied().unwrap_or(Utc.fix())
+}
+
+// A DISPLAY_TIMEZONE value: "UTC", or a fixed offset from it such as
+// "+02:00" or "UTC-05:00"
+pub fn parse_timezone(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let offset = value
+        .get(..3)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("UTC"))
+        .map_or(value, |_| &value[3..]);
+    if offset.is_empty() {
+        Some(Utc.fix())
+    } else {
+        offset.parse().ok()
+    }
+}
+
+fn format_in(epoch_seconds: i64, offset: FixedOffset) -> String {
+    DateTime::from_timestamp(epoch_seconds, 0)
+        .map(|dt| {
+            let local = dt.with_timezone(&offset);
+            if offset.local_minus_utc() == 0 {
+                local.format("%Y-%m-%d %H:%M UTC").to_string()
+            } else {
+                local.format("%Y-%m-%d %H:%M UTC%:z").to_string()
+            }
+        })
+        .unwrap_or_default()
+}
+
+// Format an epoch timestamp for display ("2024-06-03 14:22 UTC")
+pub fn format_timestamp(epoch_seconds: i64) -> String {
+    format_in(epoch_seconds, display_offset())
+}
+
+// How long before `now` a timestamp was, rounded down to the largest unit
+// ("5 minutes ago"); past a month the date is clearer
+pub fn time_ago(epoch_seconds: i64, now: i64) -> String {
+    let ago = |n: i64, unit: &str| format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+    let secs = (now - epoch_seconds).max(0);
+    match secs {
+        0..=9 => "just now".to_string(),
+        10..=59 => ago(secs, "second"),
+        60..=3599 => ago(secs / 60, "minute"),
+        3600..=86_399 => ago(secs / 3600, "hour"),
+        86_400..=2_591_999 => ago(secs / 86_400, "day"),
+        _ => DateTime::from_timestamp(epoch_seconds, 0)
+            .map(|dt| {
+                format!(
+                    "on {}",
+                    dt.with_timezone(&display_offset()).format("%Y-%m-%d")
+                )
+            })
+            .unwrap_or_default(),
+    }
+}
+
+// A moment as pages show it, in a <time> element
+pub struct DisplayTime {
+    // format_timestamp
+    pub absolute: String,
+    // time_ago
+    pub relative: String,
+    // RFC 3339, for the datetime attribute and article:published_time
+    pub iso: String,
+}
+
+pub fn display_time(epoch_seconds: i64, now: i64) -> DisplayTime {
+    DisplayTime {
+        absolute: format_timestamp(epoch_seconds),
+        relative: time_ago(epoch_seconds, now),
+        iso: DateTime::from_timestamp(epoch_seconds, 0)
+            .map(|dt| dt.with_timezone(&display_offset()).to_rfc3339())
+            .unwrap_or_default(),
+    }
+}
+
+// Format a byte count the way file sizes are usually shown ("20 MB", "245 KB")
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value.fract() == 0.0 || value >= 100.0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// Markers around matched words in search snippets; highlight_html turns them
+// into <mark> elements
+pub const MATCH_START: char = '\u{1}';
+pub const MATCH_END: char = '\u{2}';
+
+// Character index of the first case-insensitive occurrence of `needle` at or
+// after `from`
+fn find_ignoring_case(chars: &[char], needle: &[char], from: usize) -> Option<usize> {
+    if needle.is_empty() || needle.len() > chars.len() {
+        return None;
+    }
+    let same = |a: &char, b: &char| a.to_lowercase().eq(b.to_lowercase());
+    (from..=chars.len() - needle.len()).find(|&i| {
+        chars[i..i + needle.len()]
+            .iter()
+            .zip(needle)
+            .all(|(a, b)| same(a, b))
+    })
+}
+
+// Up to `max_chars` characters of `text` around the first case-insensitive
+// occurrence of `query` (or from the start when it doesn't occur), with
+// ellipses marking where text was cut
+pub fn excerpt(text: &str, query: &str, max_chars: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let needle: Vec<char> = query.chars().collect();
+
+    // Show a little context before the match
+    let start = match find_ignoring_case(&chars, &needle, 0) {
+        Some(i) if chars.len() > max_chars => {
+            i.saturating_sub(max_chars / 4).min(chars.len() - max_chars)
+        }
+        _ => 0,
+    };
+    let end = (start + max_chars).min(chars.len());
+
+    let mut excerpt = String::new();
+    if start > 0 {
+        excerpt.push('…');
+    }
+    excerpt.extend(&chars[start..end]);
+    if end < chars.len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+// The start of `text` as a single line, for meta descriptions: runs of
+// whitespace (including line breaks) become one space, and anything past
+// `max_chars` characters is cut off with an ellipsis
+pub fn summary(text: &str, max_chars: usize) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    excerpt(&collapsed, "", max_chars)
+}
+
+// Escape a search snippet and turn its match markers into <mark> elements.
+// Stray markers (which could come from user text) never produce unbalanced tags.
+pub fn highlight_html(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    let mut open = false;
+    for part in snippet.split_inclusive([MATCH_START, MATCH_END]) {
+        let (text, marker) = match part.chars().last() {
+            Some(c @ (MATCH_START | MATCH_END)) => (&part[..part.len() - c.len_utf8()], Some(c)),
+            _ => (part, None),
+        };
+        html.push_str(&escape_html(text));
+        match marker {
+            Some(MATCH_START) if !open => {
+                html.push_str("<mark>");
+                open = true;
+            }
+            Some(MATCH_END) if open => {
+                html.push_str("</mark>");
+                open = false;
+            }
+            _ => {}
+        }
+    }
+    if open {
+        html.push_str("</mark>");
+    }
+    html
+}
+
+// A search snippet as plain text, without match markers
+pub fn strip_match_markers(snippet: &str) -> String {
+    snippet.replace([MATCH_START, MATCH_END], "")
+}
+
+// Percent-encode a value for use in a URL query string
+pub fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// `path` as an absolute URL under `public_url`; URLs that are already absolute
+// (such as media on S3) are returned unchanged
+pub fn absolute_url(public_url: &str, path: &str) -> String {
+    if path.starts_with("http://") || path.starts_with("https://") {
+        path.to_string()
+    } else {
+        format!("{}/{}", public_url, path.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_in_posts_is_escaped() {
+        let text = r#"<script>alert("x")</script> & 'quoted' <b>bold</b> 1 > 0"#;
+        let escaped = "&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; &#39;quoted&#39; &lt;b&gt;bold&lt;/b&gt; 1 &gt; 0";
+        assert_eq!(escape_html(text), escaped);
+        let comment = escape_comment(&format!("{}\r\n<img src=x onerror=alert(1)>", text), 0);
+        assert_eq!(
+            comment,
+            format!("{}<br>&lt;img src=x onerror=alert(1)&gt;", escaped)
+        );
+        assert!(
+            !comment.contains("<script") && !comment.contains("<img") && !comment.contains("<b>")
+        );
+    }
+
+    #[test]
+    fn excerpt_cuts_on_character_boundaries() {
+        // Each of these characters is several bytes long, so cutting by byte
+        // count would land inside one and panic
+        assert_eq!(excerpt("héllo wörld", "", 4), "héll…");
+        assert_eq!(excerpt("日本語のテキスト", "", 3), "日本語…");
+        assert_eq!(excerpt("🦀🦀🦀", "", 2), "🦀🦀…");
+        assert_eq!(excerpt("e\u{301}e\u{301}", "", 1), "e…");
+    }
+
+    #[test]
+    fn excerpt_keeps_short_text_whole() {
+        assert_eq!(excerpt("日本語", "", 3), "日本語");
+        assert_eq!(excerpt("", "", 10), "");
+    }
+
+    #[test]
+    fn excerpt_centres_on_multibyte_match() {
+        let text = format!("{}needle ü{}", "ä".repeat(100), "ö".repeat(100));
+        let cut = excerpt(&text, "NEEDLE Ü", 40);
+        assert!(cut.starts_with('…') && cut.ends_with('…'));
+        assert!(cut.contains("needle ü"));
+        assert_eq!(cut.chars().count(), 42);
+    }
+
+    #[test]
+    fn summary_normalizes_whitespace() {
+        assert_eq!(
+            summary("  first line\r\n\n\tsecond   line ", 200),
+            "first line second line"
+        );
+        assert_eq!(summary("ünï  cödé\nwörds", 8), "ünï cödé…");
+    }
+
+    #[test]
+    fn time_ago_rounds_down() {
+        let now = 1_700_000_000;
+        assert_eq!(time_ago(now - 5, now), "just now");
+        assert_eq!(time_ago(now + 30, now), "just now");
+        assert_eq!(time_ago(now - 45, now), "45 seconds ago");
+        assert_eq!(time_ago(now - 119, now), "1 minute ago");
+        assert_eq!(time_ago(now - 5 * 60, now), "5 minutes ago");
+        assert_eq!(time_ago(now - 3 * 3600 - 59, now), "3 hours ago");
+        assert_eq!(time_ago(now - 86_400, now), "1 day ago");
+        assert_eq!(time_ago(now - 29 * 86_400, now), "29 days ago");
+        assert_eq!(time_ago(now - 40 * 86_400, now), "on 2023-10-05");
+    }
+
+    #[test]
+    fn formats_in_the_display_timezone() {
+        let june = 1_717_424_520;
+        assert_eq!(format_in(june, Utc.fix()), "2024-06-03 14:22 UTC");
+        assert_eq!(
+            format_in(june, parse_timezone("+02:00").unwrap()),
+            "2024-06-03 16:22 UTC+02:00"
+        );
+        assert_eq!(
+            format_in(june, parse_timezone("UTC-05:30").unwrap()),
+            "2024-06-03 08:52 UTC-05:30"
+        );
+        assert_eq!(parse_timezone("utc"), Some(Utc.fix()));
+        assert_eq!(parse_timezone("Europe/Berlin"), None);
+    }
+
+    #[test]
+    fn absolute_url_prefixes_local_paths_only() {
+        assert_eq!(
+            absolute_url("https://example.com", "/uploads/a.png"),
+            "https://example.com/uploads/a.png"
+        );
+        assert_eq!(
+            absolute_url("https://example.com", "https://cdn.test/a.png"),
+            "https://cdn.test/a.png"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+// Reader reports on articles and comments, and the admin queue that works
+// through them
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpResponse,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::admin::AdminUser;
+use crate::article::{article_on_board, soft_delete};
+use crate::board::{board_base, Board};
+use crate::client_ip::ClientIp;
+use crate::config::Config;
+use crate::csrf::CsrfToken;
+use crate::db::{ArticleRepository, NewReportRow, ReportRow};
+use crate::page_cache::PageCache;
+use crate::render::{format_timestamp, summary};
+use crate::slug::article_path;
+use crate::templates::{render_html, AdminReportsContext, MessageContext, ReportedItem};
+use crate::{ArticlePath, CommentPath, EXCERPT_CHARS};
+
+const MAX_REASON_CHARS: usize = 500;
+
+#[derive(Deserialize)]
+pub struct ReportForm {
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReportPath {
+    id: i32,
+}
+
+// POST /articles/{id}/report
+pub async fn report_article(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    client_ip: ClientIp,
+    path: web::Path<ArticlePath>,
+    form: web::Form<ReportForm>,
+) -> HttpResponse {
+    file_report(
+        &board,
+        repo.get_ref(),
+        &client_ip,
+        path.id,
+        None,
+        &form.reason,
+    )
+    .await
+}
+
+// POST /articles/{article_id}/comments/{comment_id}/report
+pub async fn report_comment(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    client_ip: ClientIp,
+    path: web::Path<CommentPath>,
+    form: web::Form<ReportForm>,
+) -> HttpResponse {
+    let CommentPath {
+        article_id,
+        comment_id,
+    } = path.into_inner();
+    file_report(
+        &board,
+        repo.get_ref(),
+        &client_ip,
+        article_id,
+        Some(comment_id),
+        &form.reason,
+    )
+    .await
+}
+
+async fn file_report(
+    board: &Board,
+    repo: &dyn ArticleRepository,
+    client_ip: &ClientIp,
+    article_id: i32,
+    comment_id: Option<i32>,
+    reason: &str,
+) -> HttpResponse {
+    let reason = reason.trim();
+    if reason.chars().count() > MAX_REASON_CHARS {
+        return HttpResponse::BadRequest().body(format!(
+            "Reasons are limited to {} characters",
+            MAX_REASON_CHARS
+        ));
+    }
+
+    let exists = match (article_on_board(repo, board, article_id).await, comment_id) {
+        (Ok(true), Some(comment_id)) => repo.comment_exists(article_id, comment_id).await,
+        (found, _) => found,
+    };
+    match exists {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body("Nothing to report here"),
+        Err(e) => {
+            error!(article_id, error = %e, "Failed to look up reported content");
+            return HttpResponse::InternalServerError().body("Failed to send report.");
+        }
+    }
+
+    // A repeat report is thanked like the first, it just isn't stored twice
+    let reporter_ip = client_ip.0.map(|ip| ip.to_string());
+    if let Err(e) = repo
+        .insert_report(NewReportRow {
+            article_id,
+            comment_id,
+            reason,
+            reporter_ip: reporter_ip.as_deref(),
+            created_at: Utc::now().timestamp(),
+        })
+        .await
+    {
+        error!(article_id, error = %e, "Failed to store report");
+        return HttpResponse::InternalServerError().body("Failed to send report.");
+    }
+
+    render_html(
+        StatusCode::OK,
+        &MessageContext {
+            title: "Report Sent",
+            message: "Thanks, a moderator will take a look.",
+            link_href: &format!("{}/articles/{}", board.base, article_id),
+            link_text: "Back to the article",
+        },
+    )
+}
+
+// Reports on the same article or comment are shown together, in the order the
+// first of them came in
+fn group_reports(rows: Vec<ReportRow>, default_board: &str) -> Vec<ReportedItem> {
+    let mut items: Vec<ReportedItem> = Vec::new();
+    for row in rows {
+        let target = (row.article_id, row.comment_id);
+        match items.iter_mut().find(|item| item.target == target) {
+            Some(item) => {
+                if !row.reason.is_empty() {
+                    item.reasons.push(row.reason);
+                }
+                item.count += 1;
+            }
+            None => items.push(ReportedItem {
+                target,
+                report_id: row.id,
+                href: format!(
+                    "{}{}",
+                    board_base(&row.board_slug, default_board),
+                    article_path(row.article_id, row.article_slug.as_deref())
+                ),
+                article_title: row.article_title,
+                comment_excerpt: row.comment.map(|c| summary(&c, EXCERPT_CHARS)),
+                reasons: if row.reason.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![row.reason]
+                },
+                count: 1,
+                first_reported: format_timestamp(row.created_at),
+            }),
+        }
+    }
+    items
+}
+
+// GET /admin/reports
+pub async fn reports_page(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    config: web::Data<Config>,
+    csrf: CsrfToken,
+) -> HttpResponse {
+    let rows = match repo.open_reports().await {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!(error = %e, "Failed to fetch reports");
+            return HttpResponse::InternalServerError().body("Failed to load reports");
+        }
+    };
+    render_html(
+        StatusCode::OK,
+        &AdminReportsContext {
+            csrf_token: &csrf.0,
+            items: group_reports(rows, &config.default_board),
+        },
+    )
+}
+
+fn back_to_reports() -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, "/admin/reports"))
+        .finish()
+}
+
+async fn report_target(
+    repo: &dyn ArticleRepository,
+    report_id: i32,
+) -> Result<(i32, Option<i32>), HttpResponse> {
+    match repo.report_target(report_id).await {
+        Ok(Some(target)) => Ok(target),
+        Ok(None) => Err(HttpResponse::NotFound().body("Report not found")),
+        Err(e) => {
+            error!(report_id, error = %e, "Failed to look up report");
+            Err(HttpResponse::InternalServerError().body("Failed to load report"))
+        }
+    }
+}
+
+// POST /admin/reports/{id}/dismiss: close every open report on the same
+// target, leaving the content alone
+pub async fn dismiss_report(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    path: web::Path<ReportPath>,
+) -> HttpResponse {
+    let (article_id, comment_id) = match report_target(repo.get_ref(), path.id).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    if let Err(e) = repo
+        .resolve_reports(article_id, comment_id, Utc::now().timestamp())
+        .await
+    {
+        error!(report_id = path.id, error = %e, "Failed to dismiss report");
+        return HttpResponse::InternalServerError().body("Failed to dismiss report");
+    }
+    back_to_reports()
+}
+
+// POST /admin/reports/{id}/delete: delete the reported article or comment
+// (restorable from /admin/deleted) and close its reports
+pub async fn delete_reported(
+    _admin: AdminUser,
+    repo: web::Data<dyn ArticleRepository>,
+    page_cache: web::Data<PageCache>,
+    path: web::Path<ReportPath>,
+) -> HttpResponse {
+    let (article_id, comment_id) = match report_target(repo.get_ref(), path.id).await {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+    if let Err(e) = soft_delete(repo.get_ref(), &page_cache, article_id, comment_id).await {
+        error!(report_id = path.id, article_id, error = %e, "Failed to delete reported content");
+        return HttpResponse::InternalServerError().body("Failed to delete content");
+    }
+    if let Err(e) = repo
+        .resolve_reports(article_id, comment_id, Utc::now().timestamp())
+        .await
+    {
+        error!(report_id = path.id, article_id, error = %e, "Failed to close reports on deleted content");
+        return HttpResponse::InternalServerError().body("Failed to close reports");
+    }
+    back_to_reports()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i32, article_id: i32, comment_id: Option<i32>, reason: &str) -> ReportRow {
+        ReportRow {
+            id,
+            article_id,
+            comment_id,
+            reason: reason.to_string(),
+            created_at: 0,
+            board_slug: "main".to_string(),
+            article_title: "Title".to_string(),
+            article_slug: Some("title".to_string()),
+            comment: comment_id.map(|_| "A comment".to_string()),
+        }
+    }
+
+    #[test]
+    fn groups_reports_by_target() {
+        let items = group_reports(
+            vec![
+                row(1, 5, None, "spam"),
+                row(2, 5, Some(9), ""),
+                row(3, 5, None, "off topic"),
+                row(4, 5, None, ""),
+            ],
+            "main",
+        );
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].report_id, 1);
+        assert_eq!(items[0].count, 3);
+        assert_eq!(items[0].reasons, vec!["spam", "off topic"]);
+        assert_eq!(items[0].href, "/articles/5/title");
+        assert_eq!(items[1].comment_excerpt.as_deref(), Some("A comment"));
+    }
+}
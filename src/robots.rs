@@ -0,0 +1,48 @@
+// /robots.txt, built from ROBOTS_DISALLOW: crawlers may fetch everything
+// unless paths are listed there. Like the probes it sets no cookie, so it can
+// be cached anywhere for a day.
+
+use actix_web::{http::header::CACHE_CONTROL, web, HttpResponse};
+
+use crate::config::Config;
+
+pub const PATH: &str = "/robots.txt";
+
+pub async fn robots_txt(config: web::Data<Config>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .insert_header((CACHE_CONTROL, "public, max-age=86400"))
+        .body(render(&config.robots_disallow))
+}
+
+// There's no sitemap to name in a Sitemap: line yet
+fn render(disallow: &[String]) -> String {
+    let mut text = String::from("User-agent: *\n");
+    if disallow.is_empty() {
+        // An empty Disallow allows everything
+        text.push_str("Disallow:\n");
+    }
+    for path in disallow {
+        text.push_str(&format!("Disallow: {}\n", path));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everything_is_allowed_by_default() {
+        assert_eq!(render(&[]), "User-agent: *\nDisallow:\n");
+    }
+
+    #[test]
+    fn listed_paths_are_disallowed() {
+        let disallow = ["/admin".to_string(), "/uploads".to_string()];
+        assert_eq!(
+            render(&disallow),
+            "User-agent: *\nDisallow: /admin\nDisallow: /uploads\n"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+// HMAC-SHA256 signatures over short strings, keyed by SECRET_KEY. The purpose
+// is mixed in so a value signed for one use is never accepted for another.
+
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+use std::fmt::Write as _;
+
+fn mac(secret: &[u8], purpose: &str, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes any key length");
+    mac.update(purpose.as_bytes());
+    mac.update(b":");
+    mac.update(message.as_bytes());
+    mac
+}
+
+// Raw signature of `message`
+pub fn digest(secret: &[u8], purpose: &str, message: &str) -> Vec<u8> {
+    mac(secret, purpose, message)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+}
+
+// Hex-encoded signature of `message`
+pub fn sign(secret: &[u8], purpose: &str, message: &str) -> String {
+    let mut signature = String::new();
+    for byte in digest(secret, purpose, message) {
+        let _ = write!(signature, "{:02x}", byte);
+    }
+    signature
+}
+
+// Constant-time check of a signature made by `sign`
+pub fn verify(secret: &[u8], purpose: &str, message: &str, signature: &str) -> bool {
+    match decode_hex(signature) {
+        Some(bytes) => mac(secret, purpose, message).verify_slice(&bytes).is_ok(),
+        None => false,
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// A 64-byte key for another library (e.g. cookie encryption) derived from the
+// secret, so SECRET_KEY can be any length
+pub fn derive_key(secret: &[u8], purpose: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(secret).expect("HMAC takes any key length");
+    mac.update(purpose.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,136 @@
+// URL slugs generated from article titles
+
+const MAX_SLUG_CHARS: usize = 60;
+
+// Slugs that would collide with fixed routes under /articles/{id}/
+const RESERVED: &[&str] = &["edit"];
+
+// The ASCII spelling of common accented Latin letters; other non-ASCII
+// characters are dropped
+fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'ç' | 'ć' | 'č' | 'ĉ' | 'ċ' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+        'ţ' | 'ť' | 'ŧ' | 'ț' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'þ' => "th",
+        _ => return None,
+    })
+}
+
+// Lowercase, dash-separated slug for a title, or None when nothing usable is
+// left (e.g. a title written entirely in a non-Latin script)
+pub fn slugify(title: &str) -> Option<String> {
+    let mut slug = String::new();
+    let mut pending_dash = false;
+    for c in title.chars().flat_map(char::to_lowercase) {
+        let piece = if c.is_ascii_alphanumeric() {
+            Some(c.encode_utf8(&mut [0; 4]).to_string())
+        } else {
+            transliterate(c).map(str::to_string)
+        };
+        match piece {
+            Some(piece) => {
+                if pending_dash && !slug.is_empty() {
+                    slug.push('-');
+                }
+                pending_dash = false;
+                slug.push_str(&piece);
+            }
+            None => pending_dash = true,
+        }
+    }
+
+    // Long titles are cut at a word boundary where there is one
+    if slug.len() > MAX_SLUG_CHARS {
+        let cut = slug[..MAX_SLUG_CHARS].rfind('-').unwrap_or(MAX_SLUG_CHARS);
+        slug.truncate(cut);
+    }
+    (!slug.is_empty()).then_some(slug)
+}
+
+// `base`, or `base` with the smallest numeric suffix from 2 up that isn't in
+// `taken` or reserved for a route
+pub fn dedupe(base: &str, taken: &[String]) -> String {
+    let free =
+        |candidate: &str| !RESERVED.contains(&candidate) && !taken.iter().any(|t| t == candidate);
+    if free(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|candidate| free(candidate))
+        .unwrap_or_default()
+}
+
+// An article's canonical path relative to its board
+pub fn article_path(article_id: i32, slug: Option<&str>) -> String {
+    match slug {
+        Some(slug) => format!("/articles/{}/{}", article_id, slug),
+        None => format!("/articles/{}", article_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify_joins_words_with_dashes() {
+        assert_eq!(slugify("Hello, World!").as_deref(), Some("hello-world"));
+        assert_eq!(
+            slugify("  --Rust 2024: what's new?--  ").as_deref(),
+            Some("rust-2024-what-s-new")
+        );
+    }
+
+    #[test]
+    fn slugify_transliterates_latin_letters() {
+        assert_eq!(
+            slugify("Crème Brûlée à la Straße").as_deref(),
+            Some("creme-brulee-a-la-strasse")
+        );
+        assert_eq!(slugify("ŁÓDŹ").as_deref(), Some("lodz"));
+    }
+
+    #[test]
+    fn slugify_gives_up_on_other_scripts() {
+        assert_eq!(slugify("日本語のタイトル"), None);
+        assert_eq!(slugify("🦀 !!"), None);
+        assert_eq!(slugify("Привет world").as_deref(), Some("world"));
+    }
+
+    #[test]
+    fn slugify_cuts_long_titles_at_a_dash() {
+        let slug = slugify(&"word ".repeat(30)).unwrap();
+        assert!(slug.len() <= MAX_SLUG_CHARS);
+        assert!(slug.ends_with("word"));
+        assert_eq!(slugify(&"x".repeat(100)).unwrap().len(), MAX_SLUG_CHARS);
+    }
+
+    #[test]
+    fn dedupe_appends_the_first_free_suffix() {
+        let taken = vec!["post".to_string(), "post-2".to_string()];
+        assert_eq!(dedupe("post", &taken), "post-3");
+        assert_eq!(dedupe("other", &taken), "other");
+        assert_eq!(dedupe("edit", &[]), "edit-2");
+    }
+}
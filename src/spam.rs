@@ -0,0 +1,161 @@
+// Cheap checks against form-filling bots: a honeypot field that people never
+// see, and a signed timestamp rendered into each form so posts sent back too
+// quickly (or long after the form was made) can be told apart
+
+use actix_web::{http::StatusCode, HttpResponse};
+use chrono::Utc;
+
+use crate::config::Config;
+use crate::signing;
+use crate::templates::{render_html, MessageContext};
+
+// Name of the honeypot input; CSS keeps it out of sight, so anything in it
+// was filled in by a bot
+pub const HONEYPOT_FIELD: &str = "website";
+
+// Forms older than this are refused, which also stops tokens being harvested
+// once and replayed forever
+const MAX_FORM_AGE_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormTokenError {
+    // Missing, garbled, or signed with a different key
+    Invalid,
+    TooFast,
+    Expired,
+}
+
+// "{issued_at}.{signature}"
+pub fn form_token(secret: &[u8], now: i64) -> String {
+    format!(
+        "{}.{}",
+        now,
+        signing::sign(secret, "form", &now.to_string())
+    )
+}
+
+// Check a token from a submitted form that is meant to have been open for at
+// least `min_fill_secs`
+pub fn check_form_token(
+    secret: &[u8],
+    token: &str,
+    now: i64,
+    min_fill_secs: i64,
+) -> Result<(), FormTokenError> {
+    let (issued_at, signature) = token.split_once('.').ok_or(FormTokenError::Invalid)?;
+    if !signing::verify(secret, "form", issued_at, signature) {
+        return Err(FormTokenError::Invalid);
+    }
+    let issued_at: i64 = issued_at.parse().map_err(|_| FormTokenError::Invalid)?;
+
+    let age = now - issued_at;
+    if age < min_fill_secs {
+        Err(FormTokenError::TooFast)
+    } else if age > MAX_FORM_AGE_SECS {
+        Err(FormTokenError::Expired)
+    } else {
+        Ok(())
+    }
+}
+
+// Whether a submission passes both checks. Callers answer every failure the
+// same way so bots can't tell which one they tripped.
+pub fn looks_human(
+    secret: &[u8],
+    honeypot: &str,
+    token: &str,
+    now: i64,
+    min_fill_secs: i64,
+) -> bool {
+    honeypot.is_empty() && check_form_token(secret, token, now, min_fill_secs).is_ok()
+}
+
+// Whether a form submission gets past the honeypot and fill-time checks
+pub fn passes_spam_checks(config: &Config, honeypot: &str, form_token: &str) -> bool {
+    looks_human(
+        &config.secret_key,
+        honeypot,
+        form_token,
+        Utc::now().timestamp(),
+        config.form_min_fill_secs,
+    )
+}
+
+// Deliberately vague about which check failed
+pub fn spam_rejected(back: &str) -> HttpResponse {
+    render_html(
+        StatusCode::BAD_REQUEST,
+        &MessageContext {
+            title: "Post Rejected",
+            message: "Your post could not be accepted. Please reload the page and try again.",
+            link_href: back,
+            link_text: "Go back",
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn accepts_a_form_open_long_enough() {
+        let token = form_token(SECRET, NOW);
+        assert_eq!(check_form_token(SECRET, &token, NOW + 5, 3), Ok(()));
+        assert_eq!(
+            check_form_token(SECRET, &token, NOW + MAX_FORM_AGE_SECS, 3),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_fast_and_stale_forms() {
+        let token = form_token(SECRET, NOW);
+        assert_eq!(
+            check_form_token(SECRET, &token, NOW + 1, 3),
+            Err(FormTokenError::TooFast)
+        );
+        assert_eq!(
+            check_form_token(SECRET, &token, NOW + MAX_FORM_AGE_SECS + 1, 3),
+            Err(FormTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let token = form_token(SECRET, NOW);
+        let (_, signature) = token.split_once('.').unwrap();
+        // Backdating the timestamp invalidates the signature
+        let backdated = format!("{}.{}", NOW - 60, signature);
+        assert_eq!(
+            check_form_token(SECRET, &backdated, NOW, 3),
+            Err(FormTokenError::Invalid)
+        );
+        assert_eq!(
+            check_form_token(b"other secret", &token, NOW + 5, 3),
+            Err(FormTokenError::Invalid)
+        );
+        for garbage in ["", "123", "abc.def", "123.zz", "123.é"] {
+            assert_eq!(
+                check_form_token(SECRET, garbage, NOW, 3),
+                Err(FormTokenError::Invalid)
+            );
+        }
+    }
+
+    #[test]
+    fn a_filled_honeypot_fails() {
+        let token = form_token(SECRET, NOW);
+        assert!(looks_human(SECRET, "", &token, NOW + 5, 3));
+        assert!(!looks_human(
+            SECRET,
+            "http://spam.example",
+            &token,
+            NOW + 5,
+            3
+        ));
+    }
+}
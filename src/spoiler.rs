@@ -0,0 +1,145 @@
+// Spoilers: "[spoiler]text[/spoiler]" or "||text||" in an article or comment
+// is hidden in a span that CSS reveals on hover. Markers that don't pair up
+// (unclosed, closed out of order or nested too deeply) are shown as written.
+
+const OPEN_TAG: &str = "[spoiler]";
+const CLOSE_TAG: &str = "[/spoiler]";
+const BARS: &str = "||";
+// Spoilers inside spoilers inside spoilers are as deep as it goes
+const MAX_DEPTH: usize = 3;
+// Markers past this many in one text are left as text
+const MAX_MARKERS: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Marker {
+    OpenTag,
+    CloseTag,
+    Bars,
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Marker(Marker, &'a str),
+}
+
+fn scan(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    for _ in 0..MAX_MARKERS {
+        let found = [
+            (OPEN_TAG, Marker::OpenTag),
+            (CLOSE_TAG, Marker::CloseTag),
+            (BARS, Marker::Bars),
+        ]
+        .into_iter()
+        .filter_map(|(written, marker)| rest.find(written).map(|i| (i, written, marker)))
+        .min_by_key(|&(i, _, _)| i);
+        let Some((i, written, marker)) = found else {
+            break;
+        };
+        pieces.push(Piece::Text(&rest[..i]));
+        pieces.push(Piece::Marker(marker, &rest[i..i + written.len()]));
+        rest = &rest[i + written.len()..];
+    }
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+// Render `text` with its spoilers as spans. Everything else goes through
+// `escape`, which must return safe HTML; runs of text between spoiler
+// boundaries are passed to it whole.
+pub fn render(text: &str, mut escape: impl FnMut(&str) -> String) -> String {
+    let pieces = scan(text);
+
+    // Pair each close with the open it ends; anything unpaired stays text.
+    // "||" closes when the innermost open spoiler is a "||" one, and opens
+    // otherwise.
+    let mut spans = vec![None; pieces.len()];
+    let mut open: Vec<(usize, Marker)> = Vec::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        let Piece::Marker(marker, _) = *piece else {
+            continue;
+        };
+        let closes = match marker {
+            Marker::OpenTag => None,
+            Marker::CloseTag => Some(Marker::OpenTag),
+            Marker::Bars => Some(Marker::Bars),
+        };
+        match open.last() {
+            Some(&(start, top)) if Some(top) == closes => {
+                open.pop();
+                spans[start] = Some("<span class=\"spoiler\">");
+                spans[i] = Some("</span>");
+            }
+            _ if marker != Marker::CloseTag && open.len() < MAX_DEPTH => open.push((i, marker)),
+            _ => {}
+        }
+    }
+
+    let mut html = String::with_capacity(text.len());
+    let mut pending = String::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        match (piece, spans[i]) {
+            (Piece::Marker(_, _), Some(span)) => {
+                html.push_str(&escape(&pending));
+                pending.clear();
+                html.push_str(span);
+            }
+            (Piece::Text(written) | Piece::Marker(_, written), _) => pending.push_str(written),
+        }
+    }
+    html.push_str(&escape(&pending));
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::escape_html;
+
+    fn spoil(text: &str) -> String {
+        render(text, escape_html)
+    }
+
+    #[test]
+    fn hides_both_forms() {
+        assert_eq!(
+            spoil("the [spoiler]butler[/spoiler] did ||it||"),
+            "the <span class=\"spoiler\">butler</span> did <span class=\"spoiler\">it</span>"
+        );
+        assert_eq!(spoil("||<b>||"), "<span class=\"spoiler\">&lt;b&gt;</span>");
+    }
+
+    #[test]
+    fn nests_up_to_the_cap() {
+        assert_eq!(
+            spoil("[spoiler]a ||b|| c[/spoiler]"),
+            "<span class=\"spoiler\">a <span class=\"spoiler\">b</span> c</span>"
+        );
+        assert_eq!(
+            spoil("[spoiler][spoiler][spoiler][spoiler]x[/spoiler][/spoiler][/spoiler][/spoiler]"),
+            "<span class=\"spoiler\"><span class=\"spoiler\"><span class=\"spoiler\">[spoiler]x</span>\
+             </span></span>[/spoiler]"
+        );
+    }
+
+    #[test]
+    fn leaves_unpaired_markers_as_text() {
+        assert_eq!(spoil("[spoiler]never closed"), "[spoiler]never closed");
+        assert_eq!(spoil("a || b"), "a || b");
+        assert_eq!(spoil("closed [/spoiler] first"), "closed [/spoiler] first");
+        // Closed out of order: the "||" pair still works around the stray tag
+        assert_eq!(
+            spoil("[spoiler]a||b[/spoiler]c||"),
+            "[spoiler]a<span class=\"spoiler\">b[/spoiler]c</span>"
+        );
+    }
+
+    #[test]
+    fn stops_looking_after_enough_markers() {
+        let text = "||x||".repeat(MAX_MARKERS);
+        let html = spoil(&text);
+        assert_eq!(html.matches("<span").count(), MAX_MARKERS / 2);
+        assert!(html.ends_with(&"||x||".repeat(MAX_MARKERS / 2)));
+    }
+}
@@ -0,0 +1,104 @@
+// Local storage that keeps the storage_usage row up to date as files are
+// stored and removed, by this process or any other, and refuses uploads once
+// it reaches UPLOADS_QUOTA_BYTES
+
+use async_trait::async_trait;
+use futures_util::stream::StreamExt as _;
+use std::cell::Cell;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::error;
+
+use super::{ByteStream, MediaStorage};
+use crate::db::ArticleRepository;
+
+pub struct CountedStorage<S> {
+    inner: S,
+    repo: Arc<dyn ArticleRepository>,
+    // 0 for no quota
+    quota_bytes: u64,
+}
+
+impl<S> CountedStorage<S> {
+    pub fn new(inner: S, repo: Arc<dyn ArticleRepository>, quota_bytes: u64) -> Self {
+        CountedStorage {
+            inner,
+            repo,
+            quota_bytes,
+        }
+    }
+
+    // The file is there either way, so a failure only makes the count drift
+    // until serve next starts
+    async fn add(&self, bytes: i64) {
+        if let Err(e) = self.repo.add_storage_usage(bytes).await {
+            error!(bytes, error = %e, "Failed to update storage usage");
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<S: MediaStorage> MediaStorage for CountedStorage<S> {
+    async fn put(&self, key: &str, content_type: &str, data: ByteStream<'_>) -> io::Result<String> {
+        let written = Cell::new(0u64);
+        let counting = data.map(|chunk| {
+            if let Ok(chunk) = &chunk {
+                written.set(written.get() + chunk.len() as u64);
+            }
+            chunk
+        });
+        let url = self
+            .inner
+            .put(key, content_type, Box::pin(counting))
+            .await?;
+        self.add(written.get() as i64).await;
+        Ok(url)
+    }
+
+    async fn delete(&self, url: &str) -> io::Result<()> {
+        let size = self
+            .inner
+            .local_path(url)
+            .and_then(|path| path.metadata().ok())
+            .map(|m| m.len());
+        self.inner.delete(url).await?;
+        if let Some(size) = size {
+            self.add(-(size as i64)).await;
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, url: &str) -> io::Result<bool> {
+        self.inner.exists(url).await
+    }
+
+    async fn get(&self, url: &str) -> io::Result<Vec<u8>> {
+        self.inner.get(url).await
+    }
+
+    async fn check_writable(&self) -> io::Result<()> {
+        self.inner.check_writable().await
+    }
+
+    fn local_path(&self, url: &str) -> Option<PathBuf> {
+        self.inner.local_path(url)
+    }
+
+    async fn accepts_uploads(&self) -> io::Result<()> {
+        if self.quota_bytes == 0 {
+            return Ok(());
+        }
+        let used = self.repo.storage_usage().await.map_err(io::Error::other)?;
+        match used {
+            Some(used) if used as u64 >= self.quota_bytes => Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "{} bytes are stored, and UPLOADS_QUOTA_BYTES is {}",
+                    used, self.quota_bytes
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Files on local disk under UPLOADS_DIR, served by the /uploads Files service
+
+use async_trait::async_trait;
+use futures_util::stream::StreamExt as _;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::{ByteStream, MediaStorage};
+
+const URL_PREFIX: &str = "/uploads/";
+
+pub struct LocalStorage {
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(dir.join("thumbs"))?;
+        Ok(LocalStorage { dir })
+    }
+
+    // Map a key or the relative part of a URL to its file, refusing anything
+    // that would escape the uploads directory
+    fn file_path(&self, relative: &str) -> Option<PathBuf> {
+        let relative = Path::new(relative);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        Some(self.dir.join(relative))
+    }
+}
+
+// A file being written, removed when dropped unless kept
+struct PartialFile<'a>(Option<&'a Path>);
+
+impl PartialFile<'_> {
+    fn keep(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for PartialFile<'_> {
+    fn drop(&mut self) {
+        if let Some(path) = self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl MediaStorage for LocalStorage {
+    async fn put(
+        &self,
+        key: &str,
+        _content_type: &str,
+        mut data: ByteStream<'_>,
+    ) -> io::Result<String> {
+        let path = self.file_path(key).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid key {:?}", key),
+            )
+        })?;
+
+        // Removes the file unless it's written in full, including when this
+        // future is dropped part way, as when a shutdown cuts off the upload
+        let partial = PartialFile(Some(&path));
+        let mut f = File::create(&path)?;
+        while let Some(chunk) = data.next().await {
+            f.write_all(&chunk?)?;
+        }
+        partial.keep();
+        Ok(format!("{}{}", URL_PREFIX, key))
+    }
+
+    async fn delete(&self, url: &str) -> io::Result<()> {
+        match self.local_path(url) {
+            Some(path) => fs::remove_file(path),
+            None => Ok(()),
+        }
+    }
+
+    async fn exists(&self, url: &str) -> io::Result<bool> {
+        Ok(self.local_path(url).is_some_and(|path| path.is_file()))
+    }
+
+    async fn get(&self, url: &str) -> io::Result<Vec<u8>> {
+        let path = self.local_path(url).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a local upload", url),
+            )
+        })?;
+        fs::read(path)
+    }
+
+    // Writes and removes a file of its own, named so concurrent checks don't
+    // trip over each other
+    async fn check_writable(&self) -> io::Result<()> {
+        let path = self
+            .dir
+            .join(format!(".write-check-{}", uuid::Uuid::new_v4().simple()));
+        File::create(&path)?;
+        fs::remove_file(path)
+    }
+
+    fn local_path(&self, url: &str) -> Option<PathBuf> {
+        self.file_path(url.strip_prefix(URL_PREFIX)?)
+    }
+}
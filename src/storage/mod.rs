@@ -0,0 +1,85 @@
+// Where uploaded media and generated thumbnails are kept. STORAGE_BACKEND
+// picks the implementation; everything else only sees MediaStorage. Local
+// storage is counted against UPLOADS_QUOTA_BYTES, see counted.rs.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::LocalBoxStream;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::{Config, StorageConfig};
+use crate::db::ArticleRepository;
+
+mod counted;
+mod local;
+mod s3;
+
+pub use counted::CountedStorage;
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+// Upload bodies arrive from multipart fields, which are tied to their worker
+// thread, so streams and the futures consuming them aren't Send
+pub type ByteStream<'a> = LocalBoxStream<'a, io::Result<Bytes>>;
+
+#[async_trait(?Send)]
+pub trait MediaStorage: Send + Sync {
+    // Store `data` under `key` (e.g. "article_….png" or "thumbs/thumb_….png"),
+    // returning the URL it is served from. Nothing is left behind if the
+    // stream fails part way.
+    async fn put(&self, key: &str, content_type: &str, data: ByteStream<'_>) -> io::Result<String>;
+    // Remove what `put` stored at `url`; URLs this backend didn't hand out are ignored
+    async fn delete(&self, url: &str) -> io::Result<()>;
+    #[allow(dead_code)] // part of the backend contract, not called by the app yet
+    async fn exists(&self, url: &str) -> io::Result<bool>;
+    // Read an object back in full, for post-processing such as poster frames
+    async fn get(&self, url: &str) -> io::Result<Vec<u8>>;
+
+    // Whether new uploads can be stored, for the readiness check. Only local
+    // storage looks; a bucket's availability is its provider's to report.
+    async fn check_writable(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Whether there's room for another upload; StorageFull when there isn't
+    async fn accepts_uploads(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // The file behind `url` when it is on local disk, so tools like ffmpeg can
+    // read it in place
+    fn local_path(&self, _url: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub fn from_config(
+    config: &Config,
+    repo: Arc<dyn ArticleRepository>,
+) -> Result<Arc<dyn MediaStorage>, String> {
+    match &config.storage {
+        StorageConfig::Local => {
+            let storage = LocalStorage::new(config.uploads_dir.clone())
+                .map_err(|e| format!("Failed to create {}: {}", config.uploads_dir.display(), e))?;
+            Ok(Arc::new(CountedStorage::new(
+                storage,
+                repo,
+                config.uploads_quota_bytes,
+            )))
+        }
+        StorageConfig::S3(s3_config) => {
+            let storage = S3Storage::new(s3_config)
+                .map_err(|e| format!("Failed to configure S3 storage: {}", e))?;
+            Ok(Arc::new(storage))
+        }
+    }
+}
+
+// A stream that yields `data` as a single chunk
+pub fn once(data: Vec<u8>) -> ByteStream<'static> {
+    Box::pin(futures_util::stream::once(
+        async move { Ok(Bytes::from(data)) },
+    ))
+}
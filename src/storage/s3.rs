@@ -0,0 +1,111 @@
+// Objects in an S3 bucket or an S3-compatible service
+
+use async_trait::async_trait;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use std::io;
+use tokio_util::io::StreamReader;
+
+use super::{ByteStream, MediaStorage};
+use crate::config::S3Config;
+
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+    // Prefix of every URL handed out, without a trailing slash
+    public_url: String,
+}
+
+impl S3Storage {
+    pub fn new(config: &S3Config) -> Result<Self, S3Error> {
+        let credentials = Credentials::new(
+            Some(&config.access_key),
+            Some(&config.secret_key),
+            None,
+            None,
+            None,
+        )?;
+
+        let (bucket, default_url) = match &config.endpoint {
+            Some(endpoint) => {
+                let region = Region::Custom {
+                    region: config.region.clone(),
+                    endpoint: endpoint.clone(),
+                };
+                let bucket = Bucket::new(&config.bucket, region, credentials)?.with_path_style();
+                let url = format!("{}/{}", endpoint.trim_end_matches('/'), config.bucket);
+                (bucket, url)
+            }
+            None => {
+                let region: Region = config.region.parse()?;
+                let bucket = Bucket::new(&config.bucket, region, credentials)?;
+                let url = format!(
+                    "https://{}.s3.{}.amazonaws.com",
+                    config.bucket, config.region
+                );
+                (bucket, url)
+            }
+        };
+
+        let public_url = config
+            .public_url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or(default_url);
+        Ok(S3Storage { bucket, public_url })
+    }
+
+    // The object key behind one of our URLs
+    fn key<'a>(&self, url: &'a str) -> Option<&'a str> {
+        url.strip_prefix(self.public_url.as_str())?
+            .strip_prefix('/')
+    }
+}
+
+fn to_io(e: S3Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+#[async_trait(?Send)]
+impl MediaStorage for S3Storage {
+    async fn put(&self, key: &str, content_type: &str, data: ByteStream<'_>) -> io::Result<String> {
+        // Multipart uploads are aborted by the client when the stream fails,
+        // so a rejected upload never becomes a visible object
+        let mut reader = StreamReader::new(data);
+        self.bucket
+            .put_object_stream_with_content_type(&mut reader, key, content_type)
+            .await
+            .map_err(to_io)?;
+        Ok(format!("{}/{}", self.public_url, key))
+    }
+
+    async fn delete(&self, url: &str) -> io::Result<()> {
+        let Some(key) = self.key(url) else {
+            return Ok(());
+        };
+        self.bucket.delete_object(key).await.map_err(to_io)?;
+        Ok(())
+    }
+
+    async fn exists(&self, url: &str) -> io::Result<bool> {
+        let Some(key) = self.key(url) else {
+            return Ok(false);
+        };
+        match self.bucket.head_object(key).await {
+            Ok((_, status)) => Ok(status == 200),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(false),
+            Err(e) => Err(to_io(e)),
+        }
+    }
+
+    async fn get(&self, url: &str) -> io::Result<Vec<u8>> {
+        let key = self.key(url).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in this bucket", url),
+            )
+        })?;
+        let response = self.bucket.get_object(key).await.map_err(to_io)?;
+        Ok(response.to_vec())
+    }
+}
@@ -0,0 +1,427 @@
+// Askama templates (see templates/) and the contexts they render from
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use askama::Template;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::board::Board;
+use crate::db::{ArticleSort, RelatedArticle, SearchScope};
+use crate::error::AppError;
+use crate::render::{url_encode, DisplayTime};
+use crate::slug::article_path;
+use crate::{Article, Media, DEFAULT_PER_PAGE};
+
+// The listing of a board's articles carrying `tag`
+fn tag_href(board: &Board, tag: &str) -> String {
+    format!("{}/tags/{}", board.base, url_encode(tag))
+}
+
+// Render a template into an HTML response, mapping failures to a 500
+pub fn render_html(status: StatusCode, template: &impl Template) -> HttpResponse {
+    match template.render() {
+        Ok(html) => HttpResponse::build(status)
+            .content_type("text/html")
+            .body(html),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+pub struct BoardListItem {
+    // The board's article list
+    pub href: String,
+    pub title: String,
+    pub description: String,
+    pub article_count: i64,
+}
+
+#[derive(Template)]
+#[template(path = "boards.html")]
+pub struct BoardIndexContext {
+    pub boards: Vec<BoardListItem>,
+}
+
+#[derive(Template)]
+#[template(path = "new_article.html")]
+pub struct NewArticleContext<'a> {
+    pub board: &'a Board,
+    pub csrf_token: &'a str,
+    pub max_upload: String,
+    // Token of the captcha to show, when captchas are enabled
+    pub captcha: Option<String>,
+    // Signed timestamp checked on submission, see spam.rs
+    pub form_token: String,
+    // Set when a submission is sent back, along with what was entered
+    pub error: Option<&'a str>,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub name: &'a str,
+    pub tags: &'a str,
+}
+
+pub struct ArticleListItem {
+    // Canonical path within the board, see slug::article_path
+    pub path: String,
+    pub title: String,
+    // The start of the body as plain text
+    pub excerpt: String,
+    // The first of the article's media as a picture, if it has one
+    pub thumb: Option<String>,
+    // The first media is a video still waiting for its poster frame
+    pub video: bool,
+    // The first media is audio, which has no picture
+    pub audio: bool,
+    pub tags: Vec<String>,
+    pub sticky: bool,
+    pub comment_count: i64,
+    // The latest comment, or the article itself when it has none
+    pub last_activity: DisplayTime,
+}
+
+#[derive(Template)]
+#[template(path = "articles.html")]
+pub struct ArticleListContext<'a> {
+    pub board: &'a Board,
+    // Set when only articles with this tag are listed
+    pub tag: Option<&'a str>,
+    // Set for the archive instead of the current articles
+    pub archived: bool,
+    // A message left by the request that redirected here
+    pub flash: Option<&'a str>,
+    pub sort: ArticleSort,
+    pub articles: Vec<ArticleListItem>,
+    pub page: i64,
+    pub total_pages: i64,
+    pub per_page: i64,
+}
+
+// A page of an article list at `base`, leaving out parameters at their defaults
+fn list_href(base: &str, page: i64, per_page: i64, sort: ArticleSort) -> String {
+    let mut href = format!("{}?page={}", base, page);
+    if per_page != DEFAULT_PER_PAGE {
+        href.push_str(&format!("&per_page={}", per_page));
+    }
+    if sort != ArticleSort::default() {
+        href.push_str(&format!("&sort={}", sort.as_str()));
+    }
+    href
+}
+
+impl ArticleListContext<'_> {
+    fn base(&self) -> String {
+        match self.tag {
+            Some(tag) => tag_href(self.board, tag),
+            None if self.archived => format!("{}/archive", self.board.base),
+            None => format!("{}/articles", self.board.base),
+        }
+    }
+
+    fn page_href(&self, page: i64) -> String {
+        list_href(&self.base(), page, self.per_page, self.sort)
+    }
+
+    // The first page in another order
+    fn sort_href(&self, sort: &ArticleSort) -> String {
+        list_href(&self.base(), 1, self.per_page, *sort)
+    }
+
+    fn tag_href(&self, tag: &str) -> String {
+        tag_href(self.board, tag)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "catalog.html")]
+pub struct CatalogContext<'a> {
+    pub board: &'a Board,
+    pub sort: ArticleSort,
+    pub articles: Vec<ArticleListItem>,
+    pub page: i64,
+    pub total_pages: i64,
+    pub per_page: i64,
+}
+
+impl CatalogContext<'_> {
+    fn page_href(&self, page: i64) -> String {
+        list_href(
+            &format!("{}/catalog", self.board.base),
+            page,
+            self.per_page,
+            self.sort,
+        )
+    }
+
+    fn sort_href(&self, sort: &ArticleSort) -> String {
+        list_href(
+            &format!("{}/catalog", self.board.base),
+            1,
+            self.per_page,
+            *sort,
+        )
+    }
+}
+
+#[derive(Template)]
+#[template(path = "trending.html")]
+pub struct TrendingContext<'a> {
+    pub board: &'a Board,
+    // No article was discussed lately, so these are the newest instead
+    pub newest: bool,
+    pub articles: Vec<ArticleListItem>,
+}
+
+pub struct SearchResult {
+    pub article_id: i32,
+    // Canonical path of the article within the board
+    pub path: String,
+    pub title: String,
+    // Set when the match is in a comment
+    pub comment_id: Option<i32>,
+    pub archived: bool,
+    pub snippet_html: String,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchContext<'a> {
+    pub board: &'a Board,
+    // Empty when only the form should be shown
+    pub query: &'a str,
+    pub scope: SearchScope,
+    pub results: Vec<SearchResult>,
+    pub total: i64,
+    pub page: i64,
+    pub total_pages: i64,
+    pub per_page: i64,
+}
+
+impl SearchContext<'_> {
+    fn in_comments(&self) -> bool {
+        self.scope == SearchScope::Comments
+    }
+
+    fn page_href(&self, page: i64) -> String {
+        let mut href = format!(
+            "{}/search?q={}&page={}",
+            self.board.base,
+            url_encode(self.query),
+            page
+        );
+        if self.scope == SearchScope::Comments {
+            href.push_str("&scope=comments");
+        }
+        if self.per_page != DEFAULT_PER_PAGE {
+            href.push_str(&format!("&per_page={}", self.per_page));
+        }
+        href
+    }
+}
+
+// A link to a comment, which may be on another page of the article
+pub struct CommentLink {
+    pub id: i32,
+    pub href: String,
+}
+
+pub struct CommentView {
+    pub id: i32,
+    pub body_html: String,
+    pub name: String,
+    pub tripcode: Option<String>,
+    // Shown above the text
+    pub media: Vec<Media>,
+    pub created_at: DisplayTime,
+    // Ids of the comments quoting this one
+    pub replies: Vec<i32>,
+    // Indentation, up to thread::MAX_DEPTH
+    pub depth: usize,
+    // The comment this one replies to, when it isn't the one above: the reply
+    // is nested too deep, or the parent is on an earlier page
+    pub parent: Option<CommentLink>,
+    // Shown as a "[deleted]" placeholder above its replies
+    pub deleted: bool,
+}
+
+#[derive(Template)]
+#[template(path = "article.html")]
+pub struct ArticlePageContext<'a> {
+    pub board: &'a Board,
+    pub article: &'a Article,
+    // For the meta description, Open Graph tags and canonical link
+    pub description: String,
+    pub url: String,
+    pub image_url: Option<String>,
+    pub body_html: String,
+    pub posted_at: DisplayTime,
+    pub edited_at: Option<String>,
+    // Archived articles show no comment form
+    pub archived_at: Option<String>,
+    // One page of comments, page numbers starting at 1
+    pub comments: Vec<CommentView>,
+    pub comments_page: i64,
+    pub comment_pages: i64,
+    // The comment the form replies to
+    pub reply_to: Option<CommentLink>,
+    // Show the sticky toggle
+    pub admin: bool,
+    // New comments no longer bump the article
+    pub bump_limit_reached: bool,
+    // Token of the comment form's captcha, when captchas are enabled
+    pub captcha: Option<String>,
+    // Signed timestamp checked on submission, see spam.rs
+    pub form_token: String,
+    pub csrf_token: &'a str,
+    // Set when a comment is sent back, along with what was entered
+    pub comment_error: Option<&'a str>,
+    pub comment: &'a str,
+    pub name: &'a str,
+    // Listed at the bottom when there are any
+    pub related: Vec<RelatedArticle>,
+    // Content hashes by media path, shown to admins only
+    pub media_hashes: HashMap<String, String>,
+}
+
+impl ArticlePageContext<'_> {
+    fn media_hash(&self, media: &Media) -> Option<&str> {
+        self.media_hashes.get(&media.media_path).map(String::as_str)
+    }
+
+    fn tag_href(&self, tag: &str) -> String {
+        tag_href(self.board, tag)
+    }
+
+    fn related_href(&self, related: &RelatedArticle) -> String {
+        format!(
+            "{}{}",
+            self.board.base,
+            article_path(related.id, related.slug.as_deref())
+        )
+    }
+
+    fn comments_page_href(&self, page: i64) -> String {
+        format!(
+            "{}{}?comments_page={}",
+            self.board.base,
+            article_path(self.article.id, self.article.slug.as_deref()),
+            page
+        )
+    }
+
+    fn current_page_href(&self) -> String {
+        self.comments_page_href(self.comments_page)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "edit_article.html")]
+pub struct EditArticleContext<'a> {
+    pub board: &'a Board,
+    pub csrf_token: &'a str,
+    pub article_id: i32,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub errors: &'a BTreeMap<&'static str, String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_login.html")]
+pub struct AdminLoginContext<'a> {
+    pub csrf_token: &'a str,
+    // Where to go after logging in
+    pub next: &'a str,
+    pub error: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "admin.html")]
+pub struct AdminIndexContext<'a> {
+    pub csrf_token: &'a str,
+    // How much of UPLOADS_DIR is taken, when it's counted
+    pub uploads: Option<&'a str>,
+}
+
+// An article or comment with its open reports, see report::group_reports
+pub struct ReportedItem {
+    // (article id, comment id)
+    pub target: (i32, Option<i32>),
+    // The first report; dismissing or deleting through it covers them all
+    pub report_id: i32,
+    pub href: String,
+    pub article_title: String,
+    // Set when the report is about a comment
+    pub comment_excerpt: Option<String>,
+    // Only the reports that gave one
+    pub reasons: Vec<String>,
+    pub count: usize,
+    pub first_reported: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_reports.html")]
+pub struct AdminReportsContext<'a> {
+    pub csrf_token: &'a str,
+    pub items: Vec<ReportedItem>,
+}
+
+// A deleted article or comment as listed for restoring
+pub struct DeletedItem {
+    pub id: i32,
+    // The article's title (for comments, the article they were on)
+    pub heading: String,
+    // Where the heading links, when that page is still up
+    pub href: Option<String>,
+    // The board's slug
+    pub board: String,
+    pub excerpt: String,
+    pub deleted_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_deleted.html")]
+pub struct AdminDeletedContext<'a> {
+    pub csrf_token: &'a str,
+    pub articles: Vec<DeletedItem>,
+    pub comments: Vec<DeletedItem>,
+}
+
+// An error logged while serving, as listed for admins
+pub struct AppErrorItem {
+    pub occurred_at: String,
+    // The path of the request it happened in, if any
+    pub route: Option<String>,
+    pub message: String,
+    // Its other fields, as indented JSON
+    pub context: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_errors.html")]
+pub struct AdminErrorsContext<'a> {
+    pub csrf_token: &'a str,
+    pub errors: Vec<AppErrorItem>,
+}
+
+// Swagger UI for the API's spec, see openapi.rs
+#[derive(Template)]
+#[template(path = "api_docs.html")]
+pub struct ApiDocsContext<'a> {
+    pub spec_url: &'a str,
+}
+
+// The page for an AppError (see error.rs)
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorPageContext<'a> {
+    pub status: u16,
+    pub title: &'a str,
+    pub message: &'a str,
+}
+
+// A short page with a message and a single link onward
+#[derive(Template)]
+#[template(path = "message.html")]
+pub struct MessageContext<'a> {
+    pub title: &'a str,
+    pub message: &'a str,
+    pub link_href: &'a str,
+    pub link_text: &'a str,
+}
@@ -0,0 +1,123 @@
+// Reply threads. Each comment may name a parent comment on the same article;
+// pages show the comments as a tree, indented up to MAX_DEPTH levels.
+
+use crate::DbComment;
+
+// Replies nested deeper than this are shown at this depth, under the reply
+// they answer
+pub const MAX_DEPTH: usize = 4;
+
+// Drop deleted comments unless a live comment replies to them (directly or
+// further down), so replies keep their place under a "[deleted]" placeholder.
+// `comments` must be in posting order, which puts every parent before its
+// replies.
+pub fn prune_deleted(comments: Vec<DbComment>) -> Vec<DbComment> {
+    let mut needed = std::collections::HashSet::new();
+    let mut kept: Vec<DbComment> = comments
+        .into_iter()
+        .rev()
+        .filter(|c| {
+            let keep = !c.deleted || needed.contains(&c.id);
+            if keep {
+                if let Some(parent) = c.parent_comment_id {
+                    needed.insert(parent);
+                }
+            }
+            keep
+        })
+        .collect();
+    kept.reverse();
+    kept
+}
+
+// The order to show `comments` in, as (index into `comments`, depth) pairs:
+// each comment is followed by its replies, oldest first. Comments whose parent
+// isn't in the list start threads of their own.
+pub fn thread_order(comments: &[DbComment]) -> Vec<(usize, usize)> {
+    let is_root = |c: &DbComment| {
+        c.parent_comment_id
+            .is_none_or(|parent| !comments.iter().any(|other| other.id == parent))
+    };
+
+    let mut order = Vec::with_capacity(comments.len());
+    // Depth-first, pushing children in reverse so the oldest comes off first
+    let mut stack: Vec<(usize, usize)> = (0..comments.len())
+        .rev()
+        .filter(|&i| is_root(&comments[i]))
+        .map(|i| (i, 0))
+        .collect();
+    while let Some((i, depth)) = stack.pop() {
+        order.push((i, depth.min(MAX_DEPTH)));
+        let id = comments[i].id;
+        stack.extend(
+            (0..comments.len())
+                .rev()
+                .filter(|&child| comments[child].parent_comment_id == Some(id))
+                .map(|child| (child, depth + 1)),
+        );
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(id: i32, parent: Option<i32>, deleted: bool) -> DbComment {
+        DbComment {
+            id,
+            comment: if deleted {
+                String::new()
+            } else {
+                format!("comment {}", id)
+            },
+            name: "Anonymous".to_string(),
+            tripcode: None,
+            created_at: 0,
+            parent_comment_id: parent,
+            deleted,
+        }
+    }
+
+    #[test]
+    fn nests_replies_under_their_parents() {
+        let comments = vec![
+            comment(1, None, false),
+            comment(2, None, false),
+            comment(3, Some(1), false),
+            comment(4, Some(3), false),
+            comment(5, Some(1), false),
+            comment(6, Some(99), false),
+        ];
+        let shown: Vec<(i32, usize)> = thread_order(&comments)
+            .into_iter()
+            .map(|(i, d)| (comments[i].id, d))
+            .collect();
+        assert_eq!(shown, vec![(1, 0), (3, 1), (4, 2), (5, 1), (2, 0), (6, 0)]);
+    }
+
+    #[test]
+    fn flattens_deep_threads() {
+        let comments: Vec<DbComment> = (1..=7)
+            .map(|id| comment(id, (id > 1).then(|| id - 1), false))
+            .collect();
+        let depths: Vec<usize> = thread_order(&comments)
+            .into_iter()
+            .map(|(_, d)| d)
+            .collect();
+        assert_eq!(depths, vec![0, 1, 2, 3, 4, 4, 4]);
+    }
+
+    #[test]
+    fn keeps_deleted_comments_with_live_replies() {
+        let comments = vec![
+            comment(1, None, true),
+            comment(2, Some(1), true),
+            comment(3, Some(2), false),
+            comment(4, None, true),
+            comment(5, Some(4), true),
+        ];
+        let kept: Vec<i32> = prune_deleted(comments).iter().map(|c| c.id).collect();
+        assert_eq!(kept, vec![1, 2, 3]);
+    }
+}
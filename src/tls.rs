@@ -0,0 +1,150 @@
+// HTTPS without a reverse proxy. With TLS_CERT_PATH and TLS_KEY_PATH set the
+// server speaks TLS on its TCP addresses, and rereads both files at SIGHUP so
+// a renewed certificate is picked up without a restart. TLS_REDIRECT_PORT
+// adds a plain HTTP listener that sends everything over to PUBLIC_URL.
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+
+use crate::config::{Config, TlsConfig};
+
+// The certificate handed to every client, swapped out when reloaded
+#[derive(Debug)]
+pub struct Certificates {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    pub fn load(config: &TlsConfig) -> Result<Self, String> {
+        Ok(Certificates {
+            cert_path: config.cert_path.clone(),
+            key_path: config.key_path.clone(),
+            current: RwLock::new(Arc::new(read_certified_key(
+                &config.cert_path,
+                &config.key_path,
+            )?)),
+        })
+    }
+
+    // Read the files again, keeping the certificate in use if they're broken
+    pub fn reload(&self) -> Result<(), String> {
+        let key = read_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+// A certificate chain and the private key that goes with it, each from a PEM
+// file, with errors that say which file is wrong
+fn read_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey, String> {
+    let certs = fs::read(cert_path).map_err(|e| {
+        format!(
+            "Failed to read TLS_CERT_PATH {}: {}",
+            cert_path.display(),
+            e
+        )
+    })?;
+    let certs = rustls_pemfile::certs(&mut certs.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            format!(
+                "TLS_CERT_PATH {} isn't a PEM file: {}",
+                cert_path.display(),
+                e
+            )
+        })?;
+    if certs.is_empty() {
+        return Err(format!(
+            "TLS_CERT_PATH {} holds no certificates",
+            cert_path.display()
+        ));
+    }
+
+    let key = fs::read(key_path)
+        .map_err(|e| format!("Failed to read TLS_KEY_PATH {}: {}", key_path.display(), e))?;
+    let key = rustls_pemfile::private_key(&mut key.as_slice())
+        .map_err(|e| {
+            format!(
+                "TLS_KEY_PATH {} isn't a PEM file: {}",
+                key_path.display(),
+                e
+            )
+        })?
+        .ok_or_else(|| format!("TLS_KEY_PATH {} holds no private key", key_path.display()))?;
+
+    CertifiedKey::from_der(certs, key, &provider()).map_err(|e| match e {
+        rustls::Error::InconsistentKeys(_) => format!(
+            "The key in TLS_KEY_PATH {} doesn't belong to the certificate in TLS_CERT_PATH {}",
+            key_path.display(),
+            cert_path.display()
+        ),
+        e => format!("Failed to use TLS_KEY_PATH {}: {}", key_path.display(), e),
+    })
+}
+
+pub fn server_config(certificates: Arc<Certificates>) -> Result<ServerConfig, String> {
+    Ok(ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Failed to set up TLS: {}", e))?
+        .with_no_client_auth()
+        .with_cert_resolver(certificates))
+}
+
+// Reload the certificate whenever the process gets SIGHUP
+#[cfg(unix)]
+pub fn spawn_reload_on_hangup(certificates: Arc<Certificates>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                error!(error = %e, "Failed to listen for SIGHUP; the TLS certificate won't be reloaded");
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match certificates.reload() {
+                Ok(()) => info!("Reloaded the TLS certificate"),
+                Err(e) => {
+                    error!(error = %e, "Failed to reload the TLS certificate; still using the old one")
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_reload_on_hangup(_certificates: Arc<Certificates>) {}
+
+// Everything on the plain HTTP listener: the same path and query on PUBLIC_URL
+pub async fn redirect_to_https(config: web::Data<Config>, req: HttpRequest) -> HttpResponse {
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, format!("{}{}", config.public_url, path)))
+        .finish()
+}
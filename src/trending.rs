@@ -0,0 +1,149 @@
+// /trending: a board's articles ranked by how much they've been discussed
+// lately. The ranking reads every recent comment on the board, so it's kept
+// for TRENDING_CACHE_SECONDS rather than worked out on each request.
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::board::Board;
+use crate::config::Config;
+use crate::db::{ArticleRepository, ArticleSort, ListedArticle};
+use crate::error::AppError;
+use crate::listing::list_item;
+use crate::templates::{render_html, TrendingContext};
+use crate::CATALOG_EXCERPT_CHARS;
+
+// Comments this recent count for more than older ones
+const RECENT_SECS: i64 = 24 * 60 * 60;
+// Comments older than this don't count at all
+const WINDOW_SECS: i64 = 7 * 24 * 60 * 60;
+const SHOWN: i64 = 30;
+
+#[derive(Clone)]
+struct Ranking {
+    articles: Vec<ListedArticle>,
+    // Nothing was discussed in the window, so these are just the newest
+    newest: bool,
+}
+
+pub struct TrendingCache {
+    ttl: Duration,
+    // Each board's ranking and when it was worked out
+    boards: Mutex<HashMap<i32, (Instant, Ranking)>>,
+}
+
+impl TrendingCache {
+    // A ttl of 0 works the ranking out every time
+    pub fn new(ttl: Duration) -> Self {
+        TrendingCache {
+            ttl,
+            boards: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        TrendingCache::new(Duration::from_secs(config.trending_cache_secs))
+    }
+
+    fn get(&self, board_id: i32, now: Instant) -> Option<Ranking> {
+        let boards = self.boards.lock().unwrap_or_else(|e| e.into_inner());
+        match boards.get(&board_id) {
+            Some((at, ranking)) if now.duration_since(*at) < self.ttl => Some(ranking.clone()),
+            _ => None,
+        }
+    }
+
+    fn put(&self, board_id: i32, ranking: Ranking, now: Instant) {
+        if !self.ttl.is_zero() {
+            let mut boards = self.boards.lock().unwrap_or_else(|e| e.into_inner());
+            boards.insert(board_id, (now, ranking));
+        }
+    }
+}
+
+// The most discussed articles as of `now`, or the newest when none were
+async fn ranking(
+    repo: &dyn ArticleRepository,
+    board_id: i32,
+    now: i64,
+) -> Result<Ranking, sqlx::Error> {
+    let articles = repo
+        .trending_articles(board_id, now - RECENT_SECS, now - WINDOW_SECS, SHOWN)
+        .await?;
+    if !articles.is_empty() {
+        return Ok(Ranking {
+            articles,
+            newest: false,
+        });
+    }
+    Ok(Ranking {
+        articles: repo
+            .list_articles(board_id, ArticleSort::New, SHOWN, 0)
+            .await?,
+        newest: true,
+    })
+}
+
+// GET /trending
+pub async fn trending(
+    board: Board,
+    repo: web::Data<dyn ArticleRepository>,
+    cache: web::Data<TrendingCache>,
+) -> Result<HttpResponse, AppError> {
+    let now = Utc::now().timestamp();
+    let ranking = match cache.get(board.id, Instant::now()) {
+        Some(ranking) => ranking,
+        None => {
+            let ranking = ranking(repo.get_ref(), board.id, now).await?;
+            cache.put(board.id, ranking.clone(), Instant::now());
+            ranking
+        }
+    };
+    Ok(render_html(
+        StatusCode::OK,
+        &TrendingContext {
+            board: &board,
+            newest: ranking.newest,
+            articles: ranking
+                .articles
+                .into_iter()
+                .map(|listed| list_item(listed, CATALOG_EXCERPT_CHARS, now))
+                .collect(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking() -> Ranking {
+        Ranking {
+            articles: Vec::new(),
+            newest: true,
+        }
+    }
+
+    #[test]
+    fn rankings_are_reused_until_they_expire() {
+        let cache = TrendingCache::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(cache.get(1, start).is_none());
+        cache.put(1, ranking(), start);
+        assert!(cache.get(1, start + Duration::from_secs(59)).is_some());
+        assert!(cache.get(2, start).is_none());
+        assert!(cache.get(1, start + Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn zero_ttl_keeps_nothing() {
+        let cache = TrendingCache::new(Duration::ZERO);
+        let start = Instant::now();
+        cache.put(1, ranking(), start);
+        assert!(cache.get(1, start).is_none());
+    }
+}
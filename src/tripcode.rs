@@ -0,0 +1,91 @@
+// Tripcodes, so regulars can show they're the same poster without an account.
+// A name entered as "user#secret" is shown as "user !hash"; "user##secret"
+// makes a secure trip, "user !!hash", keyed by SECRET_KEY so it can't be
+// worked out from the secret elsewhere or brute-forced offline. Only the
+// hash is stored, never the secret.
+
+use sha2::{Digest, Sha256};
+
+use crate::signing;
+
+// The characters crypt(3) tripcodes are written in
+const ALPHABET: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const HASH_CHARS: usize = 10;
+
+// Split a name field into the name to show and its tripcode, if a secret was
+// given. The name still needs poster_name's checks.
+pub fn parse<'a>(input: &'a str, secret_key: &[u8]) -> (&'a str, Option<String>) {
+    let Some((name, secret)) = input.split_once('#') else {
+        return (input, None);
+    };
+    let tripcode = match secret.strip_prefix('#') {
+        Some(secret) if !secret.is_empty() => Some(format!(
+            "!!{}",
+            encode(&signing::digest(secret_key, "tripcode", secret))
+        )),
+        Some(_) => None,
+        None if !secret.is_empty() => Some(format!("!{}", encode(&Sha256::digest(secret)))),
+        None => None,
+    };
+    (name, tripcode)
+}
+
+// The first HASH_CHARS six-bit groups of `hash`
+fn encode(hash: &[u8]) -> String {
+    let mut bits = hash
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    (0..HASH_CHARS)
+        .map(|_| {
+            let index = (0..6).fold(0, |index, _| index << 1 | bits.next().unwrap_or(0));
+            ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_trips_depend_only_on_the_secret() {
+        let (name, trip) = parse("alice#hunter2", b"key one");
+        assert_eq!(name, "alice");
+        assert_eq!(trip.as_deref(), Some("!xGyxAf8ni4"));
+        assert_eq!(parse("bob#hunter2", b"key two").1, trip);
+        assert_ne!(parse("alice#hunter3", b"key one").1, trip);
+    }
+
+    #[test]
+    fn secure_trips_depend_on_the_server_key() {
+        let (name, trip) = parse("alice##hunter2", b"key one");
+        assert_eq!(name, "alice");
+        let trip = trip.unwrap();
+        assert!(
+            trip.starts_with("!!") && trip.len() == 2 + HASH_CHARS,
+            "{}",
+            trip
+        );
+        assert_eq!(
+            parse("alice##hunter2", b"key one").1.as_deref(),
+            Some(trip.as_str())
+        );
+        assert_ne!(
+            parse("alice##hunter2", b"key two").1.as_deref(),
+            Some(trip.as_str())
+        );
+        assert_ne!(
+            parse("alice#hunter2", b"key one").1.as_deref(),
+            Some(trip.as_str())
+        );
+    }
+
+    #[test]
+    fn names_without_secrets_have_no_trip() {
+        assert_eq!(parse("alice", b"key"), ("alice", None));
+        assert_eq!(parse("alice#", b"key"), ("alice", None));
+        assert_eq!(parse("alice##", b"key"), ("alice", None));
+        assert_eq!(parse("#secret", b"key").0, "");
+        assert!(parse("#secret", b"key").1.is_some());
+    }
+}
@@ -0,0 +1,319 @@
+// Reading the multipart bodies articles and comments are posted as: their
+// text fields, and their files stored, thumbnailed and recorded
+
+use actix_web::web;
+use futures_util::stream::StreamExt as _;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{error, warn};
+
+use crate::config::Config;
+use crate::db::ArticleRepository;
+use crate::error::AppError;
+use crate::media::{self, ImageLimits, MediaType, UploadError, UploadOptions};
+use crate::storage::{self, MediaStorage};
+use crate::Media;
+
+// Most a form's text fields are read up to; the title and body are held to
+// four bytes (the longest UTF-8 character) per character they're allowed
+pub const MAX_FIELD_BYTES: usize = 1024 * 1024;
+
+// Thumbnail an uploaded image off the async executor; failures are logged and
+// the media simply goes without a thumbnail
+async fn create_thumbnail(
+    storage: &dyn MediaStorage,
+    stored: &mut media::StoredUpload,
+    limits: ImageLimits,
+) -> Option<String> {
+    let data = stored.image_data.take()?;
+    let media_type = stored.media_type;
+    let result = web::block(move || media::generate_thumbnail(&data, media_type, limits)).await;
+
+    let (thumb, thumb_type) = match result {
+        Ok(Ok(thumb)) => thumb,
+        Ok(Err(e)) => {
+            error!(key = %stored.key, error = %e, "Failed to generate thumbnail");
+            return None;
+        }
+        Err(e) => {
+            error!(key = %stored.key, error = %e, "Thumbnail task failed");
+            return None;
+        }
+    };
+
+    let key = media::thumb_key(&stored.key, thumb_type);
+    match storage
+        .put(&key, thumb_type.mime(), storage::once(thumb))
+        .await
+    {
+        Ok(url) => Some(url),
+        Err(e) => {
+            error!(key = %key, error = %e, "Failed to store thumbnail");
+            None
+        }
+    }
+}
+
+// Extract a video's poster frame in the background and attach it to the media
+// row once ready, so submissions never wait on ffmpeg. Videos that aren't on
+// local disk are downloaded to a temporary file first.
+fn spawn_poster_extraction(
+    repo: web::Data<dyn ArticleRepository>,
+    storage: web::Data<dyn MediaStorage>,
+    ffmpeg: PathBuf,
+    media_id: i32,
+    media_path: String,
+) {
+    actix_web::rt::spawn(async move {
+        let (source, temp_copy) = match storage.local_path(&media_path) {
+            Some(path) => (path, None),
+            None => {
+                let temp =
+                    env::temp_dir().join(format!("source_{}.mp4", uuid::Uuid::new_v4().simple()));
+                let downloaded = match storage.get(&media_path).await {
+                    Ok(data) => fs::write(&temp, data),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = downloaded {
+                    error!(media_path = %media_path, error = %e, "Failed to fetch video for its poster frame");
+                    let _ = fs::remove_file(&temp);
+                    return;
+                }
+                (temp.clone(), Some(temp))
+            }
+        };
+
+        let poster = media::extract_poster_frame(&ffmpeg, &source).await;
+        if let Some(temp) = temp_copy {
+            let _ = fs::remove_file(temp);
+        }
+        let poster = match poster {
+            Ok(poster) => poster,
+            Err(e) => {
+                error!(media_path = %media_path, error = %e, "Failed to extract poster frame");
+                return;
+            }
+        };
+
+        let upload_key = media_path.rsplit('/').next().unwrap_or_default();
+        let key = media::thumb_key(upload_key, MediaType::Jpeg);
+        let thumb_path = match storage
+            .put(&key, MediaType::Jpeg.mime(), storage::once(poster))
+            .await
+        {
+            Ok(url) => url,
+            Err(e) => {
+                error!(key = %key, error = %e, "Failed to store poster frame");
+                return;
+            }
+        };
+        if let Err(e) = repo.set_media_thumb(media_id, &thumb_path).await {
+            error!(media_path = %media_path, error = %e, "Failed to record poster frame");
+        }
+    });
+}
+
+// Store an uploaded file under a server-generated name with an extension
+// matching its sniffed content type, thumbnailing images. The client's name
+// for it is only kept for display. A file identical to one already stored is
+// kept until its row is in, which takes the stored one's files instead (see
+// remove_replaced_files).
+pub async fn save_media(
+    field: &mut actix_multipart::Field,
+    original_name: String,
+    storage: &dyn MediaStorage,
+    config: &Config,
+) -> Result<Media, UploadError> {
+    storage.accepts_uploads().await?;
+    let options = UploadOptions::from_config(config);
+    let mut stored = media::save_upload(field, storage, options).await?;
+    if let (MediaType::Mp4, Some(ffmpeg)) = (stored.media_type, &config.ffmpeg_path) {
+        if let Err(e) =
+            check_video(storage, &stored.url, &media::ffprobe_beside(ffmpeg), config).await
+        {
+            if let Err(e) = storage.delete(&stored.url).await {
+                warn!(media_path = %stored.url, error = %e, "Failed to remove rejected video");
+            }
+            return Err(e);
+        }
+    }
+    let thumb_path = create_thumbnail(storage, &mut stored, options.limits).await;
+    Ok(Media {
+        original_name: Some(original_name),
+        thumb_path,
+        ..Media::uploaded(stored)
+    })
+}
+
+// Refuse a stored video ffprobe finds too long or in codecs browsers won't
+// play. One ffprobe can't be run on is let through, as without FFMPEG_PATH.
+async fn check_video(
+    storage: &dyn MediaStorage,
+    url: &str,
+    ffprobe: &Path,
+    config: &Config,
+) -> Result<(), UploadError> {
+    let (source, temp_copy) = match storage.local_path(url) {
+        Some(path) => (path, None),
+        None => {
+            let temp = env::temp_dir().join(format!("probe_{}.mp4", uuid::Uuid::new_v4().simple()));
+            let downloaded = match storage.get(url).await {
+                Ok(data) => fs::write(&temp, data),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = downloaded {
+                error!(media_path = %url, error = %e, "Failed to fetch video to check it");
+                let _ = fs::remove_file(&temp);
+                return Ok(());
+            }
+            (temp.clone(), Some(temp))
+        }
+    };
+    let probe = media::probe_video(ffprobe, &source).await;
+    if let Some(temp) = temp_copy {
+        let _ = fs::remove_file(temp);
+    }
+    match probe {
+        Ok(probe) => match probe.problem(config.max_video_seconds) {
+            Some(problem) => Err(UploadError::InvalidVideo(problem)),
+            None => Ok(()),
+        },
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            warn!(media_path = %url, error = %e, "Refused a video ffprobe couldn't read");
+            Err(UploadError::InvalidVideo(
+                "The video couldn't be read; it may be damaged.".to_string(),
+            ))
+        }
+        Err(e) => {
+            error!(ffprobe = %ffprobe.display(), error = %e, "Failed to run ffprobe; the video wasn't checked");
+            Ok(())
+        }
+    }
+}
+
+// Why save_media refused an upload
+pub fn upload_rejected(e: UploadError, config: &Config) -> AppError {
+    match e {
+        UploadError::UnsupportedType => AppError::Validation {
+            field: "media",
+            message: "Only jpg, png, gif, webp, MP4, mp3, ogg, or flac files are allowed"
+                .to_string(),
+        },
+        UploadError::TooLarge(limit) => AppError::PayloadTooLarge(limit),
+        UploadError::Corrupt => AppError::Validation {
+            field: "media",
+            message: "The image couldn't be read; it may be damaged".to_string(),
+        },
+        UploadError::ImageTooLarge(reason) => AppError::ImageTooLarge(reason),
+        UploadError::InvalidVideo(message) => AppError::Validation {
+            field: "media",
+            message,
+        },
+        UploadError::Io(e) if e.kind() == io::ErrorKind::StorageFull => {
+            warn!(quota = config.uploads_quota_bytes, error = %e, "Refused an upload: UPLOADS_QUOTA_BYTES is reached");
+            AppError::InsufficientStorage
+        }
+        UploadError::Io(e) => AppError::Io(e),
+        UploadError::Multipart(e) => AppError::Multipart(e),
+    }
+}
+
+// Record saved uploads against a comment, and start extracting poster frames
+// for the videos
+pub async fn attach_media(
+    repo: &web::Data<dyn ArticleRepository>,
+    storage: &web::Data<dyn MediaStorage>,
+    config: &Config,
+    article_id: i32,
+    comment_id: i32,
+    media: Vec<Media>,
+) -> Result<(), sqlx::Error> {
+    let mut stored = Vec::with_capacity(media.len());
+    for item in &media {
+        stored.push(
+            repo.insert_media(article_id, Some(comment_id), item)
+                .await?,
+        );
+    }
+    remove_replaced_files(storage.get_ref(), &media, &stored).await;
+    spawn_poster_extractions(repo, storage, config, stored);
+    Ok(())
+}
+
+// Remove the files of uploads stored as an identical earlier one's, now
+// that their rows point at that one's files
+pub async fn remove_replaced_files(
+    storage: &dyn MediaStorage,
+    uploaded: &[Media],
+    stored: &[(i32, Media)],
+) {
+    for (item, (_, stored)) in uploaded.iter().zip(stored) {
+        if item.media_path != stored.media_path {
+            remove_media_files(storage, item.file_paths()).await;
+        }
+    }
+}
+
+// Start extracting poster frames for the videos among stored media, given
+// with their media ids
+pub fn spawn_poster_extractions(
+    repo: &web::Data<dyn ArticleRepository>,
+    storage: &web::Data<dyn MediaStorage>,
+    config: &Config,
+    stored: impl IntoIterator<Item = (i32, Media)>,
+) {
+    let Some(ffmpeg) = &config.ffmpeg_path else {
+        return;
+    };
+    for (media_id, item) in stored {
+        // A reused video may have its poster already
+        if item.is_video() && item.thumb_path.is_none() {
+            spawn_poster_extraction(
+                repo.clone(),
+                storage.clone(),
+                ffmpeg.clone(),
+                media_id,
+                item.media_path,
+            );
+        }
+    }
+}
+
+// The contents of a multipart text field, which has to be UTF-8
+pub async fn read_text_field(
+    field: &mut actix_multipart::Field,
+    max_bytes: usize,
+) -> Result<String, AppError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        value.extend_from_slice(&chunk?);
+        // Given up on as soon as it's over, rather than buffered whole
+        if value.len() > max_bytes {
+            return Err(AppError::Validation {
+                field: "form",
+                message: format!("The {} field is too long", field.name().unwrap_or_default()),
+            });
+        }
+    }
+    String::from_utf8(value).map_err(|_| AppError::Validation {
+        field: "form",
+        message: format!(
+            "The {} field isn't valid UTF-8 text",
+            field.name().unwrap_or_default()
+        ),
+    })
+}
+
+// Delete the files behind media paths from storage
+pub async fn remove_media_files<S: AsRef<str>>(
+    storage: &dyn MediaStorage,
+    media_paths: impl IntoIterator<Item = S>,
+) {
+    for media_path in media_paths {
+        if let Err(e) = storage.delete(media_path.as_ref()).await {
+            error!(media_path = media_path.as_ref(), error = %e, "Failed to remove media file");
+        }
+    }
+}
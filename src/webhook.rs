@@ -0,0 +1,438 @@
+// Webhooks: URLs added with `articles webhook add` are sent a POST for each
+// new article or comment they asked for, with the post as JSON. The body is
+// signed with the webhook's secret as `X-Signature: sha256=<hex HMAC-SHA256>`,
+// so receivers can tell it came from here. Handlers only queue the new post's
+// id; a task started by serve loads it and sends it to each webhook, retrying
+// with backoff, so a slow or failing receiver never holds up a request.
+// Deliveries that still fail after WEBHOOK_ATTEMPTS tries are logged as
+// errors and kept in webhook_failures, with their payload, for `articles
+// webhook failures`.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::{error, warn};
+
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::db::{self, ArticleRepository, WebhookFailureRow, WebhookRow};
+use crate::render::format_timestamp;
+
+// New posts waiting to be sent; past this many, new ones aren't
+const QUEUE_CAPACITY: usize = 1024;
+// The wait after the first failed try, doubled after each one after that
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(60);
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+// The event `webhook test` sends
+pub const PING: &str = "ping";
+
+// What a webhook can be told about, each a bit of its `events`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Article,
+    Comment,
+}
+
+impl Event {
+    pub const ALL: [Event; 2] = [Event::Article, Event::Comment];
+
+    pub fn bit(self) -> i32 {
+        match self {
+            Event::Article => 1,
+            Event::Comment => 2,
+        }
+    }
+
+    // As sent in X-Webhook-Event and the payload's "event"
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Article => "article.created",
+            Event::Comment => "comment.created",
+        }
+    }
+
+    // As given to --events
+    pub fn parse(value: &str) -> Option<Event> {
+        match value.trim() {
+            "article" | "articles" => Some(Event::Article),
+            "comment" | "comments" => Some(Event::Comment),
+            _ => None,
+        }
+    }
+}
+
+// The events in a bitmask, for listing
+pub fn describe(events: i32) -> String {
+    let names: Vec<_> = Event::ALL
+        .into_iter()
+        .filter(|event| events & event.bit() != 0)
+        .map(|event| event.name())
+        .collect();
+    names.join(", ")
+}
+
+// A new secret for a webhook that wasn't given one
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// The X-Signature value for `body`
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key length");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+pub fn client(config: &Config) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.webhook_timeout_secs))
+        .user_agent("articles-webhooks")
+        .build()
+        .expect("the webhook client builds")
+}
+
+// One try at sending `body`; Err says why it failed
+pub async fn send(
+    client: &reqwest::Client,
+    webhook: &WebhookRow,
+    event: &str,
+    body: &[u8],
+) -> Result<(), String> {
+    let response = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, event)
+        .header(SIGNATURE_HEADER, signature(&webhook.secret, body))
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+    Ok(())
+}
+
+// What `webhook test` sends
+pub fn ping_payload(webhook_id: i32) -> Vec<u8> {
+    json!({ "event": PING, "webhook_id": webhook_id })
+        .to_string()
+        .into_bytes()
+}
+
+// A post a handler stored
+enum Created {
+    Article(i32),
+    Comment { article_id: i32, comment_id: i32 },
+}
+
+// Taken by the handlers that store posts, as web::Data
+pub struct Webhooks {
+    queue: Sender<Created>,
+}
+
+impl Webhooks {
+    // Start the task that sends queued posts
+    pub fn spawn(repo: Arc<dyn ArticleRepository>, config: &Config) -> Self {
+        let (queue, received) = mpsc::channel(QUEUE_CAPACITY);
+        let dispatcher = Arc::new(Dispatcher {
+            repo,
+            client: client(config),
+            attempts: config.webhook_attempts.max(1),
+        });
+        tokio::spawn(dispatcher.run(received));
+        Webhooks { queue }
+    }
+
+    pub fn article_created(&self, article_id: i32) {
+        self.queue(Created::Article(article_id));
+    }
+
+    pub fn comment_created(&self, article_id: i32, comment_id: i32) {
+        self.queue(Created::Comment {
+            article_id,
+            comment_id,
+        });
+    }
+
+    fn queue(&self, created: Created) {
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(created) {
+            warn!("Too many posts waiting for webhooks; one won't be sent");
+        }
+    }
+}
+
+struct Dispatcher {
+    repo: Arc<dyn ArticleRepository>,
+    client: reqwest::Client,
+    attempts: u32,
+}
+
+impl Dispatcher {
+    async fn run(self: Arc<Self>, mut received: Receiver<Created>) {
+        while let Some(created) = received.recv().await {
+            self.dispatch(created).await;
+        }
+    }
+
+    // Send a post to each webhook that wants it, each in its own task so one
+    // retrying doesn't hold up the others
+    async fn dispatch(self: &Arc<Self>, created: Created) {
+        let event = match created {
+            Created::Article(_) => Event::Article,
+            Created::Comment { .. } => Event::Comment,
+        };
+        let webhooks = match self.repo.list_webhooks().await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                error!(event = event.name(), error = %e, "Failed to load webhooks");
+                return;
+            }
+        };
+        let webhooks: Vec<_> = webhooks
+            .into_iter()
+            .filter(|w| w.events & event.bit() != 0)
+            .collect();
+        if webhooks.is_empty() {
+            return;
+        }
+        let Some(payload) = self.payload(&created).await else {
+            return;
+        };
+        let body = Arc::new(payload.to_string().into_bytes());
+        for webhook in webhooks {
+            tokio::spawn(self.clone().deliver(webhook, event, body.clone()));
+        }
+    }
+
+    // None when the post is gone again, or can't be loaded
+    async fn payload(&self, created: &Created) -> Option<Value> {
+        match *created {
+            Created::Article(article_id) => {
+                match self.repo.get_article_with_media(article_id).await {
+                    Ok(article) => {
+                        Some(json!({ "event": Event::Article.name(), "article": article }))
+                    }
+                    Err(sqlx::Error::RowNotFound) => None,
+                    Err(e) => {
+                        error!(article_id, error = %e, "Failed to load article for webhooks");
+                        None
+                    }
+                }
+            }
+            Created::Comment {
+                article_id,
+                comment_id,
+            } => match self.repo.list_comments(article_id).await {
+                Ok(comments) => {
+                    let comment = comments.into_iter().find(|c| c.id == comment_id)?;
+                    Some(
+                        json!({ "event": Event::Comment.name(), "article_id": article_id, "comment": comment }),
+                    )
+                }
+                Err(e) => {
+                    error!(article_id, comment_id, error = %e, "Failed to load comment for webhooks");
+                    None
+                }
+            },
+        }
+    }
+
+    async fn deliver(self: Arc<Self>, webhook: WebhookRow, event: Event, body: Arc<Vec<u8>>) {
+        let mut wait = FIRST_RETRY;
+        let mut attempt = 1;
+        let last_error = loop {
+            let error = match send(&self.client, &webhook, event.name(), &body).await {
+                Ok(()) => return,
+                Err(error) => error,
+            };
+            if attempt >= self.attempts {
+                break error;
+            }
+            tokio::time::sleep(wait).await;
+            wait = (wait * 2).min(MAX_RETRY);
+            attempt += 1;
+        };
+
+        error!(
+            webhook_id = webhook.id,
+            url = %webhook.url,
+            event = event.name(),
+            attempts = self.attempts,
+            error = %last_error,
+            "Webhook delivery failed"
+        );
+        let failure = WebhookFailureRow {
+            webhook_id: webhook.id,
+            url: webhook.url,
+            event: event.name().to_string(),
+            payload: String::from_utf8_lossy(&body).into_owned(),
+            error: last_error,
+            attempts: self.attempts as i32,
+            failed_at: Utc::now().timestamp(),
+        };
+        if let Err(e) = self.repo.insert_webhook_failure(&failure).await {
+            error!(webhook_id = failure.webhook_id, error = %e, "Failed to record webhook failure");
+        }
+    }
+}
+
+// `articles webhook add URL`
+pub async fn add_webhook(
+    config: &Config,
+    url: &str,
+    events: &[Event],
+    secret: Option<String>,
+    test: bool,
+) -> CommandResult {
+    let url = url.trim();
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!(
+            "Webhook URLs must start with http:// or https://, got {:?}",
+            url
+        )
+        .into());
+    }
+    let events = events.iter().fold(0, |events, event| events | event.bit());
+    let secret = secret
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(generate_secret);
+    let repo = db::connect(&config.database_url).await?;
+    let id = repo
+        .insert_webhook(url, &secret, events, Utc::now().timestamp())
+        .await
+        .map_err(|e| format!("Failed to add webhook: {}", e))?;
+
+    println!(
+        "Added webhook {} for {} at {}; its secret is:",
+        id,
+        describe(events),
+        url
+    );
+    println!("{}", secret);
+    if test {
+        test_webhook(config, id).await?;
+    }
+    Ok(())
+}
+
+// `articles webhook remove ID`
+pub async fn remove_webhook(config: &Config, id: i32) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let removed = repo
+        .delete_webhook(id)
+        .await
+        .map_err(|e| format!("Failed to remove webhook {}: {}", id, e))?;
+    if !removed {
+        return Err(format!("There is no webhook {}", id).into());
+    }
+    println!("Removed webhook {}", id);
+    Ok(())
+}
+
+// `articles webhook list`
+pub async fn list_webhooks(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let webhooks = repo
+        .list_webhooks()
+        .await
+        .map_err(|e| format!("Failed to list webhooks: {}", e))?;
+    if webhooks.is_empty() {
+        println!("No webhooks");
+    }
+    for w in webhooks {
+        println!(
+            "{}\t{}\t{}\tadded {}",
+            w.id,
+            w.url,
+            describe(w.events),
+            format_timestamp(w.created_at)
+        );
+    }
+    Ok(())
+}
+
+// `articles webhook test ID`: one try, without retries, so the answer shows
+// straight away
+pub async fn test_webhook(config: &Config, id: i32) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let webhooks = repo
+        .list_webhooks()
+        .await
+        .map_err(|e| format!("Failed to load webhooks: {}", e))?;
+    let Some(found) = webhooks.into_iter().find(|w| w.id == id) else {
+        return Err(format!("There is no webhook {}", id).into());
+    };
+    send(&client(config), &found, PING, &ping_payload(id))
+        .await
+        .map_err(|e| format!("Webhook {} failed the ping: {}", id, e))?;
+    println!("Webhook {} answered the ping", id);
+    Ok(())
+}
+
+// `articles webhook failures`
+pub async fn list_webhook_failures(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let failures = repo
+        .recent_webhook_failures(50)
+        .await
+        .map_err(|e| format!("Failed to list webhook failures: {}", e))?;
+    if failures.is_empty() {
+        println!("No failed deliveries");
+    }
+    for f in failures {
+        println!(
+            "{}\twebhook {}\t{}\t{}\tafter {} tries: {}",
+            format_timestamp(f.failed_at),
+            f.webhook_id,
+            f.url,
+            f.event,
+            f.attempts,
+            f.error
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_are_signed_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn events_are_bits() {
+        assert_eq!(Event::parse("articles"), Some(Event::Article));
+        assert_eq!(Event::parse(" comment "), Some(Event::Comment));
+        assert_eq!(Event::parse("likes"), None);
+        assert_eq!(
+            describe(Event::Article.bit() | Event::Comment.bit()),
+            "article.created, comment.created"
+        );
+        assert_eq!(describe(Event::Comment.bit()), "comment.created");
+    }
+}
@@ -0,0 +1,327 @@
+// Word filters applied to titles, bodies and comments before they are stored.
+// The filters live in the word_filters table; the server keeps a compiled copy
+// in memory and reloads it periodically, so filters added with the CLI take
+// effect without a restart.
+
+use actix_web::{http::StatusCode, web, HttpResponse};
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::error;
+
+use crate::cli::CommandResult;
+use crate::config::Config;
+use crate::db::{self, ArticleRepository, WordFilterRow};
+use crate::templates::{render_html, MessageContext};
+
+// The regex crate matches in linear time, so patterns can't backtrack
+// catastrophically; these limits bound the cost of compiling and running them
+const MAX_PATTERN_CHARS: usize = 200;
+const MAX_COMPILED_BYTES: usize = 256 * 1024;
+const MAX_NESTING: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterAction {
+    Reject,
+    Replace,
+}
+
+impl FilterAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilterAction::Reject => "reject",
+            FilterAction::Replace => "replace",
+        }
+    }
+}
+
+// Returned when text matches a "reject" filter
+#[derive(Debug, PartialEq, Eq)]
+pub struct Blocked;
+
+struct Filter {
+    regex: Regex,
+    action: FilterAction,
+    replacement: String,
+}
+
+// Compile a pattern the way it will be matched: plain patterns are literal
+// text matched as whole words, regex patterns as written. Both ignore case.
+pub fn compile(pattern: &str, is_regex: bool) -> Result<Regex, String> {
+    if pattern.trim().is_empty() {
+        return Err("Filter patterns must not be empty".to_string());
+    }
+    if pattern.chars().count() > MAX_PATTERN_CHARS {
+        return Err(format!(
+            "Filter patterns are limited to {} characters",
+            MAX_PATTERN_CHARS
+        ));
+    }
+    let source = if is_regex {
+        pattern.to_string()
+    } else {
+        // \b only makes sense next to word characters, so "c++" still matches
+        let pattern = pattern.trim();
+        let edge = |c: Option<char>| {
+            if c.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                r"\b"
+            } else {
+                ""
+            }
+        };
+        format!(
+            "{}{}{}",
+            edge(pattern.chars().next()),
+            regex::escape(pattern),
+            edge(pattern.chars().last())
+        )
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(true)
+        .size_limit(MAX_COMPILED_BYTES)
+        .dfa_size_limit(MAX_COMPILED_BYTES)
+        .nest_limit(MAX_NESTING)
+        .build()
+        .map_err(|e| format!("Invalid filter pattern: {}", e))
+}
+
+fn parse_action(action: &str) -> Option<FilterAction> {
+    match action {
+        "reject" => Some(FilterAction::Reject),
+        "replace" => Some(FilterAction::Replace),
+        _ => None,
+    }
+}
+
+// The compiled filters, shared by every worker
+pub struct WordFilters {
+    filters: RwLock<Arc<Vec<Filter>>>,
+}
+
+impl WordFilters {
+    pub fn new() -> Self {
+        WordFilters {
+            filters: RwLock::new(Arc::new(Vec::new())),
+        }
+    }
+
+    fn set(&self, rows: Vec<WordFilterRow>) {
+        let filters = rows
+            .into_iter()
+            .filter_map(|row| {
+                // Rows are validated when added, but may have been edited by hand
+                let action = parse_action(&row.action).or_else(|| {
+                    error!(filter_id = row.id, action = %row.action, "Word filter has an unknown action");
+                    None
+                })?;
+                match compile(&row.pattern, row.is_regex) {
+                    Ok(regex) => Some(Filter {
+                        regex,
+                        action,
+                        replacement: row.replacement,
+                    }),
+                    Err(e) => {
+                        error!(filter_id = row.id, error = %e, "Skipping word filter");
+                        None
+                    }
+                }
+            })
+            .collect();
+        *self.filters.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(filters);
+    }
+
+    // Replace the filters with what's in the database. On failure the
+    // previous filters stay in place.
+    pub async fn reload(&self, repo: &dyn ArticleRepository) -> Result<(), sqlx::Error> {
+        self.set(repo.list_word_filters().await?);
+        Ok(())
+    }
+
+    // The text with "replace" filters applied, or Blocked if any "reject"
+    // filter matches
+    pub fn apply<'t>(&self, text: &'t str) -> Result<Cow<'t, str>, Blocked> {
+        let filters = self
+            .filters
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if filters
+            .iter()
+            .any(|f| f.action == FilterAction::Reject && f.regex.is_match(text))
+        {
+            return Err(Blocked);
+        }
+
+        let mut text = Cow::Borrowed(text);
+        for filter in filters.iter().filter(|f| f.action == FilterAction::Replace) {
+            if let Cow::Owned(replaced) = filter
+                .regex
+                .replace_all(&text, regex::NoExpand(&filter.replacement))
+            {
+                text = Cow::Owned(replaced);
+            }
+        }
+        Ok(text)
+    }
+}
+
+// Reload the filters every `interval` for as long as the server runs
+pub fn spawn_refresh(
+    filters: web::Data<WordFilters>,
+    repo: web::Data<dyn ArticleRepository>,
+    interval: Duration,
+) {
+    actix_web::rt::spawn(async move {
+        let mut ticks = actix_web::rt::time::interval(interval);
+        // The first tick completes immediately; serve() has just loaded them
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            if let Err(e) = filters.reload(repo.get_ref()).await {
+                error!(error = %e, "Failed to reload word filters");
+            }
+        }
+    });
+}
+
+// `articles add-filter PATTERN [--regex] [--replace TEXT]`
+pub async fn add_filter(
+    config: &Config,
+    pattern: &str,
+    is_regex: bool,
+    replacement: Option<&str>,
+) -> CommandResult {
+    compile(pattern, is_regex)?;
+    let action = match replacement {
+        Some(_) => FilterAction::Replace,
+        None => FilterAction::Reject,
+    };
+    let repo = db::connect(&config.database_url).await?;
+    repo.insert_word_filter(
+        pattern,
+        is_regex,
+        action.as_str(),
+        replacement.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| format!("Failed to add word filter: {}", e))?;
+
+    println!(
+        "Added word filter; running servers pick it up within {}s",
+        config.word_filter_refresh_secs
+    );
+    Ok(())
+}
+
+// `articles remove-filter ID`
+pub async fn remove_filter(config: &Config, id: i32) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let removed = repo
+        .delete_word_filter(id)
+        .await
+        .map_err(|e| format!("Failed to remove word filter {}: {}", id, e))?;
+    if !removed {
+        return Err(format!("There is no word filter {}", id).into());
+    }
+    println!("Removed word filter {}", id);
+    Ok(())
+}
+
+// `articles list-filters`
+pub async fn list_filters(config: &Config) -> CommandResult {
+    let repo = db::connect(&config.database_url).await?;
+    let filters = repo
+        .list_word_filters()
+        .await
+        .map_err(|e| format!("Failed to list word filters: {}", e))?;
+    if filters.is_empty() {
+        println!("No word filters");
+    }
+    for f in filters {
+        let kind = if f.is_regex { "regex" } else { "word" };
+        match f.action.as_str() {
+            "replace" => println!("{}\t{} {:?} -> {:?}", f.id, kind, f.pattern, f.replacement),
+            _ => println!("{}\t{} {:?} (reject)", f.id, kind, f.pattern),
+        }
+    }
+    Ok(())
+}
+
+// Run each text through the word filters, replacing it with the filtered
+// version
+pub fn apply_word_filters(filters: &WordFilters, texts: &mut [&mut String]) -> Result<(), Blocked> {
+    for text in texts.iter_mut() {
+        if let Cow::Owned(filtered) = filters.apply(text)? {
+            **text = filtered;
+        }
+    }
+    Ok(())
+}
+
+pub fn post_blocked(back: &str) -> HttpResponse {
+    render_html(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &MessageContext {
+            title: "Post Blocked",
+            message: "Your post contains a word or phrase that isn't allowed here.",
+            link_href: back,
+            link_text: "Go back",
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(pattern: &str, is_regex: bool, action: &str, replacement: &str) -> WordFilterRow {
+        WordFilterRow {
+            id: 0,
+            pattern: pattern.to_string(),
+            is_regex,
+            action: action.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn plain_patterns_match_whole_words_ignoring_case() {
+        let filters = WordFilters::new();
+        filters.set(vec![row("darn", false, "replace", "d***")]);
+        assert_eq!(filters.apply("Darn it, DARN").unwrap(), "d*** it, d***");
+        assert_eq!(filters.apply("darned darnit").unwrap(), "darned darnit");
+        assert!(matches!(
+            filters.apply("clean text").unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn reject_wins_over_replace() {
+        let filters = WordFilters::new();
+        filters.set(vec![
+            row("spam", false, "replace", "ham"),
+            row(r"buy\s+now", true, "reject", ""),
+        ]);
+        assert_eq!(filters.apply("spam").unwrap(), "ham");
+        assert_eq!(filters.apply("spam! BUY   now"), Err(Blocked));
+    }
+
+    #[test]
+    fn replacements_are_literal() {
+        let filters = WordFilters::new();
+        filters.set(vec![row("x", false, "replace", "$0$1")]);
+        assert_eq!(filters.apply("a x b").unwrap(), "a $0$1 b");
+    }
+
+    #[test]
+    fn bad_patterns_are_refused() {
+        assert!(compile("", false).is_err());
+        assert!(compile("(unclosed", true).is_err());
+        assert!(compile(&"a".repeat(MAX_PATTERN_CHARS + 1), false).is_err());
+        // Huge repetitions blow the compiled size limit
+        assert!(compile(r"(\w{100}){100}", true).is_err());
+        assert!(compile("c++", false).unwrap().is_match("I like C++"));
+    }
+}
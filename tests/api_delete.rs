@@ -0,0 +1,130 @@
+// Admins delete articles and comments through the API with an admin key or
+// their login; everyone else gets a 403, and what isn't there a 404
+
+mod common;
+
+use common::{admin_password_hash, Server};
+
+fn start() -> Server {
+    Server::start(&[
+        ("ADMIN_PASSWORD_HASH", &admin_password_hash()),
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+    ])
+}
+
+fn admin_key(server: &Server) -> String {
+    let output = server.run(&["api-key", "create", "moderator", "--admin"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    format!(
+        "Bearer {}",
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .last()
+            .unwrap()
+    )
+}
+
+// Post an article with a comment, returning their ids
+fn post(server: &Server) -> (i64, i64) {
+    let response = server.post_json("/api/articles", br#"{"title": "Doomed", "body": "Body"}"#);
+    assert_eq!(response.status, 201, "{}", response.body);
+    let id = serde_json::from_str::<serde_json::Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap();
+    let form = server.open_form(&format!("/articles/{}/doomed", id));
+    let response = server.submit(
+        &format!("/articles/{}/comment", id),
+        &form,
+        &[("comment", "Doomed too")],
+    );
+    assert_eq!(response.status, 302, "{}", response.body);
+    let article = server
+        .send("GET", &format!("/api/articles/{}", id), &[], b"")
+        .body;
+    let article: serde_json::Value = serde_json::from_str(&article).unwrap();
+    (id, article["comments"][0]["id"].as_i64().unwrap())
+}
+
+fn delete(server: &Server, path: &str, headers: &[(&str, &str)]) -> u16 {
+    server.send("DELETE", path, headers, b"").status
+}
+
+#[test]
+fn admin_keys_delete() {
+    let server = start();
+    let (article_id, comment_id) = post(&server);
+    let key = admin_key(&server);
+    let auth = [("Authorization", key.as_str())];
+
+    let comment = format!("/api/comments/{}", comment_id);
+    assert_eq!(delete(&server, &comment, &auth), 204);
+    assert_eq!(delete(&server, &comment, &auth), 404);
+    let article: serde_json::Value = serde_json::from_str(
+        &server
+            .send("GET", &format!("/api/articles/{}", article_id), &[], b"")
+            .body,
+    )
+    .unwrap();
+    assert_eq!(article["comments"].as_array().unwrap().len(), 0);
+
+    let article = format!("/api/articles/{}", article_id);
+    assert_eq!(delete(&server, &article, &auth), 204);
+    assert_eq!(server.send("GET", &article, &[], b"").status, 404);
+    let again = server.send("DELETE", &article, &auth, b"");
+    assert_eq!(again.status, 404);
+    assert_eq!(
+        again.body,
+        r#"{"error":"There's nothing here; it may have been deleted."}"#
+    );
+    assert_eq!(delete(&server, "/api/comments/999", &auth), 404);
+
+    // Restorable, as when deleted from the site
+    let cookie = server.log_in();
+    let deleted = server.send("GET", "/admin/deleted", &[("Cookie", &cookie)], b"");
+    assert!(deleted.body.contains("Doomed"), "{}", deleted.body);
+}
+
+#[test]
+fn admin_logins_delete() {
+    let server = start();
+    let (article_id, _) = post(&server);
+    let cookie = server.log_in();
+    assert_eq!(
+        delete(
+            &server,
+            &format!("/api/articles/{}", article_id),
+            &[("Cookie", &cookie)]
+        ),
+        204
+    );
+}
+
+#[test]
+fn everyone_else_is_refused() {
+    let server = start();
+    let (article_id, comment_id) = post(&server);
+    let article = format!("/api/articles/{}", article_id);
+    let comment = format!("/api/comments/{}", comment_id);
+    let write_key = server.authorization();
+
+    for path in [&article, &comment] {
+        let response = server.send("DELETE", path, &[], b"");
+        assert_eq!(response.status, 403);
+        assert_eq!(
+            response.body,
+            r#"{"error":"An admin API key or login is required"}"#
+        );
+        assert_eq!(delete(&server, path, &[("Authorization", &write_key)]), 403);
+        assert_eq!(
+            delete(&server, path, &[("Authorization", "Bearer ak_0000")]),
+            403
+        );
+    }
+    assert_eq!(server.send("GET", &article, &[], b"").status, 200);
+}
@@ -0,0 +1,57 @@
+// Writing through the API takes a key made with `articles api-key create`;
+// reading doesn't. Revoked keys stop working once the server reloads them.
+
+mod common;
+
+use common::Server;
+
+const JSON: &[u8] = br#"{"title": "Keyed", "body": "Body"}"#;
+
+fn post(server: &Server, authorization: Option<&str>) -> common::Response {
+    let mut headers = vec![("Content-Type", "application/json")];
+    if let Some(authorization) = authorization {
+        headers.push(("Authorization", authorization));
+    }
+    server.send("POST", "/api/articles", &headers, JSON)
+}
+
+#[test]
+fn writes_need_a_key() {
+    let server = Server::start(&[]);
+    let missing = post(&server, None);
+    assert_eq!(missing.status, 401);
+    assert_eq!(missing.body, r#"{"error":"An API key is required"}"#);
+    assert_eq!(missing.header("WWW-Authenticate"), Some("Bearer"));
+
+    let wrong = post(&server, Some("Bearer ak_0000"));
+    assert_eq!(wrong.status, 403);
+    assert_eq!(
+        wrong.body,
+        r#"{"error":"The API key is unknown or revoked"}"#
+    );
+    assert_eq!(post(&server, Some("Basic dXNlcjpwYXNz")).status, 401);
+
+    let created = post(&server, Some(&server.authorization()));
+    assert_eq!(created.status, 201, "{}", created.body);
+    assert_eq!(server.send("GET", "/api/articles", &[], b"").status, 200);
+}
+
+#[test]
+fn revoked_keys_stop_working() {
+    let server = Server::start(&[("API_KEY_CACHE_SECONDS", "0")]);
+    let authorization = server.authorization();
+    assert_eq!(post(&server, Some(&authorization)).status, 201);
+
+    let listed = server.run(&["api-key", "list"]);
+    let listed = String::from_utf8(listed.stdout).unwrap();
+    assert!(
+        listed.starts_with("1\ttests\twrite\tcreated "),
+        "{}",
+        listed
+    );
+    assert!(listed.trim_end().ends_with("in use"), "{}", listed);
+
+    assert!(server.run(&["api-key", "revoke", "1"]).status.success());
+    assert_eq!(post(&server, Some(&authorization)).status, 403);
+    assert!(!server.run(&["api-key", "revoke", "1"]).status.success());
+}
@@ -0,0 +1,59 @@
+// Errors logged while serving a request are recorded in the database with the
+// request's path and id, and admins can read and clear them at /admin/errors
+
+mod common;
+
+use common::{admin_password_hash, csrf_token, Server};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn errors_are_recorded_and_cleared() {
+    let hash = admin_password_hash();
+    let server = Server::start(&[("ADMIN_PASSWORD_HASH", &hash)]);
+    let cookie = server.log_in();
+
+    std::fs::remove_dir_all(server.path("uploads")).unwrap();
+    let response = server.send("GET", "/readyz", &[], b"");
+    assert_eq!(response.status, 503, "{}", response.body);
+    let id = response.header("X-Request-Id").unwrap().to_string();
+
+    // Errors are written from a background task
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let page = loop {
+        let page = server.send("GET", "/admin/errors", &[("Cookie", &cookie)], b"");
+        assert_eq!(page.status, 200, "{}", page.body);
+        if page.body.contains("writable") {
+            break page.body;
+        }
+        assert!(Instant::now() < deadline, "nothing recorded: {}", page.body);
+        thread::sleep(Duration::from_millis(50));
+    };
+    assert!(page.contains(" on /readyz"), "{}", page);
+    assert!(page.contains(&id), "{}", page);
+
+    let body = format!("csrf_token={}", csrf_token(&page));
+    let response = server.send(
+        "POST",
+        "/admin/errors/clear",
+        &[
+            ("Content-Type", "application/x-www-form-urlencoded"),
+            ("Cookie", &cookie),
+        ],
+        body.as_bytes(),
+    );
+    assert_eq!(response.status, 303, "{}", response.body);
+    let page = server.send("GET", "/admin/errors", &[("Cookie", &cookie)], b"");
+    assert!(
+        page.body.contains("No errors have been logged."),
+        "{}",
+        page.body
+    );
+}
+
+#[test]
+fn errors_page_is_for_admins() {
+    let server = Server::start(&[]);
+    let response = server.send("GET", "/admin/errors", &[], b"");
+    assert_eq!(response.status, 303, "{}", response.body);
+}
@@ -0,0 +1,68 @@
+// Article titles and bodies of the wrong length send the form back with a
+// message and what was entered; the API answers them with a 422
+
+mod common;
+
+use common::Server;
+
+fn start() -> Server {
+    Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("MAX_TITLE_CHARS", "10"),
+        ("MAX_BODY_CHARS", "20"),
+    ])
+}
+
+// Submit the article form, as opened just before, with `title` and `body`
+fn submit(server: &Server, title: &str, body: &str) -> (u16, String) {
+    let form = server.open_form("/");
+    let response = server.submit("/submit", &form, &[("title", title), ("body", body)]);
+    (response.status, response.body)
+}
+
+#[test]
+fn form_keeps_what_was_entered() {
+    let server = start();
+
+    let (status, page) = submit(&server, "   ", "A body");
+    assert_eq!(status, 422, "{}", page);
+    assert!(page.contains("Title must not be empty."), "{}", page);
+    assert!(page.contains(">A body</textarea>"), "{}", page);
+
+    let (status, page) = submit(&server, "Eleven char", "");
+    assert_eq!(status, 422, "{}", page);
+    assert!(
+        page.contains("Body must not be empty. Title must be at most 10 characters."),
+        "{}",
+        page
+    );
+    assert!(page.contains("value=\"Eleven char\""), "{}", page);
+
+    // Spaces around the title don't count; this one only lacks its file
+    let (status, page) = submit(&server, "  Ten chars!  ", "A body");
+    assert_eq!(status, 400, "{}", page);
+    assert!(page.contains("Media file is required"), "{}", page);
+}
+
+#[test]
+fn oversized_fields_are_refused_unread() {
+    let server = start();
+    let (status, page) = submit(&server, "Title", &"x".repeat(81));
+    assert_eq!(status, 400, "{}", page);
+    assert!(page.contains("The body field is too long"), "{}", page);
+}
+
+#[test]
+fn api_applies_the_same_limits() {
+    let server = start();
+    let json = br#"{"title": "Eleven char", "body": "A body"}"#;
+    let response = server.post_json("/api/articles", json);
+    assert_eq!(response.status, 422, "{}", response.body);
+    let body: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(body["fields"]["title"], "must be at most 10 characters");
+
+    let json = br#"{"title": " Ten chars! ", "body": "A body"}"#;
+    let response = server.post_json("/api/articles", json);
+    assert_eq!(response.status, 201, "{}", response.body);
+}
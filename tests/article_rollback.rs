@@ -0,0 +1,72 @@
+// An article whose media can't be recorded isn't stored at all, and the files
+// uploaded with it are removed
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use sqlx::{Connection, SqliteConnection};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+fn png() -> Vec<u8> {
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+// Every file under `dir`, thumbnails included
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(files_in(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+// Run `sql` against the server's database
+fn execute(server: &Server, sql: &str) -> i64 {
+    let url = format!("sqlite://{}", server.path("articles.db").display());
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut conn = SqliteConnection::connect(&url).await.unwrap();
+        sqlx::query_scalar(sql)
+            .fetch_optional(&mut conn)
+            .await
+            .unwrap()
+            .unwrap_or(0)
+    })
+}
+
+#[test]
+fn failed_media_insert_leaves_nothing_behind() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    execute(
+        &server,
+        "CREATE TRIGGER fail_media BEFORE INSERT ON article_media BEGIN SELECT RAISE(ABORT, 'no media today'); END",
+    );
+
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Title"),
+        text_part("body", b"Body"),
+        file_part("media", "cat.png", "image/png", &png()),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 500, "{}", response.body);
+
+    assert_eq!(execute(&server, "SELECT COUNT(*) FROM articles"), 0);
+    assert_eq!(files_in(&server.path("uploads")), Vec::<PathBuf>::new());
+
+    // The same post goes through once the database takes media again
+    execute(&server, "DROP TRIGGER fail_media");
+    let form = server.open_form("/");
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+    assert_eq!(execute(&server, "SELECT COUNT(*) FROM article_media"), 1);
+}
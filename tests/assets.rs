@@ -0,0 +1,121 @@
+// Pages link the stylesheet with its hash, and those links are cached for
+// good; plain or outdated links only briefly. Uploads are kept for a day.
+// Without a ./static directory the files built into the binary are served.
+// The icon is served at /favicon.ico either way, kept for a week.
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use std::io::Cursor;
+
+#[test]
+fn hashed_links_are_cached_for_good() {
+    let server = Server::start(&[]);
+    let page = server.send("GET", "/articles", &[], b"").body;
+    let link = page
+        .split("rel=\"stylesheet\" href=\"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    assert!(link.starts_with("/static/style.css?v="), "{}", link);
+
+    let css = server.send("GET", link, &[], b"");
+    assert_eq!(css.status, 200);
+    assert_eq!(
+        css.header("Cache-Control"),
+        Some("public, max-age=31536000, immutable")
+    );
+
+    for stale in ["/static/style.css", "/static/style.css?v=0000000000000000"] {
+        let css = server.send("GET", stale, &[], b"");
+        assert_eq!(css.status, 200);
+        assert_eq!(css.header("Cache-Control"), Some("public, max-age=300"));
+    }
+    assert_eq!(
+        server
+            .send("GET", "/static/missing.css", &[], b"")
+            .header("Cache-Control"),
+        None
+    );
+}
+
+#[test]
+fn uploads_are_cached_for_a_day() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Picture"),
+        text_part("body", b"Body"),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+
+    let page = server.send("GET", response.header("Location").unwrap(), &[], b"");
+    let upload = page
+        .body
+        .split("src=\"/uploads/")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    let upload = server.send("GET", &format!("/uploads/{}", upload), &[], b"");
+    assert_eq!(upload.status, 200);
+    assert_eq!(
+        upload.header("Cache-Control"),
+        Some("public, max-age=86400")
+    );
+}
+
+#[test]
+fn built_in_files_are_served_without_a_static_dir() {
+    let server = Server::start_without_static_dir(&[]);
+    let page = server.send("GET", "/articles", &[], b"").body;
+    let link = page
+        .split("rel=\"stylesheet\" href=\"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    assert!(link.starts_with("/static/style.css?v="), "{}", link);
+
+    let css = server.send("GET", link, &[], b"");
+    assert_eq!(css.status, 200);
+    assert_eq!(css.body, include_str!("../static/style.css"));
+    assert_eq!(css.header("Content-Type"), Some("text/css; charset=utf-8"));
+    assert_eq!(
+        css.header("Cache-Control"),
+        Some("public, max-age=31536000, immutable")
+    );
+    let etag = css.header("ETag").expect("built-in files have an ETag");
+    assert_eq!(
+        server
+            .send("GET", link, &[("If-None-Match", etag)], b"")
+            .status,
+        304
+    );
+
+    let missing = server.send("GET", "/static/missing.css", &[], b"");
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.header("Cache-Control"), None);
+}
+
+#[test]
+fn the_icon_is_served_at_the_root() {
+    for server in [Server::start(&[]), Server::start_without_static_dir(&[])] {
+        let icon = server.send("GET", "/favicon.ico", &[], b"");
+        assert_eq!(icon.status, 200);
+        assert_eq!(icon.bytes, include_bytes!("../static/favicon.ico"));
+        assert_eq!(icon.header("Content-Type"), Some("image/x-icon"));
+        assert_eq!(icon.header("Cache-Control"), Some("public, max-age=604800"));
+        assert_eq!(icon.header("Set-Cookie"), None);
+    }
+}
@@ -0,0 +1,100 @@
+// mp3, ogg and flac files can be attached: the article page plays them in an
+// <audio> element and the catalog shows a placeholder, as they have no
+// picture. MAX_AUDIO_UPLOAD_BYTES limits them in place of MAX_UPLOAD_BYTES.
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use serde_json::Value;
+
+const MP3: &[u8] = b"ID3\x04\0\0\0\0\0\0\xFF\xFB\x90\x64";
+const OGG: &[u8] = b"OggS\0\x02\0\0\0\0\0\0\0\0";
+const FLAC: &[u8] = b"fLaC\0\0\0\x22\x10\0\x10\0";
+
+fn post(server: &Server, title: &str, filename: &str, data: &[u8]) -> common::Response {
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", b"Have a listen"),
+        file_part("media", filename, "application/octet-stream", data),
+    ];
+    server.submit_parts("/submit", &form, &parts)
+}
+
+#[test]
+fn audio_is_played_in_an_audio_element() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    for (id, (filename, data, mime, extension)) in [
+        ("song.mp3", MP3, "audio/mpeg", "mp3"),
+        ("song.ogg", OGG, "audio/ogg", "ogg"),
+        ("song.flac", FLAC, "audio/flac", "flac"),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let id = id + 1;
+        let response = post(&server, &format!("Song {}", id), filename, data);
+        assert_eq!(response.status, 302, "{}", response.body);
+
+        let article: Value = serde_json::from_str(
+            &server
+                .send("GET", &format!("/api/articles/{}", id), &[], b"")
+                .body,
+        )
+        .unwrap();
+        let media = &article["media"][0];
+        assert_eq!(media["mime_type"], mime);
+        assert_eq!(media["byte_size"].as_u64(), Some(data.len() as u64));
+        assert!(media["thumb_path"].is_null(), "{}", media);
+        let path = media["media_path"].as_str().unwrap();
+        assert!(path.ends_with(&format!(".{}", extension)), "{}", path);
+
+        let page = server
+            .send("GET", &format!("/articles/{}/song-{}", id, id), &[], b"")
+            .body;
+        assert!(
+            page.contains(&format!(
+                "<audio controls preload=\"metadata\" src=\"{}\">",
+                path
+            )),
+            "{}",
+            page
+        );
+        assert!(
+            !page.contains(&format!("<img src=\"{}\"", path)),
+            "{}",
+            page
+        );
+    }
+
+    let catalog = server.send("GET", "/catalog", &[], b"").body;
+    assert_eq!(catalog.matches("♪ Audio").count(), 3, "{}", catalog);
+    let form = server.send("GET", "/", &[], b"").body;
+    assert!(form.contains(".mp3,.ogg,.oga,.flac"), "{}", form);
+}
+
+#[test]
+fn audio_has_its_own_size_limit() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("MAX_UPLOAD_BYTES", "1000"),
+        ("MAX_AUDIO_UPLOAD_BYTES", "4096"),
+    ]);
+    let form = server.send("GET", "/", &[], b"").body;
+    assert!(form.contains("(max 1000 bytes, audio 4 KB)"), "{}", form);
+
+    let mut song = MP3.to_vec();
+    song.resize(3000, 0);
+    let response = post(&server, "Long song", "song.mp3", &song);
+    assert_eq!(response.status, 302, "{}", response.body);
+
+    song.resize(5000, 0);
+    let response = post(&server, "Longer song", "song.mp3", &song);
+    assert_eq!(response.status, 413, "{}", response.body);
+
+    let mut picture = b"GIF89a".to_vec();
+    picture.resize(3000, 0);
+    let response = post(&server, "Big picture", "big.gif", &picture);
+    assert_eq!(response.status, 413, "{}", response.body);
+}
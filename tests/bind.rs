@@ -0,0 +1,44 @@
+// The server listens on every address in BIND_ADDR and names the ports it was
+// given; an address it can't use stops it at startup with the reason
+
+mod common;
+
+use common::Server;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+
+#[test]
+fn every_address_is_served() {
+    let server = Server::start(&[("BIND_ADDR", "127.0.0.1, 127.0.0.2"), ("WORKERS", "2")]);
+    let addrs = server.addrs();
+    assert_eq!(addrs.len(), 2, "{:?}", addrs);
+    assert_eq!(addrs[1].ip().to_string(), "127.0.0.2");
+    assert_ne!(addrs[1].port(), 0);
+
+    let mut stream = TcpStream::connect(addrs[1]).unwrap();
+    stream
+        .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+}
+
+#[test]
+fn invalid_addresses_are_reported() {
+    let output = Command::new(env!("CARGO_BIN_EXE_articles"))
+        .arg("serve")
+        .env("DATABASE_URL", "sqlite::memory:")
+        .env("BIND_ADDR", "127.0.0.1,not an address")
+        .env("PORT", "0")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("BIND_ADDR has an invalid address \"not an address\""),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,93 @@
+// The catalog shows the current articles as cards, newest bump first, with a
+// picture of each one's first media and the list page's pagination
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use std::io::Cursor;
+
+fn png() -> Vec<u8> {
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+fn post_with_file(server: &Server, title: &str, filename: &str, content_type: &str, file: &[u8]) {
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", b"Body"),
+        file_part("media", filename, content_type, file),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+}
+
+// Each card on the page, in order
+fn cards(page: &str) -> Vec<&str> {
+    page.split("class=\"catalog-card\"")
+        .skip(1)
+        .map(|card| &card[..card.find("</a>").unwrap()])
+        .collect()
+}
+
+#[test]
+fn cards_show_the_first_media() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let json = format!(
+        "{{\"title\": \"Plain\", \"body\": \"{}\"}}",
+        "word ".repeat(60)
+    );
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    post_with_file(&server, "Picture", "cat.png", "image/png", &png());
+    // Without ffmpeg the video never gets a poster frame
+    post_with_file(
+        &server,
+        "Clip",
+        "clip.mp4",
+        "video/mp4",
+        b"\0\0\0\x18ftypmp42\0\0\0\0mp42isom",
+    );
+
+    let response = server.send("GET", "/catalog", &[], b"");
+    assert_eq!(response.status, 200, "{}", response.body);
+    let found = cards(&response.body);
+    assert_eq!(found.len(), 3, "{}", response.body);
+    assert!(
+        found[0].contains("Clip") && found[0].contains("▶ Video"),
+        "{}",
+        found[0]
+    );
+    assert!(
+        found[1].contains("Picture") && found[1].contains("<img src=\"/uploads/"),
+        "{}",
+        found[1]
+    );
+    assert!(
+        found[2].contains("Plain") && found[2].contains("No image"),
+        "{}",
+        found[2]
+    );
+    assert!(found[2].contains("0 comments"), "{}", found[2]);
+    // The excerpt is cut to about 100 characters
+    assert!(
+        found[2].contains(&format!(">{}…<", "word ".repeat(20))),
+        "{}",
+        found[2]
+    );
+
+    let response = server.send("GET", "/catalog?page=2&per_page=1", &[], b"");
+    let found = cards(&response.body);
+    assert_eq!(found.len(), 1, "{}", response.body);
+    assert!(found[0].contains("Picture"), "{}", found[0]);
+    assert!(
+        response
+            .body
+            .contains("href=\"/catalog?page=3&#38;per_page=1\""),
+        "{}",
+        response.body
+    );
+}
@@ -0,0 +1,56 @@
+// Comments are stored trimmed; empty ones get a page back to the article, and
+// ones over MAX_COMMENT_CHARS are sent back to be shortened
+
+mod common;
+
+use common::Server;
+
+fn comment(server: &Server, text: &str) -> (u16, String) {
+    let form = server.open_form("/articles/1/title");
+    let response = server.submit("/articles/1/comment", &form, &[("comment", text)]);
+    (response.status, response.body)
+}
+
+fn stored_comments(server: &Server) -> Vec<String> {
+    let article = server.send("GET", "/api/articles/1", &[], b"");
+    let article: serde_json::Value = serde_json::from_str(&article.body).unwrap();
+    article["comments"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["comment"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn comments_need_text_within_the_limit() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("MAX_COMMENT_CHARS", "10"),
+    ]);
+    let json = br#"{"title": "Title", "body": "Body"}"#;
+    let created = server.post_json("/api/articles", json);
+    assert_eq!(created.status, 201, "{}", created.body);
+
+    for empty in ["", "   \r\n  "] {
+        let (status, page) = comment(&server, empty);
+        assert_eq!(status, 400, "{}", page);
+        assert!(page.contains("A comment needs some text"), "{}", page);
+        assert!(page.contains("href=\"/articles/1\""), "{}", page);
+    }
+
+    let (status, page) = comment(&server, "ünïcödé 10\n ");
+    assert_eq!(status, 302, "{}", page);
+
+    let (status, page) = comment(&server, "eleven char");
+    assert_eq!(status, 422, "{}", page);
+    assert!(
+        page.contains("Comments are limited to 10 characters, and this one has 11."),
+        "{}",
+        page
+    );
+    assert!(page.contains(">eleven char</textarea>"), "{}", page);
+
+    assert_eq!(stored_comments(&server), ["ünïcödé 10"]);
+}
@@ -0,0 +1,431 @@
+// A server started against a fresh SQLite database, for the integration tests
+
+// Each test file uses only some of this
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Numbers the scratch directories of the servers a test process starts
+static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+// A running server, killed along with its scratch directory when dropped
+pub struct Server {
+    child: Child,
+    // Kept open, so the server can go on writing to it
+    stdout: BufReader<ChildStdout>,
+    // What it said it listens on, such as http://127.0.0.1:41234
+    listening: Vec<String>,
+    addrs: Vec<SocketAddr>,
+    dir: PathBuf,
+    // Made the first time it's asked for
+    api_key: OnceLock<String>,
+}
+
+pub struct Response {
+    pub status: u16,
+    // The status line and headers, as sent
+    pub head: String,
+    pub body: String,
+    // The body as sent, for ones that aren't text
+    pub bytes: Vec<u8>,
+}
+
+impl Response {
+    // The value of the first `name` header
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+impl Server {
+    // `env` is set on top of the defaults, which listen on a free port of
+    // 127.0.0.1 and exempt localhost from rate limits
+    pub fn start(env: &[(&str, &str)]) -> Server {
+        Server::launch(env, false)
+    }
+
+    // The same, run from the scratch directory where there's no ./static, so
+    // the static files built into the binary are served
+    pub fn start_without_static_dir(env: &[(&str, &str)]) -> Server {
+        Server::launch(env, true)
+    }
+
+    fn launch(env: &[(&str, &str)], in_scratch_dir: bool) -> Server {
+        let n = STARTED.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("articles-test-{}-{}", std::process::id(), n));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_articles"));
+        if in_scratch_dir {
+            command.current_dir(&dir);
+        }
+        let mut child = command
+            .arg("serve")
+            .env("BIND_ADDR", "127.0.0.1")
+            .env("PORT", "0")
+            .env(
+                "DATABASE_URL",
+                format!("sqlite://{}", dir.join("articles.db").display()),
+            )
+            .env("UPLOADS_DIR", dir.join("uploads"))
+            .env("ERROR_LOG_PATH", dir.join("error.txt"))
+            .env("PUBLIC_URL", "https://example.com/")
+            .env("RATE_LIMIT_EXEMPT_LOCALHOST", "true")
+            .env_remove("DEFAULT_BOARD")
+            .env_remove("SKIP_MIGRATIONS")
+            .env_remove("STORAGE_BACKEND")
+            .env_remove("WORKERS")
+            .env_remove("LISTEN_UDS")
+            .env_remove("TLS_CERT_PATH")
+            .env_remove("TLS_KEY_PATH")
+            .env_remove("TLS_REDIRECT_PORT")
+            .envs(env.iter().copied())
+            .stdout(Stdio::piped())
+            .stderr(std::fs::File::create(dir.join("stderr.txt")).unwrap())
+            .spawn()
+            .unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut server = Server {
+            child,
+            stdout,
+            listening: Vec::new(),
+            addrs: Vec::new(),
+            dir,
+            api_key: OnceLock::new(),
+        };
+
+        // The server names the addresses it listens on once it's bound them,
+        // after reporting the migrations it applied
+        let mut line = String::new();
+        let addrs = loop {
+            line.clear();
+            if server.stdout.read_line(&mut line).unwrap() == 0 {
+                panic!(
+                    "server did not start: {}",
+                    std::fs::read_to_string(server.path("stderr.txt")).unwrap()
+                );
+            }
+            if let Some(addrs) = line.trim().strip_prefix("Listening on ") {
+                break addrs;
+            }
+        };
+        server.listening = addrs.split(", ").map(str::to_string).collect();
+        server.addrs = server
+            .listening
+            .iter()
+            .filter_map(|url| url.strip_prefix("http://"))
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        server
+    }
+
+    pub fn listening(&self) -> &[String] {
+        &self.listening
+    }
+
+    // Where the server listens for plain HTTP over TCP, in the order of
+    // BIND_ADDR
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    // A file in the server's scratch directory, which holds its database
+    // (articles.db), uploads/, error.txt and what it logged to stderr.txt
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // Send a request with the given headers and body
+    pub fn send(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> Response {
+        let mut stream = self.connect();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n",
+            method, path
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+        read_response(&mut stream)
+    }
+
+    // POST `json` to `path` with an API key, as the API's writes need
+    pub fn post_json(&self, path: &str, json: &[u8]) -> Response {
+        let authorization = self.authorization();
+        self.send(
+            "POST",
+            path,
+            &[
+                ("Content-Type", "application/json"),
+                ("Authorization", &authorization),
+            ],
+            json,
+        )
+    }
+
+    // An Authorization header value with a key made for this server
+    pub fn authorization(&self) -> String {
+        let key = self.api_key.get_or_init(|| {
+            let output = self.run(&["api-key", "create", "tests"]);
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            String::from_utf8(output.stdout)
+                .unwrap()
+                .lines()
+                .last()
+                .unwrap()
+                .to_string()
+        });
+        format!("Bearer {}", key)
+    }
+
+    // Run a command, such as ["list-bans"], against the server's database
+    // and uploads
+    pub fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_articles"))
+            .args(args)
+            .env(
+                "DATABASE_URL",
+                format!("sqlite://{}", self.dir.join("articles.db").display()),
+            )
+            .env("UPLOADS_DIR", self.dir.join("uploads"))
+            .output()
+            .unwrap()
+    }
+
+    // A connection to write a request to by hand, e.g. slowly
+    pub fn connect(&self) -> TcpStream {
+        TcpStream::connect(self.addrs[0]).unwrap()
+    }
+
+    // Send the server a signal, such as "TERM"
+    pub fn signal(&self, name: &str) {
+        let status = Command::new("kill")
+            .args([format!("-{}", name), self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success(), "kill -{} failed", name);
+    }
+
+    // Wait up to `timeout` for the server to exit, returning whether it
+    // exited successfully
+    pub fn wait_for_exit(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.success();
+            }
+            assert!(Instant::now() < deadline, "server did not exit");
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+// Read a whole response, up to the server closing the connection
+pub fn read_response(stream: &mut TcpStream) -> Response {
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(response[..split].to_vec()).unwrap();
+    let body = &response[split + 4..];
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    // Responses without a known length arrive chunked
+    let bytes = if head
+        .to_ascii_lowercase()
+        .contains("transfer-encoding: chunked")
+    {
+        dechunk(body)
+    } else {
+        body.to_vec()
+    };
+    Response {
+        status,
+        head,
+        body: String::from_utf8_lossy(&bytes).into_owned(),
+        bytes,
+    }
+}
+
+// A page's form as a browser would have it: the CSRF cookie the page set and
+// the fill-time token in the form
+pub struct Form {
+    pub cookie: String,
+    pub form_token: String,
+}
+
+impl Server {
+    pub fn open_form(&self, path: &str) -> Form {
+        let page = self.send("GET", path, &[], b"");
+        let cookie = page
+            .header("Set-Cookie")
+            .expect("form pages set a CSRF cookie");
+        let form_token = page
+            .body
+            .split("name=\"form_token\" value=\"")
+            .nth(1)
+            .expect("the page has a form");
+        Form {
+            cookie: cookie.split(';').next().unwrap().to_string(),
+            form_token: form_token.split('"').next().unwrap().to_string(),
+        }
+    }
+
+    // POST `form`'s tokens and `fields` to `path` as multipart/form-data
+    pub fn submit(&self, path: &str, form: &Form, fields: &[(&str, &str)]) -> Response {
+        let parts: Vec<_> = fields
+            .iter()
+            .map(|(name, value)| text_part(name, value.as_bytes()))
+            .collect();
+        self.submit_parts(path, form, &parts)
+    }
+
+    // The same with any parts, such as file_part's
+    pub fn submit_parts(&self, path: &str, form: &Form, parts: &[Vec<u8>]) -> Response {
+        let csrf_token = form.cookie.split_once('=').unwrap().1;
+        let mut body = text_part("csrf_token", csrf_token.as_bytes());
+        body.extend_from_slice(&text_part("form_token", form.form_token.as_bytes()));
+        for part in parts {
+            body.extend_from_slice(part);
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+        self.send(
+            "POST",
+            path,
+            &[("Content-Type", &content_type), ("Cookie", &form.cookie)],
+            &body,
+        )
+    }
+}
+
+// The admin password log_in uses; start the server with ADMIN_PASSWORD_HASH
+// set to admin_password_hash()
+pub const ADMIN_PASSWORD: &str = "correct horse";
+
+pub fn admin_password_hash() -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_articles"))
+        .arg("hash-password")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    writeln!(child.stdin.take().unwrap(), "{}", ADMIN_PASSWORD).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+// The name=value of every cookie a response sets
+pub fn cookies(response: &Response) -> Vec<String> {
+    response
+        .head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+        .map(|(_, value)| value.trim().split(';').next().unwrap().to_string())
+        .collect()
+}
+
+// The CSRF token a page's forms carry
+pub fn csrf_token(page: &str) -> String {
+    let token = page
+        .split("name=\"csrf_token\" value=\"")
+        .nth(1)
+        .expect("the page has a form");
+    token.split('"').next().unwrap().to_string()
+}
+
+impl Server {
+    // Log in as the admin, returning the Cookie header to send from then on
+    pub fn log_in(&self) -> String {
+        let page = self.send("GET", "/admin/login", &[], b"");
+        let csrf_cookie = cookies(&page).join("; ");
+        let body = format!(
+            "csrf_token={}&password={}",
+            csrf_token(&page.body),
+            ADMIN_PASSWORD.replace(' ', "+")
+        );
+        let response = self.send(
+            "POST",
+            "/admin/login",
+            &[
+                ("Content-Type", "application/x-www-form-urlencoded"),
+                ("Cookie", &csrf_cookie),
+            ],
+            body.as_bytes(),
+        );
+        assert_eq!(response.status, 303, "{}", response.body);
+        let mut all = vec![csrf_cookie];
+        all.extend(cookies(&response));
+        all.join("; ")
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Pieces of a multipart/form-data body; it ends with "--BOUNDARY--\r\n"
+pub const BOUNDARY: &str = "----articles-test-boundary";
+
+pub fn part(headers: &str, value: &[u8]) -> Vec<u8> {
+    let mut part = format!("--{}\r\n{}\r\n\r\n", BOUNDARY, headers).into_bytes();
+    part.extend_from_slice(value);
+    part.extend_from_slice(b"\r\n");
+    part
+}
+
+pub fn text_part(name: &str, value: &[u8]) -> Vec<u8> {
+    part(
+        &format!("Content-Disposition: form-data; name=\"{}\"", name),
+        value,
+    )
+}
+
+pub fn file_part(name: &str, filename: &str, content_type: &str, value: &[u8]) -> Vec<u8> {
+    let headers = format!(
+        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}",
+        name, filename, content_type
+    );
+    part(&headers, value)
+}
+
+fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(line_end) = body.windows(2).position(|w| w == b"\r\n") {
+        let size = std::str::from_utf8(&body[..line_end]).unwrap();
+        let size = usize::from_str_radix(size.trim(), 16).unwrap();
+        if size == 0 {
+            break;
+        }
+        let rest = &body[line_end + 2..];
+        out.extend_from_slice(&rest[..size]);
+        body = &rest[size + 2..];
+    }
+    out
+}
@@ -0,0 +1,117 @@
+// Pages and JSON are compressed for clients that accept it; uploads, which
+// are compressed already, aren't. Compressed files keep a (weak) ETag that
+// still answers revalidation with a 304.
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::io::{Cursor, Read};
+
+const GZIP: (&str, &str) = ("Accept-Encoding", "gzip");
+
+fn gunzip(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    GzDecoder::new(bytes).read_to_string(&mut text).unwrap();
+    text
+}
+
+#[test]
+fn pages_and_json_are_gzipped_on_request() {
+    let server = Server::start(&[]);
+
+    let plain = server.send("GET", "/articles", &[], b"");
+    assert_eq!(plain.header("Content-Encoding"), None);
+    assert!(plain.body.contains("</html>"), "{}", plain.body);
+
+    let gzipped = server.send("GET", "/articles", &[GZIP], b"");
+    assert_eq!(gzipped.status, 200);
+    assert_eq!(gzipped.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(gzipped.header("Vary"), Some("accept-encoding"));
+    assert!(gunzip(&gzipped.bytes).contains("</html>"));
+
+    let json = server.send("GET", "/api/articles", &[GZIP], b"");
+    assert_eq!(json.header("Content-Encoding"), Some("gzip"));
+    let plain: serde_json::Value =
+        serde_json::from_str(&server.send("GET", "/api/articles", &[], b"").body).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&gunzip(&json.bytes)).unwrap(),
+        plain
+    );
+}
+
+#[test]
+fn uploads_are_sent_as_they_are() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Picture"),
+        text_part("body", b"Body"),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+
+    let page = server.send("GET", response.header("Location").unwrap(), &[], b"");
+    let upload = page
+        .body
+        .split("src=\"/uploads/")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    let upload = server.send("GET", &format!("/uploads/{}", upload), &[GZIP], b"");
+    assert_eq!(upload.status, 200);
+    assert_eq!(upload.header("Content-Encoding"), None);
+    assert!(upload.bytes.starts_with(b"\x89PNG"));
+
+    // Audio too, though Compress would take it for compressible
+    let mp3 = [
+        b"ID3\x04\0\0\0\0\0\0\xFF\xFB\x90\x64".as_slice(),
+        &[0; 4096],
+    ]
+    .concat();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Song"),
+        text_part("body", b"Have a listen"),
+        file_part("media", "song.mp3", "audio/mpeg", &mp3),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+    let article: Value =
+        serde_json::from_str(&server.send("GET", "/api/articles/2", &[], b"").body).unwrap();
+    let upload = article["media"][0]["media_path"].as_str().unwrap();
+    let upload = server.send("GET", upload, &[GZIP], b"");
+    assert_eq!(upload.status, 200);
+    assert_eq!(upload.header("Content-Type"), Some("audio/mpeg"));
+    assert_ne!(upload.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(upload.bytes, mp3);
+}
+
+#[test]
+fn compressed_files_can_be_revalidated() {
+    let server = Server::start(&[]);
+    let plain = server.send("GET", "/static/style.css", &[], b"");
+    let strong = plain.header("ETag").unwrap();
+    assert!(strong.starts_with('"'), "{}", strong);
+
+    let gzipped = server.send("GET", "/static/style.css", &[GZIP], b"");
+    assert_eq!(gzipped.header("Content-Encoding"), Some("gzip"));
+    let weak = gzipped.header("ETag").unwrap();
+    assert_eq!(weak, format!("W/{}", strong));
+
+    let revalidated = server.send(
+        "GET",
+        "/static/style.css",
+        &[GZIP, ("If-None-Match", weak)],
+        b"",
+    );
+    assert_eq!(revalidated.status, 304);
+}
@@ -0,0 +1,97 @@
+// Article pages and the article list carry an ETag and Last-Modified; asking
+// again with either gets an empty 304 until a comment changes the page
+
+mod common;
+
+use common::{Form, Server};
+
+fn start() -> Server {
+    Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")])
+}
+
+// Post an article, returning the path of its page
+fn post_article(server: &Server) -> String {
+    let json = "{\"title\": \"Cached\", \"body\": \"Body\"}";
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    let id = serde_json::from_str::<serde_json::Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap();
+    format!("/articles/{}/cached", id)
+}
+
+// Comment on the article at `path` through `form`, without bumping it with
+// `sage`
+fn comment(server: &Server, path: &str, form: &Form, sage: bool) {
+    let comment_path = format!("{}/comment", path.trim_end_matches("/cached"));
+    let mut fields = vec![("comment", "New")];
+    if sage {
+        fields.push(("sage", "on"));
+    }
+    let response = server.submit(&comment_path, form, &fields);
+    assert_eq!(response.status, 302, "{}", response.body);
+}
+
+#[test]
+fn article_pages_revalidate_until_commented_on() {
+    let server = start();
+    let path = &post_article(&server);
+    let form = server.open_form(path);
+    let cookie = ("Cookie", form.cookie.as_str());
+
+    let page = server.send("GET", path, &[cookie], b"");
+    assert_eq!(page.status, 200);
+    assert_eq!(page.header("Cache-Control"), Some("private, no-cache"));
+    let etag = page.header("ETag").expect("article pages have an ETag");
+    let last_modified = page
+        .header("Last-Modified")
+        .expect("article pages have a Last-Modified");
+
+    let again = server.send("GET", path, &[cookie, ("If-None-Match", etag)], b"");
+    assert_eq!(again.status, 304);
+    assert!(again.bytes.is_empty());
+    assert_eq!(again.header("ETag"), Some(etag));
+    let again = server.send(
+        "GET",
+        path,
+        &[cookie, ("If-Modified-Since", last_modified)],
+        b"",
+    );
+    assert_eq!(again.status, 304);
+
+    // Another visitor's page has their own CSRF token in it
+    assert_eq!(
+        server
+            .send("GET", path, &[("If-None-Match", etag)], b"")
+            .status,
+        200
+    );
+
+    // A sage comment leaves bump_time alone but still changes the page
+    comment(&server, path, &form, true);
+    let page = server.send("GET", path, &[cookie, ("If-None-Match", etag)], b"");
+    assert_eq!(page.status, 200);
+    assert!(page.body.contains("New"));
+    assert_ne!(page.header("ETag"), Some(etag));
+}
+
+#[test]
+fn the_article_list_revalidates_until_commented_on() {
+    let server = start();
+    let path = post_article(&server);
+
+    let list = server.send("GET", "/articles", &[], b"");
+    let etag = list.header("ETag").expect("the article list has an ETag");
+    assert!(list.header("Last-Modified").is_some());
+    let again = server.send("GET", "/articles", &[("If-None-Match", etag)], b"");
+    assert_eq!(again.status, 304);
+    assert!(again.bytes.is_empty());
+
+    comment(&server, &path, &server.open_form(&path), false);
+    assert_eq!(
+        server
+            .send("GET", "/articles", &[("If-None-Match", etag)], b"")
+            .status,
+        200
+    );
+}
@@ -0,0 +1,162 @@
+// /api answers CORS_ALLOWED_ORIGINS with CORS headers, preflight included;
+// other origins and the HTML pages get none
+
+mod common;
+
+use common::Server;
+
+const ALLOWED: &str = "https://app.example.com";
+
+fn start() -> Server {
+    Server::start(&[(
+        "CORS_ALLOWED_ORIGINS",
+        "https://app.example.com, https://other.example.com/",
+    )])
+}
+
+#[test]
+fn allowed_origins_may_read_the_api() {
+    let server = start();
+    let response = server.send("GET", "/api/articles", &[("Origin", ALLOWED)], b"");
+    assert_eq!(response.status, 200, "{}", response.body);
+    assert_eq!(
+        response.header("Access-Control-Allow-Origin"),
+        Some(ALLOWED)
+    );
+    // The paging headers, and the request id for reporting problems
+    let exposed = response
+        .header("Access-Control-Expose-Headers")
+        .unwrap()
+        .to_ascii_lowercase();
+    let mut exposed: Vec<&str> = exposed.split(',').map(str::trim).collect();
+    exposed.sort();
+    assert_eq!(exposed, ["link", "x-request-id", "x-total-count"]);
+
+    // Errors too, so the front end can show them
+    let missing = server.send("GET", "/api/articles/999", &[("Origin", ALLOWED)], b"");
+    assert_eq!(missing.status, 404);
+    assert_eq!(missing.header("Access-Control-Allow-Origin"), Some(ALLOWED));
+}
+
+#[test]
+fn listings_page_through_headers_too() {
+    let server = start();
+    let authorization = server.authorization();
+    for title in ["One", "Two", "Three"] {
+        let json = format!("{{\"title\": \"{}\", \"body\": \"Body\"}}", title);
+        let headers = [
+            ("Content-Type", "application/json"),
+            ("Authorization", authorization.as_str()),
+        ];
+        assert_eq!(
+            server
+                .send("POST", "/api/articles", &headers, json.as_bytes())
+                .status,
+            201
+        );
+    }
+    let response = server.send(
+        "GET",
+        "/api/articles?sort=new&limit=1&offset=1",
+        &[("Origin", ALLOWED)],
+        b"",
+    );
+    assert_eq!(response.status, 200, "{}", response.body);
+    assert_eq!(response.header("X-Total-Count"), Some("3"));
+    assert_eq!(
+        response.header("Link"),
+        Some(
+            "</api/articles?sort=new&limit=1&offset=0>; rel=\"prev\", \
+             </api/articles?sort=new&limit=1&offset=2>; rel=\"next\""
+        )
+    );
+    let last = server.send("GET", "/api/search?q=body&limit=2&offset=2", &[], b"");
+    assert_eq!(last.header("X-Total-Count"), Some("3"));
+    assert_eq!(
+        last.header("Link"),
+        Some("</api/search?q=body&limit=2&offset=0>; rel=\"prev\"")
+    );
+}
+
+#[test]
+fn other_origins_are_refused() {
+    let server = start();
+    // Answered, but without the header the browser won't show it the answer
+    let response = server.send(
+        "GET",
+        "/api/articles",
+        &[("Origin", "https://evil.example.com")],
+        b"",
+    );
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+
+    // Pages stay same-origin only, whatever the origin
+    let page = server.send("GET", "/articles", &[("Origin", ALLOWED)], b"");
+    assert_eq!(page.status, 200);
+    assert_eq!(page.header("Access-Control-Allow-Origin"), None);
+}
+
+#[test]
+fn json_posts_are_preflighted() {
+    let server = start();
+    let preflight = [
+        ("Origin", ALLOWED),
+        ("Access-Control-Request-Method", "POST"),
+        (
+            "Access-Control-Request-Headers",
+            "content-type,authorization",
+        ),
+    ];
+    let response = server.send("OPTIONS", "/api/articles", &preflight, b"");
+    assert_eq!(response.status, 200, "{}", response.body);
+    assert_eq!(
+        response.header("Access-Control-Allow-Origin"),
+        Some(ALLOWED)
+    );
+    let methods = response.header("Access-Control-Allow-Methods").unwrap();
+    assert!(
+        methods.contains("POST") && methods.contains("DELETE") && !methods.contains("PATCH"),
+        "{}",
+        methods
+    );
+    let headers = response.header("Access-Control-Allow-Headers").unwrap();
+    assert!(
+        headers.contains("content-type") && headers.contains("authorization"),
+        "{}",
+        headers
+    );
+
+    let json = "{\"title\": \"From afar\", \"body\": \"Body\"}";
+    let authorization = server.authorization();
+    let headers = [
+        ("Origin", ALLOWED),
+        ("Content-Type", "application/json"),
+        ("Authorization", &authorization),
+    ];
+    let response = server.send("POST", "/api/articles", &headers, json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    assert_eq!(
+        response.header("Access-Control-Allow-Origin"),
+        Some(ALLOWED)
+    );
+
+    let unknown = [
+        ("Origin", "https://evil.example.com"),
+        ("Access-Control-Request-Method", "POST"),
+    ];
+    assert_eq!(
+        server
+            .send("OPTIONS", "/api/articles", &unknown, b"")
+            .status,
+        400
+    );
+}
+
+#[test]
+fn nothing_is_shared_unless_configured() {
+    let server = Server::start(&[]);
+    let response = server.send("GET", "/api/articles", &[("Origin", ALLOWED)], b"");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Access-Control-Allow-Origin"), None);
+}
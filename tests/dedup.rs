@@ -0,0 +1,82 @@
+// An upload identical to one already stored points at the stored files
+// rather than keeping a second copy, and those stay until no post uses them
+
+mod common;
+
+use common::{admin_password_hash, file_part, text_part, Server};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+
+fn post(server: &Server, title: &str, png: &[u8]) {
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", title.as_bytes()),
+        file_part("media", "cat.png", "image/png", png),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+}
+
+fn media_path(server: &Server, id: i32) -> String {
+    let article: Value = serde_json::from_str(
+        &server
+            .send("GET", &format!("/api/articles/{}", id), &[], b"")
+            .body,
+    )
+    .unwrap();
+    article["media"][0]["media_path"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+// Upload files, leaving out thumbnails
+fn stored(server: &Server) -> usize {
+    fs::read_dir(server.path("uploads"))
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_type().unwrap().is_file())
+        .count()
+}
+
+#[test]
+fn identical_uploads_share_their_files() {
+    let server = Server::start(&[
+        ("ADMIN_PASSWORD_HASH", &admin_password_hash()),
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+    ]);
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    post(&server, "First", &png);
+    post(&server, "Second", &png);
+    let shared = media_path(&server, 1);
+    assert_eq!(media_path(&server, 2), shared);
+    assert_eq!(stored(&server), 1);
+
+    let hash: String = Sha256::digest(&png)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let cookie = server.log_in();
+    let page = server
+        .send("GET", "/articles/1/first", &[("Cookie", &cookie)], b"")
+        .body;
+    assert!(page.contains(&hash), "{}", page);
+    assert!(!server
+        .send("GET", "/articles/1/first", &[], b"")
+        .body
+        .contains(&hash));
+
+    // The first article goes, and the second still has its picture
+    assert!(server.run(&["prune", "--keep", "1"]).status.success());
+    assert_eq!(server.send("GET", "/api/articles/1", &[], b"").status, 404);
+    assert_eq!(server.send("GET", &shared, &[], b"").status, 200);
+
+    assert!(server.run(&["prune", "--keep", "0"]).status.success());
+    assert_eq!(stored(&server), 0);
+}
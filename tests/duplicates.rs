@@ -0,0 +1,58 @@
+// A comment sent twice in a row is stored once, and both copies are sent to it
+
+mod common;
+
+use common::Server;
+
+fn post_twice(server: &Server, text: &str) -> [String; 2] {
+    [(); 2].map(|_| {
+        let form = server.open_form("/articles/1/title");
+        let response = server.submit("/articles/1/comment", &form, &[("comment", text)]);
+        assert_eq!(response.status, 302, "{}", response.body);
+        response.header("Location").unwrap().to_string()
+    })
+}
+
+fn start(window: &str) -> Server {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("DUPLICATE_WINDOW_SECONDS", window),
+    ]);
+    let json = br#"{"title": "Title", "body": "Body"}"#;
+    let created = server.post_json("/api/articles", json);
+    assert_eq!(created.status, 201, "{}", created.body);
+    server
+}
+
+fn comment_count(server: &Server) -> usize {
+    let article = server.send("GET", "/api/articles/1", &[], b"");
+    let article: serde_json::Value = serde_json::from_str(&article.body).unwrap();
+    article["comments"].as_array().unwrap().len()
+}
+
+#[test]
+fn copies_go_to_the_original() {
+    let server = start("300");
+    assert_eq!(
+        post_twice(&server, "Hello"),
+        ["/articles/1#c1", "/articles/1#c1"]
+    );
+    assert_eq!(comment_count(&server), 1);
+    // Different text is a different comment
+    assert_eq!(
+        post_twice(&server, "Hello again"),
+        ["/articles/1#c2", "/articles/1#c2"]
+    );
+    assert_eq!(comment_count(&server), 2);
+}
+
+#[test]
+fn zero_window_stores_every_copy() {
+    let server = start("0");
+    assert_eq!(
+        post_twice(&server, "Hello"),
+        ["/articles/1#c1", "/articles/1#c2"]
+    );
+    assert_eq!(comment_count(&server), 2);
+}
@@ -0,0 +1,61 @@
+// Missing pages get the site's styled 404 page, and the API a JSON one
+
+mod common;
+
+use common::Server;
+
+#[test]
+fn missing_pages_are_styled_404s() {
+    let server = Server::start(&[]);
+    for path in [
+        "/articles/999999",
+        "/nonexistent",
+        "/b/main/articles/999999/some-slug",
+    ] {
+        let response = server.send("GET", path, &[], b"");
+        assert_eq!(response.status, 404, "{}", path);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html"),
+            "{}",
+            path
+        );
+        assert!(
+            response
+                .body
+                .contains("<link rel=\"stylesheet\" href=\"/static/style.css?v="),
+            "{}",
+            response.body
+        );
+        assert!(
+            response.body.contains("<h1>404 Not Found</h1>"),
+            "{}",
+            response.body
+        );
+        assert!(
+            response.body.contains("<a href=\"/articles\">"),
+            "{}",
+            response.body
+        );
+    }
+}
+
+#[test]
+fn missing_api_paths_are_json_404s() {
+    let server = Server::start(&[]);
+    for path in ["/api/articles/999999", "/api/nonexistent"] {
+        let response = server.send("GET", path, &[], b"");
+        assert_eq!(response.status, 404, "{}", path);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("application/json"),
+            "{}",
+            path
+        );
+        assert!(
+            response.body.starts_with("{\"error\":"),
+            "{}",
+            response.body
+        );
+    }
+}
@@ -0,0 +1,110 @@
+// Uploaded photos are stored without their EXIF metadata, turned the way
+// their orientation said, and images that don't decode are refused
+
+mod common;
+
+use common::{file_part, text_part, Response, Server};
+use serde_json::Value;
+use std::io::Cursor;
+
+// A JPEG `width` by `height` with an EXIF block giving its orientation and a
+// GPS latitude
+fn photo(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+    let mut jpeg = Vec::new();
+    image::RgbImage::new(width, height)
+        .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+        .unwrap();
+
+    // Little-endian TIFF: IFD0 with the orientation and a pointer to the GPS
+    // IFD, which has GPSLatitudeRef
+    let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+    tiff.extend([2, 0]);
+    tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0]);
+    tiff.extend(orientation.to_le_bytes());
+    tiff.extend([0, 0]);
+    tiff.extend([0x25, 0x88, 4, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+    tiff.extend([0, 0, 0, 0]);
+    tiff.extend([1, 0]);
+    tiff.extend([1, 0, 2, 0, 2, 0, 0, 0, b'N', 0, 0, 0]);
+    tiff.extend([0, 0, 0, 0]);
+
+    let mut app1 = vec![0xff, 0xe1];
+    app1.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+    app1.extend(b"Exif\0\0");
+    app1.extend(tiff);
+    jpeg.splice(2..2, app1);
+    jpeg
+}
+
+fn post(server: &Server, title: &str, jpeg: &[u8]) -> Response {
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", title.as_bytes()),
+        file_part("media", "photo.jpg", "image/jpeg", jpeg),
+    ];
+    server.submit_parts("/submit", &form, &parts)
+}
+
+fn stored(server: &Server, id: i32) -> (Value, Vec<u8>) {
+    let article: Value = serde_json::from_str(
+        &server
+            .send("GET", &format!("/api/articles/{}", id), &[], b"")
+            .body,
+    )
+    .unwrap();
+    let media = article["media"][0].clone();
+    let path = media["media_path"]
+        .as_str()
+        .unwrap()
+        .trim_start_matches("/uploads/");
+    let file = std::fs::read(server.path("uploads").join(path)).unwrap();
+    (media, file)
+}
+
+#[test]
+fn photos_lose_their_exif() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let jpeg = photo(4, 2, 6);
+    assert_eq!(
+        image::ImageReader::new(Cursor::new(&jpeg))
+            .with_guessed_format()
+            .unwrap()
+            .into_dimensions()
+            .unwrap(),
+        (4, 2)
+    );
+    assert_eq!(post(&server, "Sideways", &jpeg).status, 302);
+
+    let (media, file) = stored(&server, 1);
+    assert!(!file.windows(4).any(|w| w == b"Exif"));
+    // Turned a quarter, so it no longer needs the orientation
+    assert_eq!(
+        (media["width"].as_i64(), media["height"].as_i64()),
+        (Some(2), Some(4))
+    );
+    assert_eq!(media["byte_size"].as_u64(), Some(file.len() as u64));
+    assert!(image::load_from_memory(&file).is_ok());
+
+    let mut broken = jpeg[..jpeg.len() / 2].to_vec();
+    broken.extend([0; 64]);
+    let refused = post(&server, "Broken", &broken);
+    assert_eq!(refused.status, 400);
+    assert!(
+        refused.body.contains("The image couldn&#39;t be read"),
+        "{}",
+        refused.body
+    );
+}
+
+#[test]
+fn stripping_can_be_turned_off() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("STRIP_EXIF", "false"),
+    ]);
+    let jpeg = photo(4, 2, 1);
+    assert_eq!(post(&server, "Kept", &jpeg).status, 302);
+    assert_eq!(stored(&server, 1).1, jpeg);
+}
@@ -0,0 +1,132 @@
+// Admins download every board, article and comment as one JSON document, or
+// write it with `articles export`, which can tar the uploads along with it
+
+mod common;
+
+use common::{admin_password_hash, file_part, text_part, Server};
+use serde_json::Value;
+use std::io::Cursor;
+
+fn start() -> Server {
+    Server::start(&[
+        ("ADMIN_PASSWORD_HASH", &admin_password_hash()),
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+    ])
+}
+
+fn post_article(server: &Server, title: &str) -> i64 {
+    let json = format!("{{\"title\": \"{}\", \"body\": \"Body\"}}", title);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    serde_json::from_str::<Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap()
+}
+
+#[test]
+fn admins_download_everything_nested() {
+    let server = start();
+    let first = post_article(&server, "First");
+    let form = server.open_form(&format!("/articles/{}/first", first));
+    let response = server.submit(
+        &format!("/articles/{}/comment", first),
+        &form,
+        &[("comment", "Me too")],
+    );
+    assert_eq!(response.status, 302, "{}", response.body);
+    let second = post_article(&server, "Second");
+    let cookie = server.log_in();
+    let deleted = server.send(
+        "DELETE",
+        &format!("/api/articles/{}", second),
+        &[("Cookie", &cookie)],
+        b"",
+    );
+    assert_eq!(deleted.status, 204);
+
+    let refused = server.send("GET", "/admin/export", &[], b"");
+    assert_ne!(refused.status, 200);
+    assert!(!refused.body.contains("articles-export"));
+
+    let response = server.send("GET", "/admin/export", &[("Cookie", &cookie)], b"");
+    assert_eq!(response.status, 200);
+    assert!(response
+        .header("Content-Disposition")
+        .is_some_and(|value| value.starts_with("attachment")));
+    assert!(
+        response
+            .body
+            .starts_with("{\"format\":\"articles-export\",\"version\":1,"),
+        "{}",
+        response.body
+    );
+    let export: Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(export["boards"][0]["slug"], "main");
+    let articles = export["articles"].as_array().unwrap();
+    assert_eq!(articles.len(), 2);
+    assert_eq!(articles[0]["id"], first);
+    assert_eq!(articles[0]["title"], "First");
+    assert!(articles[0]["deleted_at"].is_null());
+    assert_eq!(articles[0]["comments"][0]["comment"], "Me too");
+    assert_eq!(articles[1]["id"], second);
+    assert!(articles[1]["deleted_at"].is_i64());
+}
+
+#[test]
+fn the_command_writes_it_with_or_without_the_files() {
+    let server = start();
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Picture"),
+        text_part("body", b"Body"),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+
+    let out = server.path("dump.json");
+    let output = server.run(&["export", "--out", out.to_str().unwrap()]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let export: Value = serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+    assert_eq!(export["version"], 1);
+    let media = &export["articles"][0]["media"][0];
+    assert_eq!(media["original_name"], "cat.png");
+    let media_path = media["media_path"].as_str().unwrap();
+    assert!(media_path.starts_with("/uploads/"), "{}", media_path);
+
+    let out = server.path("dump.tar");
+    let output = server.run(&["export", "--out", out.to_str().unwrap(), "--include-files"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut archive = tar::Archive::new(std::fs::File::open(&out).unwrap());
+    let names: Vec<String> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .path()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    assert!(names.contains(&"export.json".to_string()), "{:?}", names);
+    assert!(
+        names.contains(&media_path.trim_start_matches('/').to_string()),
+        "{:?}",
+        names
+    );
+}
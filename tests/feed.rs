@@ -0,0 +1,130 @@
+// Runs the server against a fresh SQLite database and checks the feeds it
+// serves with a real XML parser
+
+mod common;
+
+use common::Server;
+use quick_xml::escape::unescape;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+const TRICKY_TITLE: &str = "Fish & Chips <b>\"bold\"</b> 'quoted'";
+
+fn start(feed_items: usize) -> Server {
+    Server::start(&[("FEED_ITEMS", &feed_items.to_string())])
+}
+
+fn get(server: &Server, path: &str) -> (u16, String) {
+    let response = server.send("GET", path, &[], b"");
+    (response.status, response.body)
+}
+
+fn post_article(server: &Server, title: &str, body: &str) {
+    let json = format!("{{\"title\": {:?}, \"body\": {:?}}}", title, body);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+}
+
+// The unescaped text of every element called `name` that sits directly inside
+// an element called `parent`, in document order
+fn texts(xml: &str, parent: &str, name: &str) -> Vec<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut found = Vec::new();
+
+    loop {
+        match reader.read_event().expect("feed is well-formed XML") {
+            Event::Start(e) => {
+                let tag = String::from_utf8(e.name().as_ref().to_vec()).unwrap();
+                if tag == name && path.last().map(String::as_str) == Some(parent) {
+                    current = Some(String::new());
+                }
+                path.push(tag);
+            }
+            Event::End(_) => {
+                path.pop();
+                if path.last().map(String::as_str) == Some(parent) {
+                    if let Some(raw) = current.take() {
+                        found.push(unescape(&raw).unwrap().into_owned());
+                    }
+                }
+            }
+            Event::Text(e) => {
+                if let Some(raw) = current.as_mut() {
+                    raw.push_str(&e.decode().unwrap());
+                }
+            }
+            Event::GeneralRef(e) => {
+                if let Some(raw) = current.as_mut() {
+                    raw.push_str(&format!("&{};", e.decode().unwrap()));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    found
+}
+
+#[test]
+fn rss_feed_lists_latest_articles_with_escaped_content() {
+    let server = start(2);
+    post_article(&server, "First", "Oldest article");
+    post_article(&server, "Second", "Middle article");
+    post_article(
+        &server,
+        TRICKY_TITLE,
+        "Body with <script>alert(1)</script> & more",
+    );
+
+    let (status, xml) = get(&server, "/feed.rss");
+    assert_eq!(status, 200);
+    assert!(
+        !xml.contains("<script>"),
+        "user content must be escaped: {}",
+        xml
+    );
+
+    let titles = texts(&xml, "item", "title");
+    assert_eq!(titles.len(), 2, "FEED_ITEMS limits the item count");
+    assert_eq!(titles[0], TRICKY_TITLE);
+    assert_eq!(
+        texts(&xml, "item", "description")[0],
+        "Body with <script>alert(1)</script> & more"
+    );
+
+    let links = texts(&xml, "item", "link");
+    assert!(
+        links
+            .iter()
+            .all(|l| l.starts_with("https://example.com/articles/")),
+        "{:?}",
+        links
+    );
+    assert_eq!(texts(&xml, "item", "pubDate").len(), 2);
+}
+
+#[test]
+fn atom_feed_lists_latest_articles_with_escaped_content() {
+    let server = start(5);
+    post_article(&server, "First", "Oldest article");
+    post_article(&server, TRICKY_TITLE, "x".repeat(500).as_str());
+
+    let (status, xml) = get(&server, "/feed.atom");
+    assert_eq!(status, 200);
+
+    let titles = texts(&xml, "entry", "title");
+    assert_eq!(titles, vec![TRICKY_TITLE.to_string(), "First".to_string()]);
+
+    let summaries = texts(&xml, "entry", "summary");
+    assert!(
+        summaries[0].chars().count() < 500,
+        "long bodies are truncated"
+    );
+    assert!(summaries[0].ends_with('…'));
+    assert_eq!(
+        texts(&xml, "feed", "title"),
+        vec!["All Articles".to_string()]
+    );
+}
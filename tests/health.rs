@@ -0,0 +1,17 @@
+// /healthz and /readyz answer load balancers with small JSON bodies and no
+// cookies
+
+mod common;
+
+use common::Server;
+
+#[test]
+fn probes_report_ok() {
+    let server = Server::start(&[]);
+    for path in ["/healthz", "/readyz"] {
+        let response = server.send("GET", path, &[], b"");
+        assert_eq!(response.status, 200, "{}: {}", path, response.body);
+        assert_eq!(response.body, r#"{"status":"ok"}"#);
+        assert_eq!(response.header("Set-Cookie"), None, "{}", path);
+    }
+}
@@ -0,0 +1,163 @@
+// Images are measured by their headers before anything decodes them, and
+// those too large, or GIFs with too many frames, are refused with a 422
+
+mod common;
+
+use common::{file_part, text_part, Response, Server, BOUNDARY};
+use std::io::Cursor;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend(kind);
+    chunk.extend(data);
+    chunk.extend(crc32(&[kind, data].concat()).to_be_bytes());
+    chunk
+}
+
+// A few dozen bytes claiming `width` by `height` RGB pixels
+fn bomb(width: u32, height: u32) -> Vec<u8> {
+    let mut ihdr = width.to_be_bytes().to_vec();
+    ihdr.extend(height.to_be_bytes());
+    ihdr.extend([8, 2, 0, 0, 0]);
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(png_chunk(b"IHDR", &ihdr));
+    png.extend(png_chunk(
+        b"IDAT",
+        &[0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01],
+    ));
+    png.extend(png_chunk(b"IEND", b""));
+    png
+}
+
+fn gif(frames: usize) -> Vec<u8> {
+    let mut gif = Vec::new();
+    let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+    let frames = (0..frames).map(|i| {
+        image::Frame::new(image::RgbaImage::from_pixel(
+            2,
+            2,
+            image::Rgba([i as u8, 0, 0, 255]),
+        ))
+    });
+    encoder.encode_frames(frames).unwrap();
+    drop(encoder);
+    gif
+}
+
+fn post(server: &Server, title: &str, filename: &str, data: &[u8]) -> Response {
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", title.as_bytes()),
+        file_part("media", filename, "application/octet-stream", data),
+    ];
+    server.submit_parts("/submit", &form, &parts)
+}
+
+fn stored(server: &Server) -> usize {
+    std::fs::read_dir(server.path("uploads"))
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_type().unwrap().is_file())
+        .count()
+}
+
+#[test]
+fn oversized_images_are_refused_unread() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("MAX_IMAGE_PIXELS", "1000000"),
+        ("MAX_IMAGE_WIDTH", "2000"),
+        ("MAX_IMAGE_HEIGHT", "1500"),
+        ("MAX_GIF_FRAMES", "2"),
+    ]);
+
+    let huge = post(&server, "Huge", "bomb.png", &bomb(50_000, 50_000));
+    assert_eq!(huge.status, 422);
+    assert!(huge.body.contains("Image Too Large"), "{}", huge.body);
+    assert!(
+        huge.body
+            .contains("at most 2000×1500 pixels; this one is 50000×50000"),
+        "{}",
+        huge.body
+    );
+    let tall = post(&server, "Tall", "tall.png", &bomb(10, 1501));
+    assert!(tall.body.contains("this one is 10×1501"), "{}", tall.body);
+    let many = post(&server, "Many", "many.png", &bomb(1001, 1000));
+    assert_eq!(many.status, 422);
+    assert!(
+        many.body
+            .contains("at most 1000000 pixels; this one has 1001000"),
+        "{}",
+        many.body
+    );
+
+    let animated = post(&server, "Animated", "frames.gif", &gif(3));
+    assert_eq!(animated.status, 422);
+    assert!(
+        animated.body.contains("GIFs can have at most 2 frames"),
+        "{}",
+        animated.body
+    );
+    assert_eq!(stored(&server), 0);
+
+    assert_eq!(post(&server, "Short", "short.gif", &gif(2)).status, 302);
+    let mut png = Vec::new();
+    image::RgbImage::new(1000, 1000)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    assert_eq!(post(&server, "Fits", "fits.png", &png).status, 302);
+}
+
+#[test]
+fn the_limits_hold_without_stripping() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("STRIP_EXIF", "false"),
+    ]);
+    let huge = post(&server, "Huge", "bomb.png", &bomb(20_000, 20_000));
+    assert_eq!(huge.status, 422);
+    assert_eq!(stored(&server), 0);
+
+    // Refused from the header alone: a body cut off after it is still a 422
+    // rather than a broken upload
+    let form = server.open_form("/");
+    let csrf_token = form.cookie.split_once('=').unwrap().1;
+    let mut body = text_part("csrf_token", csrf_token.as_bytes());
+    body.extend_from_slice(&text_part("form_token", form.form_token.as_bytes()));
+    body.extend_from_slice(&text_part("title", b"Cut"));
+    body.extend_from_slice(&text_part("body", b"Cut"));
+    let mut file = file_part(
+        "media",
+        "cut.png",
+        "application/octet-stream",
+        &bomb(20_000, 20_000),
+    );
+    file.truncate(file.len() - 20);
+    body.extend_from_slice(&file);
+    let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+    let cut = server.send(
+        "POST",
+        "/submit",
+        &[("Content-Type", &content_type), ("Cookie", &form.cookie)],
+        &body,
+    );
+    assert_eq!(cut.status, 422, "{}", cut.body);
+    assert_eq!(stored(&server), 0);
+}
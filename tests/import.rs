@@ -0,0 +1,263 @@
+// `articles import` reads back what `articles export` wrote: exporting a site
+// and importing it into an empty one gives the same content, files included
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use serde_json::Value;
+use std::io::Cursor;
+
+fn start() -> Server {
+    Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")])
+}
+
+fn run(server: &Server, args: &[&str]) -> String {
+    let output = server.run(args);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn export(server: &Server, name: &str) -> Value {
+    let out = server.path(name);
+    run(server, &["export", "--out", out.to_str().unwrap()]);
+    serde_json::from_slice(&std::fs::read(out).unwrap()).unwrap()
+}
+
+// The export without what an import changes: ids, and where files are
+fn content(mut export: Value) -> Value {
+    fn strip(value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for key in [
+                    "id",
+                    "board_id",
+                    "parent_comment_id",
+                    "exported_at",
+                    "media_path",
+                    "thumb_path",
+                ] {
+                    object.remove(key);
+                }
+                object.values_mut().for_each(strip);
+            }
+            Value::Array(items) => items.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+    strip(&mut export);
+    export
+}
+
+// A site with a picture, a reply, tags and a second board
+fn populated() -> Server {
+    let server = start();
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Picture"),
+        text_part("body", b"Body"),
+        text_part("tags", b"cats, pets"),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+    let form = server.open_form("/articles/1/picture");
+    let response = server.submit("/articles/1/comment", &form, &[("comment", "First")]);
+    assert_eq!(response.status, 302, "{}", response.body);
+    let form = server.open_form("/articles/1/picture");
+    let response = server.submit(
+        "/articles/1/comment",
+        &form,
+        &[("comment", "Second"), ("parent_comment_id", "1")],
+    );
+    assert_eq!(response.status, 302, "{}", response.body);
+
+    run(&server, &["add-board", "cats", "Cats"]);
+    let response = server.post_json(
+        "/api/articles",
+        br#"{"board": "cats", "title": "Plain", "body": "Text"}"#,
+    );
+    assert_eq!(response.status, 201, "{}", response.body);
+    server
+}
+
+#[test]
+fn an_export_imports_into_an_empty_site_as_it_was() {
+    let source = populated();
+    let exported = export(&source, "source.json");
+    let tar = source.path("source.tar");
+    run(
+        &source,
+        &["export", "--out", tar.to_str().unwrap(), "--include-files"],
+    );
+
+    let target = start();
+    let stdout = run(&target, &["import", tar.to_str().unwrap()]);
+    assert!(
+        stdout.contains("Imported 2 articles with 2 comments and 1 media files"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Created boards: cats"), "{}", stdout);
+
+    let imported = export(&target, "target.json");
+    assert_eq!(content(imported.clone()), content(exported.clone()));
+    let comments = &imported["articles"][0]["comments"];
+    assert_eq!(comments[1]["parent_comment_id"], comments[0]["id"]);
+    assert_eq!(
+        imported["articles"][0]["tags"],
+        serde_json::json!(["cats", "pets"])
+    );
+
+    // The files were stored again, under new names
+    let media = &imported["articles"][0]["media"][0];
+    for key in ["media_path", "thumb_path"] {
+        let path = media[key].as_str().unwrap();
+        assert_ne!(
+            path,
+            exported["articles"][0]["media"][0][key].as_str().unwrap()
+        );
+        assert_eq!(target.send("GET", path, &[], b"").status, 200, "{}", path);
+    }
+}
+
+#[test]
+fn articles_already_there_are_skipped_unless_forced() {
+    let source = populated();
+    let json = source.path("source.json");
+    run(&source, &["export", "--out", json.to_str().unwrap()]);
+    let json = json.to_str().unwrap();
+
+    let stdout = run(&source, &["import", json]);
+    assert!(stdout.contains("Skipped \"Picture\""), "{}", stdout);
+    assert!(stdout.contains("Imported 0 articles"), "{}", stdout);
+
+    let stdout = run(&source, &["import", json, "--force", "--dry-run"]);
+    assert!(
+        stdout.contains("Would import 2 articles with 2 comments"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        export(&source, "after.json")["articles"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    run(&source, &["import", json, "--force"]);
+    let articles = export(&source, "forced.json")["articles"]
+        .as_array()
+        .unwrap()
+        .len();
+    assert_eq!(articles, 4);
+
+    std::fs::write(
+        source.path("newer.json"),
+        br#"{"format": "articles-export", "version": 99}"#,
+    )
+    .unwrap();
+    let output = source.run(&["import", source.path("newer.json").to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("version 99"));
+}
+
+// An export of one article with a file under /uploads, written with the file
+// beside it, and the arguments to import it
+fn export_with(server: &Server, name: &str, data: &[u8]) -> Vec<String> {
+    let files = server.path(name);
+    std::fs::create_dir_all(&files).unwrap();
+    std::fs::write(files.join(name), data).unwrap();
+    let document = format!(
+        r#"{{"format":"articles-export","version":1,"exported_at":0,
+            "boards":[{{"id":1,"slug":"main","title":"Main","description":""}}],
+            "articles":[{{"id":1,"board_id":1,"title":"{name}","slug":null,"body":"B","name":"Anonymous",
+                "tripcode":null,"created_at":1,"bump_time":1,"edited_at":null,"archived_at":null,
+                "deleted_at":null,"is_sticky":false,"delete_password_hash":null,"poster_ip":null,"tags":[],
+                "media":[{{"id":1,"media_path":"/uploads/{name}","original_name":"{name}","thumb_path":null}}],
+                "comments":[]}}]}}"#
+    );
+    let json = server.path(&format!("{}.json", name));
+    std::fs::write(&json, document).unwrap();
+    vec![
+        "import".to_string(),
+        json.display().to_string(),
+        "--files".to_string(),
+        files.display().to_string(),
+    ]
+}
+
+#[test]
+fn imported_files_are_checked_and_stripped_like_uploads() {
+    let server = start();
+    let run_import = |name: &str, data: &[u8]| {
+        let args = export_with(&server, name, data);
+        server.run(&args.iter().map(String::as_str).collect::<Vec<_>>())
+    };
+
+    let mut jpeg = Vec::new();
+    image::RgbImage::new(4, 4)
+        .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+        .unwrap();
+    let tiff = b"II\x2a\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+    let mut app1 = vec![0xff, 0xe1];
+    app1.extend((2 + 6 + tiff.len() as u16).to_be_bytes());
+    app1.extend(b"Exif\0\0");
+    app1.extend(tiff);
+    jpeg.splice(2..2, app1);
+    let output = run_import("photo.jpg", &jpeg);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let path = export(&server, "after.json")["articles"][0]["media"][0]["media_path"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let stored = std::fs::read(
+        server
+            .path("uploads")
+            .join(path.trim_start_matches("/uploads/")),
+    )
+    .unwrap();
+    assert!(!stored.windows(4).any(|w| w == b"Exif"));
+
+    // A PNG header claiming more pixels than an upload may have
+    let mut bomb = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    bomb.extend(20_000u32.to_be_bytes());
+    bomb.extend(20_000u32.to_be_bytes());
+    bomb.extend([8, 2, 0, 0, 0, 0, 0, 0, 0]);
+    let output = run_import("bomb.png", &bomb);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Images can be at most 16384×16384 pixels"),
+        "{}",
+        stderr
+    );
+
+    let output = run_import("notes.txt", b"Not media at all");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("not a type of media that can be uploaded"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        export(&server, "last.json")["articles"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
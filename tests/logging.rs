@@ -0,0 +1,111 @@
+// Every response carries an X-Request-Id, which tags its access log line and
+// any errors logged while handling it, including those in ERROR_LOG_PATH
+
+mod common;
+
+use common::Server;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn errors_are_logged_with_the_request_id() {
+    let server = Server::start(&[]);
+    let first = server.send("GET", "/healthz", &[], b"");
+    let second = server.send("GET", "/healthz", &[], b"");
+    let id = first
+        .header("X-Request-Id")
+        .expect("responses carry a request id");
+    assert_eq!(id.len(), 32, "{}", id);
+    assert_ne!(Some(id), second.header("X-Request-Id"));
+
+    std::fs::remove_dir_all(server.path("uploads")).unwrap();
+    let response = server.send("GET", "/readyz", &[], b"");
+    assert_eq!(response.status, 503, "{}", response.body);
+    let id = response.header("X-Request-Id").unwrap();
+
+    // The file is written from a background thread
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let log = std::fs::read_to_string(server.path("error.txt")).unwrap_or_default();
+        if let Some(line) = log
+            .lines()
+            .find(|line| line.contains("uploads aren't writable"))
+        {
+            assert!(line.contains("ERROR"), "{}", line);
+            assert!(line.contains(&format!("request_id={}", id)), "{}", line);
+            assert!(line.contains("path=/readyz"), "{}", line);
+            break;
+        }
+        assert!(Instant::now() < deadline, "nothing logged: {}", log);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// The access log lines in what the server has written to stderr
+fn access_log(server: &Server) -> Vec<serde_json::Value> {
+    std::fs::read_to_string(server.path("stderr.txt"))
+        .unwrap()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|line| line["target"] == "access")
+        .collect()
+}
+
+#[test]
+fn requests_are_access_logged() {
+    let server = Server::start(&[("LOG_FORMAT", "json"), ("ACCESS_LOG_EXCLUDE", "/healthz")]);
+    server.send("GET", "/healthz", &[], b"");
+    let response = server.send(
+        "GET",
+        "/articles",
+        &[("X-Forwarded-For", "203.0.113.7")],
+        b"",
+    );
+    let missing = server.send("GET", "/no-such-page", &[], b"");
+
+    let log = access_log(&server);
+    assert_eq!(log.len(), 2, "{:?}", log);
+    let (found, not_found) = (&log[0], &log[1]);
+    assert_eq!(found["span"]["method"], "GET");
+    assert_eq!(found["span"]["path"], "/articles");
+    assert_eq!(
+        found["span"]["request_id"],
+        response.header("X-Request-Id").unwrap()
+    );
+    assert_eq!(found["fields"]["status"], 200);
+    assert_eq!(found["fields"]["bytes"], response.body.len());
+    assert!(
+        found["fields"]["latency_ms"].as_f64().unwrap() > 0.0,
+        "{}",
+        found
+    );
+    // The header isn't trusted without TRUSTED_PROXY_HEADER
+    assert_eq!(found["fields"]["client_ip"], "127.0.0.1");
+    assert_eq!(not_found["span"]["path"], "/no-such-page");
+    assert_eq!(not_found["fields"]["status"], 404);
+    assert_eq!(
+        not_found["span"]["request_id"],
+        missing.header("X-Request-Id").unwrap()
+    );
+}
+
+#[test]
+fn client_addresses_come_through_the_trusted_proxy_header() {
+    let server = Server::start(&[
+        ("LOG_FORMAT", "json"),
+        ("TRUSTED_PROXY_HEADER", "X-Forwarded-For"),
+    ]);
+    server.send(
+        "GET",
+        "/articles",
+        &[("X-Forwarded-For", "198.51.100.1, 203.0.113.7")],
+        b"",
+    );
+    // Excluded by default
+    server.send("GET", "/readyz", &[], b"");
+    server.send("GET", "/static/style.css", &[], b"");
+
+    let log = access_log(&server);
+    assert_eq!(log.len(), 1, "{:?}", log);
+    assert_eq!(log[0]["fields"]["client_ip"], "203.0.113.7");
+}
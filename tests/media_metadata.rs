@@ -0,0 +1,69 @@
+// Uploads are recorded with their size, type and dimensions, shown beside
+// their names and in the API; backfill-media fills them in for older rows
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use serde_json::{json, Value};
+use sqlx::{Connection, SqliteConnection};
+use std::io::Cursor;
+
+fn execute(server: &Server, sql: &str) {
+    let url = format!("sqlite://{}", server.path("articles.db").display());
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let mut conn = SqliteConnection::connect(&url).await.unwrap();
+        sqlx::query(sql).execute(&mut conn).await.unwrap();
+    })
+}
+
+fn media(server: &Server) -> Value {
+    let article: Value =
+        serde_json::from_str(&server.send("GET", "/api/articles/1", &[], b"").body).unwrap();
+    article["media"][0].clone()
+}
+
+#[test]
+fn uploads_are_described() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let mut png = Vec::new();
+    image::RgbImage::new(3, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Picture"),
+        text_part("body", b"Body"),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    assert_eq!(server.submit_parts("/submit", &form, &parts).status, 302);
+
+    let described = media(&server);
+    assert_eq!(described["byte_size"], json!(png.len()));
+    assert_eq!(described["mime_type"], "image/png");
+    assert_eq!(
+        (described["width"].as_i64(), described["height"].as_i64()),
+        (Some(3), Some(2))
+    );
+    let page = server.send("GET", "/articles/1/picture", &[], b"").body;
+    assert!(
+        page.contains(&format!("cat.png ({} bytes, 3×2)", png.len())),
+        "{}",
+        page
+    );
+
+    // As rows stored before these were kept
+    execute(
+        &server,
+        "UPDATE article_media SET byte_size = NULL, width = NULL, height = NULL",
+    );
+    assert!(media(&server)["byte_size"].is_null());
+    let output = server.run(&["backfill-media"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("Filled in 1 media row(s); 0 file(s) were missing"));
+    assert_eq!(media(&server), described);
+}
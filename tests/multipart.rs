@@ -0,0 +1,131 @@
+// Malformed multipart bodies sent to the article and comment forms are
+// rejected as bad requests rather than failing on the server
+
+mod common;
+
+use common::{part, text_part, Server, BOUNDARY};
+
+// A form session: the CSRF cookie and the token that goes with it
+fn csrf_cookie(server: &Server) -> String {
+    let response = server.send("GET", "/", &[], b"");
+    let cookie = response
+        .header("Set-Cookie")
+        .expect("the form page sets a CSRF cookie");
+    cookie.split(';').next().unwrap().to_string()
+}
+
+// POST `parts` after the CSRF token and return the status and body. A
+// `truncated` body stops partway through the last part.
+fn post(server: &Server, path: &str, parts: &[Vec<u8>], truncated: bool) -> (u16, String) {
+    let cookie = csrf_cookie(server);
+    let token = cookie.split_once('=').unwrap().1;
+    let mut body = text_part("csrf_token", token.as_bytes());
+    for part in parts {
+        body.extend_from_slice(part);
+    }
+    if truncated {
+        body.truncate(body.len() - 3);
+    } else {
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    }
+    let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+    let response = server.send(
+        "POST",
+        path,
+        &[("Content-Type", &content_type), ("Cookie", &cookie)],
+        &body,
+    );
+    (response.status, response.body)
+}
+
+fn assert_rejected((status, body): (u16, String)) {
+    assert!(
+        (400..500).contains(&status),
+        "expected a 4xx, got {}: {}",
+        status,
+        body
+    );
+}
+
+#[test]
+fn broken_article_submissions_are_client_errors() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false")]);
+
+    // A part without Content-Disposition
+    assert_rejected(post(
+        &server,
+        "/submit",
+        &[part("Content-Type: text/plain", b"x")],
+        false,
+    ));
+    // A form-data part without a name
+    assert_rejected(post(
+        &server,
+        "/submit",
+        &[part("Content-Disposition: form-data", b"x")],
+        false,
+    ));
+    // A title that isn't UTF-8
+    let (status, body) = post(
+        &server,
+        "/submit",
+        &[text_part("title", b"caf\xe9"), text_part("body", b"Body")],
+        false,
+    );
+    assert_eq!(status, 400, "{}", body);
+    assert!(
+        body.contains("title field isn&#39;t valid UTF-8"),
+        "{}",
+        body
+    );
+    // A body cut off in the middle of a field
+    assert_rejected(post(
+        &server,
+        "/submit",
+        &[text_part("title", b"Title")],
+        true,
+    ));
+    // A file input left empty
+    let empty_file = part(
+        "Content-Disposition: form-data; name=\"media\"; filename=\"\"\r\nContent-Type: application/octet-stream",
+        b"",
+    );
+    assert_rejected(post(
+        &server,
+        "/submit",
+        &[
+            text_part("title", b"Title"),
+            text_part("body", b"Body"),
+            empty_file,
+        ],
+        false,
+    ));
+}
+
+#[test]
+fn broken_comment_submissions_are_client_errors() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false")]);
+    let json = br#"{"title": "Title", "body": "Body"}"#;
+    let created = server.post_json("/api/articles", json);
+    assert_eq!(created.status, 201, "{}", created.body);
+    let path = "/articles/1/comment";
+
+    assert_rejected(post(
+        &server,
+        path,
+        &[part("Content-Disposition: form-data", b"x")],
+        false,
+    ));
+    assert_rejected(post(
+        &server,
+        path,
+        &[text_part("comment", b"\xff\xfe")],
+        false,
+    ));
+    assert_rejected(post(
+        &server,
+        path,
+        &[text_part("comment", b"Comment")],
+        true,
+    ));
+}
@@ -0,0 +1,100 @@
+// /api/openapi.json describes every route under /api, with the keys they
+// need; /api/docs shows it only with API_DOCS=true
+
+mod common;
+
+use common::Server;
+use serde_json::Value;
+
+#[test]
+fn the_spec_lists_every_route() {
+    let server = Server::start(&[]);
+    let response = server.send("GET", "/api/openapi.json", &[], b"");
+    assert_eq!(response.status, 200, "{}", response.body);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    let spec: Value = serde_json::from_str(&response.body).unwrap();
+    assert!(spec["openapi"]
+        .as_str()
+        .is_some_and(|version| version.starts_with("3.")));
+
+    let expected = [
+        ("/api/articles", "get"),
+        ("/api/articles", "post"),
+        ("/api/articles/{id}", "get"),
+        ("/api/articles/{id}", "delete"),
+        ("/api/articles/{id}/related", "get"),
+        ("/api/comments/{id}", "delete"),
+        ("/api/search", "get"),
+    ];
+    for (path, method) in expected {
+        assert!(
+            spec["paths"][path][method].is_object(),
+            "{} {} is missing",
+            method,
+            path
+        );
+    }
+    let described = spec["paths"]
+        .as_object()
+        .unwrap()
+        .values()
+        .map(|path| path.as_object().unwrap().len())
+        .sum::<usize>();
+    assert_eq!(described, expected.len());
+}
+
+#[test]
+fn the_spec_describes_keys_paging_and_errors() {
+    let server = Server::start(&[]);
+    let spec: Value =
+        serde_json::from_str(&server.send("GET", "/api/openapi.json", &[], b"").body).unwrap();
+
+    let schemes = &spec["components"]["securitySchemes"];
+    assert_eq!(schemes["api_key"]["scheme"], "bearer");
+    assert_eq!(schemes["admin_session"]["in"], "cookie");
+    assert_eq!(schemes["admin_session"]["name"], "session");
+    assert_eq!(
+        spec["paths"]["/api/articles"]["post"]["security"],
+        serde_json::json!([{ "api_key": [] }])
+    );
+    assert_eq!(
+        spec["paths"]["/api/articles/{id}"]["delete"]["security"],
+        serde_json::json!([{ "api_key": [] }, { "admin_session": [] }])
+    );
+    assert!(spec["paths"]["/api/articles"]["get"]["security"].is_null());
+
+    let params = spec["paths"]["/api/articles"]["get"]["parameters"]
+        .as_array()
+        .unwrap();
+    let names: Vec<_> = params
+        .iter()
+        .map(|param| param["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["board", "limit", "offset", "sort"]);
+    assert!(params.iter().all(|param| param["in"] == "query"));
+
+    let error = &spec["components"]["schemas"]["ErrorBody"];
+    assert_eq!(error["required"], serde_json::json!(["error"]));
+    let missing = &spec["paths"]["/api/articles/{id}"]["get"]["responses"]["404"];
+    assert_eq!(
+        missing["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ErrorBody"
+    );
+}
+
+#[test]
+fn docs_are_only_served_when_turned_on() {
+    let server = Server::start(&[]);
+    let response = server.send("GET", "/api/docs", &[], b"");
+    assert_eq!(response.status, 404);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+
+    let server = Server::start(&[("API_DOCS", "true")]);
+    let response = server.send("GET", "/api/docs", &[], b"");
+    assert_eq!(response.status, 200);
+    assert!(
+        response.body.contains("url: \"/api/openapi.json\""),
+        "{}",
+        response.body
+    );
+}
@@ -0,0 +1,92 @@
+// Rendered article pages are kept and reused, with each visitor's own tokens
+// filled in, and dropped as soon as a comment changes them
+
+mod common;
+
+use common::Server;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start() -> Server {
+    Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("PAGE_CACHE_SECONDS", "600"),
+    ])
+}
+
+// Post an article, returning the path of its page
+fn post_article(server: &Server) -> String {
+    let json = "{\"title\": \"Busy\", \"body\": \"Body\"}";
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    let id = serde_json::from_str::<serde_json::Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap();
+    format!("/articles/{}/busy", id)
+}
+
+#[test]
+fn kept_pages_carry_each_visitors_own_token() {
+    let server = start();
+    let path = post_article(&server);
+    for _ in 0..3 {
+        let page = server.send("GET", &path, &[], b"");
+        let cookie = page.header("Set-Cookie").unwrap();
+        let token = cookie.split(';').next().unwrap().split_once('=').unwrap().1;
+        assert!(
+            page.body
+                .contains(&format!("name=\"csrf_token\" value=\"{}\"", token)),
+            "{}",
+            page.body
+        );
+    }
+}
+
+#[test]
+fn comments_show_up_while_the_page_is_hammered() {
+    let server = Arc::new(start());
+    let path = post_article(&server);
+    // Kept before the comment
+    assert_eq!(server.send("GET", &path, &[], b"").status, 200);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let (server, path, stop) = (server.clone(), path.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                while !stop.load(Ordering::SeqCst) {
+                    let started = Instant::now();
+                    let page = server.send("GET", &path, &[], b"");
+                    assert_eq!(page.status, 200, "{}", page.body);
+                    seen.push((started, page.body.contains("Hammered")));
+                }
+                seen
+            })
+        })
+        .collect();
+
+    thread::sleep(Duration::from_millis(100));
+    let form = server.open_form(&path);
+    let comment_path = format!("{}/comment", path.trim_end_matches("/busy"));
+    let response = server.submit(&comment_path, &form, &[("comment", "Hammered")]);
+    assert_eq!(response.status, 302, "{}", response.body);
+    let commented = Instant::now();
+    thread::sleep(Duration::from_millis(200));
+    stop.store(true, Ordering::SeqCst);
+
+    let seen: Vec<_> = readers
+        .into_iter()
+        .flat_map(|reader| reader.join().unwrap())
+        .collect();
+    let after: Vec<_> = seen
+        .iter()
+        .filter(|(started, _)| *started > commented)
+        .collect();
+    assert!(!after.is_empty());
+    assert!(after.iter().all(|(_, has_comment)| *has_comment));
+    assert!(seen.iter().any(|(_, has_comment)| !has_comment));
+}
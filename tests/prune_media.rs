@@ -0,0 +1,76 @@
+// `articles prune-media` removes upload files no media row refers to, once
+// they're older than the grace period
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+fn age(path: &Path) {
+    let old = SystemTime::now() - Duration::from_secs(2 * 60 * 60);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+}
+
+#[test]
+fn old_unreferenced_files_are_removed() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"Kept"),
+        text_part("body", b"Body"),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+    let article: Value =
+        serde_json::from_str(&server.send("GET", "/api/articles/1", &[], b"").body).unwrap();
+    let media = article["media"][0]["media_path"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let uploads = server.path("uploads");
+    fs::write(uploads.join("article_orphan.png"), b"orphan").unwrap();
+    fs::write(uploads.join("thumbs/thumb_orphan.png"), b"thumb").unwrap();
+    fs::write(uploads.join("article_fresh.png"), b"fresh").unwrap();
+    for name in [
+        "article_orphan.png",
+        "thumbs/thumb_orphan.png",
+        media.trim_start_matches("/uploads/"),
+    ] {
+        age(&uploads.join(name));
+    }
+
+    let output = server.run(&["prune-media", "--dry-run"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("Would remove 2 orphaned file(s), 11 bytes"));
+    assert!(uploads.join("article_orphan.png").exists());
+
+    let output = server.run(&["prune-media"]);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("Removed 2 orphaned file(s), 11 bytes")
+    );
+    assert!(!uploads.join("article_orphan.png").exists());
+    assert!(!uploads.join("thumbs/thumb_orphan.png").exists());
+    // Too new to be an orphan yet
+    assert!(uploads.join("article_fresh.png").exists());
+    assert_eq!(server.send("GET", &media, &[], b"").status, 200);
+}
@@ -0,0 +1,78 @@
+// Past UPLOADS_QUOTA_BYTES, posts with files are refused with a 507; the
+// usage is shown to admins and at /metrics, and goes down as files are removed
+
+mod common;
+
+use common::{admin_password_hash, file_part, text_part, Response, Server};
+use std::io::Cursor;
+
+fn post(server: &Server, title: &str) -> Response {
+    let mut png = Vec::new();
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", title.as_bytes()),
+        file_part("media", "cat.png", "image/png", &png),
+    ];
+    server.submit_parts("/submit", &form, &parts)
+}
+
+// The articles_uploads_bytes gauge
+fn used(server: &Server) -> u64 {
+    let metrics = server.send("GET", "/metrics", &[], b"").body;
+    let line = metrics
+        .lines()
+        .find(|line| line.starts_with("articles_uploads_bytes "))
+        .unwrap();
+    line.split(' ').nth(1).unwrap().parse().unwrap()
+}
+
+#[test]
+fn uploads_stop_at_the_quota() {
+    let server = Server::start(&[
+        ("ADMIN_PASSWORD_HASH", &admin_password_hash()),
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("UPLOADS_QUOTA_BYTES", "100"),
+    ]);
+    assert_eq!(used(&server), 0);
+    assert_eq!(post(&server, "First").status, 302);
+    let stored = used(&server);
+    // The upload and its thumbnail
+    assert!(stored > 100, "{}", stored);
+    let metrics = server.send("GET", "/metrics", &[], b"").body;
+    assert!(
+        metrics.contains(
+            "# TYPE articles_uploads_quota_bytes gauge\narticles_uploads_quota_bytes 100\n"
+        ),
+        "{}",
+        metrics
+    );
+
+    let refused = post(&server, "Second");
+    assert_eq!(refused.status, 507);
+    assert!(refused.body.contains("Out of Space"), "{}", refused.body);
+    // Comments without files still go through
+    let form = server.open_form("/articles/1/first");
+    assert_eq!(
+        server
+            .submit("/articles/1/comment", &form, &[("comment", "Words only")])
+            .status,
+        302
+    );
+    assert_eq!(used(&server), stored);
+
+    let cookie = server.log_in();
+    let admin = server
+        .send("GET", "/admin", &[("Cookie", &cookie)], b"")
+        .body;
+    assert!(admin.contains("of the 100 bytes quota used"), "{}", admin);
+
+    // Removed by another process, and counted there
+    assert!(server.run(&["prune", "--keep", "0"]).status.success());
+    assert_eq!(used(&server), 0);
+    assert_eq!(post(&server, "Third").status, 302);
+}
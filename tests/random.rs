@@ -0,0 +1,80 @@
+// /articles/random sends the reader to one of the current articles, or back to
+// the list with a message when there are none
+
+mod common;
+
+use common::Server;
+
+fn start() -> Server {
+    Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")])
+}
+
+fn post_article(server: &Server, title: &str) -> i64 {
+    let json = format!("{{\"title\": {:?}, \"body\": \"Body\"}}", title);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    serde_json::from_str::<serde_json::Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap()
+}
+
+#[test]
+fn redirects_to_a_current_article() {
+    let server = start();
+    let ids: Vec<i64> = (1..=5)
+        .map(|n| post_article(&server, &format!("Article {}", n)))
+        .collect();
+
+    for _ in 0..20 {
+        let response = server.send("GET", "/articles/random", &[], b"");
+        assert_eq!(response.status, 302, "{}", response.body);
+        let location = response.header("Location").unwrap();
+        let id: i64 = location
+            .strip_prefix("/articles/")
+            .unwrap()
+            .split('/')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(ids.contains(&id), "{}", location);
+        assert_eq!(
+            server.send("GET", location, &[], b"").status,
+            200,
+            "{}",
+            location
+        );
+    }
+}
+
+#[test]
+fn says_so_when_there_are_no_articles() {
+    let server = start();
+    let response = server.send("GET", "/articles/random", &[], b"");
+    assert_eq!(response.status, 302, "{}", response.body);
+    assert_eq!(response.header("Location"), Some("/articles"));
+    let cookie = response
+        .head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find_map(|(key, value)| {
+            let value = value.trim();
+            (key.eq_ignore_ascii_case("Set-Cookie") && value.starts_with("session="))
+                .then_some(value)
+        })
+        .expect("the message is kept in the session");
+    let cookie = cookie.split(';').next().unwrap();
+
+    let page = server
+        .send("GET", "/articles", &[("Cookie", cookie)], b"")
+        .body;
+    assert!(
+        page.contains("<p class=\"flash\">There are no articles to pick from yet.</p>"),
+        "{}",
+        page
+    );
+    assert!(!server
+        .send("GET", "/articles", &[], b"")
+        .body
+        .contains("class=\"flash\""));
+}
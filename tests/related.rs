@@ -0,0 +1,63 @@
+// Articles list related ones under their comments, and /api/articles/{id}/related
+// gives the same list as JSON
+
+mod common;
+
+use common::Server;
+
+fn post_article(server: &Server, title: &str) -> i64 {
+    let json = format!("{{\"title\": {:?}, \"body\": \"Body\"}}", title);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    serde_json::from_str::<serde_json::Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap()
+}
+
+#[test]
+fn matching_titles_are_related() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let first = post_article(&server, "Sourdough starter");
+    let second = post_article(&server, "Feeding a sourdough starter");
+    let alone = post_article(&server, "Bicycle repair");
+
+    let response = server.send("GET", &format!("/api/articles/{}/related", first), &[], b"");
+    assert_eq!(response.status, 200, "{}", response.body);
+    let related: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(
+        related,
+        serde_json::json!([{ "id": second, "title": "Feeding a sourdough starter", "slug": "feeding-a-sourdough-starter" }])
+    );
+
+    let page = server
+        .send(
+            "GET",
+            &format!("/articles/{}/sourdough-starter", first),
+            &[],
+            b"",
+        )
+        .body;
+    assert!(page.contains("<h3>Related Articles</h3>"), "{}", page);
+    assert!(
+        page.contains(&format!(
+            "<a href=\"/articles/{}/feeding-a-sourdough-starter\">Feeding a sourdough starter</a>",
+            second
+        )),
+        "{}",
+        page
+    );
+
+    // Nothing matches, so there's no section
+    let page = server
+        .send(
+            "GET",
+            &format!("/articles/{}/bicycle-repair", alone),
+            &[],
+            b"",
+        )
+        .body;
+    assert!(!page.contains("Related Articles"), "{}", page);
+
+    let response = server.send("GET", "/api/articles/9999/related", &[], b"");
+    assert_eq!(response.status, 404, "{}", response.body);
+}
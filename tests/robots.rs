@@ -0,0 +1,33 @@
+// /robots.txt allows everything unless ROBOTS_DISALLOW lists paths, and is
+// cached publicly without a cookie
+
+mod common;
+
+use common::Server;
+
+#[test]
+fn everything_is_allowed_by_default() {
+    let server = Server::start(&[]);
+    let robots = server.send("GET", "/robots.txt", &[], b"");
+    assert_eq!(robots.status, 200);
+    assert_eq!(robots.body, "User-agent: *\nDisallow:\n");
+    assert_eq!(
+        robots.header("Content-Type"),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(
+        robots.header("Cache-Control"),
+        Some("public, max-age=86400")
+    );
+    assert_eq!(robots.header("Set-Cookie"), None);
+}
+
+#[test]
+fn listed_paths_are_disallowed() {
+    let server = Server::start(&[("ROBOTS_DISALLOW", "/admin, /uploads")]);
+    let robots = server.send("GET", "/robots.txt", &[], b"");
+    assert_eq!(
+        robots.body,
+        "User-agent: *\nDisallow: /admin\nDisallow: /uploads\n"
+    );
+}
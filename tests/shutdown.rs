@@ -0,0 +1,101 @@
+// At SIGTERM the server stops taking connections but lets uploads in flight
+// finish; those still going after SHUTDOWN_TIMEOUT_SECONDS are cut off and
+// their half-written files removed
+
+mod common;
+
+use common::{file_part, read_response, text_part, Server, BOUNDARY};
+use std::io::{Cursor, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start(timeout_secs: &str) -> Server {
+    Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("SHUTDOWN_TIMEOUT_SECONDS", timeout_secs),
+        // Stripped images are only written once they're all in, and these
+        // need one written as it arrives
+        ("STRIP_EXIF", "false"),
+    ])
+}
+
+// Start posting an article with a picture, sending the request up to the
+// middle of the file; the rest of the body is returned
+fn begin_upload(server: &Server) -> (TcpStream, Vec<u8>) {
+    let form = server.open_form("/");
+    let csrf_token = form.cookie.split_once('=').unwrap().1;
+    let mut png = Vec::new();
+    image::RgbImage::new(64, 64)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+
+    let mut body = text_part("csrf_token", csrf_token.as_bytes());
+    body.extend(text_part("form_token", form.form_token.as_bytes()));
+    body.extend(text_part("title", b"Slow"));
+    body.extend(text_part("body", b"Body"));
+    body.extend(file_part("media", "slow.png", "image/png", &png));
+    body.extend(format!("--{}--\r\n", BOUNDARY).into_bytes());
+    let head = format!(
+        "POST /submit HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nCookie: {}\r\n\
+         Content-Type: multipart/form-data; boundary={}\r\nContent-Length: {}\r\n\r\n",
+        form.cookie,
+        BOUNDARY,
+        body.len()
+    );
+
+    let mut stream = server.connect();
+    stream.write_all(head.as_bytes()).unwrap();
+    let rest = body.split_off(body.len() - png.len() / 2);
+    stream.write_all(&body).unwrap();
+    (stream, rest)
+}
+
+// The uploaded files (not thumbnails) in the server's uploads directory
+fn uploads(server: &Server) -> Vec<String> {
+    std::fs::read_dir(server.path("uploads"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("article_"))
+        .collect()
+}
+
+fn wait_for_upload(server: &Server) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while uploads(server).is_empty() {
+        assert!(Instant::now() < deadline, "the upload was not started");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn uploads_in_flight_finish() {
+    let mut server = start("30");
+    let (mut stream, rest) = begin_upload(&server);
+    wait_for_upload(&server);
+
+    server.signal("TERM");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while TcpStream::connect(server.addrs()[0]).is_ok() {
+        assert!(Instant::now() < deadline, "still taking connections");
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    stream.write_all(&rest).unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 302, "{}", response.body);
+    assert!(server.wait_for_exit(Duration::from_secs(10)));
+    assert_eq!(uploads(&server).len(), 1);
+}
+
+#[test]
+fn cut_off_uploads_are_removed() {
+    let mut server = start("1");
+    let (_stream, _) = begin_upload(&server);
+    wait_for_upload(&server);
+
+    server.signal("TERM");
+    assert!(server.wait_for_exit(Duration::from_secs(10)));
+    assert!(uploads(&server).is_empty(), "{:?}", uploads(&server));
+}
@@ -0,0 +1,53 @@
+// Article lists and the JSON list API take ?sort=, and the list's links keep it
+
+mod common;
+
+use common::Server;
+
+fn post_article(server: &Server, title: &str) {
+    let json = format!("{{\"title\": {:?}, \"body\": \"Body\"}}", title);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+}
+
+#[test]
+fn lists_keep_their_order_across_pages() {
+    let server = Server::start(&[]);
+    post_article(&server, "First");
+    post_article(&server, "Second");
+
+    let page = server
+        .send("GET", "/articles?sort=old&per_page=1", &[], b"")
+        .body;
+    assert!(
+        page.contains(">First</a></h2>") && !page.contains(">Second</a></h2>"),
+        "{}",
+        page
+    );
+    assert!(page.contains("<strong>Oldest</strong>"), "{}", page);
+    assert!(
+        page.contains("href=\"/articles?page=2&#38;per_page=1&#38;sort=old\""),
+        "{}",
+        page
+    );
+    assert!(
+        page.contains("href=\"/articles?page=1&#38;per_page=1&#38;sort=new\""),
+        "{}",
+        page
+    );
+
+    // Nonsense reads as the default
+    let page = server
+        .send("GET", "/articles?sort=sideways&per_page=1", &[], b"")
+        .body;
+    assert!(
+        page.contains(">Second</a></h2>") && page.contains("<strong>Recently bumped</strong>"),
+        "{}",
+        page
+    );
+
+    let response = server.send("GET", "/api/articles?sort=old", &[], b"");
+    let articles: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(articles[0]["title"], "First");
+    assert_eq!(articles[1]["title"], "Second");
+}
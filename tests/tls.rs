@@ -0,0 +1,152 @@
+// With a certificate and key the server speaks HTTPS, marks its cookies
+// Secure, and can redirect plain HTTP there; broken files stop it at startup
+// with a message naming them
+
+mod common;
+
+use common::Server;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+
+// A scratch directory with a self-signed certificate for localhost in
+// cert.pem and its key in key.pem, and another key in other_key.pem
+struct Files {
+    dir: PathBuf,
+    cert: rcgen::Certificate,
+}
+
+impl Files {
+    fn new(name: &str) -> Files {
+        let dir =
+            std::env::temp_dir().join(format!("articles-tls-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), certified.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), certified.key_pair.serialize_pem()).unwrap();
+        std::fs::write(
+            dir.join("other_key.pem"),
+            rcgen::KeyPair::generate().unwrap().serialize_pem(),
+        )
+        .unwrap();
+        Files {
+            dir,
+            cert: certified.cert,
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.join(name).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for Files {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// GET `path` over TLS from `addr`, trusting only `files`' certificate
+fn get_https(files: &Files, addr: &str, path: &str) -> String {
+    let mut roots = RootCertStore::empty();
+    roots.add(files.cert.der().clone()).unwrap();
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let connection =
+        ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap())
+            .unwrap();
+    let mut stream = StreamOwned::new(connection, TcpStream::connect(addr).unwrap());
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+    let mut response = Vec::new();
+    // The server may close the connection without saying goodbye first
+    if let Err(e) = stream.read_to_end(&mut response) {
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof, "{}", e);
+    }
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn https_is_served_and_plain_http_redirected() {
+    let files = Files::new("serve");
+    let server = Server::start(&[
+        ("TLS_CERT_PATH", &files.path("cert.pem")),
+        ("TLS_KEY_PATH", &files.path("key.pem")),
+        ("TLS_REDIRECT_PORT", "0"),
+        ("PUBLIC_URL", "https://articles.example"),
+    ]);
+    let https = server.listening()[0]
+        .strip_prefix("https://")
+        .expect("the TCP address serves HTTPS")
+        .to_string();
+
+    let response = get_https(&files, &https, "/healthz");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let response = get_https(&files, &https, "/");
+    let cookie = response
+        .lines()
+        .find(|line| line.to_ascii_lowercase().starts_with("set-cookie:"))
+        .unwrap();
+    assert!(cookie.contains("Secure"), "{}", cookie);
+
+    let response = server.send("GET", "/articles?page=2", &[], b"");
+    assert_eq!(response.status, 301, "{}", response.body);
+    assert_eq!(
+        response.header("Location"),
+        Some("https://articles.example/articles?page=2")
+    );
+}
+
+// Start the server with the given TLS files, expecting it to fail with `error`
+fn assert_refused(cert: &str, key: &str, error: &str) {
+    let output = Command::new(env!("CARGO_BIN_EXE_articles"))
+        .arg("serve")
+        .env("DATABASE_URL", "sqlite::memory:")
+        .env("PORT", "0")
+        .env("TLS_CERT_PATH", cert)
+        .env("TLS_KEY_PATH", key)
+        .env_remove("BIND_ADDR")
+        .env_remove("LISTEN_UDS")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(error), "{}", stderr);
+}
+
+#[test]
+fn broken_files_are_named() {
+    let files = Files::new("broken");
+    assert_refused(
+        &files.path("missing.pem"),
+        &files.path("key.pem"),
+        "Failed to read TLS_CERT_PATH",
+    );
+    assert_refused(
+        &files.path("key.pem"),
+        &files.path("key.pem"),
+        "holds no certificates",
+    );
+    assert_refused(
+        &files.path("cert.pem"),
+        &files.path("cert.pem"),
+        "holds no private key",
+    );
+    assert_refused(
+        &files.path("cert.pem"),
+        &files.path("other_key.pem"),
+        "doesn't belong to the certificate",
+    );
+}
@@ -0,0 +1,51 @@
+// /trending ranks articles by recent comments, and shows the newest articles
+// when nothing has been discussed
+
+mod common;
+
+use common::Server;
+
+fn post_article(server: &Server, title: &str) -> i64 {
+    let json = format!("{{\"title\": {:?}, \"body\": \"Body\"}}", title);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    serde_json::from_str::<serde_json::Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap()
+}
+
+// The titles on the trending page, in order
+fn titles(page: &str) -> Vec<&str> {
+    page.split("<strong>")
+        .skip(1)
+        .map(|card| &card[..card.find("</strong>").unwrap()])
+        .collect()
+}
+
+#[test]
+fn falls_back_to_the_newest_articles() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("TRENDING_CACHE_SECONDS", "0"),
+    ]);
+    let discussed = post_article(&server, "Discussed");
+    post_article(&server, "Quiet");
+
+    let page = server.send("GET", "/trending", &[], b"").body;
+    assert!(page.contains("here are the newest articles"), "{}", page);
+    assert_eq!(titles(&page), ["Quiet", "Discussed"]);
+
+    let path = format!("/articles/{}/discussed", discussed);
+    let form = server.open_form(&path);
+    let response = server.submit(
+        &format!("/articles/{}/comment", discussed),
+        &form,
+        &[("comment", "First!")],
+    );
+    assert_eq!(response.status, 302, "{}", response.body);
+
+    let page = server.send("GET", "/trending", &[], b"").body;
+    assert!(!page.contains("here are the newest articles"), "{}", page);
+    assert_eq!(titles(&page), ["Discussed"]);
+}
@@ -0,0 +1,54 @@
+// With LISTEN_UDS the server answers on a Unix socket, which it takes over
+// from an earlier run and removes again when shut down
+#![cfg(unix)]
+
+mod common;
+
+use common::Server;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt as _;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+fn get(socket: &Path, path: &str) -> String {
+    let mut stream = UnixStream::connect(socket).unwrap();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn requests_are_served_over_the_socket() {
+    let socket = std::env::temp_dir().join(format!("articles-test-{}.sock", std::process::id()));
+    // What a server that was killed leaves behind
+    drop(UnixListener::bind(&socket).unwrap());
+
+    let mut server = Server::start(&[
+        ("BIND_ADDR", "none"),
+        ("LISTEN_UDS", socket.to_str().unwrap()),
+        ("LISTEN_UDS_MODE", "600"),
+    ]);
+    assert!(server.addrs().is_empty());
+    assert_eq!(
+        std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+
+    let response = get(&socket, "/healthz");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let response = get(&socket, "/readyz");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let response = get(&socket, "/");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    server.signal("TERM");
+    assert!(server.wait_for_exit(Duration::from_secs(10)));
+    assert!(!socket.exists());
+}
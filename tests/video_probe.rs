@@ -0,0 +1,150 @@
+// With FFMPEG_PATH set, the ffprobe beside it checks each uploaded MP4, and
+// ones too long or in the wrong codecs are refused and their files removed.
+// These use a stand-in ffprobe that prints probe.json from its directory.
+#![cfg(unix)]
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use std::os::unix::fs::PermissionsExt as _;
+use std::path::PathBuf;
+
+const MP4: &[u8] = b"\0\0\0\x18ftypmp42\0\0\0\0mp42isom";
+
+// A scratch directory with an ffprobe that answers with probe.json
+struct Tools {
+    dir: PathBuf,
+}
+
+impl Tools {
+    fn new(name: &str, probe: &str) -> Tools {
+        let dir =
+            std::env::temp_dir().join(format!("articles-ffprobe-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ffprobe = dir.join("ffprobe");
+        std::fs::write(
+            &ffprobe,
+            "#!/bin/sh\ncat \"$(dirname \"$0\")/probe.json\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&ffprobe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let tools = Tools { dir };
+        tools.answer(probe);
+        tools
+    }
+
+    fn answer(&self, probe: &str) {
+        std::fs::write(self.dir.join("probe.json"), probe).unwrap();
+    }
+}
+
+impl Drop for Tools {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn probe(duration: &str, video: &str, audio: &str) -> String {
+    format!(
+        r#"{{"streams":[{{"codec_name":"{}","codec_type":"video"}},{{"codec_name":"{}","codec_type":"audio"}}],"format":{{"duration":"{}"}}}}"#,
+        video, audio, duration
+    )
+}
+
+fn start(tools: &Tools) -> Server {
+    // Only ffprobe is there, so accepted videos get no poster
+    let ffmpeg = tools.dir.join("ffmpeg");
+    Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("FFMPEG_PATH", ffmpeg.to_str().unwrap()),
+    ])
+}
+
+fn post(server: &Server) -> common::Response {
+    let form = server.open_form("/");
+    let parts = [
+        text_part("title", b"A video"),
+        text_part("body", b"Watch this"),
+        file_part("media", "clip.mp4", "video/mp4", MP4),
+    ];
+    server.submit_parts("/submit", &form, &parts)
+}
+
+// Files stored, leaving out the thumbs directory
+fn uploads(server: &Server) -> usize {
+    std::fs::read_dir(server.path("uploads")).map_or(0, |entries| {
+        entries
+            .filter(|entry| entry.as_ref().unwrap().path().is_file())
+            .count()
+    })
+}
+
+#[test]
+fn short_h264_videos_are_taken() {
+    let tools = Tools::new("ok", &probe("42.5", "h264", "aac"));
+    let server = start(&tools);
+    let response = post(&server);
+    assert_eq!(response.status, 302, "{}", response.body);
+    assert_eq!(uploads(&server), 1);
+}
+
+#[test]
+fn long_videos_are_refused() {
+    let tools = Tools::new("long", &probe("754.2", "h264", "aac"));
+    let server = start(&tools);
+    let response = post(&server);
+    assert_eq!(response.status, 400, "{}", response.body);
+    assert!(
+        response
+            .body
+            .contains("Videos can be at most 5:00 long; this one is 12:35."),
+        "{}",
+        response.body
+    );
+    assert_eq!(uploads(&server), 0);
+    assert!(!server
+        .send("GET", "/api/articles/1", &[], b"")
+        .body
+        .contains("clip.mp4"));
+}
+
+#[test]
+fn videos_in_other_codecs_are_refused() {
+    let tools = Tools::new("codec", &probe("10", "hevc", "aac"));
+    let server = start(&tools);
+    let response = post(&server);
+    assert_eq!(response.status, 400, "{}", response.body);
+    assert!(
+        response
+            .body
+            .contains("Videos need to be H.264; this one is hevc."),
+        "{}",
+        response.body
+    );
+    assert_eq!(uploads(&server), 0);
+
+    tools.answer(&probe("10", "h264", "mp3"));
+    let response = post(&server);
+    assert_eq!(response.status, 400, "{}", response.body);
+    assert!(
+        response.body.contains("this one&#39;s is mp3"),
+        "{}",
+        response.body
+    );
+    assert_eq!(uploads(&server), 0);
+}
+
+#[test]
+fn videos_ffprobe_cant_read_are_refused() {
+    let tools = Tools::new("unreadable", "not json");
+    let server = start(&tools);
+    let response = post(&server);
+    assert_eq!(response.status, 400, "{}", response.body);
+    assert!(
+        response.body.contains("The video couldn&#39;t be read"),
+        "{}",
+        response.body
+    );
+    assert_eq!(uploads(&server), 0);
+}
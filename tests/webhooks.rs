@@ -0,0 +1,231 @@
+// Webhooks are sent each new post they asked for, signed with their secret;
+// deliveries that keep failing are retried, then listed by `webhook failures`
+
+mod common;
+
+use common::Server;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SECRET: &str = "s3cret";
+
+// A request the mock receiver was sent
+struct Received {
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Received {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+// A receiver answering every request with `status`, and its URL
+fn receiver(status: u16) -> (String, Receiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sent, received) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = BufReader::new(stream.unwrap());
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            let path = line.split(' ').nth(1).unwrap().to_string();
+            let mut headers = Vec::new();
+            loop {
+                line.clear();
+                stream.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((key, value)) => headers.push((key.to_string(), value.trim().to_string())),
+                    None => break,
+                }
+            }
+            let length = headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+                .unwrap()
+                .1
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).unwrap();
+            let reply = format!(
+                "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+            stream.get_mut().write_all(reply.as_bytes()).unwrap();
+            if sent
+                .send(Received {
+                    path,
+                    headers,
+                    body,
+                })
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    (url, received)
+}
+
+fn signature(body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(body);
+    format!(
+        "sha256={}",
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    )
+}
+
+fn post_article(server: &Server, title: &str) -> i64 {
+    let json = format!("{{\"title\": \"{}\", \"body\": \"Body\"}}", title);
+    let response = server.post_json("/api/articles", json.as_bytes());
+    assert_eq!(response.status, 201, "{}", response.body);
+    serde_json::from_str::<Value>(&response.body).unwrap()["id"]
+        .as_i64()
+        .unwrap()
+}
+
+#[test]
+fn new_posts_are_sent_signed() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let (url, received) = receiver(200);
+    let output = server.run(&["webhook", "add", &url, "--secret", SECRET, "--test"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("answered the ping"), "{}", stdout);
+
+    let ping = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(ping.path, "/hook");
+    assert_eq!(ping.header("X-Webhook-Event"), Some("ping"));
+    assert_eq!(
+        ping.header("X-Signature"),
+        Some(signature(&ping.body).as_str())
+    );
+
+    let id = post_article(&server, "Hooked");
+    let article = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(article.header("Content-Type"), Some("application/json"));
+    assert_eq!(article.header("X-Webhook-Event"), Some("article.created"));
+    assert_eq!(
+        article.header("X-Signature"),
+        Some(signature(&article.body).as_str())
+    );
+    let json = article.json();
+    assert_eq!(json["event"], "article.created");
+    assert_eq!(json["article"]["id"], id);
+    assert_eq!(json["article"]["title"], "Hooked");
+
+    let path = format!("/articles/{}/hooked", id);
+    let form = server.open_form(&path);
+    let response = server.submit(
+        &format!("/articles/{}/comment", id),
+        &form,
+        &[("comment", "Caught")],
+    );
+    assert_eq!(response.status, 302, "{}", response.body);
+    let comment = received.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(
+        comment.header("X-Signature"),
+        Some(signature(&comment.body).as_str())
+    );
+    let json = comment.json();
+    assert_eq!(json["event"], "comment.created");
+    assert_eq!(json["article_id"], id);
+    assert_eq!(json["comment"]["comment"], "Caught");
+}
+
+#[test]
+fn webhooks_only_get_the_events_they_asked_for() {
+    let server = Server::start(&[]);
+    let (url, received) = receiver(200);
+    let output = server.run(&["webhook", "add", &url, "--events", "comment"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Made up, and shown once
+    let secret = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .last()
+        .unwrap()
+        .to_string();
+    assert_eq!(secret.len(), 48);
+
+    post_article(&server, "Unheard");
+    assert!(received.recv_timeout(Duration::from_secs(2)).is_err());
+
+    let list = String::from_utf8(server.run(&["webhook", "list"]).stdout).unwrap();
+    assert!(
+        list.contains(&format!("{}\tcomment.created", url)),
+        "{}",
+        list
+    );
+    assert!(server.run(&["webhook", "remove", "1"]).status.success());
+    assert!(!server.run(&["webhook", "remove", "1"]).status.success());
+    assert!(!server
+        .run(&["webhook", "add", "ftp://example.com/"])
+        .status
+        .success());
+}
+
+#[test]
+fn failed_deliveries_are_retried_then_recorded() {
+    let server = Server::start(&[("WEBHOOK_ATTEMPTS", "2")]);
+    let (url, received) = receiver(500);
+    assert!(server
+        .run(&["webhook", "add", &url, "--secret", SECRET])
+        .status
+        .success());
+    let output = server.run(&["webhook", "test", "1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("HTTP 500"));
+    received.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    // The post goes through whatever the receiver says
+    post_article(&server, "Unwanted");
+    for _ in 0..2 {
+        let tried = received.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(tried.header("X-Webhook-Event"), Some("article.created"));
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let failures = loop {
+        let failures = String::from_utf8(server.run(&["webhook", "failures"]).stdout).unwrap();
+        if !failures.starts_with("No failed deliveries") || Instant::now() > deadline {
+            break failures;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    assert!(
+        failures.contains("article.created\tafter 2 tries: HTTP 500"),
+        "{}",
+        failures
+    );
+}
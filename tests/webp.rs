@@ -0,0 +1,90 @@
+// With WEBP_CONVERT, JPEG and PNG uploads over WEBP_MIN_BYTES are stored as
+// WebPs when that makes them smaller; GIFs and small images are left alone
+
+mod common;
+
+use common::{file_part, text_part, Server};
+use serde_json::Value;
+use std::io::Cursor;
+
+fn encode(img: image::DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+    let mut data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut data), format).unwrap();
+    data
+}
+
+// Flat artwork, which a lossless WebP stores in far less than a PNG
+fn artwork() -> image::DynamicImage {
+    image::RgbImage::from_fn(300, 200, |x, _| image::Rgb([(x / 30 * 25) as u8, 80, 160])).into()
+}
+
+// The stored media of the article posted with this file
+fn post(server: &Server, id: i32, filename: &str, data: &[u8]) -> (Value, Vec<u8>) {
+    let form = server.open_form("/");
+    let title = format!("Upload {}", id);
+    let parts = [
+        text_part("title", title.as_bytes()),
+        text_part("body", title.as_bytes()),
+        file_part("media", filename, "application/octet-stream", data),
+    ];
+    let response = server.submit_parts("/submit", &form, &parts);
+    assert_eq!(response.status, 302, "{}", response.body);
+    let article: Value = serde_json::from_str(
+        &server
+            .send("GET", &format!("/api/articles/{}", id), &[], b"")
+            .body,
+    )
+    .unwrap();
+    let media = article["media"][0].clone();
+    let path = media["media_path"]
+        .as_str()
+        .unwrap()
+        .trim_start_matches("/uploads/");
+    let file = std::fs::read(server.path("uploads").join(path)).unwrap();
+    (media, file)
+}
+
+#[test]
+fn large_images_are_stored_as_webp() {
+    let server = Server::start(&[
+        ("CAPTCHA_ENABLED", "false"),
+        ("FORM_MIN_FILL_SECONDS", "0"),
+        ("WEBP_CONVERT", "true"),
+        ("WEBP_MIN_BYTES", "500"),
+        ("RUST_LOG", "warn,articles::media=info"),
+    ]);
+    let png = encode(artwork(), image::ImageFormat::Png);
+    assert!(png.len() >= 500);
+    let (media, file) = post(&server, 1, "art.png", &png);
+    assert!(
+        media["media_path"].as_str().unwrap().ends_with(".webp"),
+        "{}",
+        media
+    );
+    assert_eq!(media["mime_type"], "image/webp");
+    assert_eq!(media["original_name"], "art.png");
+    assert_eq!(media["byte_size"].as_u64(), Some(file.len() as u64));
+    assert!(file.len() < png.len());
+    let stored = image::load_from_memory_with_format(&file, image::ImageFormat::WebP).unwrap();
+    assert_eq!(stored.to_rgb8(), artwork().to_rgb8());
+    let stderr = std::fs::read_to_string(server.path("stderr.txt")).unwrap();
+    assert!(stderr.contains("Converted an upload to WebP"), "{}", stderr);
+
+    let small = encode(image::RgbImage::new(4, 4).into(), image::ImageFormat::Png);
+    assert!(small.len() < 500);
+    let (media, _) = post(&server, 2, "small.png", &small);
+    assert_eq!(media["mime_type"], "image/png");
+
+    let gif = encode(artwork(), image::ImageFormat::Gif);
+    let (media, file) = post(&server, 3, "art.gif", &gif);
+    assert_eq!(media["mime_type"], "image/gif");
+    assert_eq!(file, gif);
+}
+
+#[test]
+fn conversion_is_off_by_default() {
+    let server = Server::start(&[("CAPTCHA_ENABLED", "false"), ("FORM_MIN_FILL_SECONDS", "0")]);
+    let png = encode(artwork(), image::ImageFormat::Png);
+    let (media, _) = post(&server, 1, "art.png", &png);
+    assert_eq!(media["mime_type"], "image/png");
+}